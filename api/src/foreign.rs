@@ -103,7 +103,7 @@ where
 	/// // A NodeClient must first be created to handle communication between
 	/// // the wallet and the node.
 	///
-	/// let node_client = HTTPNodeClient::new(&wallet_config.check_node_api_http_addr, None);
+	/// let node_client = HTTPNodeClient::new(&wallet_config.check_node_api_http_addr, None, None);
 	/// let mut wallet:Arc<Mutex<WalletBackend<HTTPNodeClient, ExtKeychain>>> =
 	///		Arc::new(Mutex::new(
 	///			LMDBBackend::new(wallet_config.clone(), "", node_client).unwrap()
@@ -400,7 +400,7 @@ macro_rules! doctest_helper_setup_doc_env_foreign {
 		wallet_config.data_file_dir = dir.to_owned();
 		let pw = "";
 
-		let node_client = HTTPNodeClient::new(&wallet_config.check_node_api_http_addr, None);
+		let node_client = HTTPNodeClient::new(&wallet_config.check_node_api_http_addr, None, None);
 		let mut $wallet: Arc<Mutex<WalletBackend<HTTPNodeClient, ExtKeychain>>> = Arc::new(
 			Mutex::new(LMDBBackend::new(wallet_config.clone(), pw, node_client).unwrap()),
 			);
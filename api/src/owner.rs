@@ -14,19 +14,25 @@
 
 //! Owner API External Definition
 
-use crate::util::Mutex;
+use crate::util::{Mutex, RwLock};
 use chrono::prelude::*;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use uuid::Uuid;
 
 use crate::core::core::Transaction;
 use crate::impls::{HTTPWalletCommAdapter, KeybaseWalletCommAdapter};
 use crate::keychain::{Identifier, Keychain};
 use crate::libwallet::api_impl::owner;
+use crate::libwallet::api_impl::owner_updater::Updater;
 use crate::libwallet::{
-	AcctPathMapping, Error, ErrorKind, InitTxArgs, IssueInvoiceTxArgs, NodeClient,
-	NodeHeightResult, OutputCommitMapping, Slate, TxLogEntry, WalletBackend, WalletInfo,
+	AcctPathMapping, ArchiveSummary, CheckDiscrepancy, Error, ErrorKind, GcReport, InitTxArgs,
+	IntegrityReport, IssueInvoiceTxArgs, NodeClient, NodeHeightResult, OutputCommitMapping,
+	RefreshSchedule, RefreshStatus, ScanKind, ScanPhase, ScanStatus, Slate, TxLogEntry,
+	WalletBackend, WalletInfo,
 };
 
 /// Main interface into all wallet API functions.
@@ -54,10 +60,36 @@ where
 	pub wallet: Arc<Mutex<W>>,
 	/// Flag to normalize some output during testing. Can mostly be ignored.
 	pub doctest_mode: bool,
+	/// Latest status published by a restore or check run through this instance
+	pub latest_scan_status: Arc<Mutex<Option<ScanStatus>>>,
+	/// What the background updater started through this instance last found
+	refresh_status: Arc<RwLock<Option<RefreshStatus>>>,
+	/// Flag keeping that updater running, while it is
+	updater_running: Arc<Mutex<Option<Arc<AtomicBool>>>>,
 	phantom: PhantomData<K>,
 	phantom_c: PhantomData<C>,
 }
 
+/// Clones share the scan status and background updater of the original
+impl<W: ?Sized, C, K> Clone for Owner<W, C, K>
+where
+	W: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	fn clone(&self) -> Self {
+		Owner {
+			wallet: self.wallet.clone(),
+			doctest_mode: self.doctest_mode,
+			latest_scan_status: self.latest_scan_status.clone(),
+			refresh_status: self.refresh_status.clone(),
+			updater_running: self.updater_running.clone(),
+			phantom: PhantomData,
+			phantom_c: PhantomData,
+		}
+	}
+}
+
 impl<W: ?Sized, C, K> Owner<W, C, K>
 where
 	W: WalletBackend<C, K>,
@@ -111,7 +143,7 @@ where
 	/// // A NodeClient must first be created to handle communication between
 	/// // the wallet and the node.
 	///
-	/// let node_client = HTTPNodeClient::new(&wallet_config.check_node_api_http_addr, None);
+	/// let node_client = HTTPNodeClient::new(&wallet_config.check_node_api_http_addr, None, None);
 	/// let mut wallet:Arc<Mutex<WalletBackend<HTTPNodeClient, ExtKeychain>>> =
 	///		Arc::new(Mutex::new(
 	///			LMDBBackend::new(wallet_config.clone(), "", node_client).unwrap()
@@ -126,6 +158,9 @@ where
 		Owner {
 			wallet: wallet_in,
 			doctest_mode: false,
+			latest_scan_status: Arc::new(Mutex::new(None)),
+			refresh_status: Arc::new(RwLock::new(None)),
+			updater_running: Arc::new(Mutex::new(None)),
 			phantom: PhantomData,
 			phantom_c: PhantomData,
 		}
@@ -258,6 +293,9 @@ where
 	/// provided during wallet instantiation). If `false`, the results will
 	/// contain output information that may be out-of-date (from the last time
 	/// the wallet's output set was refreshed against the node).
+	/// While a background updater is running (see
+	/// [`start_updater`](struct.Owner.html#method.start_updater)) the node
+	/// isn't contacted, and the results are as of its last refresh.
 	/// * `tx_id` - If `Some(i)`, only return the outputs associated with
 	/// the transaction log entry of id `i`.
 	///
@@ -266,6 +304,8 @@ where
 	/// * The first `bool` element indicates whether the data was successfully
 	/// refreshed from the node (note this may be false even if the `refresh_from_node`
 	/// argument was set to `true`.
+	/// Results served from a background updater's last refresh are counted as
+	/// refreshed unless they may be stale.
 	/// * The second element contains a vector of
 	/// [OutputCommitMapping](../grin_wallet_libwallet/types/struct.OutputCommitMapping.html)
 	/// of which each element is a mapping between the wallet's internal
@@ -295,11 +335,20 @@ where
 		refresh_from_node: bool,
 		tx_id: Option<u32>,
 	) -> Result<(bool, Vec<OutputCommitMapping>), Error> {
+		let cached = self.cached_refresh(refresh_from_node);
 		let mut w = self.wallet.lock();
 		w.open_with_credentials()?;
-		let res = owner::retrieve_outputs(&mut *w, include_spent, refresh_from_node, tx_id);
+		let res = owner::retrieve_outputs(
+			&mut *w,
+			include_spent,
+			refresh_from_node && cached.is_none(),
+			tx_id,
+		);
 		w.close()?;
-		res
+		match cached {
+			Some(fresh) => res.map(|(_, outputs)| (fresh, outputs)),
+			None => res,
+		}
 	}
 
 	/// Returns a list of [Transaction Log Entries](../grin_wallet_libwallet/types/struct.TxLogEntry.html)
@@ -311,6 +360,9 @@ where
 	/// provided during wallet instantiation). If `false`, the results will
 	/// contain transaction information that may be out-of-date (from the last time
 	/// the wallet's output set was refreshed against the node).
+	/// While a background updater is running (see
+	/// [`start_updater`](struct.Owner.html#method.start_updater)) the node
+	/// isn't contacted, and the results are as of its last refresh.
 	/// * `tx_id` - If `Some(i)`, only return the transactions associated with
 	/// the transaction log entry of id `i`.
 	/// * `tx_slate_id` - If `Some(uuid)`, only return transactions associated with
@@ -321,6 +373,8 @@ where
 	/// * The first `bool` element indicates whether the data was successfully
 	/// refreshed from the node (note this may be false even if the `refresh_from_node`
 	/// argument was set to `true`.
+	/// Results served from a background updater's last refresh are counted as
+	/// refreshed unless they may be stale.
 	/// * The second element contains the set of retrieved
 	/// [TxLogEntries](../grin_wallet_libwallet/types/struct.TxLogEntry.html)
 	///
@@ -348,9 +402,14 @@ where
 		tx_id: Option<u32>,
 		tx_slate_id: Option<Uuid>,
 	) -> Result<(bool, Vec<TxLogEntry>), Error> {
+		let cached = self.cached_refresh(refresh_from_node);
 		let mut w = self.wallet.lock();
 		w.open_with_credentials()?;
+		let refresh_from_node = refresh_from_node && cached.is_none();
 		let mut res = owner::retrieve_txs(&mut *w, refresh_from_node, tx_id, tx_slate_id)?;
+		if let Some(fresh) = cached {
+			res.0 = fresh;
+		}
 		if self.doctest_mode {
 			res.1 = res
 				.1
@@ -374,6 +433,9 @@ where
 	/// provided during wallet instantiation). If `false`, the results will
 	/// contain transaction information that may be out-of-date (from the last time
 	/// the wallet's output set was refreshed against the node).
+	/// While a background updater is running (see
+	/// [`start_updater`](struct.Owner.html#method.start_updater)) the node
+	/// isn't contacted, and the results are as of its last refresh.
 	/// * `minimum_confirmations` - The minimum number of confirmations an output
 	/// should have before it's included in the 'amount_currently_spendable' total
 	///
@@ -382,6 +444,8 @@ where
 	/// * The first `bool` element indicates whether the data was successfully
 	/// refreshed from the node (note this may be false even if the `refresh_from_node`
 	/// argument was set to `true`.
+	/// Results served from a background updater's last refresh are counted as
+	/// refreshed unless they may be stale.
 	/// * The second element contains the Summary [`WalletInfo`](../grin_wallet_libwallet/types/struct.WalletInfo.html)
 	///
 	/// # Example
@@ -406,11 +470,19 @@ where
 		refresh_from_node: bool,
 		minimum_confirmations: u64,
 	) -> Result<(bool, WalletInfo), Error> {
+		let cached = self.cached_refresh(refresh_from_node);
 		let mut w = self.wallet.lock();
 		w.open_with_credentials()?;
-		let res = owner::retrieve_summary_info(&mut *w, refresh_from_node, minimum_confirmations);
+		let res = owner::retrieve_summary_info(
+			&mut *w,
+			refresh_from_node && cached.is_none(),
+			minimum_confirmations,
+		);
 		w.close()?;
-		res
+		match cached {
+			Some(fresh) => res.map(|(_, info)| (fresh, info)),
+			None => res,
+		}
 	}
 
 	/// Initiates a new transaction as the sender, creating a new
@@ -488,19 +560,20 @@ where
 
 	pub fn init_send_tx(&self, args: InitTxArgs) -> Result<Slate, Error> {
 		let send_args = args.send_args.clone();
-		let mut slate = {
+		let (mut slate, socks_proxy) = {
 			let mut w = self.wallet.lock();
 			w.open_with_credentials()?;
 			let slate = owner::init_send_tx(&mut *w, args, self.doctest_mode)?;
 			w.close()?;
-			slate
+			(slate, w.w2n_client().socks_proxy())
 		};
 		// Helper functionality. If send arguments exist, attempt to send
 		match send_args {
 			Some(sa) => {
 				match sa.method.as_ref() {
 					"http" => {
-						slate = HTTPWalletCommAdapter::new().send_tx_sync(&sa.dest, &slate)?
+						slate = HTTPWalletCommAdapter::new(socks_proxy)
+							.send_tx_sync(&sa.dest, &slate)?
 					}
 					"keybase" => {
 						//TODO: in case of keybase, the response might take 60s and leave the service hanging
@@ -960,20 +1033,32 @@ where
 
 	/// Scans the entire UTXO set from the node, creating outputs for each scanned
 	/// output that matches the wallet's master seed. This function is intended to be called as part
-	/// of a recovery process (either from BIP32 phrase or backup seed files,) and will error if the
-	/// wallet is non-empty, i.e. contains any outputs at all.
+	/// of a recovery process (either from BIP32 phrase or backup seed files,) and does nothing if
+	/// the wallet is non-empty, i.e. contains any outputs at all, other than leave a
+	/// [`scan_status`](struct.Owner.html#method.scan_status) in the `Skipped` phase.
 	///
 	/// This operation scans the entire chain, and is expected to be time intensive. It is imperative
 	/// that no other processes should be trying to use the wallet at the same time this function is
 	/// running.
 	///
-	/// A single [TxLogEntry](../grin_wallet_libwallet/types/struct.TxLogEntry.html) is created for
-	/// all non-coinbase outputs discovered and restored during this process. A separate entry
-	/// is created for each coinbase output.
+	/// A [TxLogEntry](../grin_wallet_libwallet/types/struct.TxLogEntry.html) is created for the
+	/// non-coinbase outputs discovered in each block, per account, as the node can't say which of a
+	/// block's transactions they came from. A separate entry is created for each coinbase output.
+	/// Entries carry the height and time of the block the outputs were confirmed in.
 	///
 	/// # Arguments
 	///
-	/// * None
+	/// * `start_height` - block height to start scanning from. Outputs created before it aren't
+	/// restored. If `None`, the scan starts from the height the wallet was created at, if it was
+	/// recorded when the wallet was initialized, or from the start of the chain otherwise.
+	/// * `account_labels` - labels of the wallet's accounts, by parent derivation path, as returned
+	/// by [`accounts`](struct.Owner.html#method.accounts) before the wallet was lost. The accounts
+	/// are restored with these labels, and accounts found at other paths are labelled
+	/// `account_N`. If `None`, all accounts found other than the default are labelled `account_N`.
+	///
+	/// Outputs are recovered at any derivation path depth. Outputs that belong to the wallet but
+	/// whose derivation path can't be recovered aren't restored, and are listed in the
+	/// [`scan_status`](struct.Owner.html#method.scan_status) once the restore finishes.
 	///
 	/// # Returns
 	/// * `Ok(())` if successful
@@ -985,19 +1070,21 @@ where
 	/// # grin_wallet_api::doctest_helper_setup_doc_env!(wallet, wallet_config);
 	///
 	/// let mut api_owner = Owner::new(wallet.clone());
-	/// let result = api_owner.restore();
+	/// let result = api_owner.restore(None, None);
 	///
 	/// if let Ok(_) = result {
 	///		// Wallet outputs should be consistent with what's on chain
 	///		// ...
 	/// }
 	/// ```
-	pub fn restore(&self) -> Result<(), Error> {
-		let mut w = self.wallet.lock();
-		w.open_with_credentials()?;
-		let res = owner::restore(&mut *w);
-		w.close()?;
-		res
+	pub fn restore(
+		&self,
+		start_height: Option<u64>,
+		account_labels: Option<Vec<AcctPathMapping>>,
+	) -> Result<(), Error> {
+		self.run_scan(ScanKind::Restore, |w, status_send| {
+			owner::restore(w, start_height, account_labels, status_send)
+		})
 	}
 
 	/// Scans the entire UTXO set from the node, identify which outputs belong to the given wallet
@@ -1025,6 +1112,8 @@ where
 	/// Note this completely removes all outstanding transactions, so users should be very aware what
 	/// will happen if this flag is set. Note that if transactions/outputs are removed that later
 	/// confirm on the chain, another call to this function will restore them.
+	/// * `start_height` - block height to start scanning from, as for
+	/// [`restore`](struct.Owner.html#method.restore). Outputs created before it are left as they are.
 	///
 	/// # Returns
 	/// * `Ok(())` if successful
@@ -1038,6 +1127,7 @@ where
 	/// let mut api_owner = Owner::new(wallet.clone());
 	/// let result = api_owner.check_repair(
 	/// 	false,
+	/// 	None,
 	/// );
 	///
 	/// if let Ok(_) = result {
@@ -1046,10 +1136,375 @@ where
 	/// }
 	/// ```
 
-	pub fn check_repair(&self, delete_unconfirmed: bool) -> Result<(), Error> {
+	pub fn check_repair(
+		&self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+	) -> Result<(), Error> {
+		self.run_check_repair(delete_unconfirmed, start_height, false)?;
+		Ok(())
+	}
+
+	/// Runs the same check as [`check_repair`](struct.Owner.html#method.check_repair), but only
+	/// reports what it would repair. Outputs are refreshed from the node first, as for any other
+	/// call that refreshes, but nothing the check finds is changed, and no scan progress is saved.
+	///
+	/// # Arguments
+	///
+	/// * `delete_unconfirmed` - as for [`check_repair`](struct.Owner.html#method.check_repair),
+	/// whether to report the locked and unconfirmed outputs it would unlock and delete.
+	/// * `start_height` - block height to start scanning from, as for
+	/// [`check_repair`](struct.Owner.html#method.check_repair).
+	///
+	/// # Returns
+	/// * `Ok(Vec<CheckDiscrepancy>)` with each of the discrepancies found, and how a repair would
+	/// deal with it, see [`CheckDiscrepancy`](../grin_wallet_libwallet/types/enum.CheckDiscrepancy.html)
+	/// * or [`libwallet::Error`](../grin_wallet_libwallet/struct.Error.html) if an error is encountered.
+
+	/// # Example
+	/// Set up as in [`new`](struct.Owner.html#method.new) method above.
+	/// ```
+	/// # grin_wallet_api::doctest_helper_setup_doc_env!(wallet, wallet_config);
+	///
+	/// let mut api_owner = Owner::new(wallet.clone());
+	/// let result = api_owner.check_repair_dry_run(
+	/// 	true,
+	/// 	None,
+	/// );
+	///
+	/// if let Ok(discrepancies) = result {
+	///		// Show what check_repair would change
+	///		// ...
+	/// }
+	/// ```
+
+	pub fn check_repair_dry_run(
+		&self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+	) -> Result<Vec<CheckDiscrepancy>, Error> {
+		self.run_check_repair(delete_unconfirmed, start_height, true)
+	}
+
+	fn run_check_repair(
+		&self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+		dry_run: bool,
+	) -> Result<Vec<CheckDiscrepancy>, Error> {
+		self.run_scan(ScanKind::CheckRepair, |w, status_send| {
+			owner::check_repair(w, delete_unconfirmed, start_height, dry_run, status_send)
+		})
+	}
+
+	/// Checks the consistency of everything the wallet stores, without contacting a node.
+	/// Outputs are checked for links to transaction log entries that don't exist, transaction
+	/// log entries for stored transactions that are missing or unreadable, private contexts for
+	/// transactions that are finished with or unknown, accounts for child indices behind their
+	/// outputs, missing labels and duplicate labels, and scanned outputs for a lost checkpoint.
+	///
+	/// # Arguments
+	///
+	/// * `fix` - If true, fix the issues found that can be fixed without losing anything.
+	/// Private contexts without a transaction log entry (which may belong to a transaction still
+	/// being built) and accounts with more than one label are only reported.
+	///
+	/// # Returns
+	/// * Ok with an [`IntegrityReport`](../grin_wallet_libwallet/types/struct.IntegrityReport.html)
+	/// of the records checked and the issues found, if successful
+	/// * or [`libwallet::Error`](../grin_wallet_libwallet/struct.Error.html) if an error is encountered.
+	///
+	/// # Example
+	/// Set up as in [`new`](struct.Owner.html#method.new) method above.
+	/// ```
+	/// # grin_wallet_api::doctest_helper_setup_doc_env!(wallet, wallet_config);
+	///
+	/// let api_owner = Owner::new(wallet.clone());
+	/// let result = api_owner.check_integrity(false);
+	///
+	/// if let Ok(report) = result {
+	///		// report.issues holds anything found
+	///		// ...
+	/// }
+	/// ```
+
+	pub fn check_integrity(&self, fix: bool) -> Result<IntegrityReport, Error> {
+		let mut w = self.wallet.lock();
+		w.open_with_credentials()?;
+		let res = owner::check_integrity(&mut *w, fix);
+		w.close()?;
+		res
+	}
+
+	/// Deletes the private contexts and stored transactions the wallet no longer needs, as set
+	/// by its retention settings. Contexts are deleted once all of their transactions are
+	/// cancelled or confirmed, or once they're older than the configured number of blocks.
+	/// Stored transactions of confirmed transactions are moved to the `archive` directory
+	/// beside them, or deleted, unless set to be kept. This is also done after each refresh
+	/// from the node, unless turned off in the configuration.
+	///
+	/// # Returns
+	/// * Ok with a [`GcReport`](../grin_wallet_libwallet/types/struct.GcReport.html)
+	/// of what was removed, if successful
+	/// * or [`libwallet::Error`](../grin_wallet_libwallet/struct.Error.html) if an error is encountered.
+	///
+	/// # Example
+	/// Set up as in [`new`](struct.Owner.html#method.new) method above.
+	/// ```
+	/// # grin_wallet_api::doctest_helper_setup_doc_env!(wallet, wallet_config);
+	///
+	/// let api_owner = Owner::new(wallet.clone());
+	/// let result = api_owner.collect_garbage();
+	///
+	/// if let Ok(report) = result {
+	///		// report.contexts holds the private contexts deleted
+	///		// ...
+	/// }
+	/// ```
+
+	pub fn collect_garbage(&self) -> Result<GcReport, Error> {
+		let mut w = self.wallet.lock();
+		w.open_with_credentials()?;
+		let res = owner::collect_garbage(&mut *w);
+		w.close()?;
+		res
+	}
+
+	/// Returns the latest status of a [`restore`](struct.Owner.html#method.restore) or
+	/// [`check_repair`](struct.Owner.html#method.check_repair) run through this API instance,
+	/// including how much of the output PMMR has been scanned and how many of the wallet's outputs
+	/// have been found. The wallet isn't opened, so this can be called while a scan is running.
+	///
+	/// # Returns
+	/// * `Ok(Some(ScanStatus))` with the status of the running scan, or of the last scan if none
+	/// is running, see [`ScanStatus`](../grin_wallet_libwallet/types/struct.ScanStatus.html)
+	/// * `Ok(None)` if no scan has been run
+	///
+	/// # Example
+	/// Set up as in [`new`](struct.Owner.html#method.new) method above.
+	/// ```
+	/// # grin_wallet_api::doctest_helper_setup_doc_env!(wallet, wallet_config);
+	///
+	/// let api_owner = Owner::new(wallet.clone());
+	/// let result = api_owner.scan_status();
+	///
+	/// if let Ok(Some(status)) = result {
+	///		// show progress of the scan
+	///		// ...
+	/// }
+	/// ```
+	pub fn scan_status(&self) -> Result<Option<ScanStatus>, Error> {
+		Ok(self.latest_scan_status.lock().clone())
+	}
+
+	/// Returns what the background updater started through this instance (or
+	/// one it was cloned from or to) last found, including whether reads
+	/// served from it may be stale.
+	///
+	/// # Returns
+	/// * Ok with the [`RefreshStatus`](../grin_wallet_libwallet/api_impl/types/struct.RefreshStatus.html)
+	/// of the running updater, or `None` if there's none, in which case reads
+	/// go to the node when asked to refresh
+	/// * or [`libwallet::Error`](../grin_wallet_libwallet/struct.Error.html) if an error is encountered.
+	///
+	/// # Example
+	/// Set up as in [`new`](struct.Owner.html#method.new) method above.
+	/// ```
+	/// # grin_wallet_api::doctest_helper_setup_doc_env!(wallet, wallet_config);
+	///
+	/// let api_owner = Owner::new(wallet.clone());
+	/// let result = api_owner.refresh_status();
+	///
+	/// if let Ok(Some(status)) = result {
+	///		if status.stale {
+	///			// reads may be out of date
+	///		}
+	///		// ...
+	/// }
+	/// ```
+	pub fn refresh_status(&self) -> Result<Option<RefreshStatus>, Error> {
+		match self.updater_running.lock().is_some() {
+			true => Ok(self.refresh_status.read().clone()),
+			false => Ok(None),
+		}
+	}
+
+	/// Whether a read asked to refresh is served from the background
+	/// updater's last refresh instead, and if so whether that's fresh
+	fn cached_refresh(&self, refresh_from_node: bool) -> Option<bool> {
+		if !refresh_from_node || self.updater_running.lock().is_none() {
+			return None;
+		}
+		let status = self.refresh_status.read();
+		Some(status.as_ref().map(|s| !s.stale).unwrap_or(false))
+	}
+
+	/// Channel for a scan to publish its status to, and the thread recording
+	/// what's published as the latest status until the scan drops the channel
+	fn start_scan_status(&self) -> (Sender<ScanStatus>, JoinHandle<()>) {
+		let (status_send, status_recv) = channel();
+		let latest = self.latest_scan_status.clone();
+		*latest.lock() = None;
+		let status_thread = thread::spawn(move || {
+			for status in status_recv {
+				*latest.lock() = Some(status);
+			}
+		});
+		(status_send, status_thread)
+	}
+
+	/// Run a restore or check with the wallet open, recording the status it
+	/// publishes. Its status ends as failed if it fails, even if the wallet
+	/// couldn't be opened for it to publish anything.
+	fn run_scan<R, F>(&self, kind: ScanKind, scan: F) -> Result<R, Error>
+	where
+		F: FnOnce(&mut W, &Option<Sender<ScanStatus>>) -> Result<R, Error>,
+	{
+		let (status_send, status_thread) = self.start_scan_status();
+		// the sender is dropped at the end of the block whatever happens, so
+		// the status thread always finishes
+		let res = {
+			let status_send = Some(status_send);
+			let mut w = self.wallet.lock();
+			match w.open_with_credentials() {
+				Ok(_) => {
+					let res = scan(&mut *w, &status_send);
+					w.close().and(res)
+				}
+				Err(e) => Err(e),
+			}
+		};
+		self.finish_scan_status(kind, status_thread, &res);
+		res
+	}
+
+	/// Wait until everything published by a finished scan is recorded, and
+	/// mark its status as failed if it didn't succeed
+	fn finish_scan_status<R>(
+		&self,
+		kind: ScanKind,
+		status_thread: JoinHandle<()>,
+		res: &Result<R, Error>,
+	) {
+		let _ = status_thread.join();
+		if res.is_err() {
+			let mut latest = self.latest_scan_status.lock();
+			let mut status = latest.take().unwrap_or_else(|| ScanStatus::new(kind));
+			status.phase = ScanPhase::Failed;
+			*latest = Some(status);
+		}
+	}
+
+	/// Changes the password protecting the wallet's seed file. The seed is decrypted with the
+	/// current password and re-encrypted under the new one. The new file is written in full
+	/// before it replaces the old one, which is kept alongside it as a backup, so the wallet
+	/// remains recoverable if the process is interrupted. The backup is `wallet.seed.bak`, or
+	/// if that's already taken by an earlier backup, `wallet.seed.bak.N` with the lowest `N`
+	/// from 1 not yet taken.
+	///
+	/// # Arguments
+	///
+	/// * `old_password` - The current wallet password.
+	/// * `new_password` - The password to encrypt the seed with from now on.
+	///
+	/// # Returns
+	/// * `Ok(())` if successful
+	/// * or [`libwallet::Error`](../grin_wallet_libwallet/struct.Error.html) if an error is encountered,
+	/// including if `old_password` doesn't decrypt the seed.
+	///
+	/// # Example
+	/// Set up as in [`new`](struct.Owner.html#method.new) method above.
+	/// ```
+	/// # grin_wallet_api::doctest_helper_setup_doc_env!(wallet, wallet_config);
+	///
+	/// let api_owner = Owner::new(wallet.clone());
+	/// let result = api_owner.change_password("", "my new password");
+	///
+	/// if let Ok(_) = result {
+	///		// The wallet must be opened with the new password from now on
+	///		// ...
+	/// }
+	/// ```
+
+	pub fn change_password(&self, old_password: &str, new_password: &str) -> Result<(), Error> {
+		let mut w = self.wallet.lock();
+		owner::change_password(&mut *w, old_password, new_password)
+	}
+
+	/// Writes everything the wallet stores (accounts, outputs, the transaction log, private
+	/// transaction contexts and stored transactions) to a single archive file, encrypted with
+	/// a key derived from the wallet seed. The archive can be imported into a wallet restored
+	/// from the same seed on another host with
+	/// [`import_wallet`](struct.Owner.html#method.import_wallet).
+	///
+	/// # Arguments
+	///
+	/// * `path` - The file to write the archive to. Any existing file is overwritten.
+	///
+	/// # Returns
+	/// * Ok with an [`ArchiveSummary`](../grin_wallet_libwallet/types/struct.ArchiveSummary.html)
+	/// of the number of records written, if successful
+	/// * or [`libwallet::Error`](../grin_wallet_libwallet/struct.Error.html) if an error is encountered.
+	///
+	/// # Example
+	/// Set up as in [`new`](struct.Owner.html#method.new) method above.
+	/// ```
+	/// # grin_wallet_api::doctest_helper_setup_doc_env!(wallet, wallet_config);
+	///
+	/// let api_owner = Owner::new(wallet.clone());
+	/// let path = std::env::temp_dir().join("wallet_archive.json");
+	/// let result = api_owner.export_wallet(path.to_str().unwrap());
+	///
+	/// if let Ok(summary) = result {
+	///		// The archive holds summary.outputs outputs
+	///		// ...
+	/// }
+	/// ```
+
+	pub fn export_wallet(&self, path: &str) -> Result<ArchiveSummary, Error> {
+		let mut w = self.wallet.lock();
+		w.open_with_credentials()?;
+		let res = owner::export_wallet(&mut *w, path);
+		w.close()?;
+		res
+	}
+
+	/// Imports an archive written by [`export_wallet`](struct.Owner.html#method.export_wallet).
+	/// The archive must have been exported from a wallet with the same seed, and is only
+	/// imported into a wallet with no outputs or transactions of its own, such as one newly
+	/// created with `init --recover`. Everything but the stored transactions is written in a
+	/// single batch, and stored transactions written before it are removed again if it fails,
+	/// so an import that fails leaves the wallet unchanged.
+	///
+	/// # Arguments
+	///
+	/// * `path` - The archive file to import.
+	///
+	/// # Returns
+	/// * Ok with an [`ArchiveSummary`](../grin_wallet_libwallet/types/struct.ArchiveSummary.html)
+	/// of the number of records imported, if successful
+	/// * or [`libwallet::Error`](../grin_wallet_libwallet/struct.Error.html) if an error is encountered,
+	/// including if the archive was exported from a different seed.
+	///
+	/// # Example
+	/// Set up as in [`new`](struct.Owner.html#method.new) method above.
+	/// ```
+	/// # grin_wallet_api::doctest_helper_setup_doc_env!(wallet, wallet_config);
+	///
+	/// let api_owner = Owner::new(wallet.clone());
+	/// let path = std::env::temp_dir().join("wallet_archive.json");
+	/// let result = api_owner.import_wallet(path.to_str().unwrap());
+	///
+	/// if let Ok(summary) = result {
+	///		// The wallet now holds the archived outputs and transactions
+	///		// ...
+	/// }
+	/// ```
+
+	pub fn import_wallet(&self, path: &str) -> Result<ArchiveSummary, Error> {
 		let mut w = self.wallet.lock();
 		w.open_with_credentials()?;
-		let res = owner::check_repair(&mut *w, delete_unconfirmed);
+		let res = owner::import_wallet(&mut *w, path);
 		w.close()?;
 		res
 	}
@@ -1100,6 +1555,50 @@ where
 	}
 }
 
+impl<W: ?Sized, C, K> Owner<W, C, K>
+where
+	W: WalletBackend<C, K> + Send + 'static,
+	C: NodeClient + 'static,
+	K: Keychain + 'static,
+{
+	/// Starts a thread refreshing the wallet's outputs from the node in the
+	/// background, so that reads asked to refresh through this instance (or
+	/// its clones) are served from what it last found instead of each going
+	/// to the node. It checks the node's chain height every
+	/// `schedule.height_poll`, refreshing the outputs of every account as
+	/// soon as it changes, and at least every `schedule.interval`. Keeps
+	/// running until [`stop_updater`](struct.Owner.html#method.stop_updater)
+	/// is called.
+	///
+	/// # Arguments
+	/// * `schedule` - When the outputs are refreshed
+	///
+	/// # Returns
+	/// * Ok with the handle of the updater thread if successful
+	/// * or [`libwallet::Error`](../grin_wallet_libwallet/struct.Error.html) if an updater is
+	/// already running.
+	pub fn start_updater(&self, schedule: RefreshSchedule) -> Result<JoinHandle<()>, Error> {
+		let mut running = self.updater_running.lock();
+		if running.is_some() {
+			return Err(ErrorKind::GenericError(
+				"A background updater is already running".to_owned(),
+			))?;
+		}
+		let keep_running = Arc::new(AtomicBool::new(true));
+		*running = Some(keep_running.clone());
+		let updater = Updater::new(self.wallet.clone(), self.refresh_status.clone());
+		Ok(thread::spawn(move || updater.run(schedule, keep_running)))
+	}
+
+	/// Stops the background updater, if one is running. Reads asked to
+	/// refresh go to the node again from then on.
+	pub fn stop_updater(&self) {
+		if let Some(keep_running) = self.updater_running.lock().take() {
+			keep_running.store(false, Ordering::SeqCst);
+		}
+	}
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! doctest_helper_setup_doc_env {
@@ -1132,7 +1631,7 @@ macro_rules! doctest_helper_setup_doc_env {
 		wallet_config.data_file_dir = dir.to_owned();
 		let pw = "";
 
-		let node_client = HTTPNodeClient::new(&wallet_config.check_node_api_http_addr, None);
+		let node_client = HTTPNodeClient::new(&wallet_config.check_node_api_http_addr, None, None);
 		let mut $wallet: Arc<Mutex<WalletBackend<HTTPNodeClient, ExtKeychain>>> = Arc::new(
 			Mutex::new(LMDBBackend::new(wallet_config.clone(), pw, node_client).unwrap()),
 			);
@@ -18,8 +18,9 @@ use uuid::Uuid;
 use crate::core::core::Transaction;
 use crate::keychain::{Identifier, Keychain};
 use crate::libwallet::{
-	AcctPathMapping, ErrorKind, InitTxArgs, IssueInvoiceTxArgs, NodeClient, NodeHeightResult,
-	OutputCommitMapping, Slate, TxLogEntry, WalletBackend, WalletInfo,
+	AcctPathMapping, CheckDiscrepancy, ErrorKind, GcReport, InitTxArgs, IntegrityReport,
+	IssueInvoiceTxArgs, NodeClient, NodeHeightResult, OutputCommitMapping, RefreshStatus,
+	ScanStatus, Slate, TxLogEntry, WalletBackend, WalletInfo,
 };
 use crate::Owner;
 use easy_jsonrpc;
@@ -152,6 +153,7 @@ pub trait OwnerRpc {
 					{
 						"commit": "08e1da9e6dc4d6e808a718b2f110a991dd775d65ce5ae408a4e1f002a4961aa9e7",
 						"output": {
+							"block_hash": "0e2f9c1a5b0e2f8c3d4a6b7c8d9e0f1a2b3c4d5e6f708192a3b4c5d6e7f80912",
 							"commit": "08e1da9e6dc4d6e808a718b2f110a991dd775d65ce5ae408a4e1f002a4961aa9e7",
 							"height": "1",
 							"is_coinbase": true,
@@ -168,6 +170,7 @@ pub trait OwnerRpc {
 					{
 						"commit": "087df32304c5d4ae8b2af0bc31e700019d722910ef87dd4eec3197b80b207e3045",
 						"output": {
+							"block_hash": "0e2f9c1a5b0e2f8c3d4a6b7c8d9e0f1a2b3c4d5e6f708192a3b4c5d6e7f80912",
 							"commit": "087df32304c5d4ae8b2af0bc31e700019d722910ef87dd4eec3197b80b207e3045",
 							"height": "2",
 							"is_coinbase": true,
@@ -223,6 +226,7 @@ pub trait OwnerRpc {
 			{
 			  "amount_credited": "60000000000",
 			  "amount_debited": "0",
+			  "confirmation_height": "1",
 			  "confirmation_ts": "2019-01-15T16:01:26Z",
 			  "confirmed": true,
 			  "creation_ts": "2019-01-15T16:01:26Z",
@@ -239,6 +243,7 @@ pub trait OwnerRpc {
 			{
 			  "amount_credited": "60000000000",
 			  "amount_debited": "0",
+			  "confirmation_height": "2",
 			  "confirmation_ts": "2019-01-15T16:01:26Z",
 			  "confirmed": true,
 			  "creation_ts": "2019-01-15T16:01:26Z",
@@ -1152,7 +1157,7 @@ pub trait OwnerRpc {
 	{
 		"jsonrpc": "2.0",
 		"method": "restore",
-		"params": [],
+		"params": [null, null],
 		"id": 1
 	}
 	# "#
@@ -1169,7 +1174,11 @@ pub trait OwnerRpc {
 	# , 1, false, false, false);
 	```
 	 */
-	fn restore(&self) -> Result<(), ErrorKind>;
+	fn restore(
+		&self,
+		start_height: Option<u64>,
+		account_labels: Option<Vec<AcctPathMapping>>,
+	) -> Result<(), ErrorKind>;
 
 	/**
 	Networked version of [Owner::check_repair](struct.Owner.html#method.check_repair).
@@ -1181,7 +1190,7 @@ pub trait OwnerRpc {
 	{
 		"jsonrpc": "2.0",
 		"method": "check_repair",
-		"params": [false],
+		"params": [false, null],
 		"id": 1
 	}
 	# "#
@@ -1198,7 +1207,202 @@ pub trait OwnerRpc {
 	# , 1, false, false, false);
 	```
 	 */
-	fn check_repair(&self, delete_unconfirmed: bool) -> Result<(), ErrorKind>;
+	fn check_repair(
+		&self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+	) -> Result<(), ErrorKind>;
+
+	/**
+	Networked version of [Owner::check_repair_dry_run](struct.Owner.html#method.check_repair_dry_run).
+
+
+	```
+	# grin_wallet_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "check_repair_dry_run",
+		"params": [true, null],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": []
+		}
+	}
+	# "#
+	# , 1, false, false, false);
+	```
+	 */
+	fn check_repair_dry_run(
+		&self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+	) -> Result<Vec<CheckDiscrepancy>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::check_integrity](struct.Owner.html#method.check_integrity).
+
+
+	```
+	# grin_wallet_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "check_integrity",
+		"params": [false],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"accounts": 1,
+				"fixed": 0,
+				"issues": [],
+				"outputs": 0,
+				"private_contexts": 0,
+				"tx_log_entries": 0
+			}
+		}
+	}
+	# "#
+	# , 0, false, false, false);
+	```
+	 */
+	fn check_integrity(&self, fix: bool) -> Result<IntegrityReport, ErrorKind>;
+
+	/**
+	Networked version of [Owner::collect_garbage](struct.Owner.html#method.collect_garbage).
+
+
+	```
+	# grin_wallet_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "collect_garbage",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": {
+				"archived_txs": [],
+				"contexts": [],
+				"pruned_txs": []
+			}
+		}
+	}
+	# "#
+	# , 0, false, false, false);
+	```
+	 */
+	fn collect_garbage(&self) -> Result<GcReport, ErrorKind>;
+
+	/**
+	Networked version of [Owner::scan_status](struct.Owner.html#method.scan_status).
+	Each request is handled by the same Owner instance, so this can be called from another client
+	while a `restore` or `check_repair` request is running.
+
+	```
+	# grin_wallet_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "scan_status",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
+	}
+	# "#
+	# , 0, false, false, false);
+	```
+	 */
+	fn scan_status(&self) -> Result<Option<ScanStatus>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::refresh_status](struct.Owner.html#method.refresh_status).
+	Null unless the listener was started with a background updater, in which case reads asked to
+	refresh are served from its last refresh, and this tells whether that may be stale.
+
+	```
+	# grin_wallet_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "refresh_status",
+		"params": [],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
+	}
+	# "#
+	# , 0, false, false, false);
+	```
+	 */
+	fn refresh_status(&self) -> Result<Option<RefreshStatus>, ErrorKind>;
+
+	/**
+	Networked version of [Owner::change_password](struct.Owner.html#method.change_password).
+
+
+	```
+	# grin_wallet_api::doctest_helper_json_rpc_owner_assert_response!(
+	# r#"
+	{
+		"jsonrpc": "2.0",
+		"method": "change_password",
+		"params": ["", "my new password"],
+		"id": 1
+	}
+	# "#
+	# ,
+	# r#"
+	{
+		"id": 1,
+		"jsonrpc": "2.0",
+		"result": {
+			"Ok": null
+		}
+	}
+	# "#
+	# , 0, false, false, false);
+	```
+	 */
+	fn change_password(&self, old_password: String, new_password: String) -> Result<(), ErrorKind>;
 
 	/**
 	Networked version of [Owner::node_height](struct.Owner.html#method.node_height).
@@ -1314,12 +1518,48 @@ where
 		Owner::verify_slate_messages(self, slate).map_err(|e| e.kind())
 	}
 
-	fn restore(&self) -> Result<(), ErrorKind> {
-		Owner::restore(self).map_err(|e| e.kind())
+	fn restore(
+		&self,
+		start_height: Option<u64>,
+		account_labels: Option<Vec<AcctPathMapping>>,
+	) -> Result<(), ErrorKind> {
+		Owner::restore(self, start_height, account_labels).map_err(|e| e.kind())
+	}
+
+	fn check_repair(
+		&self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+	) -> Result<(), ErrorKind> {
+		Owner::check_repair(self, delete_unconfirmed, start_height).map_err(|e| e.kind())
+	}
+
+	fn check_repair_dry_run(
+		&self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+	) -> Result<Vec<CheckDiscrepancy>, ErrorKind> {
+		Owner::check_repair_dry_run(self, delete_unconfirmed, start_height).map_err(|e| e.kind())
+	}
+
+	fn check_integrity(&self, fix: bool) -> Result<IntegrityReport, ErrorKind> {
+		Owner::check_integrity(self, fix).map_err(|e| e.kind())
+	}
+
+	fn collect_garbage(&self) -> Result<GcReport, ErrorKind> {
+		Owner::collect_garbage(self).map_err(|e| e.kind())
+	}
+
+	fn scan_status(&self) -> Result<Option<ScanStatus>, ErrorKind> {
+		Owner::scan_status(self).map_err(|e| e.kind())
 	}
 
-	fn check_repair(&self, delete_unconfirmed: bool) -> Result<(), ErrorKind> {
-		Owner::check_repair(self, delete_unconfirmed).map_err(|e| e.kind())
+	fn refresh_status(&self) -> Result<Option<RefreshStatus>, ErrorKind> {
+		Owner::refresh_status(self).map_err(|e| e.kind())
+	}
+
+	fn change_password(&self, old_password: String, new_password: String) -> Result<(), ErrorKind> {
+		Owner::change_password(self, &old_password, &new_password).map_err(|e| e.kind())
 	}
 
 	fn node_height(&self) -> Result<NodeHeightResult, ErrorKind> {
@@ -1327,6 +1567,33 @@ where
 	}
 }
 
+/// Block hash all recorded block hashes in doctest responses are given, as
+/// blocks differ from run to run along with their coinbase kernels
+const DOCTEST_BLOCK_HASH: &'static str =
+	"0e2f9c1a5b0e2f8c3d4a6b7c8d9e0f1a2b3c4d5e6f708192a3b4c5d6e7f80912";
+
+/// Replace every recorded block hash in a doctest response with
+/// `DOCTEST_BLOCK_HASH`
+fn normalize_block_hashes(value: &mut serde_json::Value) {
+	match value {
+		serde_json::Value::Object(map) => {
+			for (k, v) in map.iter_mut() {
+				if k == "block_hash" && v.is_string() {
+					*v = serde_json::Value::String(DOCTEST_BLOCK_HASH.to_owned());
+				} else {
+					normalize_block_hashes(v);
+				}
+			}
+		}
+		serde_json::Value::Array(values) => {
+			for v in values.iter_mut() {
+				normalize_block_hashes(v);
+			}
+		}
+		_ => {}
+	}
+}
+
 /// helper to set up a real environment to run integrated doctests
 pub fn run_doctest_owner(
 	request: serde_json::Value,
@@ -1440,7 +1707,11 @@ pub fn run_doctest_owner(
 	let mut api_owner = Owner::new(wallet1.clone());
 	api_owner.doctest_mode = true;
 	let owner_api = &api_owner as &dyn OwnerRpc;
-	Ok(owner_api.handle_request(request).as_option())
+	let mut response = owner_api.handle_request(request).as_option();
+	if let Some(ref mut r) = response {
+		normalize_block_hashes(r);
+	}
+	Ok(response)
 }
 
 #[doc(hidden)]
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 //! Comments for configuration + injection into output .toml
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// maps entries to Comments that should precede them
 fn comments() -> HashMap<String, String> {
@@ -119,6 +119,129 @@ fn comments() -> HashMap<String, String> {
 #Unit: Minute. Default value 1440 minutes for one day.
 #Refer to https://keybase.io/blog/keybase-exploding-messages for detail.
 #To disable this notification, set it as 0.
+"
+		.to_string(),
+	);
+	retval.insert(
+		"wallet_backend".to_string(),
+		"
+#Storage engine for wallet data, either \"lmdb\" or \"sqlite\".
+#SQLite keeps everything in a single db/wallet.sqlite file, which
+#can be easier to back up and inspect. Existing wallets are not
+#converted automatically when this value is changed.
+"
+		.to_string(),
+	);
+	retval.insert(
+		"seed_kdf_memory_kib".to_string(),
+		"
+#Memory cost in KiB of the Argon2id key derivation used to encrypt
+#wallet.seed with your password. Higher values make guessing the
+#password from a stolen seed file slower. Applies when the seed file
+#is created or re-encrypted (see the upgrade-seed command).
+"
+		.to_string(),
+	);
+	retval.insert(
+		"seed_kdf_iterations".to_string(),
+		"
+#Number of Argon2id passes used when encrypting wallet.seed.
+"
+		.to_string(),
+	);
+	retval.insert(
+		"restore_threads".to_string(),
+		"
+#Number of threads used to check chain outputs against the wallet
+#seed during restore and check. Set to 1 to use a single thread.
+"
+		.to_string(),
+	);
+	retval.insert(
+		"private_context_retention_blocks".to_string(),
+		"
+#Number of blocks after which the private keys kept for a transaction
+#that was never finished are deleted. Keys for cancelled or confirmed
+#transactions are always deleted, once confirmed ones are buried deep
+#enough. Set to 0 to keep unfinished ones.
+"
+		.to_string(),
+	);
+	retval.insert(
+		"stored_tx_retention".to_string(),
+		"
+#What to do with the saved copies of confirmed transactions, either
+#\"keep\", \"archive\" (move them to saved_txs/archive) or \"prune\"
+#(delete them).
+"
+		.to_string(),
+	);
+	retval.insert(
+		"gc_min_confirmation_depth".to_string(),
+		"
+#Number of blocks a transaction must have been confirmed for before its
+#private keys and saved transaction are removed, so a reorg that undoes
+#the transaction can't leave the wallet without them
+"
+		.to_string(),
+	);
+	retval.insert(
+		"gc_on_refresh".to_string(),
+		"
+#Whether to remove stale private keys and saved transactions each time
+#the wallet refreshes from the node. Off by default, run `db gc` to remove
+#them by hand.
+"
+		.to_string(),
+	);
+	retval.insert(
+		"node_quorum".to_string(),
+		"
+#Number of nodes, of the check node and the additional nodes, that must
+#agree on the chain height and on the outputs the wallet looks up.
+#1 trusts the first node that answers. A quorum higher than the number
+#of nodes is refused.
+"
+		.to_string(),
+	);
+	retval.insert(
+		"[[wallet.additional_nodes]]".to_string(),
+		"
+#Nodes to fail over to when the check node can't be reached or gives
+#an error, each with the path of its API secret if it needs one
+"
+		.to_string(),
+	);
+	retval.insert(
+		"node_timeout_secs".to_string(),
+		"
+#Seconds to wait for a node to answer before trying the next one
+"
+		.to_string(),
+	);
+	retval.insert(
+		"socks_proxy_addr".to_string(),
+		"
+#Address of a SOCKS5 proxy, such as Tor's, that node queries and slates
+#sent to other wallets go through. https:// addresses are still sent over
+#TLS, and host names are resolved by the proxy.
+"
+		.to_string(),
+	);
+	retval.insert(
+		"owner_api_refresh_secs".to_string(),
+		"
+#If above 0, the owner API listener refreshes the wallet from the node in
+#the background, as soon as the chain height changes and at least this
+#often (in seconds). Reads are then served from its last refresh instead
+#of each going to the node, and `refresh_status` tells how fresh that is.
+"
+		.to_string(),
+	);
+	retval.insert(
+		"owner_api_height_poll_secs".to_string(),
+		"
+#Seconds between the background refresh's checks of the chain height
 "
 		.to_string(),
 	);
@@ -193,6 +316,24 @@ fn comments() -> HashMap<String, String> {
 	retval
 }
 
+/// Keys left out of the output when unset, each written commented out
+/// after the key it follows, so the comment for it still appears:
+/// (preceding key, key as written when set, commented out example)
+const UNSET_KEYS: &[(&str, &str, &str)] = &[
+	(
+		"node_quorum",
+		"[[wallet.additional_nodes]]",
+		"#additional_nodes = [
+#	{ url = \"http://127.0.0.1:13413\", api_secret_path = \"/path/to/.api_secret\" },
+#]",
+	),
+	(
+		"node_timeout_secs",
+		"socks_proxy_addr",
+		"#socks_proxy_addr = \"127.0.0.1:9050\"",
+	),
+];
+
 fn get_key(line: &str) -> String {
 	if line.contains("[") && line.contains("]") {
 		return line.to_owned();
@@ -206,14 +347,27 @@ fn get_key(line: &str) -> String {
 pub fn insert_comments(orig: String) -> String {
 	let comments = comments();
 	let lines: Vec<&str> = orig.split("\n").collect();
+	let keys: Vec<String> = lines.iter().map(|l| get_key(l)).collect();
 	let mut out_lines = vec![];
-	for l in lines {
-		let key = get_key(l);
-		if let Some(v) = comments.get(&key) {
-			out_lines.push(v.to_owned());
+	let mut commented = HashSet::new();
+	for (l, key) in lines.iter().zip(keys.iter()) {
+		// keys repeated in arrays of tables are only commented the first time
+		if let Some(v) = comments.get(key) {
+			if commented.insert(key.clone()) {
+				out_lines.push(v.to_owned());
+			}
 		}
-		out_lines.push(l.to_owned());
+		out_lines.push(l.to_string());
 		out_lines.push("\n".to_owned());
+		for (_, unset_key, example) in UNSET_KEYS.iter().filter(|u| u.0 == key) {
+			if keys.iter().any(|k| k == unset_key) {
+				continue;
+			}
+			if let Some(v) = comments.get(*unset_key) {
+				out_lines.push(v.to_owned());
+			}
+			out_lines.push(format!("{}\n", example));
+		}
 	}
 	let mut ret_val = String::from("");
 	for l in out_lines {
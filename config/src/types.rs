@@ -14,6 +14,7 @@
 
 //! Public types for config modules
 
+use std::cmp;
 use std::fmt;
 use std::io;
 use std::path::PathBuf;
@@ -56,6 +57,108 @@ pub struct WalletConfig {
 	pub dark_background_color_scheme: Option<bool>,
 	/// The exploding lifetime (minutes) for keybase notification on coins received
 	pub keybase_notify_ttl: Option<u16>,
+	/// Storage backend used for wallet data (defaults to LMDB)
+	pub wallet_backend: Option<WalletBackendType>,
+	/// Memory cost (KiB) of the key derivation function protecting the seed file
+	pub seed_kdf_memory_kib: Option<u32>,
+	/// Number of passes of the key derivation function protecting the seed file
+	pub seed_kdf_iterations: Option<u32>,
+	/// Number of threads rewinding range proofs during restore and check
+	pub restore_threads: Option<usize>,
+	/// Age in blocks after which the private context of an unfinished
+	/// transaction is deleted (0 keeps them)
+	pub private_context_retention_blocks: Option<u64>,
+	/// What happens to the stored transactions of confirmed transactions
+	pub stored_tx_retention: Option<StoredTxRetention>,
+	/// Blocks a transaction must have been confirmed for before garbage
+	/// collection removes its private context and stored transaction
+	pub gc_min_confirmation_depth: Option<u64>,
+	/// Whether stale contexts and stored transactions are removed after each
+	/// refresh from the node (off unless enabled)
+	pub gc_on_refresh: Option<bool>,
+	/// Number of nodes that must agree on the chain height and on the outputs
+	/// looked up by the wallet
+	pub node_quorum: Option<usize>,
+	/// Seconds to wait for a node to answer before trying another
+	pub node_timeout_secs: Option<u64>,
+	/// Address (host:port) of a SOCKS5 proxy all outbound HTTP goes through
+	pub socks_proxy_addr: Option<String>,
+	/// Longest time in seconds between refreshes by the owner API listener's
+	/// background updater (0 refreshes on each read instead)
+	pub owner_api_refresh_secs: Option<u64>,
+	/// Seconds between the background updater's checks of the chain height
+	pub owner_api_height_poll_secs: Option<u64>,
+	/// Nodes to fail over to besides the check node. Kept last, as tables
+	/// can't be followed by plain values in the config file.
+	pub additional_nodes: Option<Vec<NodeConfig>>,
+}
+
+/// A node the wallet can use besides its check node
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NodeConfig {
+	/// The api address of the node
+	pub url: String,
+	/// Location of the node api secret for basic auth on the node's API
+	pub api_secret_path: Option<String>,
+}
+
+/// Storage engines available for the wallet database
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WalletBackendType {
+	/// LMDB key-value store (default)
+	Lmdb,
+	/// Single-file SQLite database
+	Sqlite,
+	/// Ephemeral in-memory store, nothing besides the seed is read from or
+	/// written to disk. Only for tests, the wallet refuses to run with it.
+	Memory,
+}
+
+impl Default for WalletBackendType {
+	fn default() -> WalletBackendType {
+		WalletBackendType::Lmdb
+	}
+}
+
+impl fmt::Display for WalletBackendType {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self {
+			WalletBackendType::Lmdb => write!(f, "lmdb"),
+			WalletBackendType::Sqlite => write!(f, "sqlite"),
+			WalletBackendType::Memory => write!(f, "memory"),
+		}
+	}
+}
+
+/// What garbage collection does with the stored transactions of confirmed
+/// transactions
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoredTxRetention {
+	/// Leave them in the saved transaction directory
+	Keep,
+	/// Move them to the archive under the saved transaction directory
+	/// (default)
+	Archive,
+	/// Delete them
+	Prune,
+}
+
+impl Default for StoredTxRetention {
+	fn default() -> StoredTxRetention {
+		StoredTxRetention::Archive
+	}
+}
+
+impl fmt::Display for StoredTxRetention {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self {
+			StoredTxRetention::Keep => write!(f, "keep"),
+			StoredTxRetention::Archive => write!(f, "archive"),
+			StoredTxRetention::Prune => write!(f, "prune"),
+		}
+	}
 }
 
 impl Default for WalletConfig {
@@ -75,6 +178,22 @@ impl Default for WalletConfig {
 			tls_certificate_key: None,
 			dark_background_color_scheme: Some(true),
 			keybase_notify_ttl: Some(1440),
+			wallet_backend: Some(WalletBackendType::Lmdb),
+			seed_kdf_memory_kib: Some(WalletConfig::default_seed_kdf_memory_kib()),
+			seed_kdf_iterations: Some(WalletConfig::default_seed_kdf_iterations()),
+			restore_threads: Some(WalletConfig::default_restore_threads()),
+			private_context_retention_blocks: Some(
+				WalletConfig::default_private_context_retention_blocks(),
+			),
+			stored_tx_retention: Some(StoredTxRetention::Archive),
+			gc_min_confirmation_depth: Some(WalletConfig::default_gc_min_confirmation_depth()),
+			gc_on_refresh: Some(false),
+			node_quorum: Some(1),
+			node_timeout_secs: Some(WalletConfig::default_node_timeout_secs()),
+			socks_proxy_addr: None,
+			owner_api_refresh_secs: Some(0),
+			owner_api_height_poll_secs: Some(WalletConfig::default_owner_api_height_poll_secs()),
+			additional_nodes: None,
 		}
 	}
 }
@@ -100,6 +219,123 @@ impl WalletConfig {
 	pub fn owner_api_listen_addr(&self) -> String {
 		format!("127.0.0.1:{}", self.owner_api_listen_port())
 	}
+
+	/// Use value from config file, defaulting to LMDB if missing.
+	pub fn wallet_backend(&self) -> WalletBackendType {
+		self.wallet_backend.unwrap_or_default()
+	}
+
+	/// Default seed KDF memory cost, 64 MiB
+	pub fn default_seed_kdf_memory_kib() -> u32 {
+		65536
+	}
+
+	/// Default seed KDF passes
+	pub fn default_seed_kdf_iterations() -> u32 {
+		3
+	}
+
+	/// Use value from config file, defaulting to sensible value if missing.
+	pub fn seed_kdf_memory_kib(&self) -> u32 {
+		self.seed_kdf_memory_kib
+			.unwrap_or(WalletConfig::default_seed_kdf_memory_kib())
+	}
+
+	/// Use value from config file, defaulting to sensible value if missing.
+	pub fn seed_kdf_iterations(&self) -> u32 {
+		self.seed_kdf_iterations
+			.unwrap_or(WalletConfig::default_seed_kdf_iterations())
+	}
+
+	/// Default number of restore threads
+	pub fn default_restore_threads() -> usize {
+		4
+	}
+
+	/// Use value from config file, defaulting to sensible value if missing.
+	/// Always at least one.
+	pub fn restore_threads(&self) -> usize {
+		cmp::max(
+			self.restore_threads
+				.unwrap_or(WalletConfig::default_restore_threads()),
+			1,
+		)
+	}
+
+	/// Default private context retention, one week of blocks
+	pub fn default_private_context_retention_blocks() -> u64 {
+		10080
+	}
+
+	/// Use value from config file, defaulting to sensible value if missing.
+	pub fn private_context_retention_blocks(&self) -> u64 {
+		self.private_context_retention_blocks
+			.unwrap_or(WalletConfig::default_private_context_retention_blocks())
+	}
+
+	/// Use value from config file, defaulting to archiving if missing.
+	pub fn stored_tx_retention(&self) -> StoredTxRetention {
+		self.stored_tx_retention.unwrap_or_default()
+	}
+
+	/// Default minimum confirmation depth before garbage collection, the
+	/// same as the default number of confirmations needed to spend
+	pub fn default_gc_min_confirmation_depth() -> u64 {
+		10
+	}
+
+	/// Use value from config file, defaulting to sensible value if missing.
+	pub fn gc_min_confirmation_depth(&self) -> u64 {
+		self.gc_min_confirmation_depth
+			.unwrap_or(WalletConfig::default_gc_min_confirmation_depth())
+	}
+
+	/// Use value from config file, defaulting to false if missing.
+	pub fn gc_on_refresh(&self) -> bool {
+		self.gc_on_refresh.unwrap_or(false)
+	}
+
+	/// Use value from config file, defaulting to a single node if missing.
+	pub fn node_quorum(&self) -> usize {
+		self.node_quorum.unwrap_or(1)
+	}
+
+	/// Default node timeout
+	pub fn default_node_timeout_secs() -> u64 {
+		20
+	}
+
+	/// Use value from config file, defaulting to sensible value if missing.
+	pub fn node_timeout_secs(&self) -> u64 {
+		self.node_timeout_secs
+			.unwrap_or(WalletConfig::default_node_timeout_secs())
+	}
+
+	/// Use value from config file, defaulting to no background refresh if
+	/// missing.
+	pub fn owner_api_refresh_secs(&self) -> u64 {
+		self.owner_api_refresh_secs.unwrap_or(0)
+	}
+
+	/// Default time between chain height checks
+	pub fn default_owner_api_height_poll_secs() -> u64 {
+		10
+	}
+
+	/// Use value from config file, defaulting to sensible value if missing.
+	/// Always at least one.
+	pub fn owner_api_height_poll_secs(&self) -> u64 {
+		cmp::max(
+			self.owner_api_height_poll_secs
+				.unwrap_or(WalletConfig::default_owner_api_height_poll_secs()),
+			1,
+		)
+	}
+
+	/// Use value from config file, defaulting to none if missing.
+	pub fn additional_nodes(&self) -> Vec<NodeConfig> {
+		self.additional_nodes.clone().unwrap_or(vec![])
+	}
 }
 /// Error type wrapping config errors.
 #[derive(Debug)]
@@ -17,6 +17,7 @@ use std::collections::HashMap;
 /// Grin wallet command-line function implementations
 use std::fs::File;
 use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
@@ -28,14 +29,17 @@ use crate::api::TLSConfig;
 use crate::core::core;
 use crate::keychain;
 
-use crate::config::WalletConfig;
+use crate::config::{WalletBackendType, WalletConfig};
 use crate::error::{Error, ErrorKind};
 use crate::impls::{
-	instantiate_wallet, FileWalletCommAdapter, HTTPWalletCommAdapter, KeybaseWalletCommAdapter,
-	LMDBBackend, NullWalletCommAdapter,
+	instantiate_wallet, migrate_wallet_db, FileWalletCommAdapter, HTTPWalletCommAdapter,
+	KeybaseWalletCommAdapter, LMDBBackend, NullWalletCommAdapter, SqliteBackend,
 };
 use crate::impls::{HTTPNodeClient, WalletSeed};
-use crate::libwallet::{InitTxArgs, IssueInvoiceTxArgs, NodeClient, WalletInst};
+use crate::libwallet::{
+	AcctPathMapping, InitTxArgs, IssueInvoiceTxArgs, NodeClient, RefreshSchedule, ScanStatus,
+	WalletInst,
+};
 use crate::{controller, display};
 
 /// Arguments common to all wallet commands
@@ -55,30 +59,71 @@ pub struct InitArgs {
 	pub password: ZeroingString,
 	pub config: WalletConfig,
 	pub recovery_phrase: Option<ZeroingString>,
+	/// BIP39 passphrase combined with the recovery phrase
+	pub mnemonic_passphrase: Option<ZeroingString>,
+	/// Split the recovery phrase into shares, (threshold, shares)
+	pub shares: Option<(u8, u8)>,
 	pub restore: bool,
 }
 
 pub fn init(g_args: &GlobalArgs, args: InitArgs) -> Result<(), Error> {
-	WalletSeed::init_file(
+	let client_n = HTTPNodeClient::new(
+		&args.config.check_node_api_http_addr,
+		g_args.node_api_secret.clone(),
+		args.config.socks_proxy_addr.clone(),
+	);
+	// a new seed can't have outputs before the current chain height, but one
+	// from a recovery phrase may be of any age
+	let birthday_height = match args.recovery_phrase {
+		Some(_) => None,
+		None => match client_n.get_chain_height() {
+			Ok(h) => Some(h),
+			Err(e) => {
+				warn!(
+					"Couldn't get the chain height from the node, restoring this wallet \
+					 will scan the whole chain: {}",
+					e
+				);
+				None
+			}
+		},
+	};
+	let seed = WalletSeed::init_file_with_birthday(
 		&args.config,
 		args.list_length,
 		args.recovery_phrase,
+		args.mnemonic_passphrase,
 		&args.password,
+		birthday_height,
 	)?;
+	match args.shares {
+		Some((threshold, shares)) => seed.show_recovery_shares(threshold, shares)?,
+		None => seed.show_recovery_phrase()?,
+	}
 	info!("Wallet seed file created");
-	let client_n = HTTPNodeClient::new(
-		&args.config.check_node_api_http_addr,
-		g_args.node_api_secret.clone(),
+	match args.config.wallet_backend() {
+		WalletBackendType::Lmdb => {
+			let _: LMDBBackend<HTTPNodeClient, keychain::ExtKeychain> =
+				LMDBBackend::new(args.config.clone(), &args.password, client_n)?;
+		}
+		WalletBackendType::Sqlite => {
+			let _: SqliteBackend<HTTPNodeClient, keychain::ExtKeychain> =
+				SqliteBackend::new(args.config.clone(), &args.password, client_n)?;
+		}
+		// nothing to create, in-memory wallets start empty each run
+		WalletBackendType::Memory => {}
+	}
+	info!(
+		"Wallet database backend created ({})",
+		args.config.wallet_backend()
 	);
-	let _: LMDBBackend<HTTPNodeClient, keychain::ExtKeychain> =
-		LMDBBackend::new(args.config.clone(), &args.password, client_n)?;
-	info!("Wallet database backend created");
 	Ok(())
 }
 
 /// Argument for recover
 pub struct RecoverArgs {
 	pub recovery_phrase: Option<ZeroingString>,
+	pub mnemonic_passphrase: Option<ZeroingString>,
 	pub passphrase: ZeroingString,
 }
 
@@ -100,6 +145,7 @@ pub fn recover(config: &WalletConfig, args: RecoverArgs) -> Result<(), Error> {
 		let res = WalletSeed::recover_from_phrase(
 			&config,
 			&args.recovery_phrase.as_ref().unwrap(),
+			args.mnemonic_passphrase,
 			&args.passphrase,
 		);
 		if let Err(e) = res {
@@ -110,6 +156,70 @@ pub fn recover(config: &WalletConfig, args: RecoverArgs) -> Result<(), Error> {
 	Ok(())
 }
 
+/// Arguments for upgrade-seed command
+pub struct UpgradeSeedArgs {
+	pub password: ZeroingString,
+}
+
+pub fn upgrade_seed(config: &WalletConfig, args: UpgradeSeedArgs) -> Result<(), Error> {
+	let upgraded = WalletSeed::upgrade_file(config, &args.password)?;
+	if upgraded {
+		println!("Wallet seed file re-encrypted with the current key derivation settings.");
+	} else {
+		println!("Wallet seed file is already up to date.");
+	}
+	Ok(())
+}
+
+/// Arguments for verify-phrase command
+pub struct VerifyPhraseArgs {
+	pub password: ZeroingString,
+	/// The full recovery phrase, if it was asked for
+	pub recovery_phrase: Option<ZeroingString>,
+	/// Otherwise, words entered for a subset of (zero based) positions
+	pub words: Vec<(usize, ZeroingString)>,
+}
+
+pub fn verify_phrase(config: &WalletConfig, args: VerifyPhraseArgs) -> Result<(), Error> {
+	let seed = WalletSeed::from_file(config, &args.password)?;
+	let matches = match args.recovery_phrase {
+		Some(ref p) => seed.verify_phrase(p),
+		None => seed.verify_words(&args.words)?,
+	};
+	if !matches {
+		return Err(ErrorKind::GenericError(
+			"The entered words do not match the wallet's recovery phrase".to_owned(),
+		))?;
+	}
+	println!("The entered words match the wallet's recovery phrase.");
+	Ok(())
+}
+
+/// Arguments for passwd command
+pub struct PasswdArgs {
+	pub old_password: ZeroingString,
+	pub new_password: ZeroingString,
+}
+
+pub fn passwd(config: &WalletConfig, g_args: &GlobalArgs, args: PasswdArgs) -> Result<(), Error> {
+	let client_n = HTTPNodeClient::new(
+		&config.check_node_api_http_addr,
+		g_args.node_api_secret.clone(),
+		config.socks_proxy_addr.clone(),
+	);
+	let wallet = instantiate_wallet(
+		config.clone(),
+		client_n,
+		&args.old_password,
+		&g_args.account,
+	)?;
+	controller::owner_single_use(wallet, |api| {
+		api.change_password(&args.old_password, &args.new_password)
+	})?;
+	println!("Wallet password changed, the previous seed file was kept as a backup.");
+	Ok(())
+}
+
 /// Arguments for listen command
 pub struct ListenArgs {
 	pub method: String,
@@ -129,6 +239,7 @@ pub fn listen(config: &WalletConfig, args: &ListenArgs, g_args: &GlobalArgs) ->
 			let node_client = HTTPNodeClient::new(
 				&config.check_node_api_http_addr,
 				g_args.node_api_secret.clone(),
+				config.socks_proxy_addr.clone(),
 			);
 			let wallet = instantiate_wallet(
 				config.clone(),
@@ -171,12 +282,20 @@ pub fn owner_api(
 	config: &WalletConfig,
 	g_args: &GlobalArgs,
 ) -> Result<(), Error> {
+	let refresh_schedule = match config.owner_api_refresh_secs() {
+		0 => None,
+		secs => Some(RefreshSchedule {
+			interval: Duration::from_secs(secs),
+			height_poll: Duration::from_secs(config.owner_api_height_poll_secs()),
+		}),
+	};
 	let res = controller::owner_listener(
 		wallet,
 		config.owner_api_listen_addr().as_str(),
 		g_args.node_api_secret.clone(),
 		g_args.tls_conf.clone(),
 		config.owner_api_include_foreign.clone(),
+		refresh_schedule,
 	);
 	if let Err(e) = res {
 		return Err(ErrorKind::LibWallet(e.kind(), e.cause_string()).into());
@@ -292,7 +411,7 @@ pub fn send(
 				}
 			};
 			let adapter = match args.method.as_str() {
-				"http" => HTTPWalletCommAdapter::new(),
+				"http" => HTTPWalletCommAdapter::new(api.wallet.lock().w2n_client().socks_proxy()),
 				"file" => FileWalletCommAdapter::new(),
 				"keybase" => KeybaseWalletCommAdapter::new(),
 				"self" => NullWalletCommAdapter::new(),
@@ -527,7 +646,7 @@ pub fn process_invoice(
 				}
 			};
 			let adapter = match args.method.as_str() {
-				"http" => HTTPWalletCommAdapter::new(),
+				"http" => HTTPWalletCommAdapter::new(api.wallet.lock().w2n_client().socks_proxy()),
 				"file" => FileWalletCommAdapter::new(),
 				"self" => NullWalletCommAdapter::new(),
 				_ => NullWalletCommAdapter::new(),
@@ -695,13 +814,55 @@ pub fn cancel(
 	Ok(())
 }
 
+/// Print the status of a restore or check each time it changes, until
+/// `done` is set
+fn show_scan_status(
+	latest: Arc<Mutex<Option<ScanStatus>>>,
+	done: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+	thread::spawn(move || {
+		let mut shown = None;
+		while !done.load(Ordering::Relaxed) {
+			let status = latest.lock().clone();
+			if status != shown {
+				if let Some(ref s) = status {
+					println!("{}", s);
+				}
+				shown = status;
+			}
+			thread::sleep(Duration::from_millis(500));
+		}
+	})
+}
+
+/// List the outputs a finished restore or check couldn't restore
+fn show_unknown_path_outputs(latest: &Arc<Mutex<Option<ScanStatus>>>) {
+	if let Some(ref s) = *latest.lock() {
+		if !s.unknown_path_outputs.is_empty() {
+			display::unknown_path_outputs(&s.unknown_path_outputs);
+		}
+	}
+}
+
+/// Arguments for the restore command
+pub struct RestoreArgs {
+	pub start_height: Option<u64>,
+	pub account_labels: Option<Vec<AcctPathMapping>>,
+}
+
 pub fn restore(
 	wallet: Arc<Mutex<WalletInst<impl NodeClient + 'static, keychain::ExtKeychain>>>,
+	args: RestoreArgs,
 ) -> Result<(), Error> {
 	controller::owner_single_use(wallet.clone(), |api| {
-		let result = api.restore();
+		let done = Arc::new(AtomicBool::new(false));
+		let status_thread = show_scan_status(api.latest_scan_status.clone(), done.clone());
+		let result = api.restore(args.start_height, args.account_labels);
+		done.store(true, Ordering::Relaxed);
+		let _ = status_thread.join();
 		match result {
 			Ok(_) => {
+				show_unknown_path_outputs(&api.latest_scan_status);
 				warn!("Wallet restore complete",);
 				Ok(())
 			}
@@ -718,6 +879,8 @@ pub fn restore(
 /// wallet check
 pub struct CheckArgs {
 	pub delete_unconfirmed: bool,
+	pub start_height: Option<u64>,
+	pub dry_run: bool,
 }
 
 pub fn check_repair(
@@ -727,9 +890,19 @@ pub fn check_repair(
 	controller::owner_single_use(wallet.clone(), |api| {
 		warn!("Starting wallet check...",);
 		warn!("Updating all wallet outputs, please wait ...",);
-		let result = api.check_repair(args.delete_unconfirmed);
+		let done = Arc::new(AtomicBool::new(false));
+		let status_thread = show_scan_status(api.latest_scan_status.clone(), done.clone());
+		let result = match args.dry_run {
+			true => api
+				.check_repair_dry_run(args.delete_unconfirmed, args.start_height)
+				.map(|d| display::check_discrepancies(&d)),
+			false => api.check_repair(args.delete_unconfirmed, args.start_height),
+		};
+		done.store(true, Ordering::Relaxed);
+		let _ = status_thread.join();
 		match result {
 			Ok(_) => {
+				show_unknown_path_outputs(&api.latest_scan_status);
 				warn!("Wallet check complete",);
 				Ok(())
 			}
@@ -742,3 +915,98 @@ pub fn check_repair(
 	})?;
 	Ok(())
 }
+
+/// Arguments for the export command
+pub struct ExportArgs {
+	pub dest: String,
+}
+
+pub fn export(
+	wallet: Arc<Mutex<WalletInst<impl NodeClient + 'static, keychain::ExtKeychain>>>,
+	args: ExportArgs,
+) -> Result<(), Error> {
+	controller::owner_single_use(wallet.clone(), |api| {
+		let summary = api.export_wallet(&args.dest)?;
+		println!(
+			"Wallet exported to {}: {} accounts, {} outputs, {} transactions, {} stored transactions.",
+			args.dest,
+			summary.accounts,
+			summary.outputs,
+			summary.tx_log_entries,
+			summary.stored_txs
+		);
+		Ok(())
+	})?;
+	Ok(())
+}
+
+/// Arguments for the import command
+pub struct ImportArgs {
+	pub input: String,
+}
+
+pub fn import(
+	wallet: Arc<Mutex<WalletInst<impl NodeClient + 'static, keychain::ExtKeychain>>>,
+	args: ImportArgs,
+) -> Result<(), Error> {
+	controller::owner_single_use(wallet.clone(), |api| {
+		let summary = api.import_wallet(&args.input)?;
+		println!(
+			"Wallet imported from {}: {} accounts, {} outputs, {} transactions, {} stored transactions.",
+			args.input,
+			summary.accounts,
+			summary.outputs,
+			summary.tx_log_entries,
+			summary.stored_txs
+		);
+		Ok(())
+	})?;
+	Ok(())
+}
+
+/// Arguments for the db migrate command
+pub struct DbMigrateArgs {
+	pub password: ZeroingString,
+	pub dry_run: bool,
+}
+
+pub fn db_migrate(config: &WalletConfig, args: DbMigrateArgs) -> Result<(), Error> {
+	if config.wallet_backend() != WalletBackendType::Lmdb {
+		let msg = format!(
+			"Schema migrations only apply to the lmdb backend, this wallet uses {}",
+			config.wallet_backend()
+		);
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let report = migrate_wallet_db(config, &args.password, args.dry_run)?;
+	display::migration_report(&report);
+	Ok(())
+}
+
+/// Arguments for the db fsck command
+pub struct DbFsckArgs {
+	pub fix: bool,
+}
+
+pub fn db_fsck(
+	wallet: Arc<Mutex<WalletInst<impl NodeClient + 'static, keychain::ExtKeychain>>>,
+	args: DbFsckArgs,
+) -> Result<(), Error> {
+	controller::owner_single_use(wallet.clone(), |api| {
+		let report = api.check_integrity(args.fix)?;
+		display::integrity_report(&report);
+		Ok(())
+	})?;
+	Ok(())
+}
+
+pub fn db_gc(
+	wallet: Arc<Mutex<WalletInst<impl NodeClient + 'static, keychain::ExtKeychain>>>,
+) -> Result<(), Error> {
+	controller::owner_single_use(wallet.clone(), |api| {
+		let report = api.collect_garbage()?;
+		display::gc_report(&report);
+		Ok(())
+	})?;
+	Ok(())
+}
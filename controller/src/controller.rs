@@ -21,8 +21,8 @@ use crate::core::core::Transaction;
 use crate::impls::{FileWalletCommAdapter, HTTPWalletCommAdapter, KeybaseWalletCommAdapter};
 use crate::keychain::Keychain;
 use crate::libwallet::{
-	CbData, Error, ErrorKind, InitTxArgs, NodeClient, OutputCommitMapping, SendTXArgs, Slate,
-	TxLogEntry, WalletBackend, WalletInfo,
+	CbData, Error, ErrorKind, InitTxArgs, NodeClient, OutputCommitMapping, RefreshSchedule,
+	SendTXArgs, Slate, TxLogEntry, WalletBackend, WalletInfo,
 };
 use crate::util::to_base64;
 use crate::util::Mutex;
@@ -76,13 +76,16 @@ where
 }
 
 /// Listener version, providing same API but listening for requests on a
-/// port and wrapping the calls
+/// port and wrapping the calls. With a refresh schedule, a background
+/// updater refreshes the wallet from the node, and reads are served from
+/// its last refresh.
 pub fn owner_listener<T: ?Sized, C, K>(
 	wallet: Arc<Mutex<T>>,
 	addr: &str,
 	api_secret: Option<String>,
 	tls_config: Option<TLSConfig>,
 	owner_api_include_foreign: Option<bool>,
+	refresh_schedule: Option<RefreshSchedule>,
 ) -> Result<(), Error>
 where
 	T: WalletBackend<C, K> + Send + Sync + 'static,
@@ -90,8 +93,16 @@ where
 	C: NodeClient + 'static,
 	K: Keychain + 'static,
 {
-	let api_handler = OwnerAPIHandler::new(wallet.clone());
-	let api_handler_v2 = OwnerAPIHandlerV2::new(wallet.clone());
+	let owner = Arc::new(Owner::new(wallet.clone()));
+	if let Some(schedule) = refresh_schedule {
+		info!(
+			"Starting background refresh every {:?}, checking the chain height every {:?}.",
+			schedule.interval, schedule.height_poll
+		);
+		owner.start_updater(schedule)?;
+	}
+	let api_handler = OwnerAPIHandler::with_owner(owner.clone());
+	let api_handler_v2 = OwnerAPIHandlerV2::with_owner(owner);
 
 	let mut router = Router::new();
 	if api_secret.is_some() {
@@ -189,8 +200,9 @@ where
 {
 	/// Wallet instance
 	pub wallet: Arc<Mutex<T>>,
-	phantom: PhantomData<K>,
-	phantom_c: PhantomData<C>,
+	/// Owner API cloned for each request, so they share its background
+	/// updater
+	owner: Arc<Owner<T, C, K>>,
 }
 
 impl<T: ?Sized, C, K> OwnerAPIHandler<T, C, K>
@@ -201,10 +213,14 @@ where
 {
 	/// Create a new owner API handler for GET methods
 	pub fn new(wallet: Arc<Mutex<T>>) -> OwnerAPIHandler<T, C, K> {
+		OwnerAPIHandler::with_owner(Arc::new(Owner::new(wallet)))
+	}
+
+	/// Create a new owner API handler for requests to the given Owner API
+	pub fn with_owner(owner: Arc<Owner<T, C, K>>) -> OwnerAPIHandler<T, C, K> {
 		OwnerAPIHandler {
-			wallet,
-			phantom: PhantomData,
-			phantom_c: PhantomData,
+			wallet: owner.wallet.clone(),
+			owner,
 		}
 	}
 
@@ -320,7 +336,7 @@ where
 	}
 
 	fn handle_get_request(&self, req: &Request<Body>) -> Result<Response<Body>, Error> {
-		let api = Owner::new(self.wallet.clone());
+		let api = (*self.owner).clone();
 
 		Ok(
 			match req
@@ -385,7 +401,11 @@ where
 				}
 			};
 			match args.method.as_ref() {
-				"http" => slate = HTTPWalletCommAdapter::new().send_tx_sync(&args.dest, &slate)?,
+				"http" => {
+					let socks_proxy = api.wallet.lock().w2n_client().socks_proxy();
+					slate =
+						HTTPWalletCommAdapter::new(socks_proxy).send_tx_sync(&args.dest, &slate)?
+				}
 				"file" => {
 					FileWalletCommAdapter::new().send_tx_async(&args.dest, &slate)?;
 				}
@@ -580,7 +600,7 @@ where
 	}
 
 	fn handle_post_request(&self, req: Request<Body>) -> WalletResponseFuture {
-		let api = Owner::new(self.wallet.clone());
+		let api = (*self.owner).clone();
 		match req
 			.uri()
 			.path()
@@ -658,8 +678,9 @@ where
 {
 	/// Wallet instance
 	pub wallet: Arc<Mutex<T>>,
-	phantom: PhantomData<K>,
-	phantom_c: PhantomData<C>,
+	/// Owner API shared by all requests, so the status of a scan started by
+	/// one request can be read by another
+	owner: Arc<Owner<T, C, K>>,
 }
 
 impl<T: ?Sized, C, K> OwnerAPIHandlerV2<T, C, K>
@@ -670,20 +691,24 @@ where
 {
 	/// Create a new owner API handler for GET methods
 	pub fn new(wallet: Arc<Mutex<T>>) -> OwnerAPIHandlerV2<T, C, K> {
+		OwnerAPIHandlerV2::with_owner(Arc::new(Owner::new(wallet)))
+	}
+
+	/// Create a new owner API handler for requests to the given Owner API
+	pub fn with_owner(owner: Arc<Owner<T, C, K>>) -> OwnerAPIHandlerV2<T, C, K> {
 		OwnerAPIHandlerV2 {
-			wallet,
-			phantom: PhantomData,
-			phantom_c: PhantomData,
+			wallet: owner.wallet.clone(),
+			owner,
 		}
 	}
 
 	fn call_api(
 		&self,
 		req: Request<Body>,
-		api: Owner<T, C, K>,
+		api: Arc<Owner<T, C, K>>,
 	) -> Box<dyn Future<Item = serde_json::Value, Error = Error> + Send> {
 		Box::new(parse_body(req).and_then(move |val: serde_json::Value| {
-			let owner_api = &api as &dyn OwnerRpc;
+			let owner_api = &*api as &dyn OwnerRpc;
 			match owner_api.handle_request(val) {
 				MaybeReply::Reply(r) => ok(r),
 				MaybeReply::DontReply => {
@@ -696,9 +721,8 @@ where
 	}
 
 	fn handle_post_request(&self, req: Request<Body>) -> WalletResponseFuture {
-		let api = Owner::new(self.wallet.clone());
 		Box::new(
-			self.call_api(req, api)
+			self.call_api(req, self.owner.clone())
 				.and_then(|resp| ok(json_response_pretty(&resp))),
 		)
 	}
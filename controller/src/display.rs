@@ -14,8 +14,10 @@
 
 use crate::core::core::{self, amount_to_hr_string};
 use crate::core::global;
+use crate::impls::MigrationReport;
 use crate::libwallet::{
-	AcctPathMapping, Error, OutputCommitMapping, OutputStatus, TxLogEntry, WalletInfo,
+	AcctPathMapping, CheckDiscrepancy, ContextRemovalReason, Error, GcReport, IntegrityIssue,
+	IntegrityReport, OutputCommitMapping, OutputStatus, TxLogEntry, UnknownPathOutput, WalletInfo,
 };
 use crate::util;
 use prettytable;
@@ -413,6 +415,306 @@ pub fn accounts(acct_mappings: Vec<AcctPathMapping>) {
 	println!();
 }
 
+/// Display outputs found by a restore or check whose derivation path
+/// couldn't be recovered
+pub fn unknown_path_outputs(outputs: &[UnknownPathOutput]) {
+	println!(
+		"\n____ {} wallet output(s) not restored, derivation path unknown ____\n",
+		outputs.len()
+	);
+	let mut table = table!();
+
+	table.set_titles(row![
+		bMG->"Output Commitment",
+		bMG->"Value",
+		bMG->"Block Height",
+		bMG->"MMR Index",
+		bMG->"Rewind Message",
+	]);
+	for o in outputs {
+		table.add_row(row![
+			bFC->util::to_hex(o.commit.as_ref().to_vec()),
+			bFG->core::amount_to_hr_string(o.value, false),
+			bFB->o.height,
+			bFB->o.mmr_index,
+			bFY->o.message,
+		]);
+	}
+	table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+	table.printstd();
+	println!();
+}
+
+/// Display the discrepancies found by a wallet check, and how repairing
+/// each of them changes the wallet
+pub fn check_discrepancies(discrepancies: &[CheckDiscrepancy]) {
+	if discrepancies.is_empty() {
+		println!("\nWallet check found no discrepancies with the chain\n");
+		return;
+	}
+	println!(
+		"\n____ Wallet Check - {} discrepancies, which a repair would change ____\n",
+		discrepancies.len()
+	);
+	let mut table = table!();
+
+	table.set_titles(row![
+		bMG->"Discrepancy",
+		bMG->"Output / Tx",
+		bMG->"In Wallet",
+		bMG->"On Chain",
+		bMG->"Repair",
+	]);
+	for d in discrepancies {
+		let (kind, id, in_wallet, on_chain, repair) = match d {
+			CheckDiscrepancy::MissingOutput(o) => (
+				"Missing output",
+				format!("{}", o.key_id),
+				"-".to_owned(),
+				format!(
+					"{} at height {}",
+					core::amount_to_hr_string(o.value, false),
+					o.height
+				),
+				"Restore".to_owned(),
+			),
+			CheckDiscrepancy::WrongStatus {
+				output,
+				repaired_status,
+			} => (
+				"Wrong status",
+				format!("{}", output.key_id),
+				format!("{}", output.status),
+				match repaired_status {
+					Some(_) => "Unspent".to_owned(),
+					None => "Not found".to_owned(),
+				},
+				match repaired_status {
+					Some(s) => format!("Mark {}", s),
+					None => "Delete".to_owned(),
+				},
+			),
+			CheckDiscrepancy::WrongValue {
+				output,
+				chain_output,
+			} => (
+				"Wrong value",
+				format!("{}", output.key_id),
+				core::amount_to_hr_string(output.value, false),
+				core::amount_to_hr_string(chain_output.value, false),
+				"Replace".to_owned(),
+			),
+			CheckDiscrepancy::CancelledTx(t) => (
+				"Cancelled tx",
+				format!("Tx {}", t.id),
+				format!("{}", t.tx_type),
+				"-".to_owned(),
+				"Cancel".to_owned(),
+			),
+		};
+		table.add_row(row![
+			bFR->kind,
+			bFC->id,
+			bFB->in_wallet,
+			bFG->on_chain,
+			bFY->repair,
+		]);
+	}
+	table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+	table.printstd();
+	println!();
+}
+
+/// Display the outcome of a wallet database migration
+pub fn migration_report(report: &MigrationReport) {
+	if report.is_current() {
+		println!(
+			"\nWallet database is at the current schema version ({})\n",
+			report.to_version
+		);
+		return;
+	}
+	let action = match report.dry_run {
+		true => "would be migrated",
+		false => "migrated",
+	};
+	println!(
+		"\n____ Wallet database {} from schema version {} to {} ____\n",
+		action, report.from_version, report.to_version
+	);
+	let mut table = table!();
+
+	table.set_titles(row![
+		bMG->"Version",
+		bMG->"Description",
+		bMG->"Outputs",
+		bMG->"Tx Log Entries",
+		bMG->"Contexts",
+		bMG->"Accounts",
+	]);
+	for s in report.steps.iter() {
+		table.add_row(row![
+			bFC->s.version,
+			bFD->s.description,
+			bFY->s.changed.outputs,
+			bFY->s.changed.tx_log_entries,
+			bFY->s.changed.private_contexts,
+			bFY->s.changed.accounts,
+		]);
+	}
+	table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+	table.printstd();
+	println!();
+}
+
+/// Display the issues found by a wallet integrity check, and whether each
+/// was fixed
+pub fn integrity_report(report: &IntegrityReport) {
+	println!(
+		"\nChecked {} outputs, {} tx log entries, {} private contexts and {} accounts",
+		report.outputs, report.tx_log_entries, report.private_contexts, report.accounts
+	);
+	if report.issues.is_empty() {
+		println!("No issues found\n");
+		return;
+	}
+	println!(
+		"\n____ Wallet Integrity - {} issues found, {} fixed ____\n",
+		report.issues.len(),
+		report.fixed
+	);
+	let mut table = table!();
+
+	table.set_titles(row![
+		bMG->"Issue",
+		bMG->"Record",
+		bMG->"Details",
+		bMG->"Fix",
+	]);
+	for issue in report.issues.iter() {
+		let (kind, record, details) = match issue {
+			IntegrityIssue::MissingTxLogEntry(o) => (
+				"Missing tx log entry",
+				format!("Output {}", o.key_id),
+				match o.tx_log_entry {
+					Some(id) => format!("Linked to tx {}", id),
+					None => "".to_owned(),
+				},
+			),
+			IntegrityIssue::MissingStoredTx(t) => (
+				"Missing stored tx",
+				format!("Tx {}", t.id),
+				t.stored_tx.clone().unwrap_or_default(),
+			),
+			IntegrityIssue::UnreadableStoredTx { tx, error } => (
+				"Unreadable stored tx",
+				format!("Tx {}", tx.id),
+				format!("{}: {}", tx.stored_tx.clone().unwrap_or_default(), error),
+			),
+			IntegrityIssue::StaleContext { slate_id, tx, .. } => (
+				"Stale private context",
+				format!("Slate {}", slate_id),
+				match tx.confirmed {
+					true => format!("Tx {} is confirmed", tx.id),
+					false => format!("Tx {} is cancelled", tx.id),
+				},
+			),
+			IntegrityIssue::OrphanedContext { slate_id, .. } => (
+				"Orphaned private context",
+				format!("Slate {}", slate_id),
+				"No tx log entry".to_owned(),
+			),
+			IntegrityIssue::ChildIndexBehind {
+				parent_key_id,
+				next_child_index,
+				highest_used,
+			} => (
+				"Child index behind",
+				format!("Account {}", parent_key_id),
+				format!(
+					"Next child index {}, highest used {}",
+					next_child_index, highest_used
+				),
+			),
+			IntegrityIssue::UnlabelledAccount(parent_key_id) => (
+				"Unlabelled account",
+				format!("Account {}", parent_key_id),
+				"".to_owned(),
+			),
+			IntegrityIssue::DuplicateAccountPath {
+				parent_key_id,
+				labels,
+			} => (
+				"Duplicate account labels",
+				format!("Account {}", parent_key_id),
+				labels.join(", "),
+			),
+			IntegrityIssue::StaleScannedOutputs(count) => (
+				"Stale scanned outputs",
+				format!("{} outputs", count),
+				"No scan checkpoint".to_owned(),
+			),
+		};
+		let fix = match (issue.is_fixable(), report.fixed > 0) {
+			(true, true) => "Fixed",
+			(true, false) => "Fixable with --fix",
+			(false, _) => "Report only",
+		};
+		table.add_row(row![
+			bFR->kind,
+			bFC->record,
+			bFB->details,
+			bFY->fix,
+		]);
+	}
+	table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+	table.printstd();
+	println!();
+}
+
+/// Display what a garbage collection removed
+pub fn gc_report(report: &GcReport) {
+	if report == &GcReport::default() {
+		println!("\nNothing to remove\n");
+		return;
+	}
+	println!("\n____ Garbage Collected ____\n");
+	let mut table = table!();
+
+	table.set_titles(row![
+		bMG->"Removed",
+		bMG->"Record",
+		bMG->"Reason",
+	]);
+	for c in report.contexts.iter() {
+		let reason = match c.reason {
+			ContextRemovalReason::Cancelled => "Tx cancelled",
+			ContextRemovalReason::Confirmed => "Tx confirmed",
+			ContextRemovalReason::Expired => "Retention period passed",
+		};
+		table.add_row(row![
+			bFR->"Private context",
+			bFC->format!("Slate {}", c.slate_id),
+			bFB->reason,
+		]);
+	}
+	for (txs, action) in vec![
+		(&report.archived_txs, "Archived"),
+		(&report.pruned_txs, "Deleted"),
+	] {
+		for t in txs.iter() {
+			table.add_row(row![
+				bFR->format!("Stored tx ({})", action),
+				bFC->t,
+				bFB->"Tx confirmed",
+			]);
+		}
+	}
+	table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+	table.printstd();
+	println!();
+}
+
 /// Display transaction log messages
 pub fn tx_messages(tx: &TxLogEntry, dark_background_color_scheme: bool) -> Result<(), Error> {
 	let title = format!("Transaction Messages - Transaction '{}'", tx.id,);
@@ -0,0 +1,144 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests for the owner API's background refresh
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_api as apiwallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::apiwallet::Owner;
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use self::libwallet::{NodeClient, RefreshSchedule, RefreshStatus, WalletBackend};
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+/// Wait for the updater to have refreshed at the given height
+fn wait_for_refresh<W: ?Sized, C, K>(api: &Owner<W, C, K>, height: u64) -> RefreshStatus
+where
+	W: WalletBackend<C, K>,
+	C: NodeClient,
+	K: keychain::Keychain,
+{
+	let deadline = Instant::now() + Duration::from_secs(20);
+	loop {
+		if let Some(status) = api.refresh_status().unwrap() {
+			if status.refreshed_height == Some(height) && !status.stale {
+				return status;
+			}
+		}
+		assert!(Instant::now() < deadline, "no refresh at height {}", height);
+		thread::sleep(Duration::from_millis(50));
+	}
+}
+
+fn background_refresh_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 =
+		test_framework::create_wallet(&format!("{}/wallet1", test_dir), client1.clone(), None);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let reward = core::consensus::REWARD;
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 5, false);
+
+	let api = Owner::new(wallet1.clone());
+	assert_eq!(api.refresh_status()?, None);
+
+	// the chain height is checked far more often than the outputs would be
+	// refreshed otherwise
+	let schedule = RefreshSchedule {
+		interval: Duration::from_secs(600),
+		height_poll: Duration::from_millis(50),
+	};
+	api.start_updater(schedule)?;
+	assert!(api.start_updater(schedule).is_err());
+
+	let status = wait_for_refresh(&api, 5);
+	assert_eq!(status.chain_height, Some(5));
+	assert!(status.last_refresh.is_some());
+	assert_eq!(status.last_error, None);
+
+	// reads are served from the last refresh, by clones too
+	let api2 = api.clone();
+	let (refreshed, info) = api2.retrieve_summary_info(true, 1)?;
+	assert!(refreshed);
+	assert_eq!(info.last_confirmed_height, 5);
+	assert_eq!(info.total, 5 * reward);
+
+	// a new block is picked up without waiting out the interval
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 2, false);
+	wait_for_refresh(&api, 7);
+	let (refreshed, info) = api.retrieve_summary_info(true, 1)?;
+	assert!(refreshed);
+	assert_eq!(info.last_confirmed_height, 7);
+	assert_eq!(info.total, 7 * reward);
+	let (refreshed, txs) = api.retrieve_txs(true, None, None)?;
+	assert!(refreshed);
+	assert_eq!(txs.len(), 7);
+	let (refreshed, outputs) = api.retrieve_outputs(false, true, None)?;
+	assert!(refreshed);
+	assert_eq!(outputs.len(), 7);
+
+	// once stopped, reads go to the node again
+	api.stop_updater();
+	assert_eq!(api2.refresh_status()?, None);
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 1, false);
+	let (refreshed, info) = api.retrieve_summary_info(true, 1)?;
+	assert!(refreshed);
+	assert_eq!(info.last_confirmed_height, 8);
+
+	// and another updater can be started
+	api.start_updater(schedule)?;
+	wait_for_refresh(&api, 8);
+	api.stop_updater();
+
+	Ok(())
+}
+
+#[test]
+fn owner_background_refresh() {
+	let test_dir = "test_output/owner_background_refresh";
+	if let Err(e) = background_refresh_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
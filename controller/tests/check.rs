@@ -163,7 +163,7 @@ fn check_repair_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 
 	// this should restore our missing outputs
 	wallet::controller::owner_single_use(wallet1.clone(), |api| {
-		api.check_repair(true)?;
+		api.check_repair(true, None)?;
 		Ok(())
 	})?;
 
@@ -205,7 +205,7 @@ fn check_repair_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 
 	// unlock/restore
 	wallet::controller::owner_single_use(wallet1.clone(), |api| {
-		api.check_repair(true)?;
+		api.check_repair(true, None)?;
 		Ok(())
 	})?;
 
@@ -347,7 +347,7 @@ fn two_wallets_one_seed_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 
 	// 0) Check repair when all is okay should leave wallet contents alone
 	wallet::controller::owner_single_use(wallet1.clone(), |api| {
-		api.check_repair(true)?;
+		api.check_repair(true, None)?;
 		let info = wallet_info!(wallet1.clone())?;
 		assert_eq!(info.amount_currently_spendable, base_amount * 6);
 		assert_eq!(info.total, base_amount * 6);
@@ -377,7 +377,7 @@ fn two_wallets_one_seed_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 
 	// 1) a full restore should recover all of them:
 	wallet::controller::owner_single_use(wallet3.clone(), |api| {
-		api.restore()?;
+		api.restore(None, None)?;
 		Ok(())
 	})?;
 
@@ -392,7 +392,7 @@ fn two_wallets_one_seed_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 
 	// 2) check_repair should recover them into a single wallet
 	wallet::controller::owner_single_use(wallet1.clone(), |api| {
-		api.check_repair(true)?;
+		api.check_repair(true, None)?;
 		Ok(())
 	})?;
 
@@ -423,7 +423,7 @@ fn two_wallets_one_seed_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 	})?;
 
 	wallet::controller::owner_single_use(wallet5.clone(), |api| {
-		api.restore()?;
+		api.restore(None, None)?;
 		Ok(())
 	})?;
 
@@ -454,7 +454,7 @@ fn two_wallets_one_seed_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 	})?;
 
 	wallet::controller::owner_single_use(wallet6.clone(), |api| {
-		api.check_repair(true)?;
+		api.check_repair(true, None)?;
 		Ok(())
 	})?;
 
@@ -504,7 +504,7 @@ fn two_wallets_one_seed_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 	})?;
 
 	wallet::controller::owner_single_use(wallet8.clone(), |api| {
-		api.restore()?;
+		api.restore(None, None)?;
 		let info = wallet_info!(wallet8.clone())?;
 		let outputs = api.retrieve_outputs(true, false, None)?.1;
 		assert_eq!(outputs.len(), 15);
@@ -538,11 +538,15 @@ fn two_wallets_one_seed_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 		let outputs = api.retrieve_outputs(true, false, None)?.1;
 		assert_eq!(outputs.len(), 3);
 		assert_eq!(info.amount_currently_spendable, base_amount * 15);
-		api.check_repair(true)?;
+		api.check_repair(true, None)?;
 		let info = wallet_info!(wallet9.clone())?;
 		let outputs = api.retrieve_outputs(true, false, None)?.1;
 		assert_eq!(outputs.len(), 6);
 		assert_eq!(info.amount_currently_spendable, base_amount * 21);
+		// the account found is already labelled, so isn't labelled again
+		let mut labels: Vec<String> = api.accounts()?.into_iter().map(|a| a.label).collect();
+		labels.sort();
+		assert_eq!(labels, vec!["account_1", "default"]);
 
 		api.set_active_account("default")?;
 		let info = wallet_info!(wallet9.clone())?;
@@ -556,7 +560,7 @@ fn two_wallets_one_seed_impl(test_dir: &str) -> Result<(), libwallet::Error> {
 
 	// 7) Ensure check_repair creates missing accounts
 	wallet::controller::owner_single_use(wallet10.clone(), |api| {
-		api.check_repair(true)?;
+		api.check_repair(true, None)?;
 		api.set_active_account("account_1")?;
 		let info = wallet_info!(wallet10.clone())?;
 		let outputs = api.retrieve_outputs(true, false, None)?.1;
@@ -0,0 +1,240 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests for reporting the discrepancies a wallet check would repair
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use self::libwallet::{CheckDiscrepancy, InitTxArgs, OutputData, OutputStatus, Slate, WalletInst};
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use util::Mutex;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+/// Everything a wallet has stored about its outputs, transactions, private
+/// contexts and stored transactions
+fn wallet_snapshot(
+	wallet: Arc<Mutex<dyn WalletInst<LocalWalletClient, ExtKeychain>>>,
+	wallet_dir: &str,
+) -> Result<Vec<String>, libwallet::Error> {
+	let mut snapshot = vec![];
+	let mut w = wallet.lock();
+	w.open_with_credentials()?;
+	let mut outputs: Vec<OutputData> = w.iter()?.collect();
+	outputs.sort_by_key(|o| o.key_id.to_hex());
+	for o in outputs {
+		snapshot.push(serde_json::to_string(&o).unwrap());
+	}
+	for t in w.tx_log_iter()? {
+		snapshot.push(serde_json::to_string(&t).unwrap());
+	}
+	let mut context_ids: Vec<(Vec<u8>, usize)> = w.private_context_iter()?.collect();
+	context_ids.sort();
+	for (slate_id, participant_id) in context_ids {
+		let context = w.get_private_context(&slate_id, participant_id)?;
+		snapshot.push(serde_json::to_string(&context).unwrap());
+	}
+	w.close()?;
+
+	let mut dirs = vec![Path::new(wallet_dir).join("saved_txs")];
+	while let Some(dir) = dirs.pop() {
+		let mut entries: Vec<_> = fs::read_dir(&dir)
+			.unwrap()
+			.map(|e| e.unwrap().path())
+			.collect();
+		entries.sort();
+		for path in entries {
+			if path.is_dir() {
+				dirs.push(path);
+			} else {
+				snapshot.push(format!(
+					"{}: {:?}",
+					path.display(),
+					fs::read(&path).unwrap()
+				));
+			}
+		}
+	}
+	Ok(snapshot)
+}
+
+/// Damage a wallet's outputs, check a dry run reports the damage without
+/// touching the wallet, then repair it for real
+fn check_dry_run_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let wallet1_dir = format!("{}/wallet1", test_dir);
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 = test_framework::create_wallet(&wallet1_dir, client1.clone(), None);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	let client2 = LocalWalletClient::new("wallet2", wallet_proxy.tx.clone());
+	let wallet2 =
+		test_framework::create_wallet(&format!("{}/wallet2", test_dir), client2.clone(), None);
+	wallet_proxy.add_wallet("wallet2", client2.get_send_instance(), wallet2.clone());
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let reward = core::consensus::REWARD;
+	let bh = 10u64;
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), bh as usize, false);
+
+	let mut w1_outputs: Vec<OutputData> = vec![];
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		w1_outputs = api
+			.retrieve_outputs(false, true, None)?
+			.1
+			.into_iter()
+			.map(|m| m.output)
+			.collect();
+		Ok(())
+	})?;
+
+	// lose two outputs, and wrongly mark a third spent
+	{
+		let mut w = wallet1.lock();
+		w.open_with_credentials()?;
+		{
+			let mut batch = w.batch()?;
+			batch.delete(&w1_outputs[2].key_id, &None)?;
+			batch.delete(&w1_outputs[5].key_id, &None)?;
+			let mut wrongly_spent = w1_outputs[7].clone();
+			wrongly_spent.status = OutputStatus::Spent;
+			batch.save(wrongly_spent)?;
+			batch.commit()?;
+		}
+		w.close()?;
+	}
+
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let discrepancies = api.check_repair_dry_run(false, None)?;
+		let missing: Vec<_> = discrepancies
+			.iter()
+			.filter_map(|d| match d {
+				CheckDiscrepancy::MissingOutput(o) => Some(o.key_id.clone()),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(missing.len(), 2);
+		assert!(missing.contains(&w1_outputs[2].key_id));
+		assert!(missing.contains(&w1_outputs[5].key_id));
+		let wrong_status: Vec<_> = discrepancies
+			.iter()
+			.filter_map(|d| match d {
+				CheckDiscrepancy::WrongStatus {
+					output,
+					repaired_status,
+				} => Some((output.key_id.clone(), repaired_status.clone())),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(
+			wrong_status,
+			vec![(w1_outputs[7].key_id.clone(), Some(OutputStatus::Unspent))]
+		);
+
+		// nothing has been repaired
+		let (_, info) = api.retrieve_summary_info(false, 1)?;
+		assert_eq!(info.total, (bh - 3) * reward);
+		Ok(())
+	})?;
+
+	// and the dry run left no checkpoint behind
+	{
+		let mut w = wallet1.lock();
+		w.open_with_credentials()?;
+		assert!(w.scan_checkpoint()?.is_none());
+		assert_eq!(w.scanned_output_iter()?.count(), 0);
+		w.close()?;
+	}
+
+	// a real check repairs everything the dry run reported
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		api.check_repair(false, None)?;
+		let (_, info) = api.retrieve_summary_info(false, 1)?;
+		assert_eq!(info.total, bh * reward);
+		assert!(api.check_repair_dry_run(false, None)?.is_empty());
+		Ok(())
+	})?;
+
+	// one send is posted and mined, and another locked and never finalized,
+	// leaving outputs a refresh would update, a private context and stored
+	// transactions
+	wallet::controller::owner_single_use(wallet1.clone(), |sender_api| {
+		let args = InitTxArgs {
+			src_acct_name: None,
+			amount: reward / 2,
+			minimum_confirmations: 2,
+			max_outputs: 500,
+			num_change_outputs: 1,
+			selection_strategy_is_use_all: false,
+			..Default::default()
+		};
+		let slate_i = sender_api.init_send_tx(args.clone())?;
+		let mut slate: Slate = client1.send_tx_slate_direct("wallet2", &slate_i)?;
+		sender_api.tx_lock_outputs(&slate, 0)?;
+		slate = sender_api.finalize_tx(&slate)?;
+		sender_api.post_tx(&slate.tx, false)?;
+
+		let slate_i = sender_api.init_send_tx(args)?;
+		sender_api.tx_lock_outputs(&slate_i, 0)?;
+		Ok(())
+	})?;
+
+	let before = wallet_snapshot(wallet1.clone(), &wallet1_dir)?;
+	assert!(before.iter().any(|s| s.contains("saved_txs")));
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		api.check_repair_dry_run(false, None)?;
+		Ok(())
+	})?;
+	assert_eq!(before, wallet_snapshot(wallet1.clone(), &wallet1_dir)?);
+
+	Ok(())
+}
+
+#[test]
+fn wallet_check_dry_run() {
+	let test_dir = "test_output/wallet_check_dry_run";
+	if let Err(e) = check_dry_run_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
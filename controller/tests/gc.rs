@@ -0,0 +1,170 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests for garbage collection of private contexts and stored transactions
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use self::libwallet::{ContextRemovalReason, GcReport, InitTxArgs, RemovedContext, Slate};
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+fn send_args(amount: u64) -> InitTxArgs {
+	InitTxArgs {
+		src_acct_name: None,
+		amount,
+		minimum_confirmations: 2,
+		max_outputs: 500,
+		num_change_outputs: 1,
+		selection_strategy_is_use_all: false,
+		..Default::default()
+	}
+}
+
+/// Cancel one transaction and confirm another, and check what's collected
+/// on demand and after a refresh
+fn gc_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	// garbage is only collected on refresh when the wallet asks for it
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let mut wallet1_config = test_framework::test_wallet_config(
+		&format!("{}/wallet1", test_dir),
+		test_framework::test_backend(),
+	);
+	wallet1_config.gc_on_refresh = Some(true);
+	let wallet1 = test_framework::create_wallet_with_config(wallet1_config, client1.clone(), None);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	let client2 = LocalWalletClient::new("wallet2", wallet_proxy.tx.clone());
+	let wallet2 =
+		test_framework::create_wallet(&format!("{}/wallet2", test_dir), client2.clone(), None);
+	wallet_proxy.add_wallet("wallet2", client2.get_send_instance(), wallet2.clone());
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let reward = core::consensus::REWARD;
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 10, false);
+
+	// a cancelled transaction's context is collected on demand
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let slate = api.init_send_tx(send_args(reward))?;
+		api.tx_lock_outputs(&slate, 0)?;
+		api.cancel_tx(None, Some(slate.id))?;
+		assert_eq!(api.check_integrity(false)?.private_contexts, 1);
+
+		let report = api.collect_garbage()?;
+		assert_eq!(
+			report.contexts,
+			vec![RemovedContext {
+				slate_id: slate.id,
+				participant_id: 0,
+				reason: ContextRemovalReason::Cancelled,
+			}]
+		);
+		assert!(report.archived_txs.is_empty());
+		assert_eq!(api.check_integrity(false)?.private_contexts, 0);
+		Ok(())
+	})?;
+
+	// a confirmed transaction's stored transaction is kept by the refresh
+	// that confirms it, until it's buried as deep as the policy asks
+	let mut slate_id = None;
+	let mut stored_tx = None;
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let slate_i = api.init_send_tx(send_args(reward))?;
+		let mut slate: Slate = client1.send_tx_slate_direct("wallet2", &slate_i)?;
+		api.tx_lock_outputs(&slate, 0)?;
+		slate = api.finalize_tx(&slate)?;
+		api.post_tx(&slate.tx, false)?;
+		let (_, txs) = api.retrieve_txs(false, None, Some(slate.id))?;
+		assert!(!txs[0].confirmed);
+		slate_id = Some(slate.id);
+		stored_tx = txs[0].stored_tx.clone();
+		Ok(())
+	})?;
+	let stored_tx = stored_tx.unwrap();
+	let saved_txs_dir = format!("{}/wallet1/saved_txs", test_dir);
+	assert!(Path::new(&format!("{}/{}", saved_txs_dir, stored_tx)).exists());
+
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let (refreshed, txs) = api.retrieve_txs(true, None, slate_id)?;
+		assert!(refreshed);
+		assert!(txs[0].confirmed);
+		assert_eq!(txs[0].stored_tx, Some(stored_tx.clone()));
+		assert!(api.collect_garbage()?.archived_txs.is_empty());
+		Ok(())
+	})?;
+	assert!(Path::new(&format!("{}/{}", saved_txs_dir, stored_tx)).exists());
+
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 10, false);
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let (refreshed, txs) = api.retrieve_txs(true, None, slate_id)?;
+		assert!(refreshed);
+		assert_eq!(txs[0].stored_tx, None);
+		assert!(api.get_stored_tx(&txs[0])?.is_none());
+
+		// so there's nothing left to collect
+		assert_eq!(api.collect_garbage()?, GcReport::default());
+		Ok(())
+	})?;
+	assert!(!Path::new(&format!("{}/{}", saved_txs_dir, stored_tx)).exists());
+	assert!(Path::new(&format!("{}/archive/{}", saved_txs_dir, stored_tx)).exists());
+
+	// an unfinished transaction's context is kept until it expires
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		api.init_send_tx(send_args(reward))?;
+		assert_eq!(api.collect_garbage()?, GcReport::default());
+		assert_eq!(api.check_integrity(false)?.private_contexts, 1);
+		Ok(())
+	})?;
+
+	Ok(())
+}
+
+#[test]
+fn wallet_gc() {
+	let test_dir = "test_output/wallet_gc";
+	if let Err(e) = gc_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
@@ -0,0 +1,243 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests for the integrity check of a wallet's stored data
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::{ExtKeychain, Keychain};
+use self::libwallet::{InitTxArgs, IntegrityIssue, OutputData, Slate};
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use std::fs;
+use std::thread;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+fn send_args(amount: u64) -> InitTxArgs {
+	InitTxArgs {
+		src_acct_name: None,
+		amount,
+		minimum_confirmations: 2,
+		max_outputs: 500,
+		num_change_outputs: 1,
+		selection_strategy_is_use_all: false,
+		..Default::default()
+	}
+}
+
+/// Leave a wallet with broken references of every kind the check fixes,
+/// and one it only reports, then check and fix it
+fn integrity_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 =
+		test_framework::create_wallet(&format!("{}/wallet1", test_dir), client1.clone(), None);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	let client2 = LocalWalletClient::new("wallet2", wallet_proxy.tx.clone());
+	let wallet2 =
+		test_framework::create_wallet(&format!("{}/wallet2", test_dir), client2.clone(), None);
+	wallet_proxy.add_wallet("wallet2", client2.get_send_instance(), wallet2.clone());
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let reward = core::consensus::REWARD;
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 10, false);
+
+	// a cancelled transaction, and one that's never locked, whose context
+	// has no tx log entry
+	let mut cancelled_slate_id = None;
+	let mut unlocked_slate_id = None;
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let slate = api.init_send_tx(send_args(reward))?;
+		api.tx_lock_outputs(&slate, 0)?;
+		cancelled_slate_id = Some(slate.id);
+		unlocked_slate_id = Some(api.init_send_tx(send_args(reward))?.id);
+		Ok(())
+	})?;
+	let cancelled_context = {
+		let mut w = wallet1.lock();
+		w.open_with_credentials()?;
+		let ctx = w.get_private_context(cancelled_slate_id.unwrap().as_bytes(), 0)?;
+		w.close()?;
+		ctx
+	};
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		api.cancel_tx(None, cancelled_slate_id)
+	})?;
+
+	// a finished transaction, whose stored transaction goes missing. Sent
+	// last, as the next refresh would archive it
+	let mut stored_tx = None;
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let slate_i = api.init_send_tx(send_args(reward))?;
+		let mut slate: Slate = client1.send_tx_slate_direct("wallet2", &slate_i)?;
+		api.tx_lock_outputs(&slate, 0)?;
+		slate = api.finalize_tx(&slate)?;
+		api.post_tx(&slate.tx, false)?;
+		let (_, txs) = api.retrieve_txs(false, None, Some(slate.id))?;
+		stored_tx = txs[0].stored_tx.clone();
+		Ok(())
+	})?;
+	fs::remove_file(format!(
+		"{}/wallet1/saved_txs/{}",
+		test_dir,
+		stored_tx.unwrap()
+	))?;
+
+	// the cancelled transaction's context put back, as the refresh when
+	// sending collected it, an output linked to a tx log entry that doesn't
+	// exist, and a child index that's gone backwards
+	let parent_key_id = ExtKeychain::derive_key_id(2, 0, 0, 0, 0);
+	let mut unlinked: Option<OutputData> = None;
+	{
+		let mut w = wallet1.lock();
+		w.open_with_credentials()?;
+		{
+			let mut o = w.iter()?.next().unwrap();
+			o.tx_log_entry = Some(999);
+			unlinked = Some(o.clone());
+			let mut batch = w.batch()?;
+			batch.save_private_context(
+				cancelled_slate_id.unwrap().as_bytes(),
+				0,
+				&cancelled_context,
+			)?;
+			batch.save(o)?;
+			batch.save_child_index(&parent_key_id, 1)?;
+			batch.commit()?;
+		}
+		w.close()?;
+	}
+
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let report = api.check_integrity(false)?;
+		assert_eq!(report.private_contexts, 2);
+		assert_eq!(report.accounts, 1);
+		assert_eq!(report.issues.len(), 5);
+		assert_eq!(report.fixed, 0);
+		assert!(report.issues.contains(&IntegrityIssue::MissingTxLogEntry(
+			unlinked.clone().unwrap()
+		)));
+		assert!(report.issues.iter().any(|i| match i {
+			IntegrityIssue::MissingStoredTx(t) => t.stored_tx.is_some(),
+			_ => false,
+		}));
+		assert!(report.issues.iter().any(|i| match i {
+			IntegrityIssue::StaleContext {
+				slate_id,
+				participant_id,
+				..
+			} => Some(*slate_id) == cancelled_slate_id && *participant_id == 0,
+			_ => false,
+		}));
+		assert!(report.issues.contains(&IntegrityIssue::OrphanedContext {
+			slate_id: unlocked_slate_id.unwrap(),
+			participant_id: 0,
+		}));
+		assert!(report.issues.iter().any(|i| match i {
+			IntegrityIssue::ChildIndexBehind {
+				parent_key_id: p,
+				next_child_index,
+				..
+			} => *p == parent_key_id && *next_child_index == 1,
+			_ => false,
+		}));
+
+		// only reported, so nothing has changed
+		assert_eq!(api.check_integrity(false)?.issues, report.issues);
+		Ok(())
+	})?;
+
+	// fixing leaves only the context that may still be in use
+	let mut unreadable_tx = None;
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let report = api.check_integrity(true)?;
+		assert_eq!(report.fixed, 4);
+
+		let report = api.check_integrity(false)?;
+		assert_eq!(report.private_contexts, 1);
+		assert_eq!(
+			report.issues,
+			vec![IntegrityIssue::OrphanedContext {
+				slate_id: unlocked_slate_id.unwrap(),
+				participant_id: 0,
+			}]
+		);
+
+		// and the wallet still works
+		let slate_i = api.init_send_tx(send_args(reward))?;
+		let mut slate: Slate = client1.send_tx_slate_direct("wallet2", &slate_i)?;
+		api.tx_lock_outputs(&slate, 0)?;
+		slate = api.finalize_tx(&slate)?;
+		api.post_tx(&slate.tx, false)?;
+		let (_, txs) = api.retrieve_txs(false, None, Some(slate.id))?;
+		unreadable_tx = txs[0].stored_tx.clone();
+		Ok(())
+	})?;
+
+	// a stored transaction that can't be read is reported, but left linked
+	let stored_tx = unreadable_tx.unwrap();
+	fs::write(
+		format!("{}/wallet1/saved_txs/{}", test_dir, stored_tx),
+		"not a transaction",
+	)?;
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let report = api.check_integrity(true)?;
+		assert_eq!(report.fixed, 0);
+		assert!(report.issues.iter().any(|i| match i {
+			IntegrityIssue::UnreadableStoredTx { tx, .. } => {
+				tx.stored_tx == Some(stored_tx.clone())
+			}
+			_ => false,
+		}));
+		let (_, txs) = api.retrieve_txs(false, None, None)?;
+		assert!(txs.iter().any(|t| t.stored_tx == Some(stored_tx.clone())));
+		Ok(())
+	})?;
+
+	Ok(())
+}
+
+#[test]
+fn wallet_integrity() {
+	let test_dir = "test_output/wallet_integrity";
+	if let Err(e) = integrity_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
@@ -0,0 +1,143 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Test a send between two wallets using the in-memory backend
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_config as config;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use config::WalletBackendType;
+use grin_wallet_libwallet as libwallet;
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use libwallet::InitTxArgs;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+/// send between in-memory wallets impl
+fn memory_backend_test_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	// Create a new proxy to simulate server and wallet responses
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 = test_framework::create_wallet_with_backend(
+		&format!("{}/wallet1", test_dir),
+		client1.clone(),
+		None,
+		WalletBackendType::Memory,
+	);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	let client2 = LocalWalletClient::new("wallet2", wallet_proxy.tx.clone());
+	let wallet2 = test_framework::create_wallet_with_backend(
+		&format!("{}/wallet2", test_dir),
+		client2.clone(),
+		None,
+		WalletBackendType::Memory,
+	);
+	wallet_proxy.add_wallet("wallet2", client2.get_send_instance(), wallet2.clone());
+
+	// Set the wallet proxy listener running
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	// few values to keep things shorter
+	let reward = core::consensus::REWARD;
+	let mut bh = 10u64;
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), bh as usize, false);
+
+	let amount = reward / 2;
+	wallet::controller::owner_single_use(wallet1.clone(), |sender_api| {
+		let args = InitTxArgs {
+			src_acct_name: None,
+			amount: amount,
+			minimum_confirmations: 2,
+			max_outputs: 500,
+			num_change_outputs: 1,
+			selection_strategy_is_use_all: true,
+			..Default::default()
+		};
+		let mut slate = sender_api.init_send_tx(args)?;
+		sender_api.tx_lock_outputs(&slate, 0)?;
+		wallet::controller::foreign_single_use(wallet2.clone(), |api| {
+			slate = api.receive_tx(&slate, None, None)?;
+			Ok(())
+		})?;
+		slate = sender_api.finalize_tx(&slate)?;
+		sender_api.post_tx(&slate.tx, false)?;
+		bh += 1;
+		Ok(())
+	})?;
+
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 3, false);
+	bh += 3;
+
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let (refreshed, info) = api.retrieve_summary_info(true, 1)?;
+		assert!(refreshed);
+		assert_eq!(info.last_confirmed_height, bh);
+		// wallet 1 mined the block with the fees, so only the amount is gone
+		assert_eq!(info.total, bh * reward - amount);
+		let (_, txs) = api.retrieve_txs(true, None, None)?;
+		assert!(txs.iter().all(|t| t.confirmed));
+		Ok(())
+	})?;
+
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		let (refreshed, info) = api.retrieve_summary_info(true, 1)?;
+		assert!(refreshed);
+		assert_eq!(info.amount_currently_spendable, amount);
+		Ok(())
+	})?;
+
+	// nothing but the chain was written to disk
+	assert!(!Path::new(&format!("{}/wallet1", test_dir)).exists());
+	assert!(!Path::new(&format!("{}/wallet2", test_dir)).exists());
+
+	// let logging finish
+	thread::sleep(Duration::from_millis(200));
+	Ok(())
+}
+
+#[test]
+fn wallet_memory_backend() {
+	let test_dir = "test_output/memory_backend";
+	if let Err(e) = memory_backend_test_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+}
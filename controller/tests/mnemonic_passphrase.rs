@@ -0,0 +1,128 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Test restoring wallets created with a BIP39 passphrase
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use grin_wallet_libwallet as libwallet;
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+const REC_PHRASE: &'static str = "fat twenty mean degree forget shell check candy immense awful \
+	 flame next during february bulb bike sun wink theory day kiwi embrace peace lunch";
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+/// restore with matching, wrong and missing passphrases impl
+fn mnemonic_passphrase_test_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 = test_framework::create_wallet_with_mnemonic_passphrase(
+		&format!("{}/wallet1", test_dir),
+		client1.clone(),
+		Some(REC_PHRASE),
+		"correct horse",
+	);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	// same words, same passphrase
+	let client2 = LocalWalletClient::new("wallet2", wallet_proxy.tx.clone());
+	let wallet2 = test_framework::create_wallet_with_mnemonic_passphrase(
+		&format!("{}/wallet2", test_dir),
+		client2.clone(),
+		Some(REC_PHRASE),
+		"correct horse",
+	);
+	wallet_proxy.add_wallet("wallet2", client2.get_send_instance(), wallet2.clone());
+
+	// same words, different passphrase
+	let client3 = LocalWalletClient::new("wallet3", wallet_proxy.tx.clone());
+	let wallet3 = test_framework::create_wallet_with_mnemonic_passphrase(
+		&format!("{}/wallet3", test_dir),
+		client3.clone(),
+		Some(REC_PHRASE),
+		"battery staple",
+	);
+	wallet_proxy.add_wallet("wallet3", client3.get_send_instance(), wallet3.clone());
+
+	// same words, no passphrase
+	let client4 = LocalWalletClient::new("wallet4", wallet_proxy.tx.clone());
+	let wallet4 = test_framework::create_wallet(
+		&format!("{}/wallet4", test_dir),
+		client4.clone(),
+		Some(REC_PHRASE),
+	);
+	wallet_proxy.add_wallet("wallet4", client4.get_send_instance(), wallet4.clone());
+
+	// Set the wallet proxy listener running
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let reward = core::consensus::REWARD;
+	let bh = 5u64;
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), bh as usize, false);
+
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.total, bh * reward);
+		Ok(())
+	})?;
+
+	// only the matching passphrase finds the outputs
+	for (w, expected) in vec![(wallet2, bh * reward), (wallet3, 0), (wallet4, 0)] {
+		wallet::controller::owner_single_use(w.clone(), |api| {
+			api.restore(None, None)?;
+			let (_, info) = api.retrieve_summary_info(true, 1)?;
+			assert_eq!(info.total, expected);
+			Ok(())
+		})?;
+	}
+
+	// let logging finish
+	thread::sleep(Duration::from_millis(200));
+	Ok(())
+}
+
+#[test]
+fn wallet_mnemonic_passphrase() {
+	let test_dir = "test_output/mnemonic_passphrase";
+	if let Err(e) = mnemonic_passphrase_test_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+}
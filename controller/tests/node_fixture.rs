@@ -0,0 +1,140 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests recording a wallet's requests of the node and replaying them
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use self::libwallet::{NodeClient, TxWrapper, WalletInfo};
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use impls::{NodeFixture, NodeRequest, RecordingNodeClient, ReplayNodeClient};
+use std::fs;
+use std::thread;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+/// Record a check run against the test node, and replay it into another
+/// wallet without one
+fn record_replay_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let seed_phrase = "affair pistol cancel crush garment candy ancient flag work \
+	                   market crush dry stand focus mutual weapon offer ceiling rival turn team spring \
+	                   where swift";
+	let fixture_path = format!("{}/node_fixture.json", test_dir);
+
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 = test_framework::create_wallet(
+		&format!("{}/wallet1", test_dir),
+		client1.clone(),
+		Some(seed_phrase),
+	);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	// the same seed, recording what it asks of the node. The proxy only
+	// needs its queue to answer it.
+	let client2 = LocalWalletClient::new("wallet2", wallet_proxy.tx.clone());
+	wallet_proxy.add_wallet("wallet2", client2.get_send_instance(), wallet1.clone());
+	let recorder = RecordingNodeClient::new(client2, &fixture_path);
+	let wallet2 = test_framework::create_wallet::<_, ExtKeychain>(
+		&format!("{}/wallet2", test_dir),
+		recorder.clone(),
+		Some(seed_phrase),
+	);
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 5, false);
+
+	let mut recorded_info: Option<WalletInfo> = None;
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		api.check_repair(true, None)?;
+		let (refreshed, info) = api.retrieve_summary_info(true, 1)?;
+		assert!(refreshed);
+		assert_eq!(info.last_confirmed_height, 5);
+		assert_eq!(info.total, 5 * core::consensus::REWARD);
+		recorded_info = Some(info);
+		Ok(())
+	})?;
+	let recorded_info = recorded_info.unwrap();
+
+	// everything asked is in the fixture file, as it was answered
+	let fixture = NodeFixture::from_file(&fixture_path)?;
+	assert_eq!(fixture.exchanges.len(), recorder.fixture().exchanges.len());
+	assert!(fixture
+		.exchanges
+		.iter()
+		.any(|e| e.request == NodeRequest::GetChainHeight));
+	assert!(fixture.exchanges.iter().any(|e| match e.request {
+		NodeRequest::GetOutputsByPmmrIndex { .. } => true,
+		_ => false,
+	}));
+	assert!(fixture.exchanges.iter().all(|e| e.response.is_ok()));
+
+	// which is enough for the same run without the node
+	let replay = ReplayNodeClient::from_file(&fixture_path)?;
+	let wallet3 = test_framework::create_wallet::<_, ExtKeychain>(
+		&format!("{}/wallet3", test_dir),
+		replay.clone(),
+		Some(seed_phrase),
+	);
+	wallet::controller::owner_single_use(wallet3.clone(), |api| {
+		api.check_repair(true, None)?;
+		let (refreshed, info) = api.retrieve_summary_info(true, 1)?;
+		assert!(refreshed);
+		assert_eq!(info, recorded_info);
+		Ok(())
+	})?;
+	assert!(replay.unplayed().is_empty());
+
+	// but nothing that wasn't recorded
+	let tx = TxWrapper {
+		tx_hex: "00".to_owned(),
+	};
+	assert!(replay.post_tx(&tx, false).is_err());
+
+	Ok(())
+}
+
+#[test]
+fn node_record_replay() {
+	let test_dir = "test_output/node_record_replay";
+	if let Err(e) = record_replay_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
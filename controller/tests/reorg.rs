@@ -0,0 +1,233 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests for rolling back confirmations from blocks orphaned by a reorg
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::core::hash::Hashed;
+use self::core::core::Transaction;
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use self::libwallet::{
+	BlockIdentifier, InitTxArgs, OutputStatus, Slate, TxLogEntry, TxLogEntryType,
+};
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use std::fs;
+use std::thread;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+fn find_tx(txs: &[TxLogEntry], tx_type: TxLogEntryType) -> TxLogEntry {
+	txs.iter().find(|t| t.tx_type == tx_type).unwrap().clone()
+}
+
+/// Send between two wallets, orphan the block the transaction was confirmed
+/// in, then mine it again on the new chain
+fn reorg_rollback_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 =
+		test_framework::create_wallet(&format!("{}/wallet1", test_dir), client1.clone(), None);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	let client2 = LocalWalletClient::new("wallet2", wallet_proxy.tx.clone());
+	let wallet2 =
+		test_framework::create_wallet(&format!("{}/wallet2", test_dir), client2.clone(), None);
+	wallet_proxy.add_wallet("wallet2", client2.get_send_instance(), wallet2.clone());
+
+	// mines the competing chain
+	let client3 = LocalWalletClient::new("miner", wallet_proxy.tx.clone());
+	let miner =
+		test_framework::create_wallet(&format!("{}/miner", test_dir), client3.clone(), None);
+	wallet_proxy.add_wallet("miner", client3.get_send_instance(), miner.clone());
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let reward = core::consensus::REWARD;
+	let block_at = |height| BlockIdentifier(chain.get_header_by_height(height).unwrap().hash());
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 10, false);
+
+	// mined in block 11, rewarding wallet 1
+	let amount = 60_000_000_000;
+	let mut tx: Option<Transaction> = None;
+	wallet::controller::owner_single_use(wallet1.clone(), |sender_api| {
+		let args = InitTxArgs {
+			src_acct_name: None,
+			amount,
+			minimum_confirmations: 2,
+			max_outputs: 500,
+			num_change_outputs: 1,
+			selection_strategy_is_use_all: true,
+			..Default::default()
+		};
+		let slate_i = sender_api.init_send_tx(args)?;
+		let mut slate: Slate = client1.send_tx_slate_direct("wallet2", &slate_i)?;
+		sender_api.tx_lock_outputs(&slate, 0)?;
+		slate = sender_api.finalize_tx(&slate)?;
+		sender_api.post_tx(&slate.tx, false)?;
+		tx = Some(slate.tx.clone());
+		Ok(())
+	})?;
+	let tx = tx.unwrap();
+
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		let (_, txs) = api.retrieve_txs(true, None, None)?;
+		let received = find_tx(&txs, TxLogEntryType::TxReceived);
+		assert!(received.confirmed);
+		assert_eq!(received.confirmation_height, Some(11));
+		let (_, outputs) = api.retrieve_outputs(false, false, None)?;
+		assert_eq!(outputs.len(), 1);
+		assert_eq!(outputs[0].output.status, OutputStatus::Unspent);
+		assert_eq!(outputs[0].output.block_hash, Some(block_at(11)));
+		Ok(())
+	})?;
+
+	// a side chain with less work changes nothing
+	let _ = test_framework::award_blocks_on_fork(&chain, 9, vec![], miner.clone(), 1)?;
+	assert_eq!(chain.head_header().unwrap().height, 11);
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.last_confirmed_height, 11);
+		assert_eq!(info.amount_currently_spendable, amount);
+		Ok(())
+	})?;
+
+	// one with more work orphans blocks 10 and 11
+	let _ = test_framework::award_blocks_on_fork(&chain, 9, vec![], miner.clone(), 3)?;
+	assert_eq!(chain.head_header().unwrap().height, 12);
+
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		let (refreshed, info) = api.retrieve_summary_info(true, 1)?;
+		assert!(refreshed);
+		assert_eq!(info.last_confirmed_height, 12);
+		assert_eq!(info.amount_currently_spendable, 0);
+		// unconfirmed outputs are summed as awaiting finalization
+		assert_eq!(info.amount_awaiting_finalization, amount);
+		let (_, txs) = api.retrieve_txs(false, None, None)?;
+		let received = find_tx(&txs, TxLogEntryType::TxReceived);
+		assert!(!received.confirmed);
+		assert_eq!(received.confirmation_ts, None);
+		assert_eq!(received.confirmation_height, None);
+		let (_, outputs) = api.retrieve_outputs(false, false, None)?;
+		assert_eq!(outputs[0].output.status, OutputStatus::Unconfirmed);
+		assert_eq!(outputs[0].output.block_hash, None);
+		Ok(())
+	})?;
+
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.last_confirmed_height, 12);
+		let (_, txs) = api.retrieve_txs(false, None, None)?;
+		assert!(!find_tx(&txs, TxLogEntryType::TxSent).confirmed);
+		let coinbase: Vec<&TxLogEntry> = txs
+			.iter()
+			.filter(|t| t.tx_type == TxLogEntryType::ConfirmedCoinbase)
+			.collect();
+		assert_eq!(coinbase.len(), 10);
+		assert_eq!(coinbase.iter().filter(|t| t.confirmed).count(), 9);
+
+		// rewards from the orphaned blocks and the change are unconfirmed,
+		// and nothing is spent any more
+		let (_, outputs) = api.retrieve_outputs(true, false, None)?;
+		for o in outputs.iter().map(|m| &m.output) {
+			assert_ne!(o.status, OutputStatus::Spent);
+			if o.status == OutputStatus::Unspent || o.status == OutputStatus::Locked {
+				assert!(o.height <= 9);
+				assert_eq!(o.block_hash, Some(block_at(o.height)));
+			}
+		}
+		let confirmed: u64 = outputs
+			.iter()
+			.filter(|m| m.output.status != OutputStatus::Unconfirmed)
+			.map(|m| m.output.value)
+			.sum();
+		assert_eq!(confirmed, 9 * reward);
+		let unconfirmed: Vec<_> = outputs
+			.iter()
+			.filter(|m| m.output.status == OutputStatus::Unconfirmed)
+			.collect();
+		assert_eq!(unconfirmed.len(), 3);
+		assert_eq!(
+			unconfirmed.iter().filter(|m| m.output.is_coinbase).count(),
+			2
+		);
+		Ok(())
+	})?;
+
+	// the transaction is mined again on the new chain
+	test_framework::award_block_to_wallet(&chain, vec![&tx], miner.clone())?;
+
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.last_confirmed_height, 13);
+		assert_eq!(info.amount_currently_spendable, amount);
+		let (_, txs) = api.retrieve_txs(false, None, None)?;
+		assert_eq!(txs.len(), 1);
+		assert!(txs[0].confirmed);
+		assert_eq!(txs[0].confirmation_height, Some(13));
+		let (_, outputs) = api.retrieve_outputs(false, false, None)?;
+		assert_eq!(outputs[0].output.status, OutputStatus::Unspent);
+		assert_eq!(outputs[0].output.height, 13);
+		assert_eq!(outputs[0].output.block_hash, Some(block_at(13)));
+		Ok(())
+	})?;
+
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let (_, txs) = api.retrieve_txs(true, None, None)?;
+		let sent = find_tx(&txs, TxLogEntryType::TxSent);
+		assert!(sent.confirmed);
+		assert_eq!(sent.confirmation_height, Some(13));
+		// without new entries for the rewards that weren't
+		let coinbase = txs
+			.iter()
+			.filter(|t| t.tx_type == TxLogEntryType::ConfirmedCoinbase)
+			.count();
+		assert_eq!(coinbase, 10);
+		Ok(())
+	})?;
+
+	Ok(())
+}
+
+#[test]
+fn reorg_rollback() {
+	let test_dir = "test_output/reorg_rollback";
+	if let Err(e) = reorg_rollback_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
@@ -67,7 +67,7 @@ fn restore_wallet(base_dir: &str, wallet_dir: &str) -> Result<(), libwallet::Err
 
 	// perform the restore and update wallet info
 	wallet::controller::owner_single_use(wallet.clone(), |api| {
-		let _ = api.restore()?;
+		let _ = api.restore(None, None)?;
 		let _ = api.retrieve_summary_info(true, 1)?;
 		Ok(())
 	})?;
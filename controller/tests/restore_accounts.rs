@@ -0,0 +1,137 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests for restoring a wallet's account labels
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::{ExtKeychain, Keychain};
+use self::libwallet::AcctPathMapping;
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use std::fs;
+use std::thread;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+/// Restore a wallet with several accounts, giving the labels of some of
+/// them
+fn restore_accounts_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 =
+		test_framework::create_wallet(&format!("{}/wallet1", test_dir), client1.clone(), None);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	// restore target, with the same seed
+	let dest_dir = format!("{}/wallet1_restore", test_dir);
+	fs::create_dir_all(&dest_dir)?;
+	fs::copy(
+		format!("{}/wallet1/wallet.seed", test_dir),
+		format!("{}/wallet.seed", dest_dir),
+	)?;
+	let client2 = LocalWalletClient::new("wallet1_restore", wallet_proxy.tx.clone());
+	let wallet2 = test_framework::create_wallet(&dest_dir, client2.clone(), None);
+	wallet_proxy.add_wallet(
+		"wallet1_restore",
+		client2.get_send_instance(),
+		wallet2.clone(),
+	);
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let reward = core::consensus::REWARD;
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		api.create_account_path("savings")?;
+		api.create_account_path("holiday")?;
+		Ok(())
+	})?;
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 3, false);
+	for acct in vec!["savings", "holiday"] {
+		wallet1.lock().set_parent_key_id_by_name(acct)?;
+		let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 2, false);
+	}
+
+	// savings is known, as is an account nothing was ever sent to, but
+	// holiday has been forgotten
+	let savings_path = ExtKeychain::derive_key_id(2, 1, 0, 0, 0);
+	let holiday_path = ExtKeychain::derive_key_id(2, 2, 0, 0, 0);
+	let emergency_path = ExtKeychain::derive_key_id(2, 3, 0, 0, 0);
+	let account_labels = vec![
+		AcctPathMapping {
+			label: "savings".to_owned(),
+			path: savings_path.clone(),
+		},
+		AcctPathMapping {
+			label: "emergency".to_owned(),
+			path: emergency_path.clone(),
+		},
+	];
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		api.restore(None, Some(account_labels))?;
+		let accounts = api.accounts()?;
+		assert_eq!(accounts.len(), 4);
+		let path_of = |label: &str| {
+			accounts
+				.iter()
+				.find(|a| a.label == label)
+				.map(|a| a.path.clone())
+		};
+		assert_eq!(path_of("savings"), Some(savings_path.clone()));
+		assert_eq!(path_of("emergency"), Some(emergency_path.clone()));
+		let holiday = accounts.iter().find(|a| a.path == holiday_path).unwrap();
+		assert!(holiday.label.starts_with("account_"));
+		Ok(())
+	})?;
+
+	wallet2.lock().set_parent_key_id_by_name("savings")?;
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.total, 2 * reward);
+		Ok(())
+	})?;
+
+	Ok(())
+}
+
+#[test]
+fn wallet_restore_accounts() {
+	let test_dir = "test_output/wallet_restore_accounts";
+	if let Err(e) = restore_accounts_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
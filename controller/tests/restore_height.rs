@@ -0,0 +1,163 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests for restoring and checking a wallet from a block height
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_config as config;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::config::{WalletBackendType, WalletConfig};
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use self::libwallet::{ScanKind, ScanPhase};
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use impls::WalletSeed;
+use std::fs;
+use std::thread;
+
+const REC_PHRASE: &'static str = "fat twenty mean degree forget shell check candy immense awful \
+	 flame next during february bulb bike sun wink theory day kiwi embrace peace lunch";
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+/// Restore from an explicit start height, then from a birthday recorded in
+/// the seed file
+fn restore_height_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	// blocks up to this height are mined before the wallet's birthday
+	let birthday = 5u64;
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 = test_framework::create_wallet(
+		&format!("{}/wallet1", test_dir),
+		client1.clone(),
+		Some(REC_PHRASE),
+	);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	let client2 = LocalWalletClient::new("wallet2", wallet_proxy.tx.clone());
+	let wallet2 = test_framework::create_wallet(
+		&format!("{}/wallet2", test_dir),
+		client2.clone(),
+		Some(REC_PHRASE),
+	);
+	wallet_proxy.add_wallet("wallet2", client2.get_send_instance(), wallet2.clone());
+
+	// same seed, with its birthday recorded in the seed file
+	let mut wallet3_config = WalletConfig::default();
+	wallet3_config.data_file_dir = format!("{}/wallet3", test_dir);
+	wallet3_config.seed_kdf_memory_kib = Some(64);
+	wallet3_config.seed_kdf_iterations = Some(1);
+	WalletSeed::init_file_with_birthday(
+		&wallet3_config,
+		32,
+		Some(util::ZeroingString::from(REC_PHRASE)),
+		None,
+		"",
+		Some(birthday),
+	)
+	.unwrap();
+	assert_eq!(
+		WalletSeed::file_birthday_height(&wallet3_config).unwrap(),
+		Some(birthday)
+	);
+	let client3 = LocalWalletClient::new("wallet3", wallet_proxy.tx.clone());
+	let wallet3 = test_framework::create_wallet_with_backend(
+		&wallet3_config.data_file_dir,
+		client3.clone(),
+		Some(REC_PHRASE),
+		WalletBackendType::Lmdb,
+	);
+	wallet_proxy.add_wallet("wallet3", client3.get_send_instance(), wallet3.clone());
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let reward = core::consensus::REWARD;
+	let bh = 10u64;
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), bh as usize, false);
+
+	// only blocks from the start height on are scanned
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		api.restore(Some(birthday + 1), None)?;
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.total, (bh - birthday) * reward);
+		Ok(())
+	})?;
+
+	// without a start height or a birthday, a check scans the whole chain
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		api.check_repair(false, None)?;
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.total, bh * reward);
+		Ok(())
+	})?;
+
+	// a restore without a start height starts at the birthday
+	wallet::controller::owner_single_use(wallet3.clone(), |api| {
+		assert!(api.scan_status()?.is_none());
+		api.restore(None, None)?;
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.total, (bh - birthday + 1) * reward);
+
+		// and leaves its final status behind
+		let status = api.scan_status()?.unwrap();
+		assert_eq!(status.kind, ScanKind::Restore);
+		assert_eq!(status.phase, ScanPhase::Complete);
+		assert_eq!(status.percent_complete, 100);
+		assert_eq!(status.outputs_found as u64, bh - birthday + 1);
+		Ok(())
+	})?;
+
+	// restoring into a wallet that isn't empty does nothing, and says so
+	wallet::controller::owner_single_use(wallet3.clone(), |api| {
+		api.restore(None, None)?;
+		let status = api.scan_status()?.unwrap();
+		assert_eq!(status.phase, ScanPhase::Skipped);
+		assert_eq!(status.outputs_found, 0);
+		Ok(())
+	})?;
+
+	Ok(())
+}
+
+#[test]
+fn wallet_restore_from_height() {
+	let test_dir = "test_output/wallet_restore_from_height";
+	if let Err(e) = restore_height_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
@@ -0,0 +1,153 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests for the transaction history rebuilt by a restore
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use self::libwallet::{InitTxArgs, Slate, TxLogEntry, TxLogEntryType};
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use std::fs;
+use std::thread;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+/// Send to a wallet twice, a few blocks apart, then restore it and check
+/// each receipt gets its own entry, stamped with its block's height and time
+fn restore_history_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 =
+		test_framework::create_wallet(&format!("{}/wallet1", test_dir), client1.clone(), None);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	let client2 = LocalWalletClient::new("wallet2", wallet_proxy.tx.clone());
+	let wallet2 =
+		test_framework::create_wallet(&format!("{}/wallet2", test_dir), client2.clone(), None);
+	wallet_proxy.add_wallet("wallet2", client2.get_send_instance(), wallet2.clone());
+
+	// restore target, with wallet 2's seed
+	let dest_dir = format!("{}/wallet2_restore", test_dir);
+	fs::create_dir_all(&dest_dir)?;
+	fs::copy(
+		format!("{}/wallet2/wallet.seed", test_dir),
+		format!("{}/wallet.seed", dest_dir),
+	)?;
+	let client3 = LocalWalletClient::new("wallet2_restore", wallet_proxy.tx.clone());
+	let wallet3 = test_framework::create_wallet(&dest_dir, client3.clone(), None);
+	wallet_proxy.add_wallet(
+		"wallet2_restore",
+		client3.get_send_instance(),
+		wallet3.clone(),
+	);
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 10, false);
+
+	let amount = 60_000_000_000;
+	for i in 1..3 {
+		wallet::controller::owner_single_use(wallet1.clone(), |sender_api| {
+			let args = InitTxArgs {
+				src_acct_name: None,
+				amount: amount * i,
+				minimum_confirmations: 2,
+				max_outputs: 500,
+				num_change_outputs: 1,
+				selection_strategy_is_use_all: true,
+				..Default::default()
+			};
+			let slate_i = sender_api.init_send_tx(args)?;
+			let mut slate: Slate = client1.send_tx_slate_direct("wallet2", &slate_i)?;
+			sender_api.tx_lock_outputs(&slate, 0)?;
+			slate = sender_api.finalize_tx(&slate)?;
+			sender_api.post_tx(&slate.tx, false)?;
+			Ok(())
+		})?;
+		let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), 3, false);
+	}
+
+	wallet::controller::owner_single_use(wallet3.clone(), |api| {
+		api.restore(None, None)?;
+		let (_, txs) = api.retrieve_txs(false, None, None)?;
+		let received: Vec<&TxLogEntry> = txs
+			.iter()
+			.filter(|t| t.tx_type == TxLogEntryType::TxReceived)
+			.collect();
+		assert_eq!(received.len(), 2);
+
+		let (_, outputs) = api.retrieve_outputs(false, false, None)?;
+		assert_eq!(outputs.len(), 2);
+
+		for (i, t) in received.iter().enumerate() {
+			assert_eq!(t.amount_credited, amount * (i as u64 + 1));
+			assert_eq!(t.num_outputs, 1);
+			assert!(t.confirmed);
+
+			// stamped with the block the output was confirmed in
+			let height = t.confirmation_height.unwrap();
+			let header = chain.get_header_by_height(height).unwrap();
+			assert_eq!(t.creation_ts, header.timestamp);
+			assert_eq!(t.confirmation_ts, Some(header.timestamp));
+
+			// and linked to that output
+			let linked: Vec<_> = outputs
+				.iter()
+				.filter(|m| m.output.tx_log_entry == Some(t.id))
+				.collect();
+			assert_eq!(linked.len(), 1);
+			assert_eq!(linked[0].output.height, height);
+			assert_eq!(linked[0].output.value, t.amount_credited);
+		}
+		assert!(
+			received[0].confirmation_height.unwrap() < received[1].confirmation_height.unwrap()
+		);
+		Ok(())
+	})?;
+
+	Ok(())
+}
+
+#[test]
+fn wallet_restore_history() {
+	let test_dir = "test_output/wallet_restore_history";
+	if let Err(e) = restore_history_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
@@ -0,0 +1,188 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! tests for resuming an interrupted wallet restore
+#[macro_use]
+extern crate log;
+extern crate grin_wallet_controller as wallet;
+extern crate grin_wallet_impls as impls;
+extern crate grin_wallet_libwallet as libwallet;
+
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::core::pmmr;
+use self::core::global;
+use self::core::global::ChainTypes;
+use self::keychain::ExtKeychain;
+use self::libwallet::{
+	OutputCommitMapping, ScanCheckpoint, ScanKind, ScannedOutput, TxLogEntryType,
+};
+use impls::test_framework::{self, LocalWalletClient, WalletProxy};
+use std::fs;
+use std::thread;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+}
+
+fn scanned_output(m: &OutputCommitMapping) -> ScannedOutput {
+	ScannedOutput {
+		commit: m.commit,
+		key_id: m.output.key_id.clone(),
+		n_child: m.output.n_child,
+		mmr_index: m.output.mmr_index.unwrap(),
+		value: m.output.value,
+		height: m.output.height,
+		lock_height: m.output.lock_height,
+		is_coinbase: m.output.is_coinbase,
+	}
+}
+
+/// Restore a wallet whose first restore was interrupted half way through
+/// the chain, and check it carries on from the checkpoint
+fn restore_resume_impl(test_dir: &str) -> Result<(), libwallet::Error> {
+	setup(test_dir);
+	let mut wallet_proxy: WalletProxy<LocalWalletClient, ExtKeychain> = WalletProxy::new(test_dir);
+	let chain = wallet_proxy.chain.clone();
+
+	let client1 = LocalWalletClient::new("wallet1", wallet_proxy.tx.clone());
+	let wallet1 =
+		test_framework::create_wallet(&format!("{}/wallet1", test_dir), client1.clone(), None);
+	wallet_proxy.add_wallet("wallet1", client1.get_send_instance(), wallet1.clone());
+
+	// restore target, with the same seed
+	let dest_dir = format!("{}/wallet1_restore", test_dir);
+	fs::create_dir_all(&dest_dir)?;
+	fs::copy(
+		format!("{}/wallet1/wallet.seed", test_dir),
+		format!("{}/wallet.seed", dest_dir),
+	)?;
+	let client2 = LocalWalletClient::new("wallet1_restore", wallet_proxy.tx.clone());
+	let wallet2 = test_framework::create_wallet(&dest_dir, client2.clone(), None);
+	wallet_proxy.add_wallet(
+		"wallet1_restore",
+		client2.get_send_instance(),
+		wallet2.clone(),
+	);
+
+	thread::spawn(move || {
+		if let Err(e) = wallet_proxy.run() {
+			error!("Wallet Proxy error: {}", e);
+		}
+	});
+
+	let reward = core::consensus::REWARD;
+	let bh = 10u64;
+	let _ = test_framework::award_blocks_to_wallet(&chain, wallet1.clone(), bh as usize, false);
+
+	let mut outputs = vec![];
+	wallet::controller::owner_single_use(wallet1.clone(), |api| {
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.total, bh * reward);
+		outputs = api.retrieve_outputs(false, true, None)?.1;
+		Ok(())
+	})?;
+	// the wallet doesn't know the PMMR positions of outputs it didn't
+	// restore, so take them from the chain
+	for m in outputs.iter_mut() {
+		m.output.mmr_index = Some(chain.get_output_pos(&m.commit).unwrap());
+	}
+	outputs.sort_by_key(|m| m.output.mmr_index);
+
+	// the interrupted restore got half way, but lost one of the outputs it
+	// found, which a resumed restore won't go back for
+	let half = outputs.len() / 2;
+	let received_log_id;
+	{
+		let mut w = wallet2.lock();
+		w.open_with_credentials()?;
+		{
+			let mut batch = w.batch()?;
+			for m in outputs[1..half].iter() {
+				batch.save_scanned_output(&scanned_output(m))?;
+			}
+			// and had restored one of them, taken as received rather than
+			// mined, without getting as far as writing its tx log entry
+			let mut received = outputs[1].output.clone();
+			received.is_coinbase = false;
+			received_log_id = batch.next_tx_log_id(&received.root_key_id)?;
+			received.tx_log_entry = Some(received_log_id);
+			batch.save(received)?;
+			// checkpoints hold insertion indices rather than PMMR positions
+			let last_scanned = pmmr::n_leaves(outputs[half - 1].output.mmr_index.unwrap());
+			batch.save_scan_checkpoint(&ScanCheckpoint {
+				kind: ScanKind::Restore,
+				start_pmmr_index: 1,
+				next_pmmr_index: last_scanned + 1,
+				highest_pmmr_index: last_scanned,
+			})?;
+			batch.commit()?;
+		}
+		w.close()?;
+	}
+
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		api.restore(None, None)?;
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.total, (bh - 1) * reward);
+
+		// every restored output has its tx log entry, the one the
+		// interruption kept from being written included
+		let (_, txs) = api.retrieve_txs(true, None, None)?;
+		let (_, restored) = api.retrieve_outputs(false, true, None)?;
+		for m in restored.iter() {
+			assert!(txs.iter().any(|t| Some(t.id) == m.output.tx_log_entry));
+		}
+		let received = txs.iter().find(|t| t.id == received_log_id).unwrap();
+		assert_eq!(received.tx_type, TxLogEntryType::TxReceived);
+		assert_eq!(received.amount_credited, reward);
+		assert_eq!(received.num_outputs, 1);
+		Ok(())
+	})?;
+
+	// a finished restore leaves no checkpoint behind
+	{
+		let mut w = wallet2.lock();
+		w.open_with_credentials()?;
+		assert!(w.scan_checkpoint()?.is_none());
+		assert_eq!(w.scanned_output_iter()?.count(), 0);
+		w.close()?;
+	}
+
+	// so a check scans the whole chain, and finds the lost output
+	wallet::controller::owner_single_use(wallet2.clone(), |api| {
+		api.check_repair(false, None)?;
+		let (_, info) = api.retrieve_summary_info(true, 1)?;
+		assert_eq!(info.total, bh * reward);
+		Ok(())
+	})?;
+
+	Ok(())
+}
+
+#[test]
+fn wallet_restore_resume() {
+	let test_dir = "test_output/wallet_restore_resume";
+	if let Err(e) = restore_resume_impl(test_dir) {
+		panic!("Libwallet Error: {} - {}", e, e.backtrace().unwrap());
+	}
+	clean_output_dir(test_dir);
+}
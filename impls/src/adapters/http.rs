@@ -16,6 +16,7 @@
 
 /// HTTP Wallet 'plugin' implementation
 use crate::api;
+use crate::client;
 use crate::libwallet::{Error, ErrorKind, Slate};
 use crate::WalletCommAdapter;
 use config::WalletConfig;
@@ -23,12 +24,16 @@ use serde::Serialize;
 use std::collections::HashMap;
 
 #[derive(Clone)]
-pub struct HTTPWalletCommAdapter {}
+pub struct HTTPWalletCommAdapter {
+	/// Address of the SOCKS5 proxy slates are sent through, if any
+	socks_proxy: Option<String>,
+}
 
 impl HTTPWalletCommAdapter {
-	/// Create
-	pub fn new() -> Box<dyn WalletCommAdapter> {
-		Box::new(HTTPWalletCommAdapter {})
+	/// Create, sending through the SOCKS5 proxy at the given address
+	/// (host:port) if any
+	pub fn new(socks_proxy: Option<String>) -> Box<dyn WalletCommAdapter> {
+		Box::new(HTTPWalletCommAdapter { socks_proxy })
 	}
 }
 
@@ -48,7 +53,13 @@ impl WalletCommAdapter for HTTPWalletCommAdapter {
 		}
 		let url = format!("{}/v1/wallet/foreign/receive_tx", dest);
 		debug!("Posting transaction slate to {}", url);
-		let res: String = post(url.as_str(), None, &slate).map_err(|e| {
+		let res: String = post(
+			url.as_str(),
+			None,
+			self.socks_proxy.as_ref().map(|p| p.as_str()),
+			&slate,
+		)
+		.map_err(|e| {
 			let report = format!("Posting transaction slate (is recipient listening?): {}", e);
 			error!("{}", report);
 			ErrorKind::ClientCallback(report)
@@ -78,11 +89,14 @@ impl WalletCommAdapter for HTTPWalletCommAdapter {
 	}
 }
 
-pub fn post<IN>(url: &str, api_secret: Option<String>, input: &IN) -> Result<String, api::Error>
+pub fn post<IN>(
+	url: &str,
+	api_secret: Option<String>,
+	socks_proxy: Option<&str>,
+	input: &IN,
+) -> Result<String, api::Error>
 where
 	IN: Serialize,
 {
-	let req = api::client::create_post_request(url, api_secret, input)?;
-	let res = api::client::send_request(req)?;
-	Ok(res)
+	client::post(url, api_secret, socks_proxy, input)
 }
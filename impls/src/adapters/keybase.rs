@@ -336,7 +336,11 @@ impl WalletCommAdapter for KeybaseWalletCommAdapter {
 		account: &str,
 		node_api_secret: Option<String>,
 	) -> Result<(), Error> {
-		let node_client = HTTPNodeClient::new(&config.check_node_api_http_addr, node_api_secret);
+		let node_client = HTTPNodeClient::new(
+			&config.check_node_api_http_addr,
+			node_api_secret,
+			config.socks_proxy_addr.clone(),
+		);
 		let wallet = instantiate_wallet(config.clone(), node_client, passphrase, account)
 			.context(ErrorKind::WalletSeedDecryption)?;
 
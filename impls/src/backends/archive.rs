@@ -0,0 +1,317 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of everything a wallet stores to a single encrypted archive file,
+//! and import of that archive into a wallet with the same seed, so wallets
+//! can be moved between hosts with their history intact
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+
+use uuid::Uuid;
+
+use crate::core::core::Transaction;
+use crate::keychain::{Identifier, Keychain};
+use crate::libwallet::{
+	AcctPathMapping, ArchiveSummary, Context, Error, ErrorKind, NodeClient, OutputData, TxLogEntry,
+	TxLogEntryType, WalletBackend,
+};
+use crate::util;
+
+use super::encryption::DbCipher;
+
+/// Version of the archive format written by this wallet
+pub const WALLET_ARCHIVE_VERSION: u32 = 1;
+
+/// Label the archive key is derived under, from the wallet's root key
+const ARCHIVE_KEY_LABEL: &'static str = "wallet_archive";
+
+/// Stored transaction file extension, as written by `store_tx`
+const STORED_TX_EXT: &'static str = ".grintx";
+
+/// The archive file as written to disk. Only the version and the seed
+/// fingerprint are readable without the seed.
+#[derive(Serialize, Deserialize)]
+struct ArchiveFile {
+	version: u32,
+	/// Identifies the seed the archive was exported from, without revealing
+	/// anything about it
+	seed_fingerprint: String,
+	/// Hex of the encrypted `ArchiveContents`
+	contents: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveAccount {
+	mapping: AcctPathMapping,
+	child_index: u32,
+	last_confirmed_height: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveContext {
+	slate_id: Uuid,
+	participant_id: usize,
+	context: Context,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveStoredTx {
+	slate_id: String,
+	tx: Transaction,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveContents {
+	accounts: Vec<ArchiveAccount>,
+	outputs: Vec<OutputData>,
+	tx_log: Vec<TxLogEntry>,
+	private_contexts: Vec<ArchiveContext>,
+	stored_txs: Vec<ArchiveStoredTx>,
+}
+
+impl ArchiveContents {
+	fn summary(&self) -> ArchiveSummary {
+		ArchiveSummary {
+			accounts: self.accounts.len(),
+			outputs: self.outputs.len(),
+			tx_log_entries: self.tx_log.len(),
+			private_contexts: self.private_contexts.len(),
+			stored_txs: self.stored_txs.len(),
+		}
+	}
+}
+
+fn archive_err(msg: &str) -> Error {
+	ErrorKind::Archive(msg.to_owned()).into()
+}
+
+fn seed_fingerprint(cipher: &DbCipher) -> String {
+	util::to_hex(cipher.keyed_hash(b"seed_fingerprint"))
+}
+
+/// Associated data binding the encrypted contents to the archive header
+fn archive_ad(version: u32, seed_fingerprint: &str) -> Vec<u8> {
+	format!("{}:{}", version, seed_fingerprint).into_bytes()
+}
+
+/// Child index and confirmed height of each account
+fn account_state<T: ?Sized, C, K>(
+	wallet: &mut T,
+	mappings: Vec<AcctPathMapping>,
+) -> Result<Vec<ArchiveAccount>, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let mut res = vec![];
+	for mapping in mappings {
+		wallet.set_parent_key_id(mapping.path.clone());
+		res.push(ArchiveAccount {
+			child_index: wallet.current_child_index()?,
+			last_confirmed_height: wallet.last_confirmed_height()?,
+			mapping,
+		});
+	}
+	Ok(res)
+}
+
+/// Write everything the wallet stores to an encrypted archive at `path`.
+/// The wallet must be open.
+pub fn export_wallet<T: ?Sized, C, K>(wallet: &mut T, path: &str) -> Result<ArchiveSummary, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let cipher = DbCipher::from_keychain_with_label(wallet.keychain(), ARCHIVE_KEY_LABEL)?;
+
+	let parent_key_id = wallet.parent_key_id();
+	let mappings: Vec<AcctPathMapping> = wallet.acct_path_iter()?.collect();
+	let accounts = account_state(wallet, mappings);
+	wallet.set_parent_key_id(parent_key_id);
+	let accounts = accounts?;
+
+	let outputs: Vec<OutputData> = wallet.iter()?.collect();
+	let tx_log: Vec<TxLogEntry> = wallet.tx_log_iter()?.collect();
+
+	let context_keys: Vec<(Vec<u8>, usize)> = wallet.private_context_iter()?.collect();
+	let mut private_contexts = vec![];
+	for (slate_id, participant_id) in context_keys {
+		let context = wallet.get_private_context(&slate_id, participant_id)?;
+		let slate_id = Uuid::from_slice(&slate_id).map_err(|e| {
+			ErrorKind::Archive(format!("Private context with invalid slate id: {}", e))
+		})?;
+		private_contexts.push(ArchiveContext {
+			slate_id,
+			participant_id,
+			context,
+		});
+	}
+
+	let mut stored_txs = vec![];
+	for entry in tx_log.iter() {
+		if let Some(ref file_name) = entry.stored_tx {
+			match wallet.get_stored_tx(entry) {
+				Ok(Some(tx)) => stored_txs.push(ArchiveStoredTx {
+					slate_id: file_name.trim_end_matches(STORED_TX_EXT).to_owned(),
+					tx,
+				}),
+				Ok(None) => {}
+				Err(e) => warn!("Stored transaction {} not exported: {}", file_name, e),
+			}
+		}
+	}
+
+	let contents = ArchiveContents {
+		accounts,
+		outputs,
+		tx_log,
+		private_contexts,
+		stored_txs,
+	};
+	let data = serde_json::to_vec(&contents)
+		.map_err(|e| ErrorKind::Archive(format!("Error serializing wallet: {}", e)))?;
+	let seed_fingerprint = seed_fingerprint(&cipher);
+	let enc_data = cipher.encrypt(
+		&archive_ad(WALLET_ARCHIVE_VERSION, &seed_fingerprint),
+		&data,
+	)?;
+	let archive = ArchiveFile {
+		version: WALLET_ARCHIVE_VERSION,
+		seed_fingerprint,
+		contents: util::to_hex(enc_data),
+	};
+	let archive_json = serde_json::to_string_pretty(&archive)
+		.map_err(|e| ErrorKind::Archive(format!("Error serializing archive: {}", e)))?;
+	let mut file = File::create(path)
+		.map_err(|e| ErrorKind::Archive(format!("Error creating {}: {}", path, e)))?;
+	file.write_all(archive_json.as_bytes())
+		.map_err(|e| ErrorKind::Archive(format!("Error writing {}: {}", path, e)))?;
+	info!("Wallet exported to {}", path);
+	Ok(contents.summary())
+}
+
+/// Import an archive written by `export_wallet`. The archive must have been
+/// exported from a wallet with the same seed, and the wallet imported into
+/// mustn't have any outputs or transactions of its own yet. The wallet must
+/// be open. Stored transactions are written before the batch holding
+/// everything else, and removed again if it fails, so a failed import
+/// leaves the wallet as it was.
+pub fn import_wallet<T: ?Sized, C, K>(wallet: &mut T, path: &str) -> Result<ArchiveSummary, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let archive_json = fs::read_to_string(path)
+		.map_err(|e| ErrorKind::Archive(format!("Error reading {}: {}", path, e)))?;
+	let archive: ArchiveFile = serde_json::from_str(&archive_json)
+		.map_err(|e| ErrorKind::Archive(format!("{} is not a wallet archive: {}", path, e)))?;
+	if archive.version > WALLET_ARCHIVE_VERSION {
+		return Err(ErrorKind::Archive(format!(
+			"Archive version {} is newer than the latest supported version {}",
+			archive.version, WALLET_ARCHIVE_VERSION
+		))
+		.into());
+	}
+
+	let cipher = DbCipher::from_keychain_with_label(wallet.keychain(), ARCHIVE_KEY_LABEL)?;
+	if archive.seed_fingerprint != seed_fingerprint(&cipher) {
+		return Err(archive_err(
+			"Archive was exported from a wallet with a different seed",
+		));
+	}
+	let enc_data = util::from_hex(archive.contents.clone())
+		.map_err(|_| archive_err("Archive contents are not valid hex"))?;
+	let data = cipher
+		.decrypt(
+			&archive_ad(archive.version, &archive.seed_fingerprint),
+			&enc_data,
+		)
+		.map_err(|_| archive_err("Archive contents could not be decrypted"))?;
+	let contents: ArchiveContents = serde_json::from_slice(&data)
+		.map_err(|e| ErrorKind::Archive(format!("Unreadable archive contents: {}", e)))?;
+
+	if wallet.iter()?.next().is_some() || wallet.tx_log_iter()?.next().is_some() {
+		return Err(archive_err(
+			"Wallet already has outputs or transactions, import into a newly created wallet",
+		));
+	}
+
+	// the files written so far, to remove again if the import fails
+	let mut written = vec![];
+	let mut res = Ok(());
+	for s in contents.stored_txs.iter() {
+		res = wallet.store_tx(&s.slate_id, &s.tx);
+		if res.is_err() {
+			break;
+		}
+		written.push(format!("{}{}", s.slate_id, STORED_TX_EXT));
+	}
+	if res.is_ok() {
+		res = save_contents(wallet, &contents);
+	}
+	if let Err(e) = res {
+		for file_name in written {
+			// removal only goes by the entry's stored transaction
+			let mut entry = TxLogEntry::new(wallet.parent_key_id(), TxLogEntryType::TxSent, 0);
+			entry.stored_tx = Some(file_name);
+			if let Err(e) = wallet.remove_stored_tx(&entry, false) {
+				error!("Removing stored transaction of failed import: {}", e);
+			}
+		}
+		return Err(e);
+	}
+	info!("Wallet imported from {}", path);
+	Ok(contents.summary())
+}
+
+/// Save the accounts, outputs, tx log and contexts of an archive in a
+/// single batch
+fn save_contents<T: ?Sized, C, K>(wallet: &mut T, contents: &ArchiveContents) -> Result<(), Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let mut batch = wallet.batch()?;
+	for a in contents.accounts.iter() {
+		batch.save_acct_path(a.mapping.clone())?;
+		batch.save_child_index(&a.mapping.path, a.child_index)?;
+		batch.save_last_confirmed_height(&a.mapping.path, a.last_confirmed_height)?;
+	}
+	for out in contents.outputs.iter() {
+		batch.save(out.clone())?;
+	}
+	let mut last_tx_log_ids: HashMap<Identifier, u32> = HashMap::new();
+	for entry in contents.tx_log.iter() {
+		let last_id = last_tx_log_ids
+			.entry(entry.parent_key_id.clone())
+			.or_insert(0);
+		*last_id = (*last_id).max(entry.id);
+		batch.save_tx_log_entry(entry.clone(), &entry.parent_key_id)?;
+	}
+	// move each account's tx log id counter past the imported entries
+	for (parent_key_id, last_id) in last_tx_log_ids.iter() {
+		batch.save_next_tx_log_id(parent_key_id, last_id + 1)?;
+	}
+	for c in contents.private_contexts.iter() {
+		batch.save_private_context(c.slate_id.as_bytes(), c.participant_id, &c.context)?;
+	}
+	batch.commit()?;
+	Ok(())
+}
@@ -0,0 +1,127 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Authenticated encryption of wallet database values and stored
+//! transaction files, keyed from the wallet seed
+
+use rand::{thread_rng, Rng};
+use ring::aead;
+
+use crate::blake2::blake2b::{blake2b, Blake2b};
+use crate::core::ser;
+use crate::keychain::Keychain;
+use crate::libwallet::{Error, ErrorKind};
+use crate::util::secp::constants::SECRET_KEY_SIZE;
+
+const NONCE_LEN: usize = 12;
+
+/// An encrypted database value: nonce followed by ciphertext and tag
+pub struct EncryptedRecord(pub Vec<u8>);
+
+impl ser::Writeable for EncryptedRecord {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.0)
+	}
+}
+
+impl ser::Readable for EncryptedRecord {
+	fn read(reader: &mut dyn ser::Reader) -> Result<EncryptedRecord, ser::Error> {
+		Ok(EncryptedRecord(reader.read_bytes_len_prefix()?))
+	}
+}
+
+/// Encrypts and decrypts values with ChaCha20-Poly1305 under a key derived
+/// from the wallet's root key. The database key of each value is used as
+/// associated data, so values can't be moved between keys undetected.
+#[derive(Clone)]
+pub struct DbCipher {
+	key: [u8; SECRET_KEY_SIZE],
+}
+
+impl DbCipher {
+	/// Derive the cipher for a wallet, h(root_key|"db_encryption")
+	pub fn from_keychain<K>(keychain: &K) -> Result<DbCipher, Error>
+	where
+		K: Keychain,
+	{
+		DbCipher::from_keychain_with_label(keychain, "db_encryption")
+	}
+
+	/// Derive a cipher for another use of the wallet's root key,
+	/// h(root_key|label)
+	pub fn from_keychain_with_label<K>(keychain: &K, label: &str) -> Result<DbCipher, Error>
+	where
+		K: Keychain,
+	{
+		let root_key = keychain.derive_key(0, &K::root_key_id())?;
+		let mut hasher = Blake2b::new(SECRET_KEY_SIZE);
+		hasher.update(&root_key.0[..]);
+		hasher.update(&label.as_bytes()[..]);
+		let mut key = [0; SECRET_KEY_SIZE];
+		key.copy_from_slice(&hasher.finalize().as_bytes()[0..SECRET_KEY_SIZE]);
+		Ok(DbCipher { key })
+	}
+
+	/// Encrypt raw bytes, binding them to `ad`
+	pub fn encrypt(&self, ad: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+		let nonce: [u8; NONCE_LEN] = thread_rng().gen();
+		let suffix_len = aead::CHACHA20_POLY1305.tag_len();
+		let mut enc_bytes = data.to_vec();
+		enc_bytes.extend(vec![0; suffix_len]);
+		let sealing_key = aead::SealingKey::new(&aead::CHACHA20_POLY1305, &self.key)
+			.map_err(|_| ErrorKind::Backend("Error creating db sealing key".to_owned()))?;
+		aead::seal_in_place(&sealing_key, &nonce, ad, &mut enc_bytes, suffix_len)
+			.map_err(|_| ErrorKind::Backend("Error encrypting db value".to_owned()))?;
+		let mut res = nonce.to_vec();
+		res.extend(enc_bytes);
+		Ok(res)
+	}
+
+	/// Decrypt bytes produced by `encrypt` with the same `ad`
+	pub fn decrypt(&self, ad: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+		if data.len() < NONCE_LEN {
+			return Err(ErrorKind::Backend("Encrypted db value too short".to_owned()).into());
+		}
+		let (nonce, enc) = data.split_at(NONCE_LEN);
+		let mut enc_bytes = enc.to_vec();
+		let opening_key = aead::OpeningKey::new(&aead::CHACHA20_POLY1305, &self.key)
+			.map_err(|_| ErrorKind::Backend("Error creating db opening key".to_owned()))?;
+		let decrypted = aead::open_in_place(&opening_key, nonce, ad, 0, &mut enc_bytes)
+			.map_err(|_| ErrorKind::Backend("Error decrypting db value".to_owned()))?;
+		Ok(decrypted.to_vec())
+	}
+
+	/// Serialize and encrypt a value to be stored under `key`
+	pub fn seal<T: ser::Writeable>(&self, key: &[u8], value: &T) -> Result<EncryptedRecord, Error> {
+		let data = ser::ser_vec(value)
+			.map_err(|e| ErrorKind::Backend(format!("Error serializing db value: {}", e)))?;
+		Ok(EncryptedRecord(self.encrypt(key, &data)?))
+	}
+
+	/// Decrypt and deserialize a value stored under `key`
+	pub fn open<T: ser::Readable>(&self, key: &[u8], record: &EncryptedRecord) -> Result<T, Error> {
+		let data = self.decrypt(key, &record.0)?;
+		let res = ser::deserialize(&mut &data[..])
+			.map_err(|e| ErrorKind::Backend(format!("Error deserializing db value: {}", e)))?;
+		Ok(res)
+	}
+
+	/// Keyed hash, used in place of plaintext values (such as account
+	/// labels) that would otherwise appear in database keys
+	pub fn keyed_hash(&self, data: &[u8]) -> Vec<u8> {
+		blake2b(SECRET_KEY_SIZE, &self.key, data)
+			.as_bytes()
+			.to_vec()
+	}
+}
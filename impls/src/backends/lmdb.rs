@@ -13,27 +13,22 @@
 // limitations under the License.
 
 use std::cell::RefCell;
+use std::sync::mpsc::Sender;
 use std::{fs, path};
 
-// for writing storedtransaction files
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
-
 use failure::ResultExt;
 use uuid::Uuid;
 
-use crate::blake2::blake2b::Blake2b;
-
 use crate::keychain::{ChildNumber, ExtKeychain, Identifier, Keychain};
-use crate::store::{self, option_to_not_found, to_key, to_key_u64};
+use crate::store::{self, to_key, to_key_u64};
 
 use crate::core::core::Transaction;
-use crate::core::{global, ser};
+use crate::core::ser;
 use crate::libwallet::{check_repair, restore};
 use crate::libwallet::{
-	AcctPathMapping, Context, Error, ErrorKind, NodeClient, OutputData, TxLogEntry, WalletBackend,
-	WalletOutputBatch,
+	AcctPathMapping, ArchiveSummary, BlockHeaderInfo, CheckDiscrepancy, Context, Error, ErrorKind,
+	NodeClient, OutputData, RetentionPolicy, ScanCheckpoint, ScanStatus, ScannedOutput, TxLogEntry,
+	WalletBackend, WalletOutputBatch,
 };
 use crate::util;
 use crate::util::secp::constants::SECRET_KEY_SIZE;
@@ -41,62 +36,303 @@ use crate::util::ZeroingString;
 use crate::WalletSeed;
 use config::WalletConfig;
 
-pub const DB_DIR: &'static str = "db";
-pub const TX_SAVE_DIR: &'static str = "saved_txs";
+use super::archive::{export_wallet, import_wallet};
+use super::encryption::{DbCipher, EncryptedRecord};
+use super::lock::{LockMode, WalletDirLock};
+use super::migrations::{
+	apply_migrations, MigrationRecord, MigrationReport, RecordType, CURRENT_SCHEMA_VERSION,
+};
+use super::{
+	encrypt_stored_tx_files, keychain_from_seed, private_ctx_xor_keys, read_stored_tx,
+	remove_stored_tx_file, retention_policy, seed_keychain, write_stored_tx, DB_DIR, TX_SAVE_DIR,
+};
 
 const OUTPUT_PREFIX: u8 = 'o' as u8;
 const DERIV_PREFIX: u8 = 'd' as u8;
 const CONFIRMED_HEIGHT_PREFIX: u8 = 'c' as u8;
+const CONFIRMED_BLOCK_PREFIX: u8 = 'b' as u8;
 const PRIVATE_TX_CONTEXT_PREFIX: u8 = 'p' as u8;
 const TX_LOG_ENTRY_PREFIX: u8 = 't' as u8;
 const TX_LOG_ID_PREFIX: u8 = 'i' as u8;
 const ACCOUNT_PATH_MAPPING_PREFIX: u8 = 'a' as u8;
+const SCHEMA_VERSION_PREFIX: u8 = 'v' as u8;
+const ENCRYPTION_MARKER_PREFIX: u8 = 'e' as u8;
+const SCAN_CHECKPOINT_PREFIX: u8 = 'r' as u8;
+const SCANNED_OUTPUT_PREFIX: u8 = 's' as u8;
+
+/// Prefixes of all records stored as JSON, which migrations may rewrite
+const JSON_RECORD_PREFIXES: [(u8, RecordType); 4] = [
+	(OUTPUT_PREFIX, RecordType::Output),
+	(TX_LOG_ENTRY_PREFIX, RecordType::TxLogEntry),
+	(PRIVATE_TX_CONTEXT_PREFIX, RecordType::PrivateContext),
+	(ACCOUNT_PATH_MAPPING_PREFIX, RecordType::AcctPathMapping),
+];
+
+/// A JSON record as written by the wallet types' `Writeable` impls, read
+/// without deserializing it into the type itself
+struct RawRecord(Vec<u8>);
+
+impl ser::Writeable for RawRecord {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.0)
+	}
+}
 
-/// test to see if database files exist in the current directory. If so,
-/// use a DB backend for all operations
-pub fn wallet_db_exists(config: WalletConfig) -> bool {
-	let db_path = path::Path::new(&config.data_file_dir).join(DB_DIR);
-	db_path.exists()
+impl ser::Readable for RawRecord {
+	fn read(reader: &mut dyn ser::Reader) -> Result<RawRecord, ser::Error> {
+		Ok(RawRecord(reader.read_bytes_len_prefix()?))
+	}
+}
+
+fn schema_version_key() -> Vec<u8> {
+	to_key(SCHEMA_VERSION_PREFIX, &mut "schema".as_bytes().to_vec())
+}
+
+fn scan_checkpoint_key() -> Vec<u8> {
+	to_key(
+		SCAN_CHECKPOINT_PREFIX,
+		&mut "checkpoint".as_bytes().to_vec(),
+	)
+}
+
+/// Scanned outputs are keyed by PMMR index alone, so they iterate in
+/// PMMR order
+fn scanned_output_key(mmr_index: u64) -> Vec<u8> {
+	to_key_u64(SCANNED_OUTPUT_PREFIX, &mut vec![], mmr_index)
 }
 
-/// Helper to derive XOR keys for storing private transaction keys in the DB
-/// (blind_xor_key, nonce_xor_key)
-fn private_ctx_xor_keys<K>(
-	keychain: &K,
-	slate_id: &[u8],
-) -> Result<([u8; SECRET_KEY_SIZE], [u8; SECRET_KEY_SIZE]), Error>
+fn encryption_marker_key() -> Vec<u8> {
+	to_key(
+		ENCRYPTION_MARKER_PREFIX,
+		&mut "encrypted".as_bytes().to_vec(),
+	)
+}
+
+/// Account mappings are keyed by a hash of their label, so labels don't
+/// appear in plaintext in the database
+fn acct_key(cipher: &DbCipher, label: &str) -> Vec<u8> {
+	to_key(
+		ACCOUNT_PATH_MAPPING_PREFIX,
+		&mut cipher.keyed_hash(label.as_bytes()),
+	)
+}
+
+fn not_found(field_name: &str) -> Error {
+	store::Error::NotFoundErr(field_name.to_owned()).into()
+}
+
+/// Whether the values in the store have been converted to encrypted form
+fn is_encrypted(store: &store::Store) -> Result<bool, Error> {
+	Ok(store.get_ser::<u8>(&encryption_marker_key())?.is_some())
+}
+
+/// Decrypt a stored value, if present
+fn get_sealed<T: ser::Readable>(
+	record: Option<EncryptedRecord>,
+	cipher: &DbCipher,
+	key: &[u8],
+) -> Result<Option<T>, Error> {
+	match record {
+		Some(r) => Ok(Some(cipher.open(key, &r)?)),
+		None => Ok(None),
+	}
+}
+
+/// Decrypt all values from a store iterator. A value that fails to
+/// authenticate is an error rather than left out, so a corrupt database or
+/// the wrong key can't pass for an empty wallet.
+fn open_records<T, I>(records: I, cipher: &DbCipher) -> Result<Box<dyn Iterator<Item = T>>, Error>
 where
-	K: Keychain,
+	T: ser::Readable + 'static,
+	I: Iterator<Item = (Vec<u8>, EncryptedRecord)>,
 {
-	let root_key = keychain.derive_key(0, &K::root_key_id())?;
-
-	// derive XOR values for storing secret values in DB
-	// h(root_key|slate_id|"blind")
-	let mut hasher = Blake2b::new(SECRET_KEY_SIZE);
-	hasher.update(&root_key.0[..]);
-	hasher.update(&slate_id[..]);
-	hasher.update(&"blind".as_bytes()[..]);
-	let blind_xor_key = hasher.finalize();
-	let mut ret_blind = [0; SECRET_KEY_SIZE];
-	ret_blind.copy_from_slice(&blind_xor_key.as_bytes()[0..SECRET_KEY_SIZE]);
-
-	// h(root_key|slate_id|"nonce")
-	let mut hasher = Blake2b::new(SECRET_KEY_SIZE);
-	hasher.update(&root_key.0[..]);
-	hasher.update(&slate_id[..]);
-	hasher.update(&"nonce".as_bytes()[..]);
-	let nonce_xor_key = hasher.finalize();
-	let mut ret_nonce = [0; SECRET_KEY_SIZE];
-	ret_nonce.copy_from_slice(&nonce_xor_key.as_bytes()[0..SECRET_KEY_SIZE]);
-
-	Ok((ret_blind, ret_nonce))
+	let mut values = vec![];
+	for (key, record) in records {
+		let value = cipher
+			.open(&key, &record)
+			.map_err(|e| ErrorKind::Backend(format!("Unreadable wallet database record: {}", e)))?;
+		values.push(value);
+	}
+	Ok(Box::new(values.into_iter()))
+}
+
+/// Read the raw JSON records under a prefix, decrypting them if needed
+fn read_raw_records(
+	store: &store::Store,
+	cipher: Option<&DbCipher>,
+	prefix: u8,
+) -> Result<Vec<(Vec<u8>, RawRecord)>, Error> {
+	let mut res = vec![];
+	match cipher {
+		Some(c) => {
+			for (key, record) in store.iter::<EncryptedRecord>(&[prefix])? {
+				let raw = c.open(&key, &record)?;
+				res.push((key, raw));
+			}
+		}
+		None => {
+			for (key, raw) in store.iter::<RawRecord>(&[prefix])? {
+				res.push((key, raw));
+			}
+		}
+	}
+	Ok(res)
+}
+
+/// Bring the store up to the current schema version, running each pending
+/// migration in turn. All changes are written in a single batch along with
+/// the new version, and nothing is written at all if `dry_run` is set.
+fn migrate_store(
+	store: &store::Store,
+	cipher: Option<&DbCipher>,
+	dry_run: bool,
+) -> Result<MigrationReport, Error> {
+	let version_key = schema_version_key();
+	let from_version: u32 = store.get_ser(&version_key)?.unwrap_or(0);
+	if from_version > CURRENT_SCHEMA_VERSION {
+		return Err(ErrorKind::Backend(format!(
+			"Wallet database schema version {} is newer than the latest supported version {}",
+			from_version, CURRENT_SCHEMA_VERSION
+		))
+		.into());
+	}
+
+	let mut records = vec![];
+	for (prefix, record_type) in JSON_RECORD_PREFIXES.iter() {
+		for (key, raw) in read_raw_records(store, cipher, *prefix)? {
+			let value = serde_json::from_slice(&raw.0).map_err(|e| {
+				ErrorKind::Format(format!("Unreadable {:?} record: {}", record_type, e))
+			})?;
+			records.push(MigrationRecord {
+				record_type: *record_type,
+				key,
+				value,
+				changed: false,
+			});
+		}
+	}
+
+	let report = apply_migrations(from_version, &mut records, dry_run);
+	if dry_run || report.is_current() {
+		return Ok(report);
+	}
+
+	let batch = store.batch()?;
+	for r in records.iter().filter(|r| r.changed) {
+		let data = serde_json::to_vec(&r.value).map_err(|e| ErrorKind::Format(format!("{}", e)))?;
+		match cipher {
+			Some(c) => batch.put_ser(&r.key, &c.seal(&r.key, &RawRecord(data))?)?,
+			None => batch.put_ser(&r.key, &RawRecord(data))?,
+		}
+	}
+	batch.put_ser(&version_key, &CURRENT_SCHEMA_VERSION)?;
+	batch.commit()?;
+	info!(
+		"Migrated wallet database from schema version {} to {}",
+		report.from_version, report.to_version
+	);
+	Ok(report)
+}
+
+/// Read every value under a prefix and queue its encrypted form in `batch`
+fn seal_prefix<T>(
+	store: &store::Store,
+	batch: &store::Batch<'_>,
+	cipher: &DbCipher,
+	prefix: u8,
+) -> Result<(), Error>
+where
+	T: ser::Readable + ser::Writeable,
+{
+	let records: Vec<(Vec<u8>, T)> = store.iter(&[prefix])?.collect();
+	for (key, value) in records {
+		batch.put_ser(&key, &cipher.seal(&key, &value)?)?;
+	}
+	Ok(())
+}
+
+/// One-time conversion of a wallet written before database values were
+/// encrypted. Stored transaction files are converted first, as they can be
+/// read in either form, then every value is rewritten in a single batch
+/// along with the marker recording that the conversion is done.
+fn encrypt_plaintext_store(
+	store: &store::Store,
+	cipher: &DbCipher,
+	config: &WalletConfig,
+) -> Result<(), Error> {
+	if is_encrypted(store)? {
+		return Ok(());
+	}
+	encrypt_stored_tx_files(config, cipher)?;
+
+	let accounts = read_raw_records(store, None, ACCOUNT_PATH_MAPPING_PREFIX)?;
+	let batch = store.batch()?;
+	seal_prefix::<RawRecord>(store, &batch, cipher, OUTPUT_PREFIX)?;
+	seal_prefix::<RawRecord>(store, &batch, cipher, TX_LOG_ENTRY_PREFIX)?;
+	seal_prefix::<RawRecord>(store, &batch, cipher, PRIVATE_TX_CONTEXT_PREFIX)?;
+	seal_prefix::<u32>(store, &batch, cipher, DERIV_PREFIX)?;
+	seal_prefix::<u32>(store, &batch, cipher, TX_LOG_ID_PREFIX)?;
+	seal_prefix::<u64>(store, &batch, cipher, CONFIRMED_HEIGHT_PREFIX)?;
+	seal_prefix::<BlockHeaderInfo>(store, &batch, cipher, CONFIRMED_BLOCK_PREFIX)?;
+	for (key, raw) in accounts {
+		let mapping: AcctPathMapping = serde_json::from_slice(&raw.0)
+			.map_err(|e| ErrorKind::Format(format!("Unreadable account record: {}", e)))?;
+		let new_key = acct_key(cipher, &mapping.label);
+		batch.delete(&key)?;
+		batch.put_ser(&new_key, &cipher.seal(&new_key, &raw)?)?;
+	}
+	batch.put_ser(&encryption_marker_key(), &1u8)?;
+	batch.commit()?;
+	info!("Wallet database values encrypted");
+	Ok(())
+}
+
+/// Migrate the LMDB wallet database in the configured data directory to the
+/// current schema version, or only report what would change if `dry_run`
+/// is set. The passphrase is only needed once the database is encrypted.
+pub fn migrate_wallet_db(
+	config: &WalletConfig,
+	passphrase: &str,
+	dry_run: bool,
+) -> Result<MigrationReport, Error> {
+	let db_path = path::Path::new(&config.data_file_dir).join(DB_DIR);
+	if !db_path.exists() {
+		return Err(ErrorKind::Backend(format!(
+			"No wallet database found at {}",
+			db_path.display()
+		))
+		.into());
+	}
+	let lock_mode = match dry_run {
+		true => LockMode::Shared,
+		false => LockMode::Exclusive,
+	};
+	let _lock = WalletDirLock::acquire(&config.data_file_dir, lock_mode)?;
+	let store = store::Store::new(db_path.to_str().unwrap(), None, Some(DB_DIR), None)?;
+	let cipher = match is_encrypted(&store)? {
+		true => {
+			let keychain: ExtKeychain = seed_keychain(config, passphrase)?;
+			Some(DbCipher::from_keychain(&keychain)?)
+		}
+		false => None,
+	};
+	migrate_store(&store, cipher.as_ref(), dry_run)
 }
 
 pub struct LMDBBackend<C, K> {
 	db: store::Store,
+	/// Lock on the wallet data directory, held while the backend is open
+	_lock: WalletDirLock,
+	/// Whether the backend was opened for reading only
+	read_only: bool,
 	config: WalletConfig,
 	/// passphrase: TODO better ways of dealing with this other than storing
 	passphrase: ZeroingString,
+	/// Key for values stored in the database, derived from the seed
+	cipher: Option<DbCipher>,
+	/// Keychain derived from the seed, kept for the life of the backend so
+	/// opening the wallet doesn't decrypt the seed file again
+	seed_keychain: Option<K>,
 	/// Keychain
 	pub keychain: Option<K>,
 	/// Parent path to use by default for output operations
@@ -105,8 +341,56 @@ pub struct LMDBBackend<C, K> {
 	w2n_client: C,
 }
 
-impl<C, K> LMDBBackend<C, K> {
+impl<C, K> LMDBBackend<C, K>
+where
+	K: Keychain,
+{
 	pub fn new(config: WalletConfig, passphrase: &str, n_client: C) -> Result<Self, Error> {
+		LMDBBackend::open(config, passphrase, None, n_client, LockMode::Exclusive)
+	}
+
+	/// Open the wallet database with the seed already read from the seed
+	/// file, which unlocks it without decrypting the seed file again
+	pub fn with_seed(
+		config: WalletConfig,
+		passphrase: &str,
+		seed: &WalletSeed,
+		n_client: C,
+		lock_mode: LockMode,
+	) -> Result<Self, Error> {
+		LMDBBackend::open(config, passphrase, Some(seed), n_client, lock_mode)
+	}
+
+	/// Open an existing wallet database for reading only. The wallet
+	/// directory lock is shared with other readers, and any attempt to write
+	/// fails.
+	pub fn new_read_only(
+		config: WalletConfig,
+		passphrase: &str,
+		n_client: C,
+	) -> Result<Self, Error> {
+		LMDBBackend::open(config, passphrase, None, n_client, LockMode::Shared)
+	}
+
+	/// Open the wallet database, unlocking it with the given seed or else
+	/// the one in the seed file, if it can be read yet. Reading only needs
+	/// an existing database.
+	fn open(
+		config: WalletConfig,
+		passphrase: &str,
+		seed: Option<&WalletSeed>,
+		n_client: C,
+		lock_mode: LockMode,
+	) -> Result<Self, Error> {
+		if lock_mode == LockMode::Shared && !LMDBBackend::<C, K>::exists(config.clone()) {
+			return Err(ErrorKind::Backend(format!(
+				"No wallet database found in {}",
+				config.data_file_dir
+			))
+			.into());
+		}
+		let lock = WalletDirLock::acquire(&config.data_file_dir, lock_mode)?;
+
 		let db_path = path::Path::new(&config.data_file_dir).join(DB_DIR);
 		fs::create_dir_all(&db_path).expect("Couldn't create wallet backend directory!");
 
@@ -116,6 +400,55 @@ impl<C, K> LMDBBackend<C, K> {
 
 		let store = store::Store::new(db_path.to_str().unwrap(), None, Some(DB_DIR), None)?;
 
+		let mut res = LMDBBackend {
+			db: store,
+			_lock: lock,
+			read_only: lock_mode == LockMode::Shared,
+			config: config.clone(),
+			passphrase: ZeroingString::from(passphrase),
+			cipher: None,
+			seed_keychain: None,
+			keychain: None,
+			parent_key_id: LMDBBackend::<C, K>::default_path(),
+			w2n_client: n_client,
+		};
+
+		// The database can only be read with a key derived from the seed. If
+		// the seed can't be read yet, unlock when the wallet is first opened
+		let keychain = match seed {
+			Some(s) => Some(keychain_from_seed::<K>(s)?),
+			None => match seed_keychain::<K>(&config, passphrase) {
+				Ok(k) => Some(k),
+				Err(e) => {
+					debug!("Wallet database locked until opened: {}", e);
+					None
+				}
+			},
+		};
+		if let Some(k) = keychain {
+			res.unlock(DbCipher::from_keychain(&k)?)?;
+			res.seed_keychain = Some(k);
+		}
+		Ok(res)
+	}
+
+	/// Set the database cipher, converting any plaintext values and bringing
+	/// the schema up to date
+	fn unlock(&mut self, cipher: DbCipher) -> Result<(), Error> {
+		if self.read_only {
+			let version: u32 = self.db.get_ser(&schema_version_key())?.unwrap_or(0);
+			if !is_encrypted(&self.db)? || version != CURRENT_SCHEMA_VERSION {
+				return Err(ErrorKind::Backend(
+					"Wallet database must be upgraded by opening it for writing first".to_owned(),
+				)
+				.into());
+			}
+			self.cipher = Some(cipher);
+			return Ok(());
+		}
+		encrypt_plaintext_store(&self.db, &cipher, &self.config)?;
+		migrate_store(&self.db, Some(&cipher), false)?;
+
 		// Make sure default wallet derivation path always exists
 		// as well as path (so it can be retrieved by batches to know where to store
 		// completed transactions, for reference
@@ -123,28 +456,20 @@ impl<C, K> LMDBBackend<C, K> {
 			label: "default".to_owned(),
 			path: LMDBBackend::<C, K>::default_path(),
 		};
-		let acct_key = to_key(
-			ACCOUNT_PATH_MAPPING_PREFIX,
-			&mut default_account.label.as_bytes().to_vec(),
-		);
+		let acct_key = acct_key(&cipher, &default_account.label);
 
 		{
-			let batch = store.batch()?;
-			batch.put_ser(&acct_key, &default_account)?;
+			let batch = self.db.batch()?;
+			batch.put_ser(&acct_key, &cipher.seal(&acct_key, &default_account)?)?;
 			batch.commit()?;
 		}
 
-		let res = LMDBBackend {
-			db: store,
-			config: config.clone(),
-			passphrase: ZeroingString::from(passphrase),
-			keychain: None,
-			parent_key_id: LMDBBackend::<C, K>::default_path(),
-			w2n_client: n_client,
-		};
-		Ok(res)
+		self.cipher = Some(cipher);
+		Ok(())
 	}
+}
 
+impl<C, K> LMDBBackend<C, K> {
 	fn default_path() -> Identifier {
 		// return the default parent wallet path, corresponding to the default account
 		// in the BIP32 spec. Parent is account 0 at level 2, child output identifiers
@@ -158,6 +483,27 @@ impl<C, K> LMDBBackend<C, K> {
 		let db_path = path::Path::new(&config.data_file_dir).join(DB_DIR);
 		db_path.exists()
 	}
+
+	fn cipher(&self) -> Result<&DbCipher, Error> {
+		match self.cipher.as_ref() {
+			Some(c) => Ok(c),
+			None => Err(ErrorKind::Backend(
+				"Wallet database is locked until the wallet is opened".to_owned(),
+			)
+			.into()),
+		}
+	}
+
+	fn get_value<T: ser::Readable>(&self, key: &[u8]) -> Result<Option<T>, Error> {
+		get_sealed(self.db.get_ser(key)?, self.cipher()?, key)
+	}
+
+	fn check_writable(&self) -> Result<(), Error> {
+		match self.read_only {
+			true => Err(ErrorKind::ReadOnly.into()),
+			false => Ok(()),
+		}
+	}
 }
 
 impl<C, K> WalletBackend<C, K> for LMDBBackend<C, K>
@@ -165,19 +511,23 @@ where
 	C: NodeClient,
 	K: Keychain,
 {
-	/// Initialise with whatever stored credentials we have
+	/// Initialise with the keychain derived from the seed, only decrypting
+	/// the seed file if that couldn't be done when the backend was created
 	fn open_with_credentials(&mut self) -> Result<(), Error> {
-		let wallet_seed = WalletSeed::from_file(&self.config, &self.passphrase)
-			.context(ErrorKind::CallbackImpl("Error opening wallet"))?;
-		self.keychain = Some(
-			wallet_seed
-				.derive_keychain(global::is_floonet())
-				.context(ErrorKind::CallbackImpl("Error deriving keychain"))?,
-		);
+		let keychain = match self.seed_keychain.clone() {
+			Some(k) => k,
+			None => {
+				let k: K = seed_keychain(&self.config, &self.passphrase)?;
+				self.unlock(DbCipher::from_keychain(&k)?)?;
+				self.seed_keychain = Some(k.clone());
+				k
+			}
+		};
+		self.keychain = Some(keychain);
 		Ok(())
 	}
 
-	/// Close wallet and remove any stored credentials (TBD)
+	/// Close wallet, wiping the keychain it was opened with
 	fn close(&mut self) -> Result<(), Error> {
 		self.keychain = None;
 		Ok(())
@@ -211,7 +561,7 @@ where
 	/// Set parent path by account name
 	fn set_parent_key_id_by_name(&mut self, label: &str) -> Result<(), Error> {
 		let label = label.to_owned();
-		let res = self.acct_path_iter().find(|l| l.label == label);
+		let res = self.acct_path_iter()?.find(|l| l.label == label);
 		if let Some(a) = res {
 			self.set_parent_key_id(a.path);
 			Ok(())
@@ -234,20 +584,20 @@ where
 			Some(i) => to_key_u64(OUTPUT_PREFIX, &mut id.to_bytes().to_vec(), *i),
 			None => to_key(OUTPUT_PREFIX, &mut id.to_bytes().to_vec()),
 		};
-		option_to_not_found(self.db.get_ser(&key), &format!("Key Id: {}", id)).map_err(|e| e.into())
+		self.get_value(&key)?
+			.ok_or(not_found(&format!("Key Id: {}", id)))
 	}
 
-	fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = OutputData> + 'a> {
-		Box::new(self.db.iter(&[OUTPUT_PREFIX]).unwrap().map(|o| o.1))
+	fn iter<'a>(&'a self) -> Result<Box<dyn Iterator<Item = OutputData> + 'a>, Error> {
+		open_records(self.db.iter(&[OUTPUT_PREFIX])?, self.cipher()?)
 	}
 
 	fn get_tx_log_entry(&self, u: &Uuid) -> Result<Option<TxLogEntry>, Error> {
-		let key = to_key(TX_LOG_ENTRY_PREFIX, &mut u.as_bytes().to_vec());
-		self.db.get_ser(&key).map_err(|e| e.into())
+		Ok(self.tx_log_iter()?.find(|t| t.tx_slate_id == Some(*u)))
 	}
 
-	fn tx_log_iter<'a>(&'a self) -> Box<dyn Iterator<Item = TxLogEntry> + 'a> {
-		Box::new(self.db.iter(&[TX_LOG_ENTRY_PREFIX]).unwrap().map(|o| o.1))
+	fn tx_log_iter<'a>(&'a self) -> Result<Box<dyn Iterator<Item = TxLogEntry> + 'a>, Error> {
+		open_records(self.db.iter(&[TX_LOG_ENTRY_PREFIX])?, self.cipher()?)
 	}
 
 	fn get_private_context(
@@ -262,10 +612,9 @@ where
 		);
 		let (blind_xor_key, nonce_xor_key) = private_ctx_xor_keys(self.keychain(), slate_id)?;
 
-		let mut ctx: Context = option_to_not_found(
-			self.db.get_ser(&ctx_key),
-			&format!("Slate id: {:x?}", slate_id.to_vec()),
-		)?;
+		let mut ctx: Context = self
+			.get_value(&ctx_key)?
+			.ok_or(not_found(&format!("Slate id: {:x?}", slate_id.to_vec())))?;
 
 		for i in 0..SECRET_KEY_SIZE {
 			ctx.sec_key.0[i] = ctx.sec_key.0[i] ^ blind_xor_key[i];
@@ -275,65 +624,74 @@ where
 		Ok(ctx)
 	}
 
-	fn acct_path_iter<'a>(&'a self) -> Box<dyn Iterator<Item = AcctPathMapping> + 'a> {
-		Box::new(
+	fn private_context_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = (Vec<u8>, usize)> + 'a>, Error> {
+		// keys are the prefix, the slate id, then the participant id as a
+		// big endian u64
+		let prefix_len = to_key(PRIVATE_TX_CONTEXT_PREFIX, &mut vec![]).len();
+		Ok(Box::new(
 			self.db
-				.iter(&[ACCOUNT_PATH_MAPPING_PREFIX])
-				.unwrap()
-				.map(|o| o.1),
+				.iter::<EncryptedRecord>(&[PRIVATE_TX_CONTEXT_PREFIX])?
+				.filter(move |(key, _)| key.len() >= prefix_len + 8)
+				.map(move |(key, _)| {
+					let (slate_id, participant_id) =
+						key[prefix_len..].split_at(key.len() - prefix_len - 8);
+					let mut id_bytes = [0u8; 8];
+					id_bytes.copy_from_slice(participant_id);
+					(slate_id.to_vec(), u64::from_be_bytes(id_bytes) as usize)
+				}),
+		))
+	}
+
+	fn acct_path_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = AcctPathMapping> + 'a>, Error> {
+		open_records(
+			self.db.iter(&[ACCOUNT_PATH_MAPPING_PREFIX])?,
+			self.cipher()?,
 		)
 	}
 
 	fn get_acct_path(&self, label: String) -> Result<Option<AcctPathMapping>, Error> {
-		let acct_key = to_key(ACCOUNT_PATH_MAPPING_PREFIX, &mut label.as_bytes().to_vec());
-		self.db.get_ser(&acct_key).map_err(|e| e.into())
+		let acct_key = acct_key(self.cipher()?, &label);
+		self.get_value(&acct_key)
 	}
 
 	fn store_tx(&self, uuid: &str, tx: &Transaction) -> Result<(), Error> {
-		let filename = format!("{}.grintx", uuid);
-		let path = path::Path::new(&self.config.data_file_dir)
-			.join(TX_SAVE_DIR)
-			.join(filename);
-		let path_buf = Path::new(&path).to_path_buf();
-		let mut stored_tx = File::create(path_buf)?;
-		let tx_hex = util::to_hex(ser::ser_vec(tx).unwrap());;
-		stored_tx.write_all(&tx_hex.as_bytes())?;
-		stored_tx.sync_all()?;
-		Ok(())
+		self.check_writable()?;
+		write_stored_tx(&self.config, uuid, tx, self.cipher()?)
 	}
 
 	fn get_stored_tx(&self, entry: &TxLogEntry) -> Result<Option<Transaction>, Error> {
-		let filename = match entry.stored_tx.clone() {
-			Some(f) => f,
-			None => return Ok(None),
-		};
-		let path = path::Path::new(&self.config.data_file_dir)
-			.join(TX_SAVE_DIR)
-			.join(filename);
-		let tx_file = Path::new(&path).to_path_buf();
-		let mut tx_f = File::open(tx_file)?;
-		let mut content = String::new();
-		tx_f.read_to_string(&mut content)?;
-		let tx_bin = util::from_hex(content).unwrap();
-		Ok(Some(
-			ser::deserialize::<Transaction>(&mut &tx_bin[..]).unwrap(),
-		))
+		read_stored_tx(&self.config, entry, self.cipher()?)
+	}
+
+	fn remove_stored_tx(&self, entry: &TxLogEntry, archive: bool) -> Result<(), Error> {
+		self.check_writable()?;
+		remove_stored_tx_file(&self.config, entry, archive)
+	}
+
+	fn retention_policy(&self) -> RetentionPolicy {
+		retention_policy(&self.config)
 	}
 
 	fn batch<'a>(&'a mut self) -> Result<Box<dyn WalletOutputBatch<K> + 'a>, Error> {
+		self.check_writable()?;
+		let cipher = self.cipher()?.clone();
 		Ok(Box::new(Batch {
 			_store: self,
 			db: RefCell::new(Some(self.db.batch()?)),
 			keychain: self.keychain.clone(),
+			cipher,
 		}))
 	}
 
 	fn next_child<'a>(&mut self) -> Result<Identifier, Error> {
 		let parent_key_id = self.parent_key_id.clone();
 		let mut deriv_idx = {
-			let batch = self.db.batch()?;
 			let deriv_key = to_key(DERIV_PREFIX, &mut self.parent_key_id.to_bytes().to_vec());
-			match batch.get_ser(&deriv_key)? {
+			match self.get_value(&deriv_key)? {
 				Some(idx) => idx,
 				None => 0,
 			}
@@ -349,27 +707,100 @@ where
 	}
 
 	fn last_confirmed_height<'a>(&mut self) -> Result<u64, Error> {
-		let batch = self.db.batch()?;
 		let height_key = to_key(
 			CONFIRMED_HEIGHT_PREFIX,
 			&mut self.parent_key_id.to_bytes().to_vec(),
 		);
-		let last_confirmed_height = match batch.get_ser(&height_key)? {
+		let last_confirmed_height = match self.get_value(&height_key)? {
 			Some(h) => h,
 			None => 0,
 		};
 		Ok(last_confirmed_height)
 	}
 
-	fn restore(&mut self) -> Result<(), Error> {
-		restore(self).context(ErrorKind::Restore)?;
+	fn last_confirmed_block(
+		&self,
+		parent_key_id: &Identifier,
+	) -> Result<Option<BlockHeaderInfo>, Error> {
+		let block_key = to_key(
+			CONFIRMED_BLOCK_PREFIX,
+			&mut parent_key_id.to_bytes().to_vec(),
+		);
+		self.get_value(&block_key)
+	}
+
+	fn current_child_index<'a>(&mut self) -> Result<u32, Error> {
+		let deriv_key = to_key(DERIV_PREFIX, &mut self.parent_key_id.to_bytes().to_vec());
+		let child_index = match self.get_value(&deriv_key)? {
+			Some(idx) => idx,
+			None => 0,
+		};
+		Ok(child_index)
+	}
+
+	fn scan_checkpoint(&self) -> Result<Option<ScanCheckpoint>, Error> {
+		self.get_value(&scan_checkpoint_key())
+	}
+
+	fn scanned_output_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = ScannedOutput> + 'a>, Error> {
+		open_records(self.db.iter(&[SCANNED_OUTPUT_PREFIX])?, self.cipher()?)
+	}
+
+	fn birthday_height(&self) -> Result<Option<u64>, Error> {
+		let height = WalletSeed::file_birthday_height(&self.config)
+			.context(ErrorKind::CallbackImpl("Error reading wallet seed file"))?;
+		Ok(height)
+	}
+
+	fn restore(
+		&mut self,
+		start_height: Option<u64>,
+		account_labels: &[AcctPathMapping],
+		status_send: &Option<Sender<ScanStatus>>,
+	) -> Result<(), Error> {
+		let threads = self.config.restore_threads();
+		restore(self, start_height, account_labels, threads, status_send)
+			.context(ErrorKind::Restore)?;
 		Ok(())
 	}
 
-	fn check_repair(&mut self, delete_unconfirmed: bool) -> Result<(), Error> {
-		check_repair(self, delete_unconfirmed).context(ErrorKind::Restore)?;
+	fn check_repair(
+		&mut self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+		dry_run: bool,
+		status_send: &Option<Sender<ScanStatus>>,
+	) -> Result<Vec<CheckDiscrepancy>, Error> {
+		let threads = self.config.restore_threads();
+		let discrepancies = check_repair(
+			self,
+			delete_unconfirmed,
+			start_height,
+			dry_run,
+			threads,
+			status_send,
+		)
+		.context(ErrorKind::Restore)?;
+		Ok(discrepancies)
+	}
+
+	fn change_password(&mut self, old_password: &str, new_password: &str) -> Result<(), Error> {
+		self.check_writable()?;
+		WalletSeed::change_password(&self.config, old_password, new_password)
+			.context(ErrorKind::CallbackImpl("Error changing wallet password"))?;
+		self.passphrase = ZeroingString::from(new_password);
 		Ok(())
 	}
+
+	fn export_archive(&mut self, path: &str) -> Result<ArchiveSummary, Error> {
+		export_wallet(self, path)
+	}
+
+	fn import_archive(&mut self, path: &str) -> Result<ArchiveSummary, Error> {
+		import_wallet(self, path)
+	}
 }
 
 /// An atomic batch in which all changes can be committed all at once or
@@ -383,6 +814,35 @@ where
 	db: RefCell<Option<store::Batch<'a>>>,
 	/// Keychain
 	keychain: Option<K>,
+	/// Database cipher
+	cipher: DbCipher,
+}
+
+impl<'a, C, K> Batch<'a, C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	fn get_value<T: ser::Readable>(&self, key: &[u8]) -> Result<Option<T>, Error> {
+		let record = self.db.borrow().as_ref().unwrap().get_ser(key)?;
+		get_sealed(record, &self.cipher, key)
+	}
+
+	fn put_value<T: ser::Writeable>(&self, key: &[u8], value: &T) -> Result<(), Error> {
+		let record = self.cipher.seal(key, value)?;
+		self.db.borrow().as_ref().unwrap().put_ser(key, &record)?;
+		Ok(())
+	}
+
+	fn iter_values<T: ser::Readable + 'static>(
+		&self,
+		prefix: u8,
+	) -> Result<Box<dyn Iterator<Item = T>>, Error> {
+		open_records(
+			self.db.borrow().as_ref().unwrap().iter(&[prefix])?,
+			&self.cipher,
+		)
+	}
 }
 
 #[allow(missing_docs)]
@@ -402,7 +862,7 @@ where
 				Some(i) => to_key_u64(OUTPUT_PREFIX, &mut out.key_id.to_bytes().to_vec(), i),
 				None => to_key(OUTPUT_PREFIX, &mut out.key_id.to_bytes().to_vec()),
 			};
-			self.db.borrow().as_ref().unwrap().put_ser(&key, &out)?;
+			self.put_value(&key, &out)?;
 		}
 
 		Ok(())
@@ -413,23 +873,12 @@ where
 			Some(i) => to_key_u64(OUTPUT_PREFIX, &mut id.to_bytes().to_vec(), *i),
 			None => to_key(OUTPUT_PREFIX, &mut id.to_bytes().to_vec()),
 		};
-		option_to_not_found(
-			self.db.borrow().as_ref().unwrap().get_ser(&key),
-			&format!("Key ID: {}", id),
-		)
-		.map_err(|e| e.into())
+		self.get_value(&key)?
+			.ok_or(not_found(&format!("Key ID: {}", id)))
 	}
 
-	fn iter(&self) -> Box<dyn Iterator<Item = OutputData>> {
-		Box::new(
-			self.db
-				.borrow()
-				.as_ref()
-				.unwrap()
-				.iter(&[OUTPUT_PREFIX])
-				.unwrap()
-				.map(|o| o.1),
-		)
+	fn iter(&self) -> Result<Box<dyn Iterator<Item = OutputData>>, Error> {
+		self.iter_values(OUTPUT_PREFIX)
 	}
 
 	fn delete(&mut self, id: &Identifier, mmr_index: &Option<u64>) -> Result<(), Error> {
@@ -447,28 +896,21 @@ where
 
 	fn next_tx_log_id(&mut self, parent_key_id: &Identifier) -> Result<u32, Error> {
 		let tx_id_key = to_key(TX_LOG_ID_PREFIX, &mut parent_key_id.to_bytes().to_vec());
-		let last_tx_log_id = match self.db.borrow().as_ref().unwrap().get_ser(&tx_id_key)? {
+		let last_tx_log_id = match self.get_value(&tx_id_key)? {
 			Some(t) => t,
 			None => 0,
 		};
-		self.db
-			.borrow()
-			.as_ref()
-			.unwrap()
-			.put_ser(&tx_id_key, &(last_tx_log_id + 1))?;
+		self.put_value(&tx_id_key, &(last_tx_log_id + 1))?;
 		Ok(last_tx_log_id)
 	}
 
-	fn tx_log_iter(&self) -> Box<dyn Iterator<Item = TxLogEntry>> {
-		Box::new(
-			self.db
-				.borrow()
-				.as_ref()
-				.unwrap()
-				.iter(&[TX_LOG_ENTRY_PREFIX])
-				.unwrap()
-				.map(|o| o.1),
-		)
+	fn save_next_tx_log_id(&mut self, parent_key_id: &Identifier, id: u32) -> Result<(), Error> {
+		let tx_id_key = to_key(TX_LOG_ID_PREFIX, &mut parent_key_id.to_bytes().to_vec());
+		self.put_value(&tx_id_key, &id)
+	}
+
+	fn tx_log_iter(&self) -> Result<Box<dyn Iterator<Item = TxLogEntry>>, Error> {
+		self.iter_values(TX_LOG_ENTRY_PREFIX)
 	}
 
 	fn save_last_confirmed_height(
@@ -480,22 +922,24 @@ where
 			CONFIRMED_HEIGHT_PREFIX,
 			&mut parent_key_id.to_bytes().to_vec(),
 		);
-		self.db
-			.borrow()
-			.as_ref()
-			.unwrap()
-			.put_ser(&height_key, &height)?;
-		Ok(())
+		self.put_value(&height_key, &height)
+	}
+
+	fn save_last_confirmed_block(
+		&mut self,
+		parent_key_id: &Identifier,
+		block: &BlockHeaderInfo,
+	) -> Result<(), Error> {
+		let block_key = to_key(
+			CONFIRMED_BLOCK_PREFIX,
+			&mut parent_key_id.to_bytes().to_vec(),
+		);
+		self.put_value(&block_key, block)
 	}
 
 	fn save_child_index(&mut self, parent_id: &Identifier, child_n: u32) -> Result<(), Error> {
 		let deriv_key = to_key(DERIV_PREFIX, &mut parent_id.to_bytes().to_vec());
-		self.db
-			.borrow()
-			.as_ref()
-			.unwrap()
-			.put_ser(&deriv_key, &child_n)?;
-		Ok(())
+		self.put_value(&deriv_key, &child_n)
 	}
 
 	fn save_tx_log_entry(
@@ -508,37 +952,16 @@ where
 			&mut parent_id.to_bytes().to_vec(),
 			tx_in.id as u64,
 		);
-		self.db
-			.borrow()
-			.as_ref()
-			.unwrap()
-			.put_ser(&tx_log_key, &tx_in)?;
-		Ok(())
+		self.put_value(&tx_log_key, &tx_in)
 	}
 
 	fn save_acct_path(&mut self, mapping: AcctPathMapping) -> Result<(), Error> {
-		let acct_key = to_key(
-			ACCOUNT_PATH_MAPPING_PREFIX,
-			&mut mapping.label.as_bytes().to_vec(),
-		);
-		self.db
-			.borrow()
-			.as_ref()
-			.unwrap()
-			.put_ser(&acct_key, &mapping)?;
-		Ok(())
+		let acct_key = acct_key(&self.cipher, &mapping.label);
+		self.put_value(&acct_key, &mapping)
 	}
 
-	fn acct_path_iter(&self) -> Box<dyn Iterator<Item = AcctPathMapping>> {
-		Box::new(
-			self.db
-				.borrow()
-				.as_ref()
-				.unwrap()
-				.iter(&[ACCOUNT_PATH_MAPPING_PREFIX])
-				.unwrap()
-				.map(|o| o.1),
-		)
+	fn acct_path_iter(&self) -> Result<Box<dyn Iterator<Item = AcctPathMapping>>, Error> {
+		self.iter_values(ACCOUNT_PATH_MAPPING_PREFIX)
 	}
 
 	fn lock_output(&mut self, out: &mut OutputData) -> Result<(), Error> {
@@ -565,12 +988,7 @@ where
 			s_ctx.sec_nonce.0[i] = s_ctx.sec_nonce.0[i] ^ nonce_xor_key[i];
 		}
 
-		self.db
-			.borrow()
-			.as_ref()
-			.unwrap()
-			.put_ser(&ctx_key, &s_ctx)?;
-		Ok(())
+		self.put_value(&ctx_key, &s_ctx)
 	}
 
 	fn delete_private_context(
@@ -591,6 +1009,32 @@ where
 			.map_err(|e| e.into())
 	}
 
+	fn save_scan_checkpoint(&mut self, checkpoint: &ScanCheckpoint) -> Result<(), Error> {
+		self.put_value(&scan_checkpoint_key(), checkpoint)
+	}
+
+	fn save_scanned_output(&mut self, output: &ScannedOutput) -> Result<(), Error> {
+		self.put_value(&scanned_output_key(output.mmr_index), output)
+	}
+
+	fn clear_scan_checkpoint(&mut self) -> Result<(), Error> {
+		let db = self.db.borrow();
+		let db = db.as_ref().unwrap();
+		let keys: Vec<Vec<u8>> = db
+			.iter::<EncryptedRecord>(&[SCANNED_OUTPUT_PREFIX])?
+			.map(|(key, _)| key)
+			.collect();
+		for key in keys.iter() {
+			db.delete(key)?;
+		}
+		// there's no checkpoint once a scan has finished
+		let checkpoint_key = scan_checkpoint_key();
+		if db.exists(&checkpoint_key)? {
+			db.delete(&checkpoint_key)?;
+		}
+		Ok(())
+	}
+
 	fn commit(&self) -> Result<(), Error> {
 		let db = self.db.replace(None);
 		db.unwrap().commit()?;
@@ -0,0 +1,138 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Advisory lock on a wallet data directory, so only one process at a time
+//! can write to a wallet while any number of processes can read it
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Weak};
+
+use failure::ResultExt;
+use fs2::FileExt;
+
+use crate::libwallet::{Error, ErrorKind};
+use crate::util::Mutex;
+
+/// Lock file, in the wallet data directory
+pub const LOCK_FILE: &'static str = "wallet.lock";
+
+lazy_static! {
+	/// Locks held by this process, by data directory. Wallet instances
+	/// opened within the same process share the process' lock.
+	static ref HELD_LOCKS: Mutex<HashMap<PathBuf, Weak<LockFile>>> = Mutex::new(HashMap::new());
+}
+
+/// How a wallet data directory is locked
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LockMode {
+	/// For reading only, shared with any other readers
+	Shared,
+	/// For reading and writing, held by a single process
+	Exclusive,
+}
+
+struct LockFile {
+	file: File,
+	mode: LockMode,
+}
+
+impl Drop for LockFile {
+	fn drop(&mut self) {
+		// the PID left in the file is only meaningful while a writer holds it
+		if self.mode == LockMode::Exclusive {
+			let _ = self.file.set_len(0);
+		}
+		let _ = self.file.unlock();
+	}
+}
+
+/// Lock on a wallet data directory, released once the last clone is dropped
+#[derive(Clone)]
+pub struct WalletDirLock {
+	inner: Arc<LockFile>,
+}
+
+impl WalletDirLock {
+	/// Lock the wallet data directory, failing straight away if another
+	/// process holds a conflicting lock
+	pub fn acquire(data_dir: &str, mode: LockMode) -> Result<WalletDirLock, Error> {
+		fs::create_dir_all(data_dir).context(ErrorKind::IO)?;
+		let dir = Path::new(data_dir).canonicalize().context(ErrorKind::IO)?;
+		let mut held = HELD_LOCKS.lock();
+		if let Some(lock) = held.get(&dir).and_then(|l| l.upgrade()) {
+			if mode == LockMode::Shared || lock.mode == LockMode::Exclusive {
+				return Ok(WalletDirLock { inner: lock });
+			}
+			return Err(ErrorKind::WalletLocked(format!(
+				"{} is already open read-only in this process",
+				dir.display()
+			)))?;
+		}
+
+		let mut file = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.create(true)
+			.open(dir.join(LOCK_FILE))
+			.context(ErrorKind::IO)?;
+		let res = match mode {
+			LockMode::Shared => file.try_lock_shared(),
+			LockMode::Exclusive => file.try_lock_exclusive(),
+		};
+		match res {
+			Ok(_) => {}
+			Err(ref e) if e.kind() == fs2::lock_contended_error().kind() => {
+				return Err(ErrorKind::WalletLocked(format!(
+					"{} is locked by another process ({})",
+					dir.display(),
+					lock_holder(&mut file)
+				)))?;
+			}
+			Err(e) => Err(e).context(ErrorKind::IO)?,
+		}
+		write_pid(&mut file)?;
+
+		let lock = Arc::new(LockFile { file, mode });
+		held.insert(dir, Arc::downgrade(&lock));
+		Ok(WalletDirLock { inner: lock })
+	}
+
+	/// How this process holds the lock
+	pub fn mode(&self) -> LockMode {
+		self.inner.mode
+	}
+}
+
+/// Record our PID in the lock file. The PID is padded to a fixed width, so
+/// readers sharing the lock can't leave a mix of their PIDs behind.
+fn write_pid(file: &mut File) -> Result<(), Error> {
+	file.seek(SeekFrom::Start(0)).context(ErrorKind::IO)?;
+	file.write_all(format!("{:<10}\n", process::id()).as_bytes())
+		.context(ErrorKind::IO)?;
+	Ok(())
+}
+
+/// Describe the process holding the lock, from the PID in the lock file
+fn lock_holder(file: &mut File) -> String {
+	let mut contents = String::new();
+	let _ = file.read_to_string(&mut contents);
+	match contents.trim().parse::<u32>() {
+		Ok(pid) => format!("pid {}", pid),
+		Err(_) => "pid unknown".to_owned(),
+	}
+}
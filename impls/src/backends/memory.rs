@@ -0,0 +1,698 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet backend holding all wallet data in memory, for ephemeral wallets
+//! and tests. Nothing, including the seed and stored transactions, is ever
+//! written to disk, and everything is lost when the backend is dropped.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::Sender;
+
+use failure::ResultExt;
+use uuid::Uuid;
+
+use crate::keychain::{ChildNumber, ExtKeychain, Identifier, Keychain};
+
+use crate::core::core::Transaction;
+use crate::libwallet::{check_repair, restore};
+use crate::libwallet::{
+	AcctPathMapping, ArchiveSummary, BlockHeaderInfo, CheckDiscrepancy, Context, Error, ErrorKind,
+	NodeClient, OutputData, RetentionPolicy, ScanCheckpoint, ScanStatus, ScannedOutput, TxLogEntry,
+	WalletBackend, WalletOutputBatch,
+};
+use crate::util;
+use crate::util::secp::constants::SECRET_KEY_SIZE;
+use crate::util::Mutex;
+use crate::WalletSeed;
+use config::WalletConfig;
+
+use super::archive::{export_wallet, import_wallet};
+use super::{keychain_from_seed, private_ctx_xor_keys, retention_policy};
+
+fn not_found(field_name: &str) -> Error {
+	ErrorKind::Backend(format!("DB Not Found Error: {}", field_name)).into()
+}
+
+/// All wallet records, keyed the same way as the LMDB prefixes so that
+/// iteration order matches the on-disk backends
+#[derive(Clone, Default)]
+struct MemoryStore {
+	outputs: BTreeMap<(Vec<u8>, Option<u64>), OutputData>,
+	deriv_indices: HashMap<Vec<u8>, u32>,
+	confirmed_heights: HashMap<Vec<u8>, u64>,
+	confirmed_blocks: HashMap<Vec<u8>, BlockHeaderInfo>,
+	private_contexts: HashMap<(Vec<u8>, usize), Context>,
+	tx_log: BTreeMap<(Vec<u8>, u32), TxLogEntry>,
+	tx_log_ids: HashMap<Vec<u8>, u32>,
+	acct_paths: BTreeMap<String, AcctPathMapping>,
+	stored_txs: HashMap<String, Transaction>,
+	archived_txs: HashMap<String, Transaction>,
+	scan_checkpoint: Option<ScanCheckpoint>,
+	scanned_outputs: BTreeMap<u64, ScannedOutput>,
+}
+
+impl MemoryStore {
+	fn get_output(&self, id: &Identifier, mmr_index: &Option<u64>) -> Result<OutputData, Error> {
+		self.outputs
+			.get(&(id.to_bytes().to_vec(), *mmr_index))
+			.cloned()
+			.ok_or(not_found(&format!("Key Id: {}", id)))
+	}
+}
+
+pub struct MemoryBackend<C, K> {
+	store: Mutex<MemoryStore>,
+	/// Keychain derived from the seed when the backend is created
+	seed_keychain: K,
+	/// Keychain
+	pub keychain: Option<K>,
+	/// Parent path to use by default for output operations
+	parent_key_id: Identifier,
+	/// Garbage collection policy, from the wallet's configuration
+	retention: RetentionPolicy,
+	/// wallet to node client
+	w2n_client: C,
+}
+
+impl<C, K> MemoryBackend<C, K>
+where
+	K: Keychain,
+{
+	pub fn new(config: WalletConfig, seed: WalletSeed, n_client: C) -> Result<Self, Error> {
+		// Make sure default wallet derivation path always exists
+		let default_account = AcctPathMapping {
+			label: "default".to_owned(),
+			path: MemoryBackend::<C, K>::default_path(),
+		};
+		let mut store = MemoryStore::default();
+		store
+			.acct_paths
+			.insert(default_account.label.clone(), default_account);
+
+		Ok(MemoryBackend {
+			store: Mutex::new(store),
+			seed_keychain: keychain_from_seed(&seed)?,
+			keychain: None,
+			parent_key_id: MemoryBackend::<C, K>::default_path(),
+			retention: retention_policy(&config),
+			w2n_client: n_client,
+		})
+	}
+
+	fn default_path() -> Identifier {
+		// return the default parent wallet path, corresponding to the default account
+		// in the BIP32 spec. Parent is account 0 at level 2, child output identifiers
+		// are all at level 3
+		ExtKeychain::derive_key_id(2, 0, 0, 0, 0)
+	}
+}
+
+impl<C, K> WalletBackend<C, K> for MemoryBackend<C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	/// Initialise with the keychain derived from the seed held in memory
+	fn open_with_credentials(&mut self) -> Result<(), Error> {
+		self.keychain = Some(self.seed_keychain.clone());
+		Ok(())
+	}
+
+	/// Close wallet, wiping the keychain it was opened with
+	fn close(&mut self) -> Result<(), Error> {
+		self.keychain = None;
+		Ok(())
+	}
+
+	/// Return the keychain being used
+	fn keychain(&mut self) -> &mut K {
+		self.keychain.as_mut().unwrap()
+	}
+
+	/// Return the node client being used
+	fn w2n_client(&mut self) -> &mut C {
+		&mut self.w2n_client
+	}
+
+	/// return the version of the commit for caching
+	fn calc_commit_for_cache(
+		&mut self,
+		amount: u64,
+		id: &Identifier,
+	) -> Result<Option<String>, Error> {
+		Ok(Some(util::to_hex(
+			self.keychain().commit(amount, &id)?.0.to_vec(),
+		)))
+	}
+
+	/// Set parent path by account name
+	fn set_parent_key_id_by_name(&mut self, label: &str) -> Result<(), Error> {
+		match self.get_acct_path(label.to_owned())? {
+			Some(a) => {
+				self.set_parent_key_id(a.path);
+				Ok(())
+			}
+			None => Err(ErrorKind::UnknownAccountLabel(label.to_owned()).into()),
+		}
+	}
+
+	/// set parent path
+	fn set_parent_key_id(&mut self, id: Identifier) {
+		self.parent_key_id = id;
+	}
+
+	fn parent_key_id(&mut self) -> Identifier {
+		self.parent_key_id.clone()
+	}
+
+	fn get(&self, id: &Identifier, mmr_index: &Option<u64>) -> Result<OutputData, Error> {
+		self.store.lock().get_output(id, mmr_index)
+	}
+
+	fn iter<'a>(&'a self) -> Result<Box<dyn Iterator<Item = OutputData> + 'a>, Error> {
+		let outputs: Vec<_> = self.store.lock().outputs.values().cloned().collect();
+		Ok(Box::new(outputs.into_iter()))
+	}
+
+	fn get_tx_log_entry(&self, u: &Uuid) -> Result<Option<TxLogEntry>, Error> {
+		Ok(self
+			.store
+			.lock()
+			.tx_log
+			.values()
+			.find(|t| t.tx_slate_id == Some(*u))
+			.cloned())
+	}
+
+	fn tx_log_iter<'a>(&'a self) -> Result<Box<dyn Iterator<Item = TxLogEntry> + 'a>, Error> {
+		let entries: Vec<_> = self.store.lock().tx_log.values().cloned().collect();
+		Ok(Box::new(entries.into_iter()))
+	}
+
+	fn get_private_context(
+		&mut self,
+		slate_id: &[u8],
+		participant_id: usize,
+	) -> Result<Context, Error> {
+		let (blind_xor_key, nonce_xor_key) = private_ctx_xor_keys(self.keychain(), slate_id)?;
+
+		let mut ctx = self
+			.store
+			.lock()
+			.private_contexts
+			.get(&(slate_id.to_vec(), participant_id))
+			.cloned()
+			.ok_or(not_found(&format!("Slate id: {:x?}", slate_id.to_vec())))?;
+
+		for i in 0..SECRET_KEY_SIZE {
+			ctx.sec_key.0[i] = ctx.sec_key.0[i] ^ blind_xor_key[i];
+			ctx.sec_nonce.0[i] = ctx.sec_nonce.0[i] ^ nonce_xor_key[i];
+		}
+
+		Ok(ctx)
+	}
+
+	fn private_context_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = (Vec<u8>, usize)> + 'a>, Error> {
+		let ids: Vec<_> = self.store.lock().private_contexts.keys().cloned().collect();
+		Ok(Box::new(ids.into_iter()))
+	}
+
+	fn acct_path_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = AcctPathMapping> + 'a>, Error> {
+		let paths: Vec<_> = self.store.lock().acct_paths.values().cloned().collect();
+		Ok(Box::new(paths.into_iter()))
+	}
+
+	fn get_acct_path(&self, label: String) -> Result<Option<AcctPathMapping>, Error> {
+		Ok(self.store.lock().acct_paths.get(&label).cloned())
+	}
+
+	fn store_tx(&self, uuid: &str, tx: &Transaction) -> Result<(), Error> {
+		let filename = format!("{}.grintx", uuid);
+		self.store.lock().stored_txs.insert(filename, tx.clone());
+		Ok(())
+	}
+
+	fn get_stored_tx(&self, entry: &TxLogEntry) -> Result<Option<Transaction>, Error> {
+		let filename = match entry.stored_tx.clone() {
+			Some(f) => f,
+			None => return Ok(None),
+		};
+		Ok(self.store.lock().stored_txs.get(&filename).cloned())
+	}
+
+	fn remove_stored_tx(&self, entry: &TxLogEntry, archive: bool) -> Result<(), Error> {
+		let filename = match entry.stored_tx.clone() {
+			Some(f) => f,
+			None => return Ok(()),
+		};
+		let mut s = self.store.lock();
+		if let Some(tx) = s.stored_txs.remove(&filename) {
+			if archive {
+				s.archived_txs.insert(filename, tx);
+			}
+		}
+		Ok(())
+	}
+
+	fn retention_policy(&self) -> RetentionPolicy {
+		self.retention.clone()
+	}
+
+	fn batch<'a>(&'a mut self) -> Result<Box<dyn WalletOutputBatch<K> + 'a>, Error> {
+		Ok(Box::new(Batch {
+			store: self,
+			changes: RefCell::new(Some(WriteSet::default())),
+			keychain: self.keychain.clone(),
+		}))
+	}
+
+	fn next_child<'a>(&mut self) -> Result<Identifier, Error> {
+		let parent_key_id = self.parent_key_id.clone();
+		let mut deriv_idx = self
+			.store
+			.lock()
+			.deriv_indices
+			.get(&parent_key_id.to_bytes().to_vec())
+			.cloned()
+			.unwrap_or(0);
+		let mut return_path = self.parent_key_id.to_path();
+		return_path.depth = return_path.depth + 1;
+		return_path.path[return_path.depth as usize - 1] = ChildNumber::from(deriv_idx);
+		deriv_idx = deriv_idx + 1;
+		let mut batch = self.batch()?;
+		batch.save_child_index(&parent_key_id, deriv_idx)?;
+		batch.commit()?;
+		Ok(Identifier::from_path(&return_path))
+	}
+
+	fn last_confirmed_height<'a>(&mut self) -> Result<u64, Error> {
+		Ok(self
+			.store
+			.lock()
+			.confirmed_heights
+			.get(&self.parent_key_id.to_bytes().to_vec())
+			.cloned()
+			.unwrap_or(0))
+	}
+
+	fn last_confirmed_block(
+		&self,
+		parent_key_id: &Identifier,
+	) -> Result<Option<BlockHeaderInfo>, Error> {
+		Ok(self
+			.store
+			.lock()
+			.confirmed_blocks
+			.get(&parent_key_id.to_bytes().to_vec())
+			.cloned())
+	}
+
+	fn current_child_index<'a>(&mut self) -> Result<u32, Error> {
+		Ok(self
+			.store
+			.lock()
+			.deriv_indices
+			.get(&self.parent_key_id.to_bytes().to_vec())
+			.cloned()
+			.unwrap_or(0))
+	}
+
+	fn scan_checkpoint(&self) -> Result<Option<ScanCheckpoint>, Error> {
+		Ok(self.store.lock().scan_checkpoint.clone())
+	}
+
+	fn scanned_output_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = ScannedOutput> + 'a>, Error> {
+		let outputs: Vec<_> = self
+			.store
+			.lock()
+			.scanned_outputs
+			.values()
+			.cloned()
+			.collect();
+		Ok(Box::new(outputs.into_iter()))
+	}
+
+	// in-memory wallets have no seed file to record a birthday in
+	fn birthday_height(&self) -> Result<Option<u64>, Error> {
+		Ok(None)
+	}
+
+	fn restore(
+		&mut self,
+		start_height: Option<u64>,
+		account_labels: &[AcctPathMapping],
+		status_send: &Option<Sender<ScanStatus>>,
+	) -> Result<(), Error> {
+		// in-memory wallets aren't given a config, scan with the default
+		let threads = WalletConfig::default_restore_threads();
+		restore(self, start_height, account_labels, threads, status_send)
+			.context(ErrorKind::Restore)?;
+		Ok(())
+	}
+
+	fn check_repair(
+		&mut self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+		dry_run: bool,
+		status_send: &Option<Sender<ScanStatus>>,
+	) -> Result<Vec<CheckDiscrepancy>, Error> {
+		let threads = WalletConfig::default_restore_threads();
+		let discrepancies = check_repair(
+			self,
+			delete_unconfirmed,
+			start_height,
+			dry_run,
+			threads,
+			status_send,
+		)
+		.context(ErrorKind::Restore)?;
+		Ok(discrepancies)
+	}
+
+	fn change_password(&mut self, _old_password: &str, _new_password: &str) -> Result<(), Error> {
+		let msg = "In-memory wallets have no seed file to re-encrypt".to_owned();
+		Err(ErrorKind::Backend(msg).into())
+	}
+
+	fn export_archive(&mut self, path: &str) -> Result<ArchiveSummary, Error> {
+		export_wallet(self, path)
+	}
+
+	fn import_archive(&mut self, path: &str) -> Result<ArchiveSummary, Error> {
+		import_wallet(self, path)
+	}
+}
+
+/// Changes made in a batch, to be applied to the store on commit. A `None`
+/// value marks a record deleted in the batch.
+#[derive(Default)]
+struct WriteSet {
+	outputs: BTreeMap<(Vec<u8>, Option<u64>), Option<OutputData>>,
+	deriv_indices: HashMap<Vec<u8>, u32>,
+	confirmed_heights: HashMap<Vec<u8>, u64>,
+	confirmed_blocks: HashMap<Vec<u8>, BlockHeaderInfo>,
+	private_contexts: HashMap<(Vec<u8>, usize), Option<Context>>,
+	tx_log: BTreeMap<(Vec<u8>, u32), TxLogEntry>,
+	tx_log_ids: HashMap<Vec<u8>, u32>,
+	acct_paths: BTreeMap<String, AcctPathMapping>,
+	scan_checkpoint: Option<Option<ScanCheckpoint>>,
+	scanned_outputs: BTreeMap<u64, ScannedOutput>,
+	clear_scanned_outputs: bool,
+}
+
+impl WriteSet {
+	fn apply(self, s: &mut MemoryStore) {
+		for (key, out) in self.outputs {
+			match out {
+				Some(o) => s.outputs.insert(key, o),
+				None => s.outputs.remove(&key),
+			};
+		}
+		for (key, ctx) in self.private_contexts {
+			match ctx {
+				Some(c) => s.private_contexts.insert(key, c),
+				None => s.private_contexts.remove(&key),
+			};
+		}
+		// ids handed out by another batch committed in the meantime aren't
+		// handed out again
+		for (key, id) in self.tx_log_ids {
+			let next = s.tx_log_ids.entry(key).or_insert(0);
+			*next = cmp::max(*next, id);
+		}
+		s.deriv_indices.extend(self.deriv_indices);
+		s.confirmed_heights.extend(self.confirmed_heights);
+		s.confirmed_blocks.extend(self.confirmed_blocks);
+		s.tx_log.extend(self.tx_log);
+		s.acct_paths.extend(self.acct_paths);
+		if let Some(c) = self.scan_checkpoint {
+			s.scan_checkpoint = c;
+		}
+		if self.clear_scanned_outputs {
+			s.scanned_outputs.clear();
+		}
+		s.scanned_outputs.extend(self.scanned_outputs);
+	}
+}
+
+/// The records of a store table with a batch's changes made to them
+fn with_changes<T: Ord + Clone, V: Clone>(
+	stored: &BTreeMap<T, V>,
+	changes: &BTreeMap<T, Option<V>>,
+) -> Vec<V> {
+	let mut records = stored.clone();
+	for (key, value) in changes.iter() {
+		match value {
+			Some(v) => records.insert(key.clone(), v.clone()),
+			None => records.remove(key),
+		};
+	}
+	records.into_iter().map(|(_, v)| v).collect()
+}
+
+/// An atomic batch in which all changes can be committed all at once or
+/// discarded on error. Changes are collected in a write set, which reads in
+/// the batch see over the backend's store, and which is applied to the store
+/// under its lock on commit. Changes made to the store outside the batch in
+/// the meantime, by the backend or another batch, are kept. A batch dropped
+/// without being committed is simply discarded.
+pub struct Batch<'a, C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	store: &'a MemoryBackend<C, K>,
+	changes: RefCell<Option<WriteSet>>,
+	/// Keychain
+	keychain: Option<K>,
+}
+
+impl<'a, C, K> Batch<'a, C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	fn with_changes<F, T>(&self, f: F) -> Result<T, Error>
+	where
+		F: FnOnce(&mut WriteSet) -> T,
+	{
+		match self.changes.borrow_mut().as_mut() {
+			Some(c) => Ok(f(c)),
+			None => Err(ErrorKind::Backend("Batch used after commit".to_owned()).into()),
+		}
+	}
+}
+
+#[allow(missing_docs)]
+impl<'a, C, K> WalletOutputBatch<K> for Batch<'a, C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	fn keychain(&mut self) -> &mut K {
+		self.keychain.as_mut().unwrap()
+	}
+
+	fn save(&mut self, out: OutputData) -> Result<(), Error> {
+		self.with_changes(|c| {
+			c.outputs
+				.insert((out.key_id.to_bytes().to_vec(), out.mmr_index), Some(out))
+		})?;
+		Ok(())
+	}
+
+	fn get(&self, id: &Identifier, mmr_index: &Option<u64>) -> Result<OutputData, Error> {
+		let changed = self.with_changes(|c| {
+			c.outputs
+				.get(&(id.to_bytes().to_vec(), *mmr_index))
+				.cloned()
+		})?;
+		match changed {
+			Some(Some(o)) => Ok(o),
+			Some(None) => Err(not_found(&format!("Key Id: {}", id))),
+			None => self.store.store.lock().get_output(id, mmr_index),
+		}
+	}
+
+	fn iter(&self) -> Result<Box<dyn Iterator<Item = OutputData>>, Error> {
+		let store = self.store.store.lock();
+		let outputs = self.with_changes(|c| with_changes(&store.outputs, &c.outputs))?;
+		Ok(Box::new(outputs.into_iter()))
+	}
+
+	fn delete(&mut self, id: &Identifier, mmr_index: &Option<u64>) -> Result<(), Error> {
+		self.with_changes(|c| c.outputs.insert((id.to_bytes().to_vec(), *mmr_index), None))?;
+		Ok(())
+	}
+
+	fn next_tx_log_id(&mut self, parent_key_id: &Identifier) -> Result<u32, Error> {
+		let key = parent_key_id.to_bytes().to_vec();
+		let stored = self.store.store.lock().tx_log_ids.get(&key).cloned();
+		self.with_changes(|c| {
+			let id = c.tx_log_ids.entry(key).or_insert(stored.unwrap_or(0));
+			*id = *id + 1;
+			*id - 1
+		})
+	}
+
+	fn save_next_tx_log_id(&mut self, parent_key_id: &Identifier, id: u32) -> Result<(), Error> {
+		self.with_changes(|c| {
+			c.tx_log_ids.insert(parent_key_id.to_bytes().to_vec(), id);
+		})
+	}
+
+	fn tx_log_iter(&self) -> Result<Box<dyn Iterator<Item = TxLogEntry>>, Error> {
+		let mut entries = self.store.store.lock().tx_log.clone();
+		self.with_changes(|c| entries.extend(c.tx_log.clone()))?;
+		Ok(Box::new(entries.into_iter().map(|(_, t)| t)))
+	}
+
+	fn save_last_confirmed_height(
+		&mut self,
+		parent_key_id: &Identifier,
+		height: u64,
+	) -> Result<(), Error> {
+		self.with_changes(|c| {
+			c.confirmed_heights
+				.insert(parent_key_id.to_bytes().to_vec(), height)
+		})?;
+		Ok(())
+	}
+
+	fn save_last_confirmed_block(
+		&mut self,
+		parent_key_id: &Identifier,
+		block: &BlockHeaderInfo,
+	) -> Result<(), Error> {
+		self.with_changes(|c| {
+			c.confirmed_blocks
+				.insert(parent_key_id.to_bytes().to_vec(), block.clone())
+		})?;
+		Ok(())
+	}
+
+	fn save_child_index(&mut self, parent_id: &Identifier, child_n: u32) -> Result<(), Error> {
+		self.with_changes(|c| {
+			c.deriv_indices
+				.insert(parent_id.to_bytes().to_vec(), child_n)
+		})?;
+		Ok(())
+	}
+
+	fn save_tx_log_entry(
+		&mut self,
+		tx_in: TxLogEntry,
+		parent_id: &Identifier,
+	) -> Result<(), Error> {
+		self.with_changes(|c| {
+			c.tx_log
+				.insert((parent_id.to_bytes().to_vec(), tx_in.id), tx_in)
+		})?;
+		Ok(())
+	}
+
+	fn save_acct_path(&mut self, mapping: AcctPathMapping) -> Result<(), Error> {
+		self.with_changes(|c| c.acct_paths.insert(mapping.label.clone(), mapping))?;
+		Ok(())
+	}
+
+	fn acct_path_iter(&self) -> Result<Box<dyn Iterator<Item = AcctPathMapping>>, Error> {
+		let mut paths = self.store.store.lock().acct_paths.clone();
+		self.with_changes(|c| paths.extend(c.acct_paths.clone()))?;
+		Ok(Box::new(paths.into_iter().map(|(_, p)| p)))
+	}
+
+	fn lock_output(&mut self, out: &mut OutputData) -> Result<(), Error> {
+		out.lock();
+		self.save(out.clone())
+	}
+
+	fn save_private_context(
+		&mut self,
+		slate_id: &[u8],
+		participant_id: usize,
+		ctx: &Context,
+	) -> Result<(), Error> {
+		let (blind_xor_key, nonce_xor_key) = private_ctx_xor_keys(self.keychain(), slate_id)?;
+
+		let mut s_ctx = ctx.clone();
+		for i in 0..SECRET_KEY_SIZE {
+			s_ctx.sec_key.0[i] = s_ctx.sec_key.0[i] ^ blind_xor_key[i];
+			s_ctx.sec_nonce.0[i] = s_ctx.sec_nonce.0[i] ^ nonce_xor_key[i];
+		}
+
+		self.with_changes(|c| {
+			c.private_contexts
+				.insert((slate_id.to_vec(), participant_id), Some(s_ctx))
+		})?;
+		Ok(())
+	}
+
+	fn delete_private_context(
+		&mut self,
+		slate_id: &[u8],
+		participant_id: usize,
+	) -> Result<(), Error> {
+		let key = (slate_id.to_vec(), participant_id);
+		let stored = self.store.store.lock().private_contexts.contains_key(&key);
+		let existed = self.with_changes(|c| {
+			let existed = match c.private_contexts.get(&key) {
+				Some(ctx) => ctx.is_some(),
+				None => stored,
+			};
+			c.private_contexts.insert(key, None);
+			existed
+		})?;
+		match existed {
+			true => Ok(()),
+			false => Err(not_found(&format!("Slate id: {:x?}", slate_id.to_vec()))),
+		}
+	}
+
+	fn save_scan_checkpoint(&mut self, checkpoint: &ScanCheckpoint) -> Result<(), Error> {
+		self.with_changes(|c| c.scan_checkpoint = Some(Some(checkpoint.clone())))
+	}
+
+	fn save_scanned_output(&mut self, output: &ScannedOutput) -> Result<(), Error> {
+		self.with_changes(|c| c.scanned_outputs.insert(output.mmr_index, output.clone()))?;
+		Ok(())
+	}
+
+	fn clear_scan_checkpoint(&mut self) -> Result<(), Error> {
+		self.with_changes(|c| {
+			c.scan_checkpoint = Some(None);
+			c.scanned_outputs.clear();
+			c.clear_scanned_outputs = true;
+		})
+	}
+
+	fn commit(&self) -> Result<(), Error> {
+		match self.changes.replace(None) {
+			Some(c) => {
+				c.apply(&mut self.store.store.lock());
+				Ok(())
+			}
+			None => Err(ErrorKind::Backend("Batch committed twice".to_owned()).into()),
+		}
+	}
+}
@@ -0,0 +1,173 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Registry of wallet database schema migrations. Records are stored as
+//! JSON documents, so each migration rewrites the JSON of records written
+//! by older versions into the shape the current types expect.
+
+use serde_json::Value;
+
+/// Schema version written by this version of the wallet. Databases written
+/// before versioning was introduced have no version key and are version 0.
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+/// Kinds of JSON records kept in the wallet database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordType {
+	/// OutputData
+	Output,
+	/// TxLogEntry
+	TxLogEntry,
+	/// Private transaction Context
+	PrivateContext,
+	/// AcctPathMapping
+	AcctPathMapping,
+}
+
+/// A stored record loaded for migration
+pub struct MigrationRecord {
+	/// What the record holds
+	pub record_type: RecordType,
+	/// Database key the record is stored under
+	pub key: Vec<u8>,
+	/// Record contents
+	pub value: Value,
+	/// Whether any migration modified the record
+	pub changed: bool,
+}
+
+/// A single upgrade step, taking records from `version - 1` to `version`
+struct Migration {
+	version: u32,
+	description: &'static str,
+	/// Upgrade a single record in place, returning whether it was modified
+	migrate: fn(RecordType, &mut Value) -> bool,
+}
+
+/// All migrations, in version order
+const MIGRATIONS: &'static [Migration] = &[Migration {
+	version: 1,
+	description: "Add explicit null values for optional fields that have custom serializers",
+	migrate: v1_explicit_optional_fields,
+}];
+
+/// Number of records of each type changed by a migration step
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecordCounts {
+	/// Outputs
+	pub outputs: usize,
+	/// Transaction log entries
+	pub tx_log_entries: usize,
+	/// Private transaction contexts
+	pub private_contexts: usize,
+	/// Account mappings
+	pub accounts: usize,
+}
+
+impl RecordCounts {
+	fn add(&mut self, record_type: RecordType) {
+		match record_type {
+			RecordType::Output => self.outputs += 1,
+			RecordType::TxLogEntry => self.tx_log_entries += 1,
+			RecordType::PrivateContext => self.private_contexts += 1,
+			RecordType::AcctPathMapping => self.accounts += 1,
+		}
+	}
+}
+
+/// Outcome of a single migration step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationStep {
+	/// Version the step upgrades to
+	pub version: u32,
+	/// What the step does
+	pub description: String,
+	/// Records modified by the step
+	pub changed: RecordCounts,
+}
+
+/// Report of the migrations applied (or that would be applied) to a wallet
+/// database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationReport {
+	/// Schema version found in the database
+	pub from_version: u32,
+	/// Schema version after migration
+	pub to_version: u32,
+	/// Whether the changes were only computed and not written
+	pub dry_run: bool,
+	/// Steps run, in order
+	pub steps: Vec<MigrationStep>,
+}
+
+impl MigrationReport {
+	/// Whether the database is already at the current version
+	pub fn is_current(&self) -> bool {
+		self.from_version == self.to_version
+	}
+}
+
+/// Run every migration newer than `from_version` over the given records in
+/// order, marking the records that were modified
+pub fn apply_migrations(
+	from_version: u32,
+	records: &mut [MigrationRecord],
+	dry_run: bool,
+) -> MigrationReport {
+	let mut steps = vec![];
+	for m in MIGRATIONS.iter().filter(|m| m.version > from_version) {
+		let mut changed = RecordCounts::default();
+		for r in records.iter_mut() {
+			if (m.migrate)(r.record_type, &mut r.value) {
+				r.changed = true;
+				changed.add(r.record_type);
+			}
+		}
+		steps.push(MigrationStep {
+			version: m.version,
+			description: m.description.to_owned(),
+			changed,
+		});
+	}
+	MigrationReport {
+		from_version,
+		to_version: CURRENT_SCHEMA_VERSION,
+		dry_run,
+		steps,
+	}
+}
+
+/// Insert `null` for each missing field, returning whether any were missing
+fn insert_missing_nulls(value: &mut Value, fields: &[&str]) -> bool {
+	let mut changed = false;
+	if let Some(obj) = value.as_object_mut() {
+		for f in fields {
+			if !obj.contains_key(*f) {
+				obj.insert(f.to_string(), Value::Null);
+				changed = true;
+			}
+		}
+	}
+	changed
+}
+
+/// Optional fields deserialized with `#[serde(with = ...)]` are required to be
+/// present, so records written before they were added can't be read
+fn v1_explicit_optional_fields(record_type: RecordType, value: &mut Value) -> bool {
+	match record_type {
+		RecordType::Output => insert_missing_nulls(value, &["mmr_index"]),
+		RecordType::TxLogEntry => insert_missing_nulls(value, &["fee"]),
+		_ => false,
+	}
+}
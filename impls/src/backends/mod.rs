@@ -12,6 +12,265 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod archive;
+mod encryption;
 mod lmdb;
+mod lock;
+mod memory;
+mod migrations;
+mod sqlite;
 
-pub use self::lmdb::{wallet_db_exists, LMDBBackend};
+pub use self::archive::WALLET_ARCHIVE_VERSION;
+pub use self::lmdb::{migrate_wallet_db, LMDBBackend};
+pub use self::lock::{LockMode, WalletDirLock, LOCK_FILE};
+pub use self::memory::MemoryBackend;
+pub use self::migrations::{MigrationReport, MigrationStep, RecordCounts, CURRENT_SCHEMA_VERSION};
+pub use self::sqlite::SqliteBackend;
+
+// for writing storedtransaction files
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path;
+
+use failure::ResultExt;
+
+use crate::blake2::blake2b::Blake2b;
+
+use crate::core::core::Transaction;
+use crate::core::{global, ser};
+use crate::keychain::Keychain;
+use crate::libwallet::{Error, ErrorKind, RetentionPolicy, TxLogEntry};
+use crate::util;
+use crate::util::secp::constants::SECRET_KEY_SIZE;
+use crate::WalletSeed;
+use config::WalletConfig;
+
+use self::encryption::DbCipher;
+
+pub const DB_DIR: &'static str = "db";
+pub const TX_SAVE_DIR: &'static str = "saved_txs";
+/// Directory under the saved transaction directory that garbage collection
+/// moves the stored transactions of confirmed transactions to
+pub const TX_ARCHIVE_DIR: &'static str = "archive";
+
+/// test to see if database files exist in the current directory. If so,
+/// use a DB backend for all operations
+pub fn wallet_db_exists(config: WalletConfig) -> bool {
+	let db_path = path::Path::new(&config.data_file_dir).join(DB_DIR);
+	db_path.exists()
+}
+
+/// Helper to derive XOR keys for storing private transaction keys in the DB
+/// (blind_xor_key, nonce_xor_key)
+fn private_ctx_xor_keys<K>(
+	keychain: &K,
+	slate_id: &[u8],
+) -> Result<([u8; SECRET_KEY_SIZE], [u8; SECRET_KEY_SIZE]), Error>
+where
+	K: Keychain,
+{
+	let root_key = keychain.derive_key(0, &K::root_key_id())?;
+
+	// derive XOR values for storing secret values in DB
+	// h(root_key|slate_id|"blind")
+	let mut hasher = Blake2b::new(SECRET_KEY_SIZE);
+	hasher.update(&root_key.0[..]);
+	hasher.update(&slate_id[..]);
+	hasher.update(&"blind".as_bytes()[..]);
+	let blind_xor_key = hasher.finalize();
+	let mut ret_blind = [0; SECRET_KEY_SIZE];
+	ret_blind.copy_from_slice(&blind_xor_key.as_bytes()[0..SECRET_KEY_SIZE]);
+
+	// h(root_key|slate_id|"nonce")
+	let mut hasher = Blake2b::new(SECRET_KEY_SIZE);
+	hasher.update(&root_key.0[..]);
+	hasher.update(&slate_id[..]);
+	hasher.update(&"nonce".as_bytes()[..]);
+	let nonce_xor_key = hasher.finalize();
+	let mut ret_nonce = [0; SECRET_KEY_SIZE];
+	ret_nonce.copy_from_slice(&nonce_xor_key.as_bytes()[0..SECRET_KEY_SIZE]);
+
+	Ok((ret_blind, ret_nonce))
+}
+
+/// Decrypt the wallet seed file and derive the keychain from it. Decrypting
+/// the seed file runs its key derivation function, so backends do this once
+/// and keep the keychain rather than repeating it on every open.
+fn seed_keychain<K>(config: &WalletConfig, passphrase: &str) -> Result<K, Error>
+where
+	K: Keychain,
+{
+	let wallet_seed = WalletSeed::from_file(config, passphrase)
+		.context(ErrorKind::CallbackImpl("Error opening wallet"))?;
+	keychain_from_seed(&wallet_seed)
+}
+
+/// Derive the keychain from an already decrypted wallet seed
+fn keychain_from_seed<K>(wallet_seed: &WalletSeed) -> Result<K, Error>
+where
+	K: Keychain,
+{
+	let keychain = wallet_seed
+		.derive_keychain(global::is_floonet())
+		.context(ErrorKind::CallbackImpl("Error deriving keychain"))?;
+	Ok(keychain)
+}
+
+/// Prefix of stored transaction files encrypted with the database cipher
+const ENCRYPTED_TX_PREFIX: &'static str = "ENC1:";
+
+/// Write a completed transaction to the saved transaction directory,
+/// shared by the database backends, encrypted with the database cipher
+fn write_stored_tx(
+	config: &WalletConfig,
+	uuid: &str,
+	tx: &Transaction,
+	cipher: &DbCipher,
+) -> Result<(), Error> {
+	let filename = format!("{}.grintx", uuid);
+	let path = path::Path::new(&config.data_file_dir)
+		.join(TX_SAVE_DIR)
+		.join(&filename);
+	let tx_bin = ser::ser_vec(tx).unwrap();
+	let content = format!(
+		"{}{}",
+		ENCRYPTED_TX_PREFIX,
+		util::to_hex(cipher.encrypt(filename.as_bytes(), &tx_bin)?)
+	);
+	let mut stored_tx = File::create(path)?;
+	stored_tx.write_all(&content.as_bytes())?;
+	stored_tx.sync_all()?;
+	Ok(())
+}
+
+/// Decode the contents of a stored transaction file. Plain hex, as written
+/// by older wallets, is only read without a cipher, which is only while
+/// converting the files. Once a wallet is encrypted, a plaintext file is
+/// refused rather than trusted.
+fn decode_stored_tx(
+	filename: &str,
+	content: &str,
+	cipher: Option<&DbCipher>,
+) -> Result<Vec<u8>, Error> {
+	let content = content.trim();
+	match (content.starts_with(ENCRYPTED_TX_PREFIX), cipher) {
+		(false, None) => util::from_hex(content.to_owned()).map_err(|e| {
+			ErrorKind::Format(format!("Invalid stored tx {}: {}", filename, e)).into()
+		}),
+		(false, Some(_)) => Err(ErrorKind::Backend(format!(
+			"Stored tx {} isn't encrypted and won't be read",
+			filename
+		))
+		.into()),
+		(true, None) => Err(ErrorKind::Backend(format!(
+			"Stored tx {} is encrypted but no key is available",
+			filename
+		))
+		.into()),
+		(true, Some(c)) => {
+			let enc = util::from_hex(content[ENCRYPTED_TX_PREFIX.len()..].to_owned())
+				.map_err(|e| ErrorKind::Format(format!("Invalid stored tx {}: {}", filename, e)))?;
+			c.decrypt(filename.as_bytes(), &enc)
+		}
+	}
+}
+
+/// Read back the transaction stored for a tx log entry, if any. A file
+/// that's missing reads as none.
+fn read_stored_tx(
+	config: &WalletConfig,
+	entry: &TxLogEntry,
+	cipher: &DbCipher,
+) -> Result<Option<Transaction>, Error> {
+	let filename = match entry.stored_tx.clone() {
+		Some(f) => f,
+		None => return Ok(None),
+	};
+	let path = path::Path::new(&config.data_file_dir)
+		.join(TX_SAVE_DIR)
+		.join(&filename);
+	let mut tx_f = match File::open(path) {
+		Ok(f) => f,
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+		Err(e) => return Err(e.into()),
+	};
+	let mut content = String::new();
+	tx_f.read_to_string(&mut content)?;
+	let tx_bin = decode_stored_tx(&filename, &content, Some(cipher))?;
+	let tx = ser::deserialize::<Transaction>(&mut &tx_bin[..])
+		.map_err(|e| ErrorKind::Format(format!("Invalid stored tx {}: {}", filename, e)))?;
+	Ok(Some(tx))
+}
+
+/// Move the stored transaction file of a tx log entry to the archive
+/// directory, or delete it. A file that's already gone is left alone.
+fn remove_stored_tx_file(
+	config: &WalletConfig,
+	entry: &TxLogEntry,
+	archive: bool,
+) -> Result<(), Error> {
+	let filename = match entry.stored_tx.clone() {
+		Some(f) => f,
+		None => return Ok(()),
+	};
+	let dir = path::Path::new(&config.data_file_dir).join(TX_SAVE_DIR);
+	let path = dir.join(&filename);
+	if !path.exists() {
+		return Ok(());
+	}
+	match archive {
+		true => {
+			let archive_dir = dir.join(TX_ARCHIVE_DIR);
+			fs::create_dir_all(&archive_dir)?;
+			fs::rename(&path, archive_dir.join(&filename))?;
+		}
+		false => fs::remove_file(&path)?,
+	}
+	Ok(())
+}
+
+/// Garbage collection policy of a wallet, from its configuration
+fn retention_policy(config: &WalletConfig) -> RetentionPolicy {
+	RetentionPolicy {
+		context_max_age: config.private_context_retention_blocks(),
+		stored_txs: config.stored_tx_retention(),
+		min_confirmation_depth: config.gc_min_confirmation_depth(),
+		on_refresh: config.gc_on_refresh(),
+	}
+}
+
+/// Encrypt any stored transaction files still in plaintext. Each file is
+/// written to a temporary file first and renamed over the original, so an
+/// interrupted conversion leaves every file readable.
+fn encrypt_stored_tx_files(config: &WalletConfig, cipher: &DbCipher) -> Result<(), Error> {
+	let dir = path::Path::new(&config.data_file_dir).join(TX_SAVE_DIR);
+	if !dir.exists() {
+		return Ok(());
+	}
+	for entry in fs::read_dir(&dir)? {
+		let path = entry?.path();
+		let filename = match path.file_name().and_then(|f| f.to_str()) {
+			Some(f) if f.ends_with(".grintx") => f.to_owned(),
+			_ => continue,
+		};
+		let mut content = String::new();
+		File::open(&path)?.read_to_string(&mut content)?;
+		if content.starts_with(ENCRYPTED_TX_PREFIX) {
+			continue;
+		}
+		let tx_bin = decode_stored_tx(&filename, &content, None)?;
+		let enc = format!(
+			"{}{}",
+			ENCRYPTED_TX_PREFIX,
+			util::to_hex(cipher.encrypt(filename.as_bytes(), &tx_bin)?)
+		);
+		let tmp_path = dir.join(format!("{}.tmp", filename));
+		{
+			let mut tmp = File::create(&tmp_path)?;
+			tmp.write_all(enc.as_bytes())?;
+			tmp.sync_all()?;
+		}
+		fs::rename(&tmp_path, &path)?;
+	}
+	Ok(())
+}
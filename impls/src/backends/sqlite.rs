@@ -0,0 +1,1184 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet backend storing all wallet data in a single SQLite database file.
+//! Records are kept as the same values the LMDB backend writes, one table
+//! per record type, so either backend can be swapped in by config. As in
+//! the LMDB backend, every value is encrypted with the database cipher and
+//! account labels are only stored as keyed hashes.
+
+use std::cell::Cell;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use std::{fs, path};
+
+use failure::ResultExt;
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::keychain::{ChildNumber, ExtKeychain, Identifier, Keychain};
+
+use crate::core::core::Transaction;
+use crate::core::ser;
+use crate::libwallet::{check_repair, restore};
+use crate::libwallet::{
+	AcctPathMapping, ArchiveSummary, BlockHeaderInfo, CheckDiscrepancy, Context, Error, ErrorKind,
+	NodeClient, OutputData, RetentionPolicy, ScanCheckpoint, ScanStatus, ScannedOutput, TxLogEntry,
+	WalletBackend, WalletOutputBatch,
+};
+use crate::util;
+use crate::util::secp::constants::SECRET_KEY_SIZE;
+use crate::util::{Mutex, ZeroingString};
+use crate::WalletSeed;
+use config::WalletConfig;
+
+use super::archive::{export_wallet, import_wallet};
+use super::encryption::{DbCipher, EncryptedRecord};
+use super::lock::{LockMode, WalletDirLock};
+use super::{
+	encrypt_stored_tx_files, private_ctx_xor_keys, read_stored_tx, remove_stored_tx_file,
+	retention_policy, seed_keychain, write_stored_tx, DB_DIR, TX_SAVE_DIR,
+};
+
+/// Name of the database file, within the wallet db directory
+pub const SQLITE_DB_FILE: &'static str = "wallet.sqlite";
+
+/// How long to wait on a database locked by another process before failing
+const BUSY_TIMEOUT_SECS: u64 = 10;
+
+/// Stands in for an output stored without an MMR index, so it can still
+/// take part in the primary key
+const NO_MMR_INDEX: i64 = -1;
+
+const CREATE_TABLES: &'static str = "
+	CREATE TABLE IF NOT EXISTS outputs (
+		key_id BLOB NOT NULL,
+		mmr_index INTEGER NOT NULL,
+		data BLOB NOT NULL,
+		PRIMARY KEY (key_id, mmr_index)
+	);
+	CREATE TABLE IF NOT EXISTS deriv_indices (
+		parent_key_id BLOB PRIMARY KEY NOT NULL,
+		data BLOB NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS confirmed_heights (
+		parent_key_id BLOB PRIMARY KEY NOT NULL,
+		data BLOB NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS confirmed_blocks (
+		parent_key_id BLOB PRIMARY KEY NOT NULL,
+		data BLOB NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS private_contexts (
+		slate_id BLOB NOT NULL,
+		participant_id INTEGER NOT NULL,
+		data BLOB NOT NULL,
+		PRIMARY KEY (slate_id, participant_id)
+	);
+	CREATE TABLE IF NOT EXISTS tx_log (
+		parent_key_id BLOB NOT NULL,
+		id INTEGER NOT NULL,
+		data BLOB NOT NULL,
+		PRIMARY KEY (parent_key_id, id)
+	);
+	CREATE TABLE IF NOT EXISTS tx_log_ids (
+		parent_key_id BLOB PRIMARY KEY NOT NULL,
+		data BLOB NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS account_paths (
+		label BLOB PRIMARY KEY NOT NULL,
+		data BLOB NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS scan_checkpoint (
+		id INTEGER PRIMARY KEY CHECK (id = 0),
+		data BLOB NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS scanned_outputs (
+		mmr_index INTEGER PRIMARY KEY NOT NULL,
+		data BLOB NOT NULL
+	);
+";
+
+/// Marks the database as converted to encrypted values. Created in the same
+/// transaction as the encrypted tables.
+const CREATE_ENCRYPTION_MARKER: &'static str = "
+	CREATE TABLE encrypted (
+		id INTEGER PRIMARY KEY CHECK (id = 0)
+	);
+	INSERT INTO encrypted (id) VALUES (0);
+";
+
+/// A table holding one sealed value per row, in its `data` column
+struct Table {
+	name: &'static str,
+	/// Columns making up the row key, in order
+	key: &'static [&'static str],
+}
+
+const OUTPUTS: Table = Table {
+	name: "outputs",
+	key: &["key_id", "mmr_index"],
+};
+const DERIV_INDICES: Table = Table {
+	name: "deriv_indices",
+	key: &["parent_key_id"],
+};
+const CONFIRMED_HEIGHTS: Table = Table {
+	name: "confirmed_heights",
+	key: &["parent_key_id"],
+};
+const CONFIRMED_BLOCKS: Table = Table {
+	name: "confirmed_blocks",
+	key: &["parent_key_id"],
+};
+const PRIVATE_CONTEXTS: Table = Table {
+	name: "private_contexts",
+	key: &["slate_id", "participant_id"],
+};
+const TX_LOG: Table = Table {
+	name: "tx_log",
+	key: &["parent_key_id", "id"],
+};
+const TX_LOG_IDS: Table = Table {
+	name: "tx_log_ids",
+	key: &["parent_key_id"],
+};
+const ACCOUNT_PATHS: Table = Table {
+	name: "account_paths",
+	key: &["label"],
+};
+const SCAN_CHECKPOINT: Table = Table {
+	name: "scan_checkpoint",
+	key: &["id"],
+};
+const SCANNED_OUTPUTS: Table = Table {
+	name: "scanned_outputs",
+	key: &["mmr_index"],
+};
+
+impl Table {
+	fn where_clause(&self) -> String {
+		let cols: Vec<String> = self
+			.key
+			.iter()
+			.enumerate()
+			.map(|(i, c)| format!("{} = ?{}", c, i + 1))
+			.collect();
+		cols.join(" AND ")
+	}
+
+	/// Associated data for a value: the table name followed by the row key,
+	/// so values can't be moved between rows or tables undetected
+	fn row_ad(&self, key: &[Value]) -> Result<Vec<u8>, Error> {
+		let mut ad = self.name.as_bytes().to_vec();
+		for k in key {
+			match k {
+				Value::Blob(b) => ad.extend_from_slice(b),
+				Value::Integer(i) => ad.extend_from_slice(&i.to_be_bytes()),
+				_ => {
+					return Err(
+						ErrorKind::Backend(format!("Invalid key in table {}", self.name)).into(),
+					)
+				}
+			}
+		}
+		Ok(ad)
+	}
+
+	fn open<T: ser::Readable>(
+		&self,
+		cipher: &DbCipher,
+		key: &[Value],
+		data: Vec<u8>,
+	) -> Result<T, Error> {
+		let value = cipher
+			.open(&self.row_ad(key)?, &EncryptedRecord(data))
+			.map_err(|e| ErrorKind::Backend(format!("Unreadable wallet database record: {}", e)))?;
+		Ok(value)
+	}
+
+	/// Read and decrypt a single value, returning None if the row doesn't
+	/// exist
+	fn get<T: ser::Readable>(
+		&self,
+		conn: &Connection,
+		cipher: &DbCipher,
+		key: Vec<Value>,
+	) -> Result<Option<T>, Error> {
+		let sql = format!(
+			"SELECT data FROM {} WHERE {}",
+			self.name,
+			self.where_clause()
+		);
+		let data: Option<Vec<u8>> = conn
+			.query_row(&sql, &key, |row| row.get(0))
+			.optional()
+			.map_err(sql_err)?;
+		match data {
+			Some(d) => Ok(Some(self.open(cipher, &key, d)?)),
+			None => Ok(None),
+		}
+	}
+
+	/// Read and decrypt every value in the table, in key order. A value
+	/// that fails to authenticate is an error rather than left out, so a
+	/// corrupt database or the wrong key can't pass for an empty wallet.
+	fn all<T: ser::Readable>(&self, conn: &Connection, cipher: &DbCipher) -> Result<Vec<T>, Error> {
+		let cols = self.key.join(", ");
+		let sql = format!("SELECT data, {} FROM {} ORDER BY {}", cols, self.name, cols);
+		let mut stmt = conn.prepare(&sql).map_err(sql_err)?;
+		let key_len = self.key.len();
+		let rows = stmt
+			.query_map(NO_PARAMS, |row| {
+				let mut key = vec![];
+				for i in 0..key_len {
+					key.push(row.get::<_, Value>(i + 1)?);
+				}
+				Ok((row.get::<_, Vec<u8>>(0)?, key))
+			})
+			.map_err(sql_err)?;
+		let mut retval = vec![];
+		for r in rows {
+			let (data, key) = r.map_err(sql_err)?;
+			retval.push(self.open(cipher, &key, data)?);
+		}
+		Ok(retval)
+	}
+
+	/// Encrypt a value and write it to its row
+	fn put<T: ser::Writeable>(
+		&self,
+		conn: &Connection,
+		cipher: &DbCipher,
+		key: Vec<Value>,
+		value: &T,
+	) -> Result<(), Error> {
+		let record = cipher.seal(&self.row_ad(&key)?, value)?;
+		let sql = format!(
+			"INSERT OR REPLACE INTO {} ({}, data) VALUES ({})",
+			self.name,
+			self.key.join(", "),
+			(1..self.key.len() + 2)
+				.map(|i| format!("?{}", i))
+				.collect::<Vec<_>>()
+				.join(", ")
+		);
+		let mut params = key;
+		params.push(Value::Blob(record.0));
+		conn.execute(&sql, &params).map_err(sql_err)?;
+		Ok(())
+	}
+
+	fn delete(&self, conn: &Connection, key: Vec<Value>) -> Result<(), Error> {
+		let sql = format!("DELETE FROM {} WHERE {}", self.name, self.where_clause());
+		conn.execute(&sql, &key).map_err(sql_err)?;
+		Ok(())
+	}
+}
+
+fn sql_err(e: rusqlite::Error) -> Error {
+	ErrorKind::Backend(format!("SQLite Error: {}", e)).into()
+}
+
+fn not_found(field_name: &str) -> Error {
+	ErrorKind::Backend(format!("DB Not Found Error: {}", field_name)).into()
+}
+
+fn from_json<T: DeserializeOwned>(data: &str) -> Result<T, Error> {
+	serde_json::from_str(data).map_err(|e| ErrorKind::Format(format!("{}", e)).into())
+}
+
+fn id_key(id: &Identifier) -> Value {
+	Value::Blob(id.to_bytes().to_vec())
+}
+
+fn output_key(id: &Identifier, mmr_index: &Option<u64>) -> Vec<Value> {
+	let mmr_index = match mmr_index {
+		Some(i) => *i as i64,
+		None => NO_MMR_INDEX,
+	};
+	vec![id_key(id), Value::Integer(mmr_index)]
+}
+
+fn context_key(slate_id: &[u8], participant_id: usize) -> Vec<Value> {
+	vec![
+		Value::Blob(slate_id.to_vec()),
+		Value::Integer(participant_id as i64),
+	]
+}
+
+/// Account mappings are keyed by a hash of their label, so labels don't
+/// appear in plaintext in the database
+fn acct_key(cipher: &DbCipher, label: &str) -> Vec<Value> {
+	vec![Value::Blob(cipher.keyed_hash(label.as_bytes()))]
+}
+
+fn scan_checkpoint_key() -> Vec<Value> {
+	vec![Value::Integer(0)]
+}
+
+fn get_output(
+	conn: &Connection,
+	cipher: &DbCipher,
+	id: &Identifier,
+	mmr_index: &Option<u64>,
+) -> Result<OutputData, Error> {
+	OUTPUTS
+		.get(conn, cipher, output_key(id, mmr_index))?
+		.ok_or(not_found(&format!("Key Id: {}", id)))
+}
+
+fn all_private_context_ids(conn: &Connection) -> Result<Vec<(Vec<u8>, usize)>, Error> {
+	let mut stmt = conn
+		.prepare("SELECT slate_id, participant_id FROM private_contexts ORDER BY slate_id")
+		.map_err(sql_err)?;
+	let rows = stmt
+		.query_map(NO_PARAMS, |row| {
+			Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, i64>(1)? as usize))
+		})
+		.map_err(sql_err)?;
+	let mut retval = vec![];
+	for r in rows {
+		retval.push(r.map_err(sql_err)?);
+	}
+	Ok(retval)
+}
+
+fn put_acct_path(
+	conn: &Connection,
+	cipher: &DbCipher,
+	mapping: &AcctPathMapping,
+) -> Result<(), Error> {
+	ACCOUNT_PATHS.put(conn, cipher, acct_key(cipher, &mapping.label), mapping)
+}
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool, Error> {
+	let count: i64 = conn
+		.query_row(
+			"SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+			params![name],
+			|row| row.get(0),
+		)
+		.map_err(sql_err)?;
+	Ok(count > 0)
+}
+
+/// Whether the values in the database have been converted to encrypted form
+fn is_encrypted(conn: &Connection) -> Result<bool, Error> {
+	table_exists(conn, "encrypted")
+}
+
+/// Read the rows of a plaintext table, renamed out of the way of its
+/// encrypted table, with values stored either as JSON or as plain integers
+fn read_plaintext<T: DeserializeOwned>(
+	conn: &Connection,
+	table: &Table,
+	column: &str,
+) -> Result<Vec<(Vec<Value>, T)>, Error> {
+	let sql = format!(
+		"SELECT {}, {} FROM plaintext_{}",
+		column,
+		table.key.join(", "),
+		table.name
+	);
+	let mut stmt = conn.prepare(&sql).map_err(sql_err)?;
+	let key_len = table.key.len();
+	let rows = stmt
+		.query_map(NO_PARAMS, |row| {
+			let mut key = vec![];
+			for i in 0..key_len {
+				key.push(row.get::<_, Value>(i + 1)?);
+			}
+			Ok((row.get::<_, Value>(0)?, key))
+		})
+		.map_err(sql_err)?;
+	let mut retval = vec![];
+	for r in rows {
+		let (data, key) = r.map_err(sql_err)?;
+		let json = match data {
+			Value::Text(s) => s,
+			Value::Integer(i) => i.to_string(),
+			_ => {
+				return Err(ErrorKind::Format(format!(
+					"Unreadable plaintext {} record",
+					table.name
+				))
+				.into())
+			}
+		};
+		retval.push((key, from_json(&json)?));
+	}
+	Ok(retval)
+}
+
+/// Move the values of a plaintext table, if there is one, into its
+/// encrypted table
+fn seal_plaintext<T>(
+	conn: &Connection,
+	cipher: &DbCipher,
+	table: &Table,
+	column: &str,
+) -> Result<(), Error>
+where
+	T: DeserializeOwned + ser::Writeable,
+{
+	if !table_exists(conn, &format!("plaintext_{}", table.name))? {
+		return Ok(());
+	}
+	for (key, value) in read_plaintext::<T>(conn, table, column)? {
+		table.put(conn, cipher, key, &value)?;
+	}
+	conn.execute_batch(&format!("DROP TABLE plaintext_{}", table.name))
+		.map_err(sql_err)?;
+	Ok(())
+}
+
+/// Create the encrypted tables, moving the values of any plaintext tables
+/// into them. Returns whether there was anything to convert.
+fn seal_plaintext_tables(conn: &Connection, cipher: &DbCipher) -> Result<bool, Error> {
+	let mut converted = false;
+	for table in [
+		&OUTPUTS,
+		&DERIV_INDICES,
+		&CONFIRMED_HEIGHTS,
+		&CONFIRMED_BLOCKS,
+		&PRIVATE_CONTEXTS,
+		&TX_LOG,
+		&TX_LOG_IDS,
+		&ACCOUNT_PATHS,
+		&SCAN_CHECKPOINT,
+		&SCANNED_OUTPUTS,
+	]
+	.iter()
+	{
+		if table_exists(conn, table.name)? {
+			conn.execute_batch(&format!(
+				"ALTER TABLE {} RENAME TO plaintext_{}",
+				table.name, table.name
+			))
+			.map_err(sql_err)?;
+			converted = true;
+		}
+	}
+	conn.execute_batch(CREATE_TABLES).map_err(sql_err)?;
+
+	seal_plaintext::<OutputData>(conn, cipher, &OUTPUTS, "data")?;
+	seal_plaintext::<u32>(conn, cipher, &DERIV_INDICES, "child_index")?;
+	seal_plaintext::<u64>(conn, cipher, &CONFIRMED_HEIGHTS, "height")?;
+	seal_plaintext::<BlockHeaderInfo>(conn, cipher, &CONFIRMED_BLOCKS, "data")?;
+	seal_plaintext::<Context>(conn, cipher, &PRIVATE_CONTEXTS, "data")?;
+	seal_plaintext::<TxLogEntry>(conn, cipher, &TX_LOG, "data")?;
+	seal_plaintext::<u32>(conn, cipher, &TX_LOG_IDS, "next_id")?;
+	seal_plaintext::<ScanCheckpoint>(conn, cipher, &SCAN_CHECKPOINT, "data")?;
+	seal_plaintext::<ScannedOutput>(conn, cipher, &SCANNED_OUTPUTS, "data")?;
+
+	// Account mappings are re-keyed by the hash of their label
+	if table_exists(conn, "plaintext_account_paths")? {
+		for (_, mapping) in read_plaintext::<AcctPathMapping>(conn, &ACCOUNT_PATHS, "data")? {
+			put_acct_path(conn, cipher, &mapping)?;
+		}
+		conn.execute_batch("DROP TABLE plaintext_account_paths")
+			.map_err(sql_err)?;
+	}
+
+	conn.execute_batch(CREATE_ENCRYPTION_MARKER)
+		.map_err(sql_err)?;
+	Ok(converted)
+}
+
+/// One-time conversion of a database written before values were encrypted,
+/// which also creates the tables of a new database. Stored transaction files
+/// are converted first, then all tables are rewritten in one transaction
+/// along with the marker recording that the conversion is done, so an
+/// interrupted conversion is simply run again.
+fn encrypt_plaintext_db(
+	conn: &Connection,
+	cipher: &DbCipher,
+	config: &WalletConfig,
+) -> Result<(), Error> {
+	if is_encrypted(conn)? {
+		return Ok(());
+	}
+	encrypt_stored_tx_files(config, cipher)?;
+	conn.execute_batch("BEGIN IMMEDIATE").map_err(sql_err)?;
+	let converted = match seal_plaintext_tables(conn, cipher) {
+		Ok(c) => c,
+		Err(e) => {
+			if let Err(e) = conn.execute_batch("ROLLBACK") {
+				error!("Failed to roll back wallet database conversion: {}", e);
+			}
+			return Err(e);
+		}
+	};
+	conn.execute_batch("COMMIT").map_err(sql_err)?;
+	if converted {
+		info!("Wallet database values encrypted");
+	}
+	Ok(())
+}
+
+pub struct SqliteBackend<C, K> {
+	db: Mutex<Connection>,
+	/// Lock on the wallet data directory, held while the backend is open
+	_lock: WalletDirLock,
+	/// Whether the backend was opened for reading only
+	read_only: bool,
+	config: WalletConfig,
+	/// passphrase: TODO better ways of dealing with this other than storing
+	passphrase: ZeroingString,
+	/// Key for values stored in the database, derived from the seed
+	cipher: Option<DbCipher>,
+	/// Keychain derived from the seed, kept for the life of the backend so
+	/// opening the wallet doesn't decrypt the seed file again
+	seed_keychain: Option<K>,
+	/// Keychain
+	pub keychain: Option<K>,
+	/// Parent path to use by default for output operations
+	parent_key_id: Identifier,
+	/// wallet to node client
+	w2n_client: C,
+}
+
+impl<C, K> SqliteBackend<C, K>
+where
+	K: Keychain,
+{
+	pub fn new(config: WalletConfig, passphrase: &str, n_client: C) -> Result<Self, Error> {
+		let lock = WalletDirLock::acquire(&config.data_file_dir, LockMode::Exclusive)?;
+
+		let db_path = path::Path::new(&config.data_file_dir).join(DB_DIR);
+		fs::create_dir_all(&db_path).expect("Couldn't create wallet backend directory!");
+
+		let stored_tx_path = path::Path::new(&config.data_file_dir).join(TX_SAVE_DIR);
+		fs::create_dir_all(&stored_tx_path)
+			.expect("Couldn't create wallet backend tx storage directory!");
+
+		let conn = Connection::open(db_path.join(SQLITE_DB_FILE)).map_err(sql_err)?;
+		conn.busy_timeout(Duration::from_secs(BUSY_TIMEOUT_SECS))
+			.map_err(sql_err)?;
+
+		let res = SqliteBackend {
+			db: Mutex::new(conn),
+			_lock: lock,
+			read_only: false,
+			config: config.clone(),
+			passphrase: ZeroingString::from(passphrase),
+			cipher: None,
+			seed_keychain: None,
+			keychain: None,
+			parent_key_id: SqliteBackend::<C, K>::default_path(),
+			w2n_client: n_client,
+		};
+		res.try_unlock()
+	}
+
+	/// Open an existing wallet database for reading only. The wallet
+	/// directory lock is shared with other readers, and any attempt to write
+	/// fails.
+	pub fn new_read_only(
+		config: WalletConfig,
+		passphrase: &str,
+		n_client: C,
+	) -> Result<Self, Error> {
+		if !SqliteBackend::<C, K>::exists(config.clone()) {
+			return Err(ErrorKind::Backend(format!(
+				"No wallet database found in {}",
+				config.data_file_dir
+			))
+			.into());
+		}
+		let lock = WalletDirLock::acquire(&config.data_file_dir, LockMode::Shared)?;
+
+		let db_path = path::Path::new(&config.data_file_dir).join(DB_DIR);
+		let conn = Connection::open(db_path.join(SQLITE_DB_FILE)).map_err(sql_err)?;
+		conn.busy_timeout(Duration::from_secs(BUSY_TIMEOUT_SECS))
+			.map_err(sql_err)?;
+
+		let res = SqliteBackend {
+			db: Mutex::new(conn),
+			_lock: lock,
+			read_only: true,
+			config: config.clone(),
+			passphrase: ZeroingString::from(passphrase),
+			cipher: None,
+			seed_keychain: None,
+			keychain: None,
+			parent_key_id: SqliteBackend::<C, K>::default_path(),
+			w2n_client: n_client,
+		};
+		res.try_unlock()
+	}
+
+	/// The database can only be read with a key derived from the seed. If
+	/// the seed can't be read yet, unlock when the wallet is first opened
+	fn try_unlock(mut self) -> Result<Self, Error> {
+		match seed_keychain::<K>(&self.config, &self.passphrase) {
+			Ok(k) => {
+				self.unlock(DbCipher::from_keychain(&k)?)?;
+				self.seed_keychain = Some(k);
+			}
+			Err(e) => debug!("Wallet database locked until opened: {}", e),
+		}
+		Ok(self)
+	}
+
+	/// Set the database cipher, converting any plaintext values first
+	fn unlock(&mut self, cipher: DbCipher) -> Result<(), Error> {
+		if self.read_only {
+			if !is_encrypted(&self.db.lock())? {
+				return Err(ErrorKind::Backend(
+					"Wallet database must be upgraded by opening it for writing first".to_owned(),
+				)
+				.into());
+			}
+			self.cipher = Some(cipher);
+			return Ok(());
+		}
+		{
+			let conn = self.db.lock();
+			encrypt_plaintext_db(&conn, &cipher, &self.config)?;
+
+			// Make sure default wallet derivation path always exists
+			let default_account = AcctPathMapping {
+				label: "default".to_owned(),
+				path: SqliteBackend::<C, K>::default_path(),
+			};
+			put_acct_path(&conn, &cipher, &default_account)?;
+		}
+		self.cipher = Some(cipher);
+		Ok(())
+	}
+}
+
+impl<C, K> SqliteBackend<C, K> {
+	fn default_path() -> Identifier {
+		// return the default parent wallet path, corresponding to the default account
+		// in the BIP32 spec. Parent is account 0 at level 2, child output identifiers
+		// are all at level 3
+		ExtKeychain::derive_key_id(2, 0, 0, 0, 0)
+	}
+
+	/// Just test to see if the database file exists in the wallet directory
+	pub fn exists(config: WalletConfig) -> bool {
+		path::Path::new(&config.data_file_dir)
+			.join(DB_DIR)
+			.join(SQLITE_DB_FILE)
+			.exists()
+	}
+
+	fn cipher(&self) -> Result<&DbCipher, Error> {
+		match self.cipher.as_ref() {
+			Some(c) => Ok(c),
+			None => Err(ErrorKind::Backend(
+				"Wallet database is locked until the wallet is opened".to_owned(),
+			)
+			.into()),
+		}
+	}
+
+	/// Read a counter kept per parent key id, which starts at 0
+	fn get_parent_value<T>(&self, table: &Table, parent_key_id: &Identifier) -> Result<T, Error>
+	where
+		T: ser::Readable + Default,
+	{
+		let res = table.get(&self.db.lock(), self.cipher()?, vec![id_key(parent_key_id)])?;
+		Ok(res.unwrap_or_default())
+	}
+
+	fn check_writable(&self) -> Result<(), Error> {
+		match self.read_only {
+			true => Err(ErrorKind::ReadOnly.into()),
+			false => Ok(()),
+		}
+	}
+}
+
+impl<C, K> WalletBackend<C, K> for SqliteBackend<C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	/// Initialise with the keychain derived from the seed, only decrypting
+	/// the seed file if that couldn't be done when the backend was created
+	fn open_with_credentials(&mut self) -> Result<(), Error> {
+		let keychain = match self.seed_keychain.clone() {
+			Some(k) => k,
+			None => {
+				let k: K = seed_keychain(&self.config, &self.passphrase)?;
+				self.unlock(DbCipher::from_keychain(&k)?)?;
+				self.seed_keychain = Some(k.clone());
+				k
+			}
+		};
+		self.keychain = Some(keychain);
+		Ok(())
+	}
+
+	/// Close wallet, wiping the keychain it was opened with
+	fn close(&mut self) -> Result<(), Error> {
+		self.keychain = None;
+		Ok(())
+	}
+
+	/// Return the keychain being used
+	fn keychain(&mut self) -> &mut K {
+		self.keychain.as_mut().unwrap()
+	}
+
+	/// Return the node client being used
+	fn w2n_client(&mut self) -> &mut C {
+		&mut self.w2n_client
+	}
+
+	/// return the version of the commit for caching
+	fn calc_commit_for_cache(
+		&mut self,
+		amount: u64,
+		id: &Identifier,
+	) -> Result<Option<String>, Error> {
+		if self.config.no_commit_cache == Some(true) {
+			Ok(None)
+		} else {
+			Ok(Some(util::to_hex(
+				self.keychain().commit(amount, &id)?.0.to_vec(),
+			)))
+		}
+	}
+
+	/// Set parent path by account name
+	fn set_parent_key_id_by_name(&mut self, label: &str) -> Result<(), Error> {
+		match self.get_acct_path(label.to_owned())? {
+			Some(a) => {
+				self.set_parent_key_id(a.path);
+				Ok(())
+			}
+			None => Err(ErrorKind::UnknownAccountLabel(label.to_owned()).into()),
+		}
+	}
+
+	/// set parent path
+	fn set_parent_key_id(&mut self, id: Identifier) {
+		self.parent_key_id = id;
+	}
+
+	fn parent_key_id(&mut self) -> Identifier {
+		self.parent_key_id.clone()
+	}
+
+	fn get(&self, id: &Identifier, mmr_index: &Option<u64>) -> Result<OutputData, Error> {
+		get_output(&self.db.lock(), self.cipher()?, id, mmr_index)
+	}
+
+	fn iter<'a>(&'a self) -> Result<Box<dyn Iterator<Item = OutputData> + 'a>, Error> {
+		let outputs: Vec<OutputData> = OUTPUTS.all(&self.db.lock(), self.cipher()?)?;
+		Ok(Box::new(outputs.into_iter()))
+	}
+
+	fn get_tx_log_entry(&self, u: &Uuid) -> Result<Option<TxLogEntry>, Error> {
+		let entries: Vec<TxLogEntry> = TX_LOG.all(&self.db.lock(), self.cipher()?)?;
+		Ok(entries.into_iter().find(|t| t.tx_slate_id == Some(*u)))
+	}
+
+	fn tx_log_iter<'a>(&'a self) -> Result<Box<dyn Iterator<Item = TxLogEntry> + 'a>, Error> {
+		let entries: Vec<TxLogEntry> = TX_LOG.all(&self.db.lock(), self.cipher()?)?;
+		Ok(Box::new(entries.into_iter()))
+	}
+
+	fn get_private_context(
+		&mut self,
+		slate_id: &[u8],
+		participant_id: usize,
+	) -> Result<Context, Error> {
+		let (blind_xor_key, nonce_xor_key) = private_ctx_xor_keys(self.keychain(), slate_id)?;
+
+		let res: Option<Context> = PRIVATE_CONTEXTS.get(
+			&self.db.lock(),
+			self.cipher()?,
+			context_key(slate_id, participant_id),
+		)?;
+		let mut ctx = res.ok_or(not_found(&format!("Slate id: {:x?}", slate_id.to_vec())))?;
+
+		for i in 0..SECRET_KEY_SIZE {
+			ctx.sec_key.0[i] = ctx.sec_key.0[i] ^ blind_xor_key[i];
+			ctx.sec_nonce.0[i] = ctx.sec_nonce.0[i] ^ nonce_xor_key[i];
+		}
+
+		Ok(ctx)
+	}
+
+	fn private_context_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = (Vec<u8>, usize)> + 'a>, Error> {
+		Ok(Box::new(
+			all_private_context_ids(&self.db.lock())?.into_iter(),
+		))
+	}
+
+	fn acct_path_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = AcctPathMapping> + 'a>, Error> {
+		let paths: Vec<AcctPathMapping> = ACCOUNT_PATHS.all(&self.db.lock(), self.cipher()?)?;
+		Ok(Box::new(paths.into_iter()))
+	}
+
+	fn get_acct_path(&self, label: String) -> Result<Option<AcctPathMapping>, Error> {
+		let cipher = self.cipher()?;
+		ACCOUNT_PATHS.get(&self.db.lock(), cipher, acct_key(cipher, &label))
+	}
+
+	fn store_tx(&self, uuid: &str, tx: &Transaction) -> Result<(), Error> {
+		self.check_writable()?;
+		write_stored_tx(&self.config, uuid, tx, self.cipher()?)
+	}
+
+	fn get_stored_tx(&self, entry: &TxLogEntry) -> Result<Option<Transaction>, Error> {
+		read_stored_tx(&self.config, entry, self.cipher()?)
+	}
+
+	fn remove_stored_tx(&self, entry: &TxLogEntry, archive: bool) -> Result<(), Error> {
+		self.check_writable()?;
+		remove_stored_tx_file(&self.config, entry, archive)
+	}
+
+	fn retention_policy(&self) -> RetentionPolicy {
+		retention_policy(&self.config)
+	}
+
+	fn batch<'a>(&'a mut self) -> Result<Box<dyn WalletOutputBatch<K> + 'a>, Error> {
+		self.check_writable()?;
+		Ok(Box::new(Batch::new(self)?))
+	}
+
+	fn next_child<'a>(&mut self) -> Result<Identifier, Error> {
+		let parent_key_id = self.parent_key_id.clone();
+		let mut deriv_idx: u32 = self.get_parent_value(&DERIV_INDICES, &parent_key_id)?;
+		let mut return_path = self.parent_key_id.to_path();
+		return_path.depth = return_path.depth + 1;
+		return_path.path[return_path.depth as usize - 1] = ChildNumber::from(deriv_idx);
+		deriv_idx = deriv_idx + 1;
+		let mut batch = self.batch()?;
+		batch.save_child_index(&parent_key_id, deriv_idx)?;
+		batch.commit()?;
+		Ok(Identifier::from_path(&return_path))
+	}
+
+	fn last_confirmed_height<'a>(&mut self) -> Result<u64, Error> {
+		self.get_parent_value(&CONFIRMED_HEIGHTS, &self.parent_key_id)
+	}
+
+	fn last_confirmed_block(
+		&self,
+		parent_key_id: &Identifier,
+	) -> Result<Option<BlockHeaderInfo>, Error> {
+		CONFIRMED_BLOCKS.get(&self.db.lock(), self.cipher()?, vec![id_key(parent_key_id)])
+	}
+
+	fn current_child_index<'a>(&mut self) -> Result<u32, Error> {
+		self.get_parent_value(&DERIV_INDICES, &self.parent_key_id)
+	}
+
+	fn scan_checkpoint(&self) -> Result<Option<ScanCheckpoint>, Error> {
+		SCAN_CHECKPOINT.get(&self.db.lock(), self.cipher()?, scan_checkpoint_key())
+	}
+
+	fn scanned_output_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = ScannedOutput> + 'a>, Error> {
+		let outputs: Vec<ScannedOutput> = SCANNED_OUTPUTS.all(&self.db.lock(), self.cipher()?)?;
+		Ok(Box::new(outputs.into_iter()))
+	}
+
+	fn birthday_height(&self) -> Result<Option<u64>, Error> {
+		let height = WalletSeed::file_birthday_height(&self.config)
+			.context(ErrorKind::CallbackImpl("Error reading wallet seed file"))?;
+		Ok(height)
+	}
+
+	fn restore(
+		&mut self,
+		start_height: Option<u64>,
+		account_labels: &[AcctPathMapping],
+		status_send: &Option<Sender<ScanStatus>>,
+	) -> Result<(), Error> {
+		let threads = self.config.restore_threads();
+		restore(self, start_height, account_labels, threads, status_send)
+			.context(ErrorKind::Restore)?;
+		Ok(())
+	}
+
+	fn check_repair(
+		&mut self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+		dry_run: bool,
+		status_send: &Option<Sender<ScanStatus>>,
+	) -> Result<Vec<CheckDiscrepancy>, Error> {
+		let threads = self.config.restore_threads();
+		let discrepancies = check_repair(
+			self,
+			delete_unconfirmed,
+			start_height,
+			dry_run,
+			threads,
+			status_send,
+		)
+		.context(ErrorKind::Restore)?;
+		Ok(discrepancies)
+	}
+
+	fn change_password(&mut self, old_password: &str, new_password: &str) -> Result<(), Error> {
+		self.check_writable()?;
+		WalletSeed::change_password(&self.config, old_password, new_password)
+			.context(ErrorKind::CallbackImpl("Error changing wallet password"))?;
+		self.passphrase = ZeroingString::from(new_password);
+		Ok(())
+	}
+
+	fn export_archive(&mut self, path: &str) -> Result<ArchiveSummary, Error> {
+		export_wallet(self, path)
+	}
+
+	fn import_archive(&mut self, path: &str) -> Result<ArchiveSummary, Error> {
+		import_wallet(self, path)
+	}
+}
+
+/// An atomic batch in which all changes can be committed all at once or
+/// discarded on error. Backed by a SQLite transaction which is rolled back
+/// if the batch is dropped without being committed.
+pub struct Batch<'a, C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	store: &'a SqliteBackend<C, K>,
+	committed: Cell<bool>,
+	/// Keychain
+	keychain: Option<K>,
+	/// Database cipher
+	cipher: DbCipher,
+}
+
+impl<'a, C, K> Batch<'a, C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	fn new(store: &'a SqliteBackend<C, K>) -> Result<Self, Error> {
+		let cipher = store.cipher()?.clone();
+		store
+			.db
+			.lock()
+			.execute_batch("BEGIN IMMEDIATE")
+			.map_err(sql_err)?;
+		Ok(Batch {
+			store,
+			committed: Cell::new(false),
+			keychain: store.keychain.clone(),
+			cipher,
+		})
+	}
+
+	fn put<T: ser::Writeable>(
+		&self,
+		table: &Table,
+		key: Vec<Value>,
+		value: &T,
+	) -> Result<(), Error> {
+		table.put(&self.store.db.lock(), &self.cipher, key, value)
+	}
+
+	fn get_value<T: ser::Readable>(
+		&self,
+		table: &Table,
+		key: Vec<Value>,
+	) -> Result<Option<T>, Error> {
+		table.get(&self.store.db.lock(), &self.cipher, key)
+	}
+
+	fn all<T: ser::Readable>(&self, table: &Table) -> Result<Vec<T>, Error> {
+		table.all(&self.store.db.lock(), &self.cipher)
+	}
+}
+
+impl<'a, C, K> Drop for Batch<'a, C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	fn drop(&mut self) {
+		if !self.committed.get() {
+			if let Err(e) = self.store.db.lock().execute_batch("ROLLBACK") {
+				error!("Failed to roll back wallet batch: {}", e);
+			}
+		}
+	}
+}
+
+#[allow(missing_docs)]
+impl<'a, C, K> WalletOutputBatch<K> for Batch<'a, C, K>
+where
+	C: NodeClient,
+	K: Keychain,
+{
+	fn keychain(&mut self) -> &mut K {
+		self.keychain.as_mut().unwrap()
+	}
+
+	fn save(&mut self, out: OutputData) -> Result<(), Error> {
+		self.put(&OUTPUTS, output_key(&out.key_id, &out.mmr_index), &out)
+	}
+
+	fn get(&self, id: &Identifier, mmr_index: &Option<u64>) -> Result<OutputData, Error> {
+		get_output(&self.store.db.lock(), &self.cipher, id, mmr_index)
+	}
+
+	fn iter(&self) -> Result<Box<dyn Iterator<Item = OutputData>>, Error> {
+		let outputs: Vec<OutputData> = self.all(&OUTPUTS)?;
+		Ok(Box::new(outputs.into_iter()))
+	}
+
+	fn delete(&mut self, id: &Identifier, mmr_index: &Option<u64>) -> Result<(), Error> {
+		OUTPUTS.delete(&self.store.db.lock(), output_key(id, mmr_index))
+	}
+
+	fn next_tx_log_id(&mut self, parent_key_id: &Identifier) -> Result<u32, Error> {
+		let last_tx_log_id: u32 = self
+			.get_value(&TX_LOG_IDS, vec![id_key(parent_key_id)])?
+			.unwrap_or(0);
+		self.put(
+			&TX_LOG_IDS,
+			vec![id_key(parent_key_id)],
+			&(last_tx_log_id + 1),
+		)?;
+		Ok(last_tx_log_id)
+	}
+
+	fn save_next_tx_log_id(&mut self, parent_key_id: &Identifier, id: u32) -> Result<(), Error> {
+		self.put(&TX_LOG_IDS, vec![id_key(parent_key_id)], &id)
+	}
+
+	fn tx_log_iter(&self) -> Result<Box<dyn Iterator<Item = TxLogEntry>>, Error> {
+		let entries: Vec<TxLogEntry> = self.all(&TX_LOG)?;
+		Ok(Box::new(entries.into_iter()))
+	}
+
+	fn save_last_confirmed_height(
+		&mut self,
+		parent_key_id: &Identifier,
+		height: u64,
+	) -> Result<(), Error> {
+		self.put(&CONFIRMED_HEIGHTS, vec![id_key(parent_key_id)], &height)
+	}
+
+	fn save_last_confirmed_block(
+		&mut self,
+		parent_key_id: &Identifier,
+		block: &BlockHeaderInfo,
+	) -> Result<(), Error> {
+		self.put(&CONFIRMED_BLOCKS, vec![id_key(parent_key_id)], block)
+	}
+
+	fn save_child_index(&mut self, parent_id: &Identifier, child_n: u32) -> Result<(), Error> {
+		self.put(&DERIV_INDICES, vec![id_key(parent_id)], &child_n)
+	}
+
+	fn save_tx_log_entry(
+		&mut self,
+		tx_in: TxLogEntry,
+		parent_id: &Identifier,
+	) -> Result<(), Error> {
+		self.put(
+			&TX_LOG,
+			vec![id_key(parent_id), Value::Integer(tx_in.id as i64)],
+			&tx_in,
+		)
+	}
+
+	fn save_acct_path(&mut self, mapping: AcctPathMapping) -> Result<(), Error> {
+		put_acct_path(&self.store.db.lock(), &self.cipher, &mapping)
+	}
+
+	fn acct_path_iter(&self) -> Result<Box<dyn Iterator<Item = AcctPathMapping>>, Error> {
+		let paths: Vec<AcctPathMapping> = self.all(&ACCOUNT_PATHS)?;
+		Ok(Box::new(paths.into_iter()))
+	}
+
+	fn lock_output(&mut self, out: &mut OutputData) -> Result<(), Error> {
+		out.lock();
+		self.save(out.clone())
+	}
+
+	fn save_private_context(
+		&mut self,
+		slate_id: &[u8],
+		participant_id: usize,
+		ctx: &Context,
+	) -> Result<(), Error> {
+		let (blind_xor_key, nonce_xor_key) = private_ctx_xor_keys(self.keychain(), slate_id)?;
+
+		let mut s_ctx = ctx.clone();
+		for i in 0..SECRET_KEY_SIZE {
+			s_ctx.sec_key.0[i] = s_ctx.sec_key.0[i] ^ blind_xor_key[i];
+			s_ctx.sec_nonce.0[i] = s_ctx.sec_nonce.0[i] ^ nonce_xor_key[i];
+		}
+
+		self.put(
+			&PRIVATE_CONTEXTS,
+			context_key(slate_id, participant_id),
+			&s_ctx,
+		)
+	}
+
+	fn delete_private_context(
+		&mut self,
+		slate_id: &[u8],
+		participant_id: usize,
+	) -> Result<(), Error> {
+		PRIVATE_CONTEXTS.delete(&self.store.db.lock(), context_key(slate_id, participant_id))
+	}
+
+	fn save_scan_checkpoint(&mut self, checkpoint: &ScanCheckpoint) -> Result<(), Error> {
+		self.put(&SCAN_CHECKPOINT, scan_checkpoint_key(), checkpoint)
+	}
+
+	fn save_scanned_output(&mut self, output: &ScannedOutput) -> Result<(), Error> {
+		self.put(
+			&SCANNED_OUTPUTS,
+			vec![Value::Integer(output.mmr_index as i64)],
+			output,
+		)
+	}
+
+	fn clear_scan_checkpoint(&mut self) -> Result<(), Error> {
+		let conn = self.store.db.lock();
+		conn.execute("DELETE FROM scanned_outputs", NO_PARAMS)
+			.map_err(sql_err)?;
+		conn.execute("DELETE FROM scan_checkpoint", NO_PARAMS)
+			.map_err(sql_err)?;
+		Ok(())
+	}
+
+	fn commit(&self) -> Result<(), Error> {
+		self.store
+			.db
+			.lock()
+			.execute_batch("COMMIT")
+			.map_err(sql_err)?;
+		self.committed.set(true);
+		Ok(())
+	}
+}
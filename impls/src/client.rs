@@ -0,0 +1,329 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Outbound HTTP requests made by the wallet, to nodes and to other
+//! wallets. Given the address of a SOCKS5 proxy, such as Tor's, requests
+//! are made through it, over TLS for https addresses as usual. Otherwise
+//! they go straight to their destination.
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, ToSocketAddrs};
+use std::time::Duration;
+
+use futures::future::{self, Either};
+use futures::{stream, Future, Stream};
+use hyper::client::connect::{Connect, Connected, Destination};
+use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use hyper::{Body, Client, Request, Uri};
+use hyper_rustls::HttpsConnector;
+use rustls::ClientConfig;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio::timer::Timeout;
+
+use crate::api;
+use crate::util::to_base64;
+
+/// How long to wait on a request made through the proxy before giving up
+const PROXY_TIMEOUT_SECS: u64 = 20;
+
+type ResponseFuture = Box<dyn Future<Item = String, Error = api::Error> + Send>;
+
+/// GET a JSON value
+pub fn get<T>(
+	url: &str,
+	api_secret: Option<String>,
+	socks_proxy: Option<&str>,
+) -> Result<T, api::Error>
+where
+	T: DeserializeOwned,
+{
+	match socks_proxy {
+		Some(proxy) => {
+			let req = build_request(url, "GET", api_secret)?;
+			parse_response(&run(send_through_proxy(proxy, req))?)
+		}
+		None => api::client::get(url, api_secret),
+	}
+}
+
+/// GET a JSON value from each of the given URLs at once
+pub fn get_many<T>(
+	urls: Vec<String>,
+	api_secret: Option<String>,
+	socks_proxy: Option<&str>,
+) -> Result<Vec<T>, api::Error>
+where
+	T: DeserializeOwned + Send + 'static,
+{
+	let tasks: Vec<Box<dyn Future<Item = T, Error = api::Error> + Send>> = match socks_proxy {
+		Some(proxy) => {
+			let mut tasks = vec![];
+			for u in urls.iter() {
+				let req = build_request(u, "GET", api_secret.clone())?;
+				let task = send_through_proxy(proxy, req).and_then(|res| parse_response(&res));
+				tasks.push(Box::new(task) as Box<dyn Future<Item = T, Error = api::Error> + Send>);
+			}
+			tasks
+		}
+		None => urls
+			.iter()
+			.map(|u| api::client::get_async::<T>(u.as_str(), api_secret.clone()))
+			.collect(),
+	};
+	run(stream::futures_unordered(tasks).collect())
+}
+
+/// POST a JSON value, returning the response body
+pub fn post<IN>(
+	url: &str,
+	api_secret: Option<String>,
+	socks_proxy: Option<&str>,
+	input: &IN,
+) -> Result<String, api::Error>
+where
+	IN: Serialize,
+{
+	let req = api::client::create_post_request(url, api_secret, input)?;
+	match socks_proxy {
+		Some(proxy) => run(send_through_proxy(proxy, req)),
+		None => api::client::send_request(req),
+	}
+}
+
+/// POST a JSON value, ignoring the response body
+pub fn post_no_ret<IN>(
+	url: &str,
+	api_secret: Option<String>,
+	socks_proxy: Option<&str>,
+	input: &IN,
+) -> Result<(), api::Error>
+where
+	IN: Serialize,
+{
+	match socks_proxy {
+		Some(_) => post(url, api_secret, socks_proxy, input).map(|_| ()),
+		None => api::client::post_no_ret(url, api_secret, input),
+	}
+}
+
+fn request_error(msg: String) -> api::Error {
+	api::ErrorKind::RequestError(msg).into()
+}
+
+fn parse_response<T: DeserializeOwned>(res: &str) -> Result<T, api::Error> {
+	serde_json::from_str(res)
+		.map_err(|e| api::ErrorKind::ResponseError(format!("Cannot parse response {}", e)).into())
+}
+
+fn run<F>(task: F) -> Result<F::Item, api::Error>
+where
+	F: Future<Error = api::Error> + Send + 'static,
+	F::Item: Send + 'static,
+{
+	let mut rt = Runtime::new()
+		.map_err(|e| api::ErrorKind::Internal(format!("can't create Tokio runtime: {}", e)))?;
+	rt.block_on(task)
+}
+
+/// Build a request without a body the same way the node API client does
+fn build_request(
+	url: &str,
+	method: &str,
+	api_secret: Option<String>,
+) -> Result<Request<Body>, api::Error> {
+	let uri = url
+		.parse::<Uri>()
+		.map_err(|e| api::ErrorKind::Argument(format!("Invalid url {}: {}", url, e)))?;
+	let mut builder = Request::builder();
+	if let Some(api_secret) = api_secret {
+		let basic_auth = format!("Basic {}", to_base64(&format!("grin:{}", api_secret)));
+		builder.header(AUTHORIZATION, basic_auth);
+	}
+	builder
+		.method(method)
+		.uri(uri)
+		.header(USER_AGENT, "grin-client")
+		.header(ACCEPT, "application/json")
+		.header(CONTENT_TYPE, "application/json")
+		.body(Body::empty())
+		.map_err(|e| request_error(format!("Bad request {} {}: {}", method, url, e)))
+}
+
+/// Make a request through the proxy, returning the response body
+fn send_through_proxy(proxy: &str, req: Request<Body>) -> ResponseFuture {
+	let mut tls_config = ClientConfig::new();
+	tls_config
+		.root_store
+		.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+	let connector = HttpsConnector::from((
+		SocksConnector {
+			proxy: proxy.to_owned(),
+		},
+		tls_config,
+	));
+	let client = Client::builder().build::<_, Body>(connector);
+
+	let url = req.uri().to_string();
+	let proxy = proxy.to_owned();
+	let task = client
+		.request(req)
+		.map_err(move |e| {
+			request_error(format!(
+				"Cannot make request to {} through SOCKS proxy {}: {}",
+				url, proxy, e
+			))
+		})
+		.and_then(|resp| {
+			let status = resp.status();
+			resp.into_body()
+				.concat2()
+				.map_err(|e| request_error(format!("Cannot read response body: {}", e)))
+				.and_then(move |body| {
+					let body = String::from_utf8_lossy(&body.to_vec()).to_string();
+					match status.is_success() {
+						true => Ok(body),
+						false => Err(request_error(format!(
+							"Wrong response code: {} with data {:?}",
+							status, body
+						))),
+					}
+				})
+		});
+	Box::new(
+		Timeout::new(task, Duration::from_secs(PROXY_TIMEOUT_SECS)).map_err(|e| {
+			match e.into_inner() {
+				Some(e) => e,
+				None => request_error("Request through SOCKS proxy timed out".to_owned()),
+			}
+		}),
+	)
+}
+
+/// Connects to a request's destination through a SOCKS5 proxy, for hyper to
+/// send the request over. Host names are left to the proxy to resolve, so
+/// they don't leak to the local resolver.
+#[derive(Clone)]
+struct SocksConnector {
+	/// Address of the proxy, host:port
+	proxy: String,
+}
+
+type IoFuture<T> = Box<dyn Future<Item = T, Error = io::Error> + Send>;
+
+fn socks_error(msg: String) -> io::Error {
+	io::Error::new(io::ErrorKind::Other, msg)
+}
+
+/// Request for the proxy to connect to the given host and port
+fn socks_connect_request(host: &str, port: u16) -> io::Result<Vec<u8>> {
+	let mut req = vec![5, 1, 0];
+	let bare_host = host.trim_start_matches('[').trim_end_matches(']');
+	if let Ok(ip) = bare_host.parse::<Ipv4Addr>() {
+		req.push(1);
+		req.extend_from_slice(&ip.octets());
+	} else if let Ok(ip) = bare_host.parse::<Ipv6Addr>() {
+		req.push(4);
+		req.extend_from_slice(&ip.octets());
+	} else {
+		if host.len() > 255 {
+			return Err(socks_error(format!("Host name {} is too long", host)));
+		}
+		req.push(3);
+		req.push(host.len() as u8);
+		req.extend_from_slice(host.as_bytes());
+	}
+	req.extend_from_slice(&[(port >> 8) as u8, port as u8]);
+	Ok(req)
+}
+
+/// Read the proxy's reply to a connect request, up to the end of the
+/// address it bound, which isn't needed
+fn read_socks_reply(stream: TcpStream, dest: String) -> IoFuture<TcpStream> {
+	let task = tokio::io::read_exact(stream, [0u8; 4]).and_then(move |(stream, reply)| {
+		if reply[1] != 0 {
+			let reason = match reply[1] {
+				1 => "general failure",
+				2 => "connection not allowed",
+				3 => "network unreachable",
+				4 => "host unreachable",
+				5 => "connection refused",
+				6 => "TTL expired",
+				7 => "command not supported",
+				8 => "address type not supported",
+				_ => "unknown error",
+			};
+			let msg = format!("SOCKS proxy couldn't connect to {}: {}", dest, reason);
+			return Either::A(future::err(socks_error(msg)));
+		}
+		let addr_len: IoFuture<(TcpStream, usize)> = match reply[3] {
+			1 => Box::new(future::ok((stream, 4))),
+			4 => Box::new(future::ok((stream, 16))),
+			3 => Box::new(
+				tokio::io::read_exact(stream, [0u8; 1])
+					.map(|(stream, len)| (stream, len[0] as usize)),
+			),
+			_ => Box::new(future::err(socks_error(
+				"Invalid SOCKS proxy reply".to_owned(),
+			))),
+		};
+		Either::B(addr_len.and_then(|(stream, len)| {
+			tokio::io::read_exact(stream, vec![0u8; len + 2]).map(|(stream, _)| stream)
+		}))
+	});
+	Box::new(task)
+}
+
+impl Connect for SocksConnector {
+	type Transport = TcpStream;
+	type Error = io::Error;
+	type Future = IoFuture<(TcpStream, Connected)>;
+
+	fn connect(&self, dst: Destination) -> Self::Future {
+		let host = dst.host().to_owned();
+		let port = dst.port().unwrap_or(match dst.scheme() {
+			"https" => 443,
+			_ => 80,
+		});
+		let dest = format!("{}:{}", host, port);
+		let connect_req = match socks_connect_request(&host, port) {
+			Ok(r) => r,
+			Err(e) => return Box::new(future::err(e)),
+		};
+		let proxy = match self.proxy.to_socket_addrs().map(|mut a| a.next()) {
+			Ok(Some(a)) => a,
+			_ => {
+				let msg = format!("Invalid SOCKS proxy address {}", self.proxy);
+				return Box::new(future::err(socks_error(msg)));
+			}
+		};
+
+		// version 5, offering no authentication
+		let task = TcpStream::connect(&proxy)
+			.and_then(|stream| tokio::io::write_all(stream, [5u8, 1, 0]))
+			.and_then(|(stream, _)| tokio::io::read_exact(stream, [0u8; 2]))
+			.and_then(|(stream, reply)| match reply {
+				[5, 0] => Ok(stream),
+				_ => Err(socks_error(
+					"SOCKS proxy requires authentication".to_owned(),
+				)),
+			})
+			.and_then(move |stream| tokio::io::write_all(stream, connect_req))
+			.and_then(move |(stream, _)| read_socks_reply(stream, dest))
+			.map(|stream| (stream, Connected::new()));
+		Box::new(task)
+	}
+}
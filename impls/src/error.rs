@@ -69,6 +69,10 @@ pub enum ErrorKind {
 	#[fail(display = "BIP39 Mnemonic (word list) Error")]
 	Mnemonic,
 
+	/// Seed backup share couldn't be used
+	#[fail(display = "Invalid seed share: {}", _0)]
+	InvalidShare(String),
+
 	/// Command line argument error
 	#[fail(display = "{}", _0)]
 	ArgumentError(String),
@@ -21,6 +21,8 @@ use blake2_rfc as blake2;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate log;
 use grin_wallet_libwallet as libwallet;
 use grin_wallet_util::grin_api as api;
@@ -33,36 +35,104 @@ extern crate grin_wallet_config as config;
 
 mod adapters;
 mod backends;
+mod client;
 mod error;
 mod node_clients;
 mod seed;
+mod seed_shares;
 pub mod test_framework;
 
 pub use crate::adapters::{
 	FileWalletCommAdapter, HTTPWalletCommAdapter, KeybaseWalletCommAdapter, NullWalletCommAdapter,
 	WalletCommAdapter,
 };
-pub use crate::backends::{wallet_db_exists, LMDBBackend};
+pub use crate::backends::{
+	migrate_wallet_db, wallet_db_exists, LMDBBackend, LockMode, MemoryBackend, MigrationReport,
+	MigrationStep, RecordCounts, SqliteBackend, WalletDirLock, CURRENT_SCHEMA_VERSION, LOCK_FILE,
+	WALLET_ARCHIVE_VERSION,
+};
 pub use crate::error::{Error, ErrorKind};
-pub use crate::node_clients::HTTPNodeClient;
-pub use crate::seed::{EncryptedWalletSeed, WalletSeed, SEED_FILE};
+pub use crate::node_clients::{
+	HTTPNodeClient, MultiNodeClient, NodeAnswer, NodeExchange, NodeFixture, NodeHealth, NodeOutput,
+	NodePmmrOutput, NodeRequest, RecordingNodeClient, ReplayNodeClient, NODE_FIXTURE_VERSION,
+};
+pub use crate::seed::{EncryptedWalletSeed, SeedKdf, WalletSeed, SEED_FILE, SEED_FILE_VERSION};
+pub use crate::seed_shares::{SeedShare, MAX_SHARES};
 
 use crate::util::Mutex;
 use std::sync::Arc;
 
+use config::WalletBackendType;
 use libwallet::{NodeClient, WalletBackend, WalletInst};
 
-/// Helper to create an instance of the LMDB wallet
+/// Helper to create an instance of the wallet, using the storage backend
+/// selected in the wallet config. The wallet directory is locked for
+/// writing, so fails if another process has the wallet open.
 pub fn instantiate_wallet(
 	wallet_config: config::WalletConfig,
 	node_client: impl NodeClient + 'static,
 	passphrase: &str,
 	account: &str,
 ) -> Result<Arc<Mutex<WalletInst<impl NodeClient, keychain::ExtKeychain>>>, Error> {
+	instantiate_wallet_with_lock(
+		wallet_config,
+		node_client,
+		passphrase,
+		account,
+		LockMode::Exclusive,
+	)
+}
+
+/// Helper to create an instance of the wallet, locking the wallet directory
+/// as given. With a shared lock, other processes can also open the wallet
+/// for reading, and any attempt to write to the wallet fails.
+pub fn instantiate_wallet_with_lock<C>(
+	wallet_config: config::WalletConfig,
+	node_client: C,
+	passphrase: &str,
+	account: &str,
+	lock_mode: LockMode,
+) -> Result<Arc<Mutex<WalletInst<C, keychain::ExtKeychain>>>, Error>
+where
+	C: NodeClient + 'static,
+{
 	// First test decryption, so we can abort early if we have the wrong password
-	let _ = WalletSeed::from_file(&wallet_config, passphrase)?;
-	let mut db_wallet = LMDBBackend::new(wallet_config.clone(), passphrase, node_client)?;
-	db_wallet.set_parent_key_id_by_name(account)?;
-	info!("Using LMDB Backend for wallet");
-	Ok(Arc::new(Mutex::new(db_wallet)))
+	let seed = WalletSeed::from_file(&wallet_config, passphrase)?;
+	let backend = wallet_config.wallet_backend();
+	let wallet: Arc<Mutex<WalletInst<_, keychain::ExtKeychain>>> = match backend {
+		WalletBackendType::Lmdb => {
+			let mut db_wallet: LMDBBackend<_, keychain::ExtKeychain> = LMDBBackend::with_seed(
+				wallet_config.clone(),
+				passphrase,
+				&seed,
+				node_client,
+				lock_mode,
+			)?;
+			db_wallet.set_parent_key_id_by_name(account)?;
+			info!("Using LMDB Backend for wallet");
+			Arc::new(Mutex::new(db_wallet))
+		}
+		WalletBackendType::Sqlite => {
+			let mut db_wallet: SqliteBackend<_, keychain::ExtKeychain> = match lock_mode {
+				LockMode::Exclusive => {
+					SqliteBackend::new(wallet_config.clone(), passphrase, node_client)?
+				}
+				LockMode::Shared => {
+					SqliteBackend::new_read_only(wallet_config.clone(), passphrase, node_client)?
+				}
+			};
+			db_wallet.set_parent_key_id_by_name(account)?;
+			info!("Using SQLite Backend for wallet");
+			Arc::new(Mutex::new(db_wallet))
+		}
+		// nothing would be kept once the wallet exits, so it's only for the
+		// test framework to create directly
+		WalletBackendType::Memory => {
+			return Err(ErrorKind::ArgumentError(
+				"The in-memory wallet backend is only available to tests".to_owned(),
+			)
+			.into());
+		}
+	};
+	Ok(wallet)
 }
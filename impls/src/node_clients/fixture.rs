@@ -0,0 +1,649 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! NodeClients recording what's asked of a node and how it answers to a
+//! fixture file, and answering from such a fixture without a node, so a
+//! wallet's run against a live node can be replayed in a test. A fixture
+//! file is JSON lines: a header giving the format version and node, then
+//! one request and its answer per line, in the order they were made.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use failure::ResultExt;
+
+use crate::core::libtx::secp_ser;
+use crate::libwallet;
+use crate::libwallet::{BlockHeaderInfo, NodeClient, TxWrapper};
+use crate::util;
+use crate::util::secp::pedersen;
+use crate::util::Mutex;
+
+/// Version of the fixture file format
+pub const NODE_FIXTURE_VERSION: u16 = 2;
+
+type OutputMap = HashMap<pedersen::Commitment, (String, u64, u64)>;
+type PmmrOutputs = (
+	u64,
+	u64,
+	Vec<(pedersen::Commitment, pedersen::RangeProof, bool, u64, u64)>,
+);
+
+/// A request made of a node. Commitments looked up are sorted, as the order
+/// the wallet gives them in isn't meaningful.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum NodeRequest {
+	/// Post a transaction
+	PostTx {
+		/// The transaction, hex encoded
+		tx_hex: String,
+		/// Whether to skip the stem phase
+		fluff: bool,
+	},
+	/// Get the chain height
+	GetChainHeight,
+	/// Look up outputs by commitment
+	GetOutputsFromNode {
+		/// The commitments, hex encoded
+		commits: Vec<String>,
+	},
+	/// List outputs by PMMR index
+	GetOutputsByPmmrIndex {
+		/// The first index
+		start_height: u64,
+		/// The number of outputs to list
+		max_outputs: u64,
+	},
+	/// Get the PMMR indices of the outputs in a range of blocks
+	HeightRangeToPmmrIndices {
+		/// First block height
+		start_height: u64,
+		/// Last block height, if not the chain tip
+		end_height: Option<u64>,
+	},
+	/// Get the headers of blocks by height
+	GetBlockHeaders {
+		/// The block heights
+		heights: Vec<u64>,
+	},
+}
+
+/// An output found by commitment
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeOutput {
+	/// The commitment, hex encoded
+	pub commit: String,
+	/// Height of the block the output is in
+	pub height: u64,
+	/// PMMR index of the output
+	pub mmr_index: u64,
+}
+
+/// An output listed by PMMR index
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodePmmrOutput {
+	/// The commitment
+	#[serde(
+		serialize_with = "secp_ser::as_hex",
+		deserialize_with = "secp_ser::commitment_from_hex"
+	)]
+	pub commit: pedersen::Commitment,
+	/// The range proof
+	#[serde(
+		serialize_with = "secp_ser::as_hex",
+		deserialize_with = "secp_ser::rangeproof_from_hex"
+	)]
+	pub proof: pedersen::RangeProof,
+	/// Whether it's a coinbase output
+	pub is_coinbase: bool,
+	/// Height of the block the output is in
+	pub height: u64,
+	/// PMMR index of the output
+	pub mmr_index: u64,
+}
+
+/// A node's answer to a request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum NodeAnswer {
+	/// The transaction was accepted
+	Posted,
+	/// The chain height
+	ChainHeight(u64),
+	/// The outputs found, sorted by commitment
+	Outputs(Vec<NodeOutput>),
+	/// A page of outputs by PMMR index
+	OutputsByPmmrIndex {
+		/// Last available output index
+		highest_index: u64,
+		/// Last index listed
+		last_retrieved_index: u64,
+		/// The outputs listed
+		outputs: Vec<NodePmmrOutput>,
+	},
+	/// First and last output PMMR index
+	PmmrIndices(u64, u64),
+	/// The block headers
+	BlockHeaders(Vec<BlockHeaderInfo>),
+}
+
+/// A request, and the answer or error the node gave
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeExchange {
+	/// The request
+	pub request: NodeRequest,
+	/// The answer, or the error as reported
+	pub response: Result<NodeAnswer, String>,
+}
+
+/// First line of a fixture file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct NodeFixtureHeader {
+	version: u16,
+	node_url: String,
+}
+
+/// What was asked of a node and how it answered, in order
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeFixture {
+	/// Version of the file format
+	pub version: u16,
+	/// Address of the node recorded
+	pub node_url: String,
+	/// Requests and answers, in the order they were made
+	pub exchanges: Vec<NodeExchange>,
+}
+
+impl NodeFixture {
+	/// An empty fixture for the given node
+	pub fn new(node_url: &str) -> NodeFixture {
+		NodeFixture {
+			version: NODE_FIXTURE_VERSION,
+			node_url: node_url.to_owned(),
+			exchanges: vec![],
+		}
+	}
+
+	/// Read a fixture file
+	pub fn from_file(path: &str) -> Result<NodeFixture, libwallet::Error> {
+		let invalid =
+			|| libwallet::ErrorKind::Format(format!("Invalid node fixture file {}", path));
+		let file = File::open(path).context(libwallet::ErrorKind::IO)?;
+		let mut lines = BufReader::new(file).lines();
+		let header = match lines.next() {
+			Some(l) => l.context(libwallet::ErrorKind::IO)?,
+			None => return Err(invalid())?,
+		};
+		let header: NodeFixtureHeader = serde_json::from_str(&header).context(invalid())?;
+		if header.version > NODE_FIXTURE_VERSION {
+			let msg = format!(
+				"Node fixture {} is version {}, newer than the {} supported",
+				path, header.version, NODE_FIXTURE_VERSION
+			);
+			return Err(libwallet::ErrorKind::Format(msg))?;
+		}
+		let mut fixture = NodeFixture::new(&header.node_url);
+		fixture.version = header.version;
+		for line in lines {
+			let line = line.context(libwallet::ErrorKind::IO)?;
+			if line.trim().is_empty() {
+				continue;
+			}
+			fixture
+				.exchanges
+				.push(serde_json::from_str(&line).context(invalid())?);
+		}
+		Ok(fixture)
+	}
+
+	fn header_line(&self) -> Result<String, libwallet::Error> {
+		let header = NodeFixtureHeader {
+			version: self.version,
+			node_url: self.node_url.clone(),
+		};
+		to_line(&header)
+	}
+
+	/// Write the fixture to a file, replacing it only once fully written
+	pub fn write_file(&self, path: &str) -> Result<(), libwallet::Error> {
+		let mut data = self.header_line()?;
+		for e in self.exchanges.iter() {
+			data.push_str(&to_line(e)?);
+		}
+		let tmp_path = format!("{}.tmp", path);
+		{
+			let mut file = File::create(&tmp_path).context(libwallet::ErrorKind::IO)?;
+			file.write_all(data.as_bytes())
+				.context(libwallet::ErrorKind::IO)?;
+			file.sync_all().context(libwallet::ErrorKind::IO)?;
+		}
+		fs::rename(&tmp_path, Path::new(path)).context(libwallet::ErrorKind::IO)?;
+		Ok(())
+	}
+}
+
+/// A line of a fixture file
+fn to_line<T: serde::Serialize>(value: &T) -> Result<String, libwallet::Error> {
+	let mut line = serde_json::to_string(value).context(libwallet::ErrorKind::Format(
+		"Can't serialize node fixture".to_owned(),
+	))?;
+	line.push('\n');
+	Ok(line)
+}
+
+fn commit_hexes(commits: &[pedersen::Commitment]) -> Vec<String> {
+	let mut hexes: Vec<String> = commits
+		.iter()
+		.map(|c| util::to_hex(c.as_ref().to_vec()))
+		.collect();
+	hexes.sort();
+	hexes
+}
+
+/// What's been recorded, and the fixture file being appended to once the
+/// first request is made
+struct Recording {
+	fixture: NodeFixture,
+	file: Option<File>,
+}
+
+/// NodeClient passing every request on to another, and appending the
+/// request and its answer to a fixture file as it goes, so what's recorded
+/// survives a run that fails part way
+#[derive(Clone)]
+pub struct RecordingNodeClient<C>
+where
+	C: NodeClient,
+{
+	inner: C,
+	path: String,
+	recording: Arc<Mutex<Recording>>,
+}
+
+impl<C> RecordingNodeClient<C>
+where
+	C: NodeClient,
+{
+	/// Record the requests made of the given client to the fixture file at
+	/// `path`, replacing any already there when the first request is made
+	pub fn new(inner: C, path: &str) -> RecordingNodeClient<C> {
+		let fixture = NodeFixture::new(inner.node_url());
+		RecordingNodeClient {
+			inner,
+			path: path.to_owned(),
+			recording: Arc::new(Mutex::new(Recording {
+				fixture,
+				file: None,
+			})),
+		}
+	}
+
+	/// What's been recorded so far
+	pub fn fixture(&self) -> NodeFixture {
+		self.recording.lock().fixture.clone()
+	}
+
+	/// Append an exchange to the fixture file, starting it with the header
+	/// if it's the first
+	fn append(
+		&self,
+		recording: &mut Recording,
+		exchange: &NodeExchange,
+	) -> Result<(), libwallet::Error> {
+		let mut data = String::new();
+		if recording.file.is_none() {
+			data.push_str(&recording.fixture.header_line()?);
+			let file = File::create(&self.path).context(libwallet::ErrorKind::IO)?;
+			recording.file = Some(file);
+		}
+		data.push_str(&to_line(exchange)?);
+		if let Some(ref mut file) = recording.file {
+			file.write_all(data.as_bytes())
+				.context(libwallet::ErrorKind::IO)?;
+		}
+		Ok(())
+	}
+
+	fn record<T, F>(&self, request: NodeRequest, res: &Result<T, libwallet::Error>, answer: F)
+	where
+		F: FnOnce(&T) -> NodeAnswer,
+	{
+		let response = match res {
+			Ok(r) => Ok(answer(r)),
+			Err(e) => Err(format!("{}", e)),
+		};
+		let exchange = NodeExchange { request, response };
+		let mut recording = self.recording.lock();
+		if let Err(e) = self.append(&mut recording, &exchange) {
+			error!("Recording node fixture to {}: {}", self.path, e);
+		}
+		recording.fixture.exchanges.push(exchange);
+	}
+}
+
+impl<C> NodeClient for RecordingNodeClient<C>
+where
+	C: NodeClient,
+{
+	fn node_url(&self) -> &str {
+		self.inner.node_url()
+	}
+	fn node_api_secret(&self) -> Option<String> {
+		self.inner.node_api_secret()
+	}
+
+	fn set_node_url(&mut self, node_url: &str) {
+		self.inner.set_node_url(node_url);
+		self.recording.lock().fixture.node_url = node_url.to_owned();
+	}
+
+	fn set_node_api_secret(&mut self, node_api_secret: Option<String>) {
+		self.inner.set_node_api_secret(node_api_secret);
+	}
+
+	fn socks_proxy(&self) -> Option<String> {
+		self.inner.socks_proxy()
+	}
+
+	fn post_tx(&self, tx: &TxWrapper, fluff: bool) -> Result<(), libwallet::Error> {
+		let res = self.inner.post_tx(tx, fluff);
+		let request = NodeRequest::PostTx {
+			tx_hex: tx.tx_hex.clone(),
+			fluff,
+		};
+		self.record(request, &res, |_| NodeAnswer::Posted);
+		res
+	}
+
+	fn get_chain_height(&self) -> Result<u64, libwallet::Error> {
+		let res = self.inner.get_chain_height();
+		self.record(NodeRequest::GetChainHeight, &res, |h| {
+			NodeAnswer::ChainHeight(*h)
+		});
+		res
+	}
+
+	fn get_outputs_from_node(
+		&self,
+		wallet_outputs: Vec<pedersen::Commitment>,
+	) -> Result<OutputMap, libwallet::Error> {
+		let request = NodeRequest::GetOutputsFromNode {
+			commits: commit_hexes(&wallet_outputs),
+		};
+		let res = self.inner.get_outputs_from_node(wallet_outputs);
+		self.record(request, &res, |outputs| {
+			let mut found: Vec<NodeOutput> = outputs
+				.values()
+				.map(|(commit, height, mmr_index)| NodeOutput {
+					commit: commit.clone(),
+					height: *height,
+					mmr_index: *mmr_index,
+				})
+				.collect();
+			found.sort_by(|a, b| a.commit.cmp(&b.commit));
+			NodeAnswer::Outputs(found)
+		});
+		res
+	}
+
+	fn get_outputs_by_pmmr_index(
+		&self,
+		start_height: u64,
+		max_outputs: u64,
+	) -> Result<PmmrOutputs, libwallet::Error> {
+		let res = self
+			.inner
+			.get_outputs_by_pmmr_index(start_height, max_outputs);
+		let request = NodeRequest::GetOutputsByPmmrIndex {
+			start_height,
+			max_outputs,
+		};
+		self.record(request, &res, |(highest, last, outputs)| {
+			NodeAnswer::OutputsByPmmrIndex {
+				highest_index: *highest,
+				last_retrieved_index: *last,
+				outputs: outputs
+					.iter()
+					.map(
+						|(commit, proof, is_coinbase, height, mmr_index)| NodePmmrOutput {
+							commit: commit.clone(),
+							proof: proof.clone(),
+							is_coinbase: *is_coinbase,
+							height: *height,
+							mmr_index: *mmr_index,
+						},
+					)
+					.collect(),
+			}
+		});
+		res
+	}
+
+	fn height_range_to_pmmr_indices(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<(u64, u64), libwallet::Error> {
+		let res = self
+			.inner
+			.height_range_to_pmmr_indices(start_height, end_height);
+		let request = NodeRequest::HeightRangeToPmmrIndices {
+			start_height,
+			end_height,
+		};
+		self.record(request, &res, |(first, last)| {
+			NodeAnswer::PmmrIndices(*first, *last)
+		});
+		res
+	}
+
+	fn get_block_headers(
+		&self,
+		heights: Vec<u64>,
+	) -> Result<Vec<BlockHeaderInfo>, libwallet::Error> {
+		let request = NodeRequest::GetBlockHeaders {
+			heights: heights.clone(),
+		};
+		let res = self.inner.get_block_headers(heights);
+		self.record(request, &res, |headers| {
+			NodeAnswer::BlockHeaders(headers.clone())
+		});
+		res
+	}
+}
+
+/// NodeClient answering from a recorded fixture, without a node. Each
+/// request is given the answer recorded for the first unplayed exchange
+/// making the same request, so requests that don't depend on each other
+/// may come in a different order than recorded. Once those are used up the
+/// last of them is given again, the fixture's chain being fixed in time. A
+/// request never recorded is an error.
+#[derive(Clone)]
+pub struct ReplayNodeClient {
+	node_url: String,
+	node_api_secret: Option<String>,
+	exchanges: Arc<Vec<NodeExchange>>,
+	played: Arc<Mutex<Vec<bool>>>,
+}
+
+impl ReplayNodeClient {
+	/// Replay the given fixture
+	pub fn new(fixture: NodeFixture) -> ReplayNodeClient {
+		ReplayNodeClient {
+			node_url: fixture.node_url,
+			node_api_secret: None,
+			played: Arc::new(Mutex::new(vec![false; fixture.exchanges.len()])),
+			exchanges: Arc::new(fixture.exchanges),
+		}
+	}
+
+	/// Replay the fixture file at `path`
+	pub fn from_file(path: &str) -> Result<ReplayNodeClient, libwallet::Error> {
+		Ok(ReplayNodeClient::new(NodeFixture::from_file(path)?))
+	}
+
+	/// The recorded requests not yet replayed, in the order they were made
+	pub fn unplayed(&self) -> Vec<NodeRequest> {
+		let played = self.played.lock();
+		self.exchanges
+			.iter()
+			.zip(played.iter())
+			.filter(|(_, p)| !**p)
+			.map(|(e, _)| e.request.clone())
+			.collect()
+	}
+
+	fn answer(&self, request: NodeRequest) -> Result<NodeAnswer, libwallet::Error> {
+		let mut played = self.played.lock();
+		let matching: Vec<usize> = (0..self.exchanges.len())
+			.filter(|i| self.exchanges[*i].request == request)
+			.collect();
+		let i = match matching.iter().find(|i| !played[**i]) {
+			Some(i) => *i,
+			None => match matching.last() {
+				Some(i) => *i,
+				None => {
+					let report = format!("No recorded answer to {:?}", request);
+					return Err(libwallet::ErrorKind::ClientCallback(report))?;
+				}
+			},
+		};
+		played[i] = true;
+		match self.exchanges[i].response.clone() {
+			Ok(a) => Ok(a),
+			Err(e) => Err(libwallet::ErrorKind::ClientCallback(e))?,
+		}
+	}
+}
+
+fn unexpected<T>(answer: NodeAnswer) -> Result<T, libwallet::Error> {
+	let report = format!("Recorded answer {:?} doesn't fit the request", answer);
+	Err(libwallet::ErrorKind::ClientCallback(report))?
+}
+
+impl NodeClient for ReplayNodeClient {
+	fn node_url(&self) -> &str {
+		&self.node_url
+	}
+	fn node_api_secret(&self) -> Option<String> {
+		self.node_api_secret.clone()
+	}
+
+	fn set_node_url(&mut self, node_url: &str) {
+		self.node_url = node_url.to_owned();
+	}
+
+	fn set_node_api_secret(&mut self, node_api_secret: Option<String>) {
+		self.node_api_secret = node_api_secret;
+	}
+
+	fn socks_proxy(&self) -> Option<String> {
+		None
+	}
+
+	fn post_tx(&self, tx: &TxWrapper, fluff: bool) -> Result<(), libwallet::Error> {
+		let request = NodeRequest::PostTx {
+			tx_hex: tx.tx_hex.clone(),
+			fluff,
+		};
+		match self.answer(request)? {
+			NodeAnswer::Posted => Ok(()),
+			a => unexpected(a),
+		}
+	}
+
+	fn get_chain_height(&self) -> Result<u64, libwallet::Error> {
+		match self.answer(NodeRequest::GetChainHeight)? {
+			NodeAnswer::ChainHeight(h) => Ok(h),
+			a => unexpected(a),
+		}
+	}
+
+	fn get_outputs_from_node(
+		&self,
+		wallet_outputs: Vec<pedersen::Commitment>,
+	) -> Result<OutputMap, libwallet::Error> {
+		let request = NodeRequest::GetOutputsFromNode {
+			commits: commit_hexes(&wallet_outputs),
+		};
+		let found = match self.answer(request)? {
+			NodeAnswer::Outputs(o) => o,
+			a => return unexpected(a),
+		};
+		let mut outputs = HashMap::new();
+		for o in found {
+			let commit = util::from_hex(o.commit.clone()).context(libwallet::ErrorKind::Format(
+				format!("Invalid recorded commitment {}", o.commit),
+			))?;
+			outputs.insert(
+				pedersen::Commitment::from_vec(commit),
+				(o.commit, o.height, o.mmr_index),
+			);
+		}
+		Ok(outputs)
+	}
+
+	fn get_outputs_by_pmmr_index(
+		&self,
+		start_height: u64,
+		max_outputs: u64,
+	) -> Result<PmmrOutputs, libwallet::Error> {
+		let request = NodeRequest::GetOutputsByPmmrIndex {
+			start_height,
+			max_outputs,
+		};
+		match self.answer(request)? {
+			NodeAnswer::OutputsByPmmrIndex {
+				highest_index,
+				last_retrieved_index,
+				outputs,
+			} => Ok((
+				highest_index,
+				last_retrieved_index,
+				outputs
+					.into_iter()
+					.map(|o| (o.commit, o.proof, o.is_coinbase, o.height, o.mmr_index))
+					.collect(),
+			)),
+			a => unexpected(a),
+		}
+	}
+
+	fn height_range_to_pmmr_indices(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<(u64, u64), libwallet::Error> {
+		let request = NodeRequest::HeightRangeToPmmrIndices {
+			start_height,
+			end_height,
+		};
+		match self.answer(request)? {
+			NodeAnswer::PmmrIndices(first, last) => Ok((first, last)),
+			a => unexpected(a),
+		}
+	}
+
+	fn get_block_headers(
+		&self,
+		heights: Vec<u64>,
+	) -> Result<Vec<BlockHeaderInfo>, libwallet::Error> {
+		match self.answer(NodeRequest::GetBlockHeaders { heights })? {
+			NodeAnswer::BlockHeaders(h) => Ok(h),
+			a => unexpected(a),
+		}
+	}
+}
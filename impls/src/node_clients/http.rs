@@ -15,13 +15,12 @@
 //! Client functions, implementations of the NodeClient trait
 //! specific to the FileWallet
 
-use futures::{stream, Stream};
-
-use crate::libwallet::{NodeClient, TxWrapper};
+use crate::libwallet::{BlockHeaderInfo, BlockIdentifier, NodeClient, TxWrapper};
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
-use tokio::runtime::Runtime;
 
 use crate::api;
+use crate::client;
 use crate::libwallet;
 use crate::util;
 use crate::util::secp::pedersen;
@@ -30,14 +29,22 @@ use crate::util::secp::pedersen;
 pub struct HTTPNodeClient {
 	node_url: String,
 	node_api_secret: Option<String>,
+	/// Address of the SOCKS5 proxy requests go through, if any
+	socks_proxy: Option<String>,
 }
 
 impl HTTPNodeClient {
-	/// Create a new client that will communicate with the given grin node
-	pub fn new(node_url: &str, node_api_secret: Option<String>) -> HTTPNodeClient {
+	/// Create a new client that will communicate with the given grin node,
+	/// through the SOCKS5 proxy at the given address (host:port) if any
+	pub fn new(
+		node_url: &str,
+		node_api_secret: Option<String>,
+		socks_proxy: Option<String>,
+	) -> HTTPNodeClient {
 		HTTPNodeClient {
 			node_url: node_url.to_owned(),
 			node_api_secret: node_api_secret,
+			socks_proxy: socks_proxy,
 		}
 	}
 
@@ -63,6 +70,10 @@ impl NodeClient for HTTPNodeClient {
 		self.node_api_secret = node_api_secret;
 	}
 
+	fn socks_proxy(&self) -> Option<String> {
+		self.socks_proxy.clone()
+	}
+
 	/// Posts a transaction to a grin node
 	fn post_tx(&self, tx: &TxWrapper, fluff: bool) -> Result<(), libwallet::Error> {
 		let url;
@@ -72,7 +83,12 @@ impl NodeClient for HTTPNodeClient {
 		} else {
 			url = format!("{}/v1/pool/push", dest);
 		}
-		let res = api::client::post_no_ret(url.as_str(), self.node_api_secret(), tx);
+		let res = client::post_no_ret(
+			url.as_str(),
+			self.node_api_secret(),
+			self.socks_proxy.as_ref().map(|p| p.as_str()),
+			tx,
+		);
 		if let Err(e) = res {
 			let report = format!("Posting transaction to node: {}", e);
 			error!("Post TX Error: {}", e);
@@ -85,7 +101,11 @@ impl NodeClient for HTTPNodeClient {
 	fn get_chain_height(&self) -> Result<u64, libwallet::Error> {
 		let addr = self.node_url();
 		let url = format!("{}/v1/chain", addr);
-		let res = api::client::get::<api::Tip>(url.as_str(), self.node_api_secret());
+		let res = client::get::<api::Tip>(
+			url.as_str(),
+			self.node_api_secret(),
+			self.socks_proxy.as_ref().map(|p| p.as_str()),
+		);
 		match res {
 			Err(e) => {
 				let report = format!("Getting chain height from node: {}", e);
@@ -111,20 +131,16 @@ impl NodeClient for HTTPNodeClient {
 
 		// build a map of api outputs by commit so we can look them up efficiently
 		let mut api_outputs: HashMap<pedersen::Commitment, (String, u64, u64)> = HashMap::new();
-		let mut tasks = Vec::new();
-
-		for query_chunk in query_params.chunks(200) {
-			let url = format!("{}/v1/chain/outputs/byids?{}", addr, query_chunk.join("&"),);
-			tasks.push(api::client::get_async::<Vec<api::Output>>(
-				url.as_str(),
-				self.node_api_secret(),
-			));
-		}
-
-		let task = stream::futures_unordered(tasks).collect();
+		let urls: Vec<String> = query_params
+			.chunks(200)
+			.map(|query_chunk| format!("{}/v1/chain/outputs/byids?{}", addr, query_chunk.join("&")))
+			.collect();
 
-		let mut rt = Runtime::new().unwrap();
-		let results = match rt.block_on(task) {
+		let results = match client::get_many::<Vec<api::Output>>(
+			urls,
+			self.node_api_secret(),
+			self.socks_proxy.as_ref().map(|p| p.as_str()),
+		) {
 			Ok(outputs) => outputs,
 			Err(e) => {
 				let report = format!("Getting outputs by id: {}", e);
@@ -164,7 +180,11 @@ impl NodeClient for HTTPNodeClient {
 		let mut api_outputs: Vec<(pedersen::Commitment, pedersen::RangeProof, bool, u64, u64)> =
 			Vec::new();
 
-		match api::client::get::<api::OutputListing>(url.as_str(), self.node_api_secret()) {
+		match client::get::<api::OutputListing>(
+			url.as_str(),
+			self.node_api_secret(),
+			self.socks_proxy.as_ref().map(|p| p.as_str()),
+		) {
 			Ok(o) => {
 				for out in o.outputs {
 					let is_coinbase = match out.output_type {
@@ -193,6 +213,88 @@ impl NodeClient for HTTPNodeClient {
 			}
 		}
 	}
+
+	fn height_range_to_pmmr_indices(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<(u64, u64), libwallet::Error> {
+		let addr = self.node_url();
+		let mut query_param = format!("start_height={}", start_height);
+		if let Some(e) = end_height {
+			query_param = format!("{}&end_height={}", query_param, e);
+		}
+
+		let url = format!("{}/v1/txhashset/heightstopmmr?{}", addr, query_param,);
+
+		match client::get::<api::OutputListing>(
+			url.as_str(),
+			self.node_api_secret(),
+			self.socks_proxy.as_ref().map(|p| p.as_str()),
+		) {
+			Ok(o) => Ok((o.last_retrieved_index, o.highest_index)),
+			Err(e) => {
+				// if we got anything other than 200 back from server, bye
+				error!(
+					"height_range_to_pmmr_indices: error contacting {}. Error: {}",
+					addr, e
+				);
+				let report = format!("height range to pmmr indices: {}", e);
+				Err(libwallet::ErrorKind::ClientCallback(report))?
+			}
+		}
+	}
+
+	fn get_block_headers(
+		&self,
+		heights: Vec<u64>,
+	) -> Result<Vec<BlockHeaderInfo>, libwallet::Error> {
+		let addr = self.node_url();
+		let tip = self.get_chain_height()?;
+		let mut headers = vec![];
+
+		for heights_chunk in heights.chunks(200) {
+			let urls: Vec<String> = heights_chunk
+				.iter()
+				.filter(|h| **h <= tip)
+				.map(|h| format!("{}/v1/headers/{}", addr, h))
+				.collect();
+			let results = match client::get_many::<api::BlockHeaderPrintable>(
+				urls,
+				self.node_api_secret(),
+				self.socks_proxy.as_ref().map(|p| p.as_str()),
+			) {
+				Ok(h) => h,
+				Err(e) => {
+					let report = format!("Getting block headers: {}", e);
+					error!("Block headers by height failed: {}", e);
+					return Err(libwallet::ErrorKind::ClientCallback(report).into());
+				}
+			};
+			for h in results {
+				headers.push(header_info(h)?);
+			}
+		}
+		headers.sort_by_key(|h| h.height);
+		Ok(headers)
+	}
+}
+
+/// The parts of a header returned by the node that the wallet keeps
+fn header_info(h: api::BlockHeaderPrintable) -> Result<BlockHeaderInfo, libwallet::Error> {
+	let err = |field: &str| {
+		let report = format!("Invalid block header {} at height {}", field, h.height);
+		libwallet::Error::from(libwallet::ErrorKind::ClientCallback(report))
+	};
+	let hash = BlockIdentifier::from_hex(&h.hash).map_err(|_| err("hash"))?;
+	let timestamp = DateTime::parse_from_rfc3339(&h.timestamp)
+		.map_err(|_| err("timestamp"))?
+		.with_timezone(&Utc);
+	Ok(BlockHeaderInfo {
+		height: h.height,
+		hash,
+		timestamp,
+	})
 }
 
 /*
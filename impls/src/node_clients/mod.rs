@@ -12,6 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod fixture;
 mod http;
+mod multi;
 
+pub use self::fixture::{
+	NodeAnswer, NodeExchange, NodeFixture, NodeOutput, NodePmmrOutput, NodeRequest,
+	RecordingNodeClient, ReplayNodeClient, NODE_FIXTURE_VERSION,
+};
 pub use self::http::HTTPNodeClient;
+pub use self::multi::{MultiNodeClient, NodeHealth};
@@ -0,0 +1,445 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! NodeClient spreading requests over several nodes, failing over from one
+//! to the next on errors and timeouts, and optionally requiring a quorum of
+//! them to agree on what the wallet relies on
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::libwallet;
+use crate::libwallet::{BlockHeaderInfo, NodeClient, TxWrapper};
+use crate::node_clients::HTTPNodeClient;
+use crate::util;
+use crate::util::file::get_first_line;
+use crate::util::secp::pedersen;
+use crate::util::RwLock;
+use config::WalletConfig;
+
+/// How a node has answered so far
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeHealth {
+	/// The api address of the node
+	pub url: String,
+	/// Number of requests answered
+	pub successes: u64,
+	/// Number of requests failed or timed out
+	pub failures: u64,
+	/// Number of requests failed since the last one answered. Nodes are
+	/// tried in order of this, so failing nodes are tried last.
+	pub consecutive_failures: u64,
+	/// Number of answers outvoted by a quorum of the other nodes
+	pub disagreements: u64,
+	/// Time of the last answer
+	pub last_success: Option<DateTime<Utc>>,
+	/// The last failure
+	pub last_error: Option<String>,
+}
+
+impl NodeHealth {
+	fn new(url: &str) -> NodeHealth {
+		NodeHealth {
+			url: url.to_owned(),
+			successes: 0,
+			failures: 0,
+			consecutive_failures: 0,
+			disagreements: 0,
+			last_success: None,
+			last_error: None,
+		}
+	}
+}
+
+type OutputMap = HashMap<pedersen::Commitment, (String, u64, u64)>;
+
+/// Most requests a node may have in flight at once. Requests that time out
+/// are left to finish on their own, so a node that hangs is given no more
+/// until some of them do.
+const MAX_IN_FLIGHT: usize = 4;
+
+/// A NodeClient made of the clients of several nodes. The first node is the
+/// primary, whose address and secret are those set through the NodeClient
+/// trait. Requests go to the node with the fewest consecutive failures,
+/// failing over to the next on an error or timeout. With a quorum above 1,
+/// the chain height, output and block header lookups are asked of every
+/// node, and only answers given by at least that many nodes are accepted.
+#[derive(Clone)]
+pub struct MultiNodeClient<C>
+where
+	C: NodeClient,
+{
+	nodes: Vec<C>,
+	quorum: usize,
+	timeout: Duration,
+	health: Arc<RwLock<Vec<NodeHealth>>>,
+	in_flight: Arc<Vec<AtomicUsize>>,
+}
+
+impl<C> MultiNodeClient<C>
+where
+	C: NodeClient + 'static,
+{
+	/// Create a client over the given nodes, the first being the primary
+	pub fn new(
+		nodes: Vec<C>,
+		quorum: usize,
+		timeout: Duration,
+	) -> Result<MultiNodeClient<C>, libwallet::Error> {
+		if nodes.is_empty() {
+			return Err(libwallet::ErrorKind::GenericError(
+				"At least one node is needed".to_owned(),
+			))?;
+		}
+		if quorum == 0 || quorum > nodes.len() {
+			let msg = format!(
+				"Node quorum must be between 1 and the number of nodes ({}), not {}",
+				nodes.len(),
+				quorum
+			);
+			return Err(libwallet::ErrorKind::GenericError(msg))?;
+		}
+		let health = nodes
+			.iter()
+			.map(|n| NodeHealth::new(n.node_url()))
+			.collect();
+		let in_flight = nodes.iter().map(|_| AtomicUsize::new(0)).collect();
+		Ok(MultiNodeClient {
+			nodes,
+			quorum,
+			timeout,
+			health: Arc::new(RwLock::new(health)),
+			in_flight: Arc::new(in_flight),
+		})
+	}
+
+	/// How each node has answered so far, in the order the nodes were given
+	pub fn node_health(&self) -> Vec<NodeHealth> {
+		self.health.read().clone()
+	}
+
+	/// Node indices in the order they're tried
+	fn node_order(&self) -> Vec<usize> {
+		let health = self.health.read();
+		let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+		order.sort_by_key(|i| health[*i].consecutive_failures);
+		order
+	}
+
+	fn record_success(&self, i: usize) {
+		let mut health = self.health.write();
+		health[i].successes += 1;
+		health[i].consecutive_failures = 0;
+		health[i].last_success = Some(Utc::now());
+	}
+
+	fn record_failure(&self, i: usize, error: String) {
+		warn!("Node {} failed: {}", self.nodes[i].node_url(), error);
+		let mut health = self.health.write();
+		health[i].failures += 1;
+		health[i].consecutive_failures += 1;
+		health[i].last_error = Some(error);
+	}
+
+	fn record_disagreement(&self, i: usize) {
+		warn!(
+			"Node {} disagrees with a quorum of nodes",
+			self.nodes[i].node_url()
+		);
+		self.health.write()[i].disagreements += 1;
+	}
+
+	/// Make a request of the given nodes at once, each on its own thread,
+	/// and collect the answers that come back before the timeout. A node
+	/// with `MAX_IN_FLIGHT` requests still running fails without being
+	/// asked.
+	fn ask<T, F>(&self, nodes: &[usize], f: F) -> Vec<(usize, T)>
+	where
+		T: Send + 'static,
+		F: Fn(&C) -> Result<T, libwallet::Error> + Clone + Send + 'static,
+	{
+		let (tx, rx) = mpsc::channel();
+		let mut pending: Vec<usize> = vec![];
+		for i in nodes.iter() {
+			if self.in_flight[*i].fetch_add(1, Ordering::SeqCst) >= MAX_IN_FLIGHT {
+				self.in_flight[*i].fetch_sub(1, Ordering::SeqCst);
+				let error = format!("{} earlier requests still unanswered", MAX_IN_FLIGHT);
+				self.record_failure(*i, error);
+				continue;
+			}
+			pending.push(*i);
+			let (i, node, f, tx) = (*i, self.nodes[*i].clone(), f.clone(), tx.clone());
+			let in_flight = self.in_flight.clone();
+			thread::spawn(move || {
+				let res = f(&node);
+				in_flight[i].fetch_sub(1, Ordering::SeqCst);
+				// the receiver is gone if the node answered too late
+				let _ = tx.send((i, res));
+			});
+		}
+		drop(tx);
+
+		let deadline = Instant::now() + self.timeout;
+		let mut answers = vec![];
+		while !pending.is_empty() {
+			let now = Instant::now();
+			if now >= deadline {
+				break;
+			}
+			match rx.recv_timeout(deadline - now) {
+				Ok((i, res)) => {
+					pending.retain(|p| *p != i);
+					match res {
+						Ok(r) => {
+							self.record_success(i);
+							answers.push((i, r));
+						}
+						Err(e) => self.record_failure(i, format!("{}", e)),
+					}
+				}
+				Err(_) => break,
+			}
+		}
+		for i in pending.into_iter() {
+			self.record_failure(i, format!("Timed out after {:?}", self.timeout));
+		}
+		answers
+	}
+
+	/// Make a request of each node in turn, until one answers
+	fn failover<T, F>(&self, what: &str, f: F) -> Result<T, libwallet::Error>
+	where
+		T: Send + 'static,
+		F: Fn(&C) -> Result<T, libwallet::Error> + Clone + Send + 'static,
+	{
+		for i in self.node_order() {
+			if let Some((_, r)) = self.ask(&[i], f.clone()).pop() {
+				return Ok(r);
+			}
+		}
+		let report = format!("{}: no node answered", what);
+		Err(libwallet::ErrorKind::ClientCallback(report))?
+	}
+
+	/// Make a request of every node at once, failing unless a quorum answer
+	fn ask_quorum<T, F>(&self, what: &str, f: F) -> Result<Vec<(usize, T)>, libwallet::Error>
+	where
+		T: Send + 'static,
+		F: Fn(&C) -> Result<T, libwallet::Error> + Clone + Send + 'static,
+	{
+		let answers = self.ask(&self.node_order(), f);
+		if answers.len() < self.quorum {
+			let report = format!(
+				"{}: {} of the {} nodes needed answered",
+				what,
+				answers.len(),
+				self.quorum
+			);
+			return Err(libwallet::ErrorKind::ClientCallback(report))?;
+		}
+		Ok(answers)
+	}
+}
+
+impl MultiNodeClient<HTTPNodeClient> {
+	/// Create a client over a wallet's check node and its additional nodes,
+	/// as set in its configuration
+	pub fn from_config(
+		config: &WalletConfig,
+		node_api_secret: Option<String>,
+	) -> Result<MultiNodeClient<HTTPNodeClient>, libwallet::Error> {
+		let mut nodes = vec![HTTPNodeClient::new(
+			&config.check_node_api_http_addr,
+			node_api_secret,
+			config.socks_proxy_addr.clone(),
+		)];
+		for n in config.additional_nodes() {
+			let secret = get_first_line(n.api_secret_path.clone());
+			nodes.push(HTTPNodeClient::new(
+				&n.url,
+				secret,
+				config.socks_proxy_addr.clone(),
+			));
+		}
+		MultiNodeClient::new(
+			nodes,
+			config.node_quorum(),
+			Duration::from_secs(config.node_timeout_secs()),
+		)
+	}
+}
+
+impl<C> NodeClient for MultiNodeClient<C>
+where
+	C: NodeClient + 'static,
+{
+	fn node_url(&self) -> &str {
+		self.nodes[0].node_url()
+	}
+	fn node_api_secret(&self) -> Option<String> {
+		self.nodes[0].node_api_secret()
+	}
+
+	fn set_node_url(&mut self, node_url: &str) {
+		self.nodes[0].set_node_url(node_url);
+		self.health.write()[0].url = node_url.to_owned();
+	}
+
+	fn set_node_api_secret(&mut self, node_api_secret: Option<String>) {
+		self.nodes[0].set_node_api_secret(node_api_secret);
+	}
+
+	fn socks_proxy(&self) -> Option<String> {
+		self.nodes[0].socks_proxy()
+	}
+
+	fn post_tx(&self, tx: &TxWrapper, fluff: bool) -> Result<(), libwallet::Error> {
+		let tx = tx.clone();
+		self.failover("Posting transaction", move |n| n.post_tx(&tx, fluff))
+	}
+
+	/// The highest height a quorum of nodes have reached, as nodes that are
+	/// a block or two behind are expected
+	fn get_chain_height(&self) -> Result<u64, libwallet::Error> {
+		if self.quorum == 1 {
+			return self.failover("Getting chain height", |n| n.get_chain_height());
+		}
+		let answers = self.ask_quorum("Getting chain height", |n| n.get_chain_height())?;
+		let mut heights: Vec<u64> = answers.iter().map(|(_, h)| *h).collect();
+		heights.sort_by(|a, b| b.cmp(a));
+		Ok(heights[self.quorum - 1])
+	}
+
+	/// Each output as a quorum of nodes see it, including whether it's
+	/// there at all
+	fn get_outputs_from_node(
+		&self,
+		wallet_outputs: Vec<pedersen::Commitment>,
+	) -> Result<OutputMap, libwallet::Error> {
+		let commits = wallet_outputs.clone();
+		let lookup = move |n: &C| n.get_outputs_from_node(commits.clone());
+		if self.quorum == 1 {
+			return self.failover("Getting outputs by id", lookup);
+		}
+		let answers = self.ask_quorum("Getting outputs by id", lookup)?;
+
+		let mut agreed = HashMap::new();
+		for commit in wallet_outputs.iter() {
+			let mut votes: Vec<(Option<&(String, u64, u64)>, Vec<usize>)> = vec![];
+			for (i, outputs) in answers.iter() {
+				let seen = outputs.get(commit);
+				match votes.iter_mut().find(|(v, _)| *v == seen) {
+					Some((_, voters)) => voters.push(*i),
+					None => votes.push((seen, vec![*i])),
+				}
+			}
+			let (seen, voters) = match votes.iter().find(|(_, v)| v.len() >= self.quorum) {
+				Some(v) => v,
+				None => {
+					let report = format!(
+						"Getting outputs by id: no quorum of nodes agree on output {}",
+						util::to_hex(commit.as_ref().to_vec())
+					);
+					return Err(libwallet::ErrorKind::ClientCallback(report))?;
+				}
+			};
+			for (i, _) in answers.iter().filter(|(i, _)| !voters.contains(i)) {
+				self.record_disagreement(*i);
+			}
+			if let Some(o) = seen {
+				agreed.insert(commit.clone(), (*o).clone());
+			}
+		}
+		Ok(agreed)
+	}
+
+	fn get_outputs_by_pmmr_index(
+		&self,
+		start_height: u64,
+		max_outputs: u64,
+	) -> Result<
+		(
+			u64,
+			u64,
+			Vec<(pedersen::Commitment, pedersen::RangeProof, bool, u64, u64)>,
+		),
+		libwallet::Error,
+	> {
+		self.failover("Getting outputs by pmmr index", move |n| {
+			n.get_outputs_by_pmmr_index(start_height, max_outputs)
+		})
+	}
+
+	fn height_range_to_pmmr_indices(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<(u64, u64), libwallet::Error> {
+		self.failover("Getting height range to pmmr indices", move |n| {
+			n.height_range_to_pmmr_indices(start_height, end_height)
+		})
+	}
+
+	/// The block at each height as a quorum of nodes see it, agreeing on
+	/// its hash, including whether the height has been reached at all
+	fn get_block_headers(
+		&self,
+		heights: Vec<u64>,
+	) -> Result<Vec<BlockHeaderInfo>, libwallet::Error> {
+		let lookup_heights = heights.clone();
+		let lookup = move |n: &C| n.get_block_headers(lookup_heights.clone());
+		if self.quorum == 1 {
+			return self.failover("Getting block headers", lookup);
+		}
+		let answers = self.ask_quorum("Getting block headers", lookup)?;
+
+		let mut agreed = vec![];
+		for height in heights.iter() {
+			let mut votes: Vec<(Option<&BlockHeaderInfo>, Vec<usize>)> = vec![];
+			for (i, headers) in answers.iter() {
+				let seen = headers.iter().find(|h| h.height == *height);
+				match votes
+					.iter_mut()
+					.find(|(v, _)| v.map(|h| &h.hash) == seen.map(|h| &h.hash))
+				{
+					Some((_, voters)) => voters.push(*i),
+					None => votes.push((seen, vec![*i])),
+				}
+			}
+			let (seen, voters) = match votes.iter().find(|(_, v)| v.len() >= self.quorum) {
+				Some(v) => v,
+				None => {
+					let report = format!(
+						"Getting block headers: no quorum of nodes agree on the block at {}",
+						height
+					);
+					return Err(libwallet::ErrorKind::ClientCallback(report))?;
+				}
+			};
+			for (i, _) in answers.iter().filter(|(i, _)| !voters.contains(i)) {
+				self.record_disagreement(*i);
+			}
+			if let Some(h) = seen {
+				agreed.push((*h).clone());
+			}
+		}
+		Ok(agreed)
+	}
+}
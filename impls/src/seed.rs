@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::Path;
@@ -22,9 +23,10 @@ use rand::{thread_rng, Rng};
 use serde_json;
 
 use ring::aead;
-use ring::{digest, pbkdf2};
+use ring::{constant_time, digest, pbkdf2};
 
 use crate::keychain::{mnemonic, Keychain};
+use crate::seed_shares::{self, SeedShare};
 use crate::util;
 use crate::{Error, ErrorKind};
 use config::WalletConfig;
@@ -32,12 +34,43 @@ use failure::ResultExt;
 
 pub const SEED_FILE: &'static str = "wallet.seed";
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct WalletSeed(Vec<u8>);
+/// Seed entropy, along with the optional BIP39 passphrase (the "25th word")
+/// it's combined with when deriving the keychain
+#[derive(Clone, PartialEq)]
+pub struct WalletSeed(Vec<u8>, Option<util::ZeroingString>);
+
+// the passphrase is left out of debug output
+impl fmt::Debug for WalletSeed {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("WalletSeed")
+			.field(&self.0)
+			.field(&self.1.as_ref().map(|_| "<passphrase>"))
+			.finish()
+	}
+}
 
 impl WalletSeed {
 	pub fn from_bytes(bytes: &[u8]) -> WalletSeed {
-		WalletSeed(bytes.to_vec())
+		WalletSeed(bytes.to_vec(), None)
+	}
+
+	/// Set the BIP39 passphrase combined with the seed. An empty passphrase
+	/// is the same as none. Any other passphrase yields an entirely
+	/// different keychain, so a wrong one opens a different, empty wallet.
+	pub fn with_mnemonic_passphrase(
+		mut self,
+		mnemonic_passphrase: Option<util::ZeroingString>,
+	) -> WalletSeed {
+		self.1 = match mnemonic_passphrase {
+			Some(ref p) if p.is_empty() => None,
+			p => p,
+		};
+		self
+	}
+
+	/// Whether the seed is combined with a BIP39 passphrase
+	pub fn has_mnemonic_passphrase(&self) -> bool {
+		self.1.is_some()
 	}
 
 	pub fn from_mnemonic(word_list: &str) -> Result<WalletSeed, Error> {
@@ -72,7 +105,18 @@ impl WalletSeed {
 	}
 
 	pub fn derive_keychain<K: Keychain>(&self, is_floonet: bool) -> Result<K, Error> {
-		let result = K::from_seed(&self.0, is_floonet)?;
+		let result = match self.1 {
+			// keychains of wallets without a passphrase are derived from the
+			// entropy directly, as they always have been
+			None => K::from_seed(&self.0, is_floonet)?,
+			Some(ref p) => {
+				let seed = match mnemonic::to_seed(&self.to_mnemonic()?, &**p) {
+					Ok(s) => s,
+					Err(_) => return Err(ErrorKind::Mnemonic.into()),
+				};
+				K::from_seed(&seed[..], is_floonet)?
+			}
+		};
 		Ok(result)
 	}
 
@@ -82,7 +126,7 @@ impl WalletSeed {
 		for _ in 0..seed_length {
 			seed.push(rng.gen());
 		}
-		WalletSeed(seed)
+		WalletSeed(seed, None)
 	}
 
 	pub fn seed_file_exists(wallet_config: &WalletConfig) -> Result<(), Error> {
@@ -96,14 +140,8 @@ impl WalletSeed {
 		Ok(())
 	}
 
-	pub fn backup_seed(wallet_config: &WalletConfig) -> Result<(), Error> {
-		let seed_file_name = &format!(
-			"{}{}{}",
-			wallet_config.data_file_dir, MAIN_SEPARATOR, SEED_FILE,
-		);
-
-		let mut path = Path::new(seed_file_name).to_path_buf();
-		path.pop();
+	/// First unused backup file name for the seed file
+	fn backup_seed_file_name(wallet_config: &WalletConfig) -> String {
 		let mut backup_seed_file_name = format!(
 			"{}{}{}.bak",
 			wallet_config.data_file_dir, MAIN_SEPARATOR, SEED_FILE
@@ -116,7 +154,15 @@ impl WalletSeed {
 			);
 			i += 1;
 		}
-		path.push(backup_seed_file_name.clone());
+		backup_seed_file_name
+	}
+
+	pub fn backup_seed(wallet_config: &WalletConfig) -> Result<(), Error> {
+		let seed_file_name = &format!(
+			"{}{}{}",
+			wallet_config.data_file_dir, MAIN_SEPARATOR, SEED_FILE,
+		);
+		let backup_seed_file_name = WalletSeed::backup_seed_file_name(wallet_config);
 		if let Err(_) = fs::rename(seed_file_name, backup_seed_file_name.as_str()) {
 			return Err(ErrorKind::GenericError(
 				"Can't rename wallet seed file".to_owned(),
@@ -126,9 +172,99 @@ impl WalletSeed {
 		Ok(())
 	}
 
+	/// Replace the seed file with a new encrypted seed. The previous file is
+	/// copied to a backup and the new one written to a temporary file that's
+	/// then renamed over it, so the seed file is always complete.
+	fn replace_seed_file(
+		wallet_config: &WalletConfig,
+		enc_seed: &EncryptedWalletSeed,
+	) -> Result<(), Error> {
+		let seed_file_path = &format!(
+			"{}{}{}",
+			wallet_config.data_file_dir, MAIN_SEPARATOR, SEED_FILE,
+		);
+		let tmp_file_path = format!("{}.tmp", seed_file_path);
+		let enc_seed_json = serde_json::to_string_pretty(enc_seed).context(ErrorKind::Format)?;
+		{
+			let mut file = File::create(&tmp_file_path).context(ErrorKind::IO)?;
+			file.write_all(&enc_seed_json.as_bytes())
+				.context(ErrorKind::IO)?;
+			file.sync_all().context(ErrorKind::IO)?;
+		}
+		let backup_seed_file_name = WalletSeed::backup_seed_file_name(wallet_config);
+		fs::copy(seed_file_path, &backup_seed_file_name).context(ErrorKind::IO)?;
+		fs::rename(&tmp_file_path, seed_file_path).context(ErrorKind::IO)?;
+		warn!("{} backed up as {}", seed_file_path, backup_seed_file_name);
+		Ok(())
+	}
+
+	/// Read the encrypted seed file, without decrypting it
+	fn read_seed_file(wallet_config: &WalletConfig) -> Result<EncryptedWalletSeed, Error> {
+		let seed_file_path = &format!(
+			"{}{}{}",
+			wallet_config.data_file_dir, MAIN_SEPARATOR, SEED_FILE,
+		);
+
+		debug!("Using wallet seed file at: {}", seed_file_path);
+
+		if Path::new(seed_file_path).exists() {
+			let mut file = File::open(seed_file_path).context(ErrorKind::IO)?;
+			let mut buffer = String::new();
+			file.read_to_string(&mut buffer).context(ErrorKind::IO)?;
+			let enc_seed: EncryptedWalletSeed =
+				serde_json::from_str(&buffer).context(ErrorKind::Format)?;
+			Ok(enc_seed)
+		} else {
+			error!(
+				"wallet seed file {} could not be opened (grin wallet init). \
+				 Run \"grin wallet init\" to initialize a new wallet.",
+				seed_file_path
+			);
+			Err(ErrorKind::WalletSeedDoesntExist)?
+		}
+	}
+
+	/// Re-encrypt the seed file under the current seed file version and the
+	/// configured key derivation function, if it isn't already. Returns
+	/// whether the file was rewritten.
+	pub fn upgrade_file(wallet_config: &WalletConfig, password: &str) -> Result<bool, Error> {
+		let enc_seed = WalletSeed::read_seed_file(wallet_config)?;
+		let seed = enc_seed.decrypt(password)?;
+		let kdf = SeedKdf::from_config(wallet_config);
+		if enc_seed.version == SEED_FILE_VERSION && enc_seed.kdf == kdf {
+			return Ok(false);
+		}
+		let mut new_enc_seed = EncryptedWalletSeed::from_seed(&seed, password, kdf)?;
+		new_enc_seed.birthday_height = enc_seed.birthday_height;
+		WalletSeed::replace_seed_file(wallet_config, &new_enc_seed)?;
+		warn!(
+			"Seed file upgraded from version {} to {}",
+			enc_seed.version, SEED_FILE_VERSION
+		);
+		Ok(true)
+	}
+
+	/// Re-encrypt the seed file under a new password. The old password must
+	/// decrypt the current file, and the previous file is kept as a backup.
+	pub fn change_password(
+		wallet_config: &WalletConfig,
+		old_password: &str,
+		new_password: &str,
+	) -> Result<(), Error> {
+		let enc_seed = WalletSeed::read_seed_file(wallet_config)?;
+		let seed = enc_seed.decrypt(old_password)?;
+		let kdf = SeedKdf::from_config(wallet_config);
+		let mut new_enc_seed = EncryptedWalletSeed::from_seed(&seed, new_password, kdf)?;
+		new_enc_seed.birthday_height = enc_seed.birthday_height;
+		WalletSeed::replace_seed_file(wallet_config, &new_enc_seed)?;
+		warn!("Wallet password changed");
+		Ok(())
+	}
+
 	pub fn recover_from_phrase(
 		wallet_config: &WalletConfig,
 		word_list: &str,
+		mnemonic_passphrase: Option<util::ZeroingString>,
 		password: &str,
 	) -> Result<(), Error> {
 		let seed_file_path = &format!(
@@ -145,8 +281,10 @@ impl WalletSeed {
 					.to_owned(),
 			))?;
 		}
-		let seed = WalletSeed::from_mnemonic(word_list)?;
-		let enc_seed = EncryptedWalletSeed::from_seed(&seed, password)?;
+		let seed =
+			WalletSeed::from_mnemonic(word_list)?.with_mnemonic_passphrase(mnemonic_passphrase);
+		let enc_seed =
+			EncryptedWalletSeed::from_seed(&seed, password, SeedKdf::from_config(wallet_config))?;
 		let enc_seed_json = serde_json::to_string_pretty(&enc_seed).context(ErrorKind::Format)?;
 		let mut file = File::create(seed_file_path).context(ErrorKind::IO)?;
 		file.write_all(&enc_seed_json.as_bytes())
@@ -161,6 +299,117 @@ impl WalletSeed {
 		println!("{}", self.to_mnemonic()?);
 		println!();
 		println!("Please back-up these words in a non-digital format.");
+		self.show_passphrase_reminder();
+		Ok(())
+	}
+
+	/// Check a recovery phrase against this seed. The entered phrase is
+	/// compared with the seed in constant time, and a phrase that doesn't
+	/// decode (unknown word, bad checksum) is simply a mismatch.
+	pub fn verify_phrase(&self, word_list: &str) -> bool {
+		let entered = match WalletSeed::from_mnemonic(word_list) {
+			Ok(s) => s.0.clone(),
+			Err(_) => vec![],
+		};
+		constant_time::verify_slices_are_equal(&entered, &self.0).is_ok()
+	}
+
+	/// Check words of the recovery phrase at the given (zero based)
+	/// positions. Words are compared by their index in the word list, all
+	/// positions at once and in constant time, so a mismatch doesn't reveal
+	/// which word was wrong.
+	pub fn verify_words(&self, words: &[(usize, util::ZeroingString)]) -> Result<bool, Error> {
+		if words.is_empty() {
+			return Err(ErrorKind::ArgumentError("No words to verify".to_owned()))?;
+		}
+		let phrase = util::ZeroingString::from(self.to_mnemonic()?);
+		let expected: Vec<&str> = phrase.split_whitespace().collect();
+		let mut expected_bytes = Vec::with_capacity(words.len() * 2);
+		let mut entered_bytes = Vec::with_capacity(words.len() * 2);
+		for (pos, word) in words {
+			let e = match expected.get(*pos) {
+				Some(w) => *w,
+				None => {
+					return Err(ErrorKind::ArgumentError(format!(
+						"Word position {} is out of range, the recovery phrase has {} words",
+						pos + 1,
+						expected.len()
+					)))?;
+				}
+			};
+			// unknown words get an index outside of the word list
+			let e_idx = mnemonic::search(e).unwrap_or(u16::max_value());
+			let w_idx = mnemonic::search(word.trim()).unwrap_or(u16::max_value());
+			expected_bytes.extend_from_slice(&[(e_idx >> 8) as u8, e_idx as u8]);
+			entered_bytes.extend_from_slice(&[(w_idx >> 8) as u8, w_idx as u8]);
+		}
+		Ok(constant_time::verify_slices_are_equal(&entered_bytes, &expected_bytes).is_ok())
+	}
+
+	/// Pick `count` distinct (zero based) positions, in order, of a recovery
+	/// phrase of `word_count` words, for spot checking a written backup
+	pub fn random_word_positions(word_count: usize, count: usize) -> Vec<usize> {
+		let mut positions =
+			rand::seq::sample_indices(&mut thread_rng(), word_count, count.min(word_count));
+		positions.sort();
+		positions
+	}
+
+	/// Chain height when the seed in the seed file was created, if known.
+	/// No outputs of the wallet can be below it, so scans can start there.
+	pub fn file_birthday_height(wallet_config: &WalletConfig) -> Result<Option<u64>, Error> {
+		let enc_seed = WalletSeed::read_seed_file(wallet_config)?;
+		Ok(enc_seed.birthday_height)
+	}
+
+	/// Number of words in the recovery phrase of the seed in the seed file,
+	/// known from the length of the encrypted seed so no password is needed
+	pub fn file_word_count(wallet_config: &WalletConfig) -> Result<usize, Error> {
+		let enc_seed = WalletSeed::read_seed_file(wallet_config)?;
+		Ok(enc_seed.seed_len()? * 3 / 4)
+	}
+
+	fn show_passphrase_reminder(&self) {
+		if self.has_mnemonic_passphrase() {
+			println!();
+			println!(
+				"This wallet also uses a BIP39 passphrase, which is needed to recover it. \
+				 Recovering with a different passphrase gives a different, empty wallet."
+			);
+		}
+	}
+
+	/// Split the seed into `shares` share phrases, any `threshold` of which
+	/// recover it. The BIP39 passphrase, if any, isn't part of the shares.
+	pub fn to_shares(&self, threshold: u8, shares: u8) -> Result<Vec<String>, Error> {
+		let res = seed_shares::split_secret(&self.0, threshold, shares)?;
+		Ok(res.iter().map(|s| s.to_phrase()).collect())
+	}
+
+	/// Recover a seed from share phrases
+	pub fn from_shares(phrases: &[util::ZeroingString]) -> Result<WalletSeed, Error> {
+		let mut shares = vec![];
+		for p in phrases {
+			shares.push(SeedShare::from_phrase(p)?);
+		}
+		let secret = seed_shares::combine_shares(&shares)?;
+		Ok(WalletSeed::from_bytes(&secret))
+	}
+
+	pub fn show_recovery_shares(&self, threshold: u8, shares: u8) -> Result<(), Error> {
+		let phrases = self.to_shares(threshold, shares)?;
+		println!(
+			"Your recovery phrase has been split into {} shares, any {} of which recover the wallet.",
+			shares, threshold
+		);
+		for (i, p) in phrases.iter().enumerate() {
+			println!();
+			println!("Share {} of {}:", i + 1, shares);
+			println!("{}", p);
+		}
+		println!();
+		println!("Please back-up each share in a non-digital format, and give each to a different holder.");
+		self.show_passphrase_reminder();
 		Ok(())
 	}
 
@@ -168,7 +417,28 @@ impl WalletSeed {
 		wallet_config: &WalletConfig,
 		seed_length: usize,
 		recovery_phrase: Option<util::ZeroingString>,
+		mnemonic_passphrase: Option<util::ZeroingString>,
+		password: &str,
+	) -> Result<WalletSeed, Error> {
+		WalletSeed::init_file_with_birthday(
+			wallet_config,
+			seed_length,
+			recovery_phrase,
+			mnemonic_passphrase,
+			password,
+			None,
+		)
+	}
+
+	/// Create the seed file as `init_file` does, recording the chain height
+	/// the wallet was created at as its birthday
+	pub fn init_file_with_birthday(
+		wallet_config: &WalletConfig,
+		seed_length: usize,
+		recovery_phrase: Option<util::ZeroingString>,
+		mnemonic_passphrase: Option<util::ZeroingString>,
 		password: &str,
+		birthday_height: Option<u64>,
 	) -> Result<WalletSeed, Error> {
 		// create directory if it doesn't exist
 		fs::create_dir_all(&wallet_config.data_file_dir).context(ErrorKind::IO)?;
@@ -184,14 +454,16 @@ impl WalletSeed {
 		let seed = match recovery_phrase {
 			Some(p) => WalletSeed::from_mnemonic(&p)?,
 			None => WalletSeed::init_new(seed_length),
-		};
+		}
+		.with_mnemonic_passphrase(mnemonic_passphrase);
 
-		let enc_seed = EncryptedWalletSeed::from_seed(&seed, password)?;
+		let mut enc_seed =
+			EncryptedWalletSeed::from_seed(&seed, password, SeedKdf::from_config(wallet_config))?;
+		enc_seed.birthday_height = birthday_height;
 		let enc_seed_json = serde_json::to_string_pretty(&enc_seed).context(ErrorKind::Format)?;
 		let mut file = File::create(seed_file_path).context(ErrorKind::IO)?;
 		file.write_all(&enc_seed_json.as_bytes())
 			.context(ErrorKind::IO)?;
-		seed.show_recovery_phrase()?;
 		Ok(seed)
 	}
 
@@ -199,30 +471,89 @@ impl WalletSeed {
 		// create directory if it doesn't exist
 		fs::create_dir_all(&wallet_config.data_file_dir).context(ErrorKind::IO)?;
 
-		let seed_file_path = &format!(
-			"{}{}{}",
-			wallet_config.data_file_dir, MAIN_SEPARATOR, SEED_FILE,
-		);
+		let enc_seed = WalletSeed::read_seed_file(wallet_config)?;
+		let wallet_seed = enc_seed.decrypt(password)?;
+		Ok(wallet_seed)
+	}
+}
 
-		debug!("Using wallet seed file at: {}", seed_file_path);
+/// Version of the seed file format written by this wallet. Files written
+/// before the format recorded its key derivation function are version 1.
+pub const SEED_FILE_VERSION: u32 = 2;
 
-		if Path::new(seed_file_path).exists() {
-			let mut file = File::open(seed_file_path).context(ErrorKind::IO)?;
-			let mut buffer = String::new();
-			file.read_to_string(&mut buffer).context(ErrorKind::IO)?;
-			let enc_seed: EncryptedWalletSeed =
-				serde_json::from_str(&buffer).context(ErrorKind::Format)?;
-			let wallet_seed = enc_seed.decrypt(password)?;
-			Ok(wallet_seed)
-		} else {
-			error!(
-				"wallet seed file {} could not be opened (grin wallet init). \
-				 Run \"grin wallet init\" to initialize a new wallet.",
-				seed_file_path
-			);
-			Err(ErrorKind::WalletSeedDoesntExist)?
+fn legacy_seed_file_version() -> u32 {
+	1
+}
+
+/// Key derivation function, with its parameters, used to derive the seed
+/// encryption key from the wallet password
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum SeedKdf {
+	/// PBKDF2-SHA512, as used by version 1 seed files
+	Pbkdf2 {
+		/// Number of iterations
+		iterations: u32,
+	},
+	/// Argon2id (memory-hard)
+	Argon2id {
+		/// Memory cost, in KiB
+		memory_kib: u32,
+		/// Number of passes
+		iterations: u32,
+		/// Degree of parallelism
+		parallelism: u32,
+	},
+}
+
+impl SeedKdf {
+	/// The KDF used by seed files written before it was recorded
+	pub fn legacy() -> SeedKdf {
+		SeedKdf::Pbkdf2 { iterations: 100 }
+	}
+
+	/// The KDF new seed files are written with, with cost from the config
+	pub fn from_config(wallet_config: &WalletConfig) -> SeedKdf {
+		SeedKdf::Argon2id {
+			memory_kib: wallet_config.seed_kdf_memory_kib(),
+			iterations: wallet_config.seed_kdf_iterations(),
+			parallelism: 1,
 		}
 	}
+
+	/// Derive a 32 byte key from the password and salt
+	fn derive_key(&self, password: &[u8], salt: &[u8]) -> Result<[u8; 32], Error> {
+		let mut key = [0; 32];
+		match *self {
+			SeedKdf::Pbkdf2 { iterations } => {
+				if iterations == 0 {
+					return Err(ErrorKind::Encryption)?;
+				}
+				pbkdf2::derive(&digest::SHA512, iterations, salt, password, &mut key);
+			}
+			SeedKdf::Argon2id {
+				memory_kib,
+				iterations,
+				parallelism,
+			} => {
+				let config = argon2::Config {
+					variant: argon2::Variant::Argon2id,
+					version: argon2::Version::Version13,
+					mem_cost: memory_kib,
+					time_cost: iterations,
+					lanes: parallelism,
+					thread_mode: argon2::ThreadMode::Sequential,
+					secret: &[],
+					ad: &[],
+					hash_length: key.len() as u32,
+				};
+				let hash =
+					argon2::hash_raw(password, salt, &config).context(ErrorKind::Encryption)?;
+				key.copy_from_slice(&hash);
+			}
+		}
+		Ok(key)
+	}
 }
 
 /// Encrypted wallet seed, for storing on disk and decrypting
@@ -230,45 +561,105 @@ impl WalletSeed {
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct EncryptedWalletSeed {
+	/// Seed file format version
+	#[serde(default = "legacy_seed_file_version")]
+	pub version: u32,
+	/// Key derivation function used to derive the key from the password
+	#[serde(default = "SeedKdf::legacy")]
+	pub kdf: SeedKdf,
 	encrypted_seed: String,
+	/// BIP39 passphrase combined with the seed, if any, encrypted with the
+	/// same key: hex of a 12 byte nonce followed by the ciphertext
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	encrypted_passphrase: Option<String>,
 	/// Salt, not so useful in single case but include anyhow for situations
 	/// where someone wants to store many of these
 	pub salt: String,
 	/// Nonce
 	pub nonce: String,
+	/// Chain height when the seed was created, if known. Stored in the
+	/// clear, as scans need it before the wallet is opened.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub birthday_height: Option<u64>,
+}
+
+/// Encrypt `data` with ChaCha20-Poly1305
+fn seal(key: &[u8; 32], nonce: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+	let mut enc_bytes = data.to_vec();
+	let suffix_len = aead::CHACHA20_POLY1305.tag_len();
+	for _ in 0..suffix_len {
+		enc_bytes.push(0);
+	}
+	let sealing_key =
+		aead::SealingKey::new(&aead::CHACHA20_POLY1305, key).context(ErrorKind::Encryption)?;
+	aead::seal_in_place(&sealing_key, nonce, &[], &mut enc_bytes, suffix_len)
+		.context(ErrorKind::Encryption)?;
+	Ok(enc_bytes)
+}
+
+/// Decrypt hex encoded data encrypted by `seal`
+fn open(key: &[u8; 32], nonce: &[u8], enc_hex: &str) -> Result<Vec<u8>, Error> {
+	let mut enc_bytes = match util::from_hex(enc_hex.to_owned()) {
+		Ok(s) => s,
+		Err(_) => return Err(ErrorKind::Encryption)?,
+	};
+	let opening_key =
+		aead::OpeningKey::new(&aead::CHACHA20_POLY1305, key).context(ErrorKind::Encryption)?;
+	let decrypted_data = aead::open_in_place(&opening_key, nonce, &[], 0, &mut enc_bytes)
+		.context(ErrorKind::Encryption)?;
+	Ok(decrypted_data.to_vec())
 }
 
 impl EncryptedWalletSeed {
-	/// Create a new encrypted seed from the given seed + password
-	pub fn from_seed(seed: &WalletSeed, password: &str) -> Result<EncryptedWalletSeed, Error> {
-		let salt: [u8; 8] = thread_rng().gen();
+	/// Create a new encrypted seed from the given seed + password, with the
+	/// key derived by `kdf`
+	pub fn from_seed(
+		seed: &WalletSeed,
+		password: &str,
+		kdf: SeedKdf,
+	) -> Result<EncryptedWalletSeed, Error> {
+		let salt: [u8; 16] = thread_rng().gen();
 		let nonce: [u8; 12] = thread_rng().gen();
-		let password = password.as_bytes();
-		let mut key = [0; 32];
-		pbkdf2::derive(&digest::SHA512, 100, &salt, password, &mut key);
-		let content = seed.0.to_vec();
-		let mut enc_bytes = content.clone();
-		let suffix_len = aead::CHACHA20_POLY1305.tag_len();
-		for _ in 0..suffix_len {
-			enc_bytes.push(0);
-		}
-		let sealing_key =
-			aead::SealingKey::new(&aead::CHACHA20_POLY1305, &key).context(ErrorKind::Encryption)?;
-		aead::seal_in_place(&sealing_key, &nonce, &[], &mut enc_bytes, suffix_len)
-			.context(ErrorKind::Encryption)?;
+		let key = kdf.derive_key(password.as_bytes(), &salt)?;
+		let enc_bytes = seal(&key, &nonce, &seed.0)?;
+		let encrypted_passphrase = match seed.1 {
+			Some(ref p) => {
+				let p_nonce: [u8; 12] = thread_rng().gen();
+				let mut p_bytes = p_nonce.to_vec();
+				p_bytes.extend(seal(&key, &p_nonce, p.as_bytes())?);
+				Some(util::to_hex(p_bytes))
+			}
+			None => None,
+		};
 		Ok(EncryptedWalletSeed {
-			encrypted_seed: util::to_hex(enc_bytes.to_vec()),
+			version: SEED_FILE_VERSION,
+			kdf,
+			encrypted_seed: util::to_hex(enc_bytes),
+			encrypted_passphrase,
 			salt: util::to_hex(salt.to_vec()),
 			nonce: util::to_hex(nonce.to_vec()),
+			birthday_height: None,
 		})
 	}
 
+	/// Length of the encrypted seed, in bytes
+	fn seed_len(&self) -> Result<usize, Error> {
+		let hex_len = self.encrypted_seed.len();
+		let tag_len = aead::CHACHA20_POLY1305.tag_len();
+		if hex_len % 2 != 0 || hex_len / 2 < tag_len {
+			return Err(ErrorKind::Format)?;
+		}
+		Ok(hex_len / 2 - tag_len)
+	}
+
 	/// Decrypt seed
 	pub fn decrypt(&self, password: &str) -> Result<WalletSeed, Error> {
-		let mut encrypted_seed = match util::from_hex(self.encrypted_seed.clone()) {
-			Ok(s) => s,
-			Err(_) => return Err(ErrorKind::Encryption)?,
-		};
+		if self.version > SEED_FILE_VERSION {
+			return Err(ErrorKind::GenericError(format!(
+				"Seed file version {} is newer than the latest supported version {}",
+				self.version, SEED_FILE_VERSION
+			)))?;
+		}
 		let salt = match util::from_hex(self.salt.clone()) {
 			Ok(s) => s,
 			Err(_) => return Err(ErrorKind::Encryption)?,
@@ -277,15 +668,24 @@ impl EncryptedWalletSeed {
 			Ok(s) => s,
 			Err(_) => return Err(ErrorKind::Encryption)?,
 		};
-		let password = password.as_bytes();
-		let mut key = [0; 32];
-		pbkdf2::derive(&digest::SHA512, 100, &salt, password, &mut key);
-
-		let opening_key =
-			aead::OpeningKey::new(&aead::CHACHA20_POLY1305, &key).context(ErrorKind::Encryption)?;
-		let decrypted_data = aead::open_in_place(&opening_key, &nonce, &[], 0, &mut encrypted_seed)
-			.context(ErrorKind::Encryption)?;
+		let key = self.kdf.derive_key(password.as_bytes(), &salt)?;
+		let decrypted_data = open(&key, &nonce, &self.encrypted_seed)?;
+
+		let mnemonic_passphrase = match self.encrypted_passphrase {
+			Some(ref p) if p.len() > 24 => {
+				let (p_nonce, p_enc) = p.split_at(24);
+				let p_nonce = match util::from_hex(p_nonce.to_owned()) {
+					Ok(n) => n,
+					Err(_) => return Err(ErrorKind::Encryption)?,
+				};
+				let p_bytes = open(&key, &p_nonce, p_enc)?;
+				let p_str = String::from_utf8(p_bytes).context(ErrorKind::Encryption)?;
+				Some(util::ZeroingString::from(p_str.as_str()))
+			}
+			Some(_) => return Err(ErrorKind::Encryption)?,
+			None => None,
+		};
 
-		Ok(WalletSeed::from_bytes(&decrypted_data))
+		Ok(WalletSeed::from_bytes(&decrypted_data).with_mnemonic_passphrase(mnemonic_passphrase))
 	}
 }
@@ -0,0 +1,263 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! M-of-N Shamir secret sharing of the wallet seed, in the spirit of
+//! SLIP-0039. Each byte of the seed is split independently over GF(256),
+//! and each share is written as a phrase from the BIP39 word list.
+//!
+//! A share phrase encodes:
+//! `set id (2 bytes) | threshold (1) | index (1) | length (1) | data | checksum (4)`
+//! where the checksum is the first 4 bytes of blake2b over everything
+//! before it. Shares from different splits have different set ids, so they
+//! can't be combined by mistake.
+
+use rand::{thread_rng, Rng};
+
+use crate::blake2::blake2b::blake2b;
+use crate::keychain::mnemonic;
+use crate::{Error, ErrorKind};
+
+/// Largest number of shares a seed can be split into
+pub const MAX_SHARES: u8 = 16;
+
+const CHECKSUM_LEN: usize = 4;
+const HEADER_LEN: usize = 5;
+const BITS_PER_WORD: usize = 11;
+
+/// A single decoded share
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeedShare {
+	/// Identifies the set of shares made by one split
+	pub set_id: u16,
+	/// Number of shares needed to recover the seed
+	pub threshold: u8,
+	/// Share index, from 1
+	pub index: u8,
+	/// Share data, as long as the seed
+	pub data: Vec<u8>,
+}
+
+impl SeedShare {
+	/// Encode the share as a phrase
+	pub fn to_phrase(&self) -> String {
+		let mut bytes = vec![
+			(self.set_id >> 8) as u8,
+			self.set_id as u8,
+			self.threshold,
+			self.index,
+			self.data.len() as u8,
+		];
+		bytes.extend(&self.data);
+		let checksum = blake2b(32, &[], &bytes);
+		bytes.extend(&checksum.as_bytes()[..CHECKSUM_LEN]);
+		bytes_to_words(&bytes)
+	}
+
+	/// Decode a share phrase, checking its checksum
+	pub fn from_phrase(phrase: &str) -> Result<SeedShare, Error> {
+		let bytes = words_to_bytes(phrase)?;
+		let invalid = |msg: &str| ErrorKind::InvalidShare(msg.to_owned());
+		if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+			return Err(invalid("too few words"))?;
+		}
+		let data_len = bytes[4] as usize;
+		let checksum_start = HEADER_LEN + data_len;
+		if bytes.len() < checksum_start + CHECKSUM_LEN {
+			return Err(invalid("too few words"))?;
+		}
+		// anything past the checksum is padding from the last word
+		if bytes[checksum_start + CHECKSUM_LEN..]
+			.iter()
+			.any(|b| *b != 0)
+		{
+			return Err(invalid("checksum mismatch"))?;
+		}
+		let checksum = blake2b(32, &[], &bytes[..checksum_start]);
+		if checksum.as_bytes()[..CHECKSUM_LEN]
+			!= bytes[checksum_start..checksum_start + CHECKSUM_LEN]
+		{
+			return Err(invalid("checksum mismatch"))?;
+		}
+		let share = SeedShare {
+			set_id: ((bytes[0] as u16) << 8) | bytes[1] as u16,
+			threshold: bytes[2],
+			index: bytes[3],
+			data: bytes[HEADER_LEN..checksum_start].to_vec(),
+		};
+		if share.threshold == 0 || share.index == 0 || share.index > MAX_SHARES {
+			return Err(invalid("bad share parameters"))?;
+		}
+		Ok(share)
+	}
+}
+
+/// Split `secret` into `shares` shares, any `threshold` of which recover it
+pub fn split_secret(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<SeedShare>, Error> {
+	if threshold == 0 || threshold > shares || shares > MAX_SHARES {
+		return Err(ErrorKind::ArgumentError(format!(
+			"Invalid share scheme {}-of-{}, need 1 <= M <= N <= {}",
+			threshold, shares, MAX_SHARES
+		)))?;
+	}
+	let set_id: u16 = thread_rng().gen();
+	let mut res: Vec<SeedShare> = (1..=shares)
+		.map(|index| SeedShare {
+			set_id,
+			threshold,
+			index,
+			data: Vec::with_capacity(secret.len()),
+		})
+		.collect();
+	let mut rng = thread_rng();
+	for s in secret {
+		// random polynomial of degree threshold - 1 with the secret byte as
+		// its constant term
+		let mut coeffs = vec![*s];
+		for _ in 1..threshold {
+			coeffs.push(rng.gen());
+		}
+		for share in res.iter_mut() {
+			share.data.push(eval_poly(&coeffs, share.index));
+		}
+	}
+	Ok(res)
+}
+
+/// Recover the secret from at least `threshold` shares of the same set
+pub fn combine_shares(shares: &[SeedShare]) -> Result<Vec<u8>, Error> {
+	let first = match shares.first() {
+		Some(s) => s,
+		None => return Err(ErrorKind::InvalidShare("no shares given".to_owned()))?,
+	};
+	for (i, s) in shares.iter().enumerate() {
+		if s.set_id != first.set_id || s.threshold != first.threshold {
+			return Err(ErrorKind::InvalidShare(format!(
+				"share {} is from a different backup",
+				s.index
+			)))?;
+		}
+		if s.data.len() != first.data.len() {
+			return Err(ErrorKind::InvalidShare(format!(
+				"share {} has the wrong length",
+				s.index
+			)))?;
+		}
+		if shares[..i].iter().any(|p| p.index == s.index) {
+			return Err(ErrorKind::InvalidShare(format!(
+				"share {} given more than once",
+				s.index
+			)))?;
+		}
+	}
+	let threshold = first.threshold as usize;
+	if shares.len() < threshold {
+		return Err(ErrorKind::InvalidShare(format!(
+			"{} shares needed, only {} given",
+			threshold,
+			shares.len()
+		)))?;
+	}
+	let shares = &shares[..threshold];
+
+	// Lagrange interpolation at x = 0
+	let mut secret = vec![0u8; first.data.len()];
+	for (i, si) in shares.iter().enumerate() {
+		let mut basis = 1u8;
+		for (j, sj) in shares.iter().enumerate() {
+			if i != j {
+				basis = gf_mul(basis, gf_div(sj.index, sj.index ^ si.index));
+			}
+		}
+		for (b, d) in secret.iter_mut().zip(si.data.iter()) {
+			*b ^= gf_mul(*d, basis);
+		}
+	}
+	Ok(secret)
+}
+
+/// Multiplication in GF(2^8) with the AES polynomial
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+	let mut res = 0u8;
+	while b != 0 {
+		if b & 1 != 0 {
+			res ^= a;
+		}
+		let carry = a & 0x80;
+		a <<= 1;
+		if carry != 0 {
+			a ^= 0x1b;
+		}
+		b >>= 1;
+	}
+	res
+}
+
+/// Inverse in GF(2^8), as a^254
+fn gf_inv(a: u8) -> u8 {
+	let mut res = 1u8;
+	for _ in 0..254 {
+		res = gf_mul(res, a);
+	}
+	res
+}
+
+fn gf_div(a: u8, b: u8) -> u8 {
+	gf_mul(a, gf_inv(b))
+}
+
+fn eval_poly(coeffs: &[u8], x: u8) -> u8 {
+	coeffs.iter().rev().fold(0u8, |acc, c| gf_mul(acc, x) ^ c)
+}
+
+fn bytes_to_words(bytes: &[u8]) -> String {
+	let total_bits = bytes.len() * 8;
+	let num_words = (total_bits + BITS_PER_WORD - 1) / BITS_PER_WORD;
+	let bit = |i: usize| -> usize {
+		if i < total_bits {
+			((bytes[i / 8] >> (7 - i % 8)) & 1) as usize
+		} else {
+			0
+		}
+	};
+	(0..num_words)
+		.map(|w| {
+			let idx = (0..BITS_PER_WORD).fold(0, |acc, b| (acc << 1) | bit(w * BITS_PER_WORD + b));
+			mnemonic::WORDS[idx].to_string()
+		})
+		.collect::<Vec<String>>()
+		.join(" ")
+}
+
+fn words_to_bytes(phrase: &str) -> Result<Vec<u8>, Error> {
+	let mut indices = vec![];
+	for w in phrase.split_whitespace() {
+		match mnemonic::search(w) {
+			Ok(i) => indices.push(i as usize),
+			Err(_) => {
+				return Err(ErrorKind::InvalidShare(format!("unknown word '{}'", w)))?;
+			}
+		}
+	}
+	let num_bytes = indices.len() * BITS_PER_WORD / 8;
+	let mut bytes = vec![0u8; num_bytes];
+	for (w, idx) in indices.iter().enumerate() {
+		for b in 0..BITS_PER_WORD {
+			let i = w * BITS_PER_WORD + b;
+			if i / 8 < num_bytes && (idx >> (BITS_PER_WORD - 1 - b)) & 1 == 1 {
+				bytes[i / 8] |= 1 << (7 - i % 8);
+			}
+		}
+	}
+	Ok(bytes)
+}
@@ -15,9 +15,10 @@
 use crate::api;
 use crate::chain;
 use crate::chain::Chain;
-use crate::config::WalletConfig;
+use crate::config::{WalletBackendType, WalletConfig};
 use crate::core;
-use crate::core::core::{OutputFeatures, OutputIdentifier, Transaction};
+use crate::core::core::hash::Hashed;
+use crate::core::core::{pmmr, BlockHeader, OutputFeatures, OutputIdentifier, Transaction};
 use crate::core::{consensus, global, pow};
 use crate::keychain;
 use crate::libwallet;
@@ -28,9 +29,10 @@ use crate::libwallet::{
 use crate::util;
 use crate::util::secp::pedersen;
 use crate::util::Mutex;
-use crate::LMDBBackend;
 use crate::WalletSeed;
+use crate::{LMDBBackend, MemoryBackend, SqliteBackend};
 use chrono::Duration;
+use std::env;
 use std::sync::Arc;
 use std::thread;
 
@@ -77,12 +79,69 @@ fn get_outputs_by_pmmr_index_local(
 	}
 }
 
+/// get the output pmmr index range of a block height range from local, as
+/// an output listing without outputs
+fn height_range_to_pmmr_indices_local(
+	chain: Arc<chain::Chain>,
+	start_height: u64,
+	end_height: Option<u64>,
+) -> api::OutputListing {
+	let head = chain.head_header().unwrap();
+	// outputs of a block come after those of the block before, and headers
+	// hold MMR sizes rather than the insertion indices outputs are listed by
+	let start_index = match start_height {
+		0 => 1,
+		h if h > head.height => pmmr::n_leaves(head.output_mmr_size) + 1,
+		h => pmmr::n_leaves(chain.get_header_by_height(h - 1).unwrap().output_mmr_size) + 1,
+	};
+	let end_index = match end_height {
+		Some(h) if h < head.height => {
+			pmmr::n_leaves(chain.get_header_by_height(h).unwrap().output_mmr_size)
+		}
+		_ => pmmr::n_leaves(head.output_mmr_size),
+	};
+	api::OutputListing {
+		last_retrieved_index: start_index,
+		highest_index: end_index,
+		outputs: vec![],
+	}
+}
+
+/// get the headers of the blocks at the given heights from local, skipping
+/// heights past the chain head
+fn get_block_headers_local(
+	chain: Arc<chain::Chain>,
+	heights: Vec<u64>,
+) -> Vec<libwallet::BlockHeaderInfo> {
+	heights
+		.into_iter()
+		.filter_map(|h| chain.get_header_by_height(h).ok())
+		.map(|header| libwallet::BlockHeaderInfo {
+			height: header.height,
+			hash: libwallet::BlockIdentifier(header.hash()),
+			timestamp: header.timestamp,
+		})
+		.collect()
+}
+
 /// Adds a block with a given reward to the chain and mines it
 pub fn add_block_with_reward(chain: &Chain, txs: Vec<&Transaction>, reward: CbData) {
 	let prev = chain.head_header().unwrap();
+	add_block_with_reward_on(chain, &prev, txs, reward);
+}
+
+/// Adds a block with a given reward on top of `prev` rather than the chain
+/// head and mines it, returning its header. The chain reorgs onto the block
+/// if that gives it the most work.
+pub fn add_block_with_reward_on(
+	chain: &Chain,
+	prev: &BlockHeader,
+	txs: Vec<&Transaction>,
+	reward: CbData,
+) -> BlockHeader {
 	let next_header_info = consensus::next_difficulty(1, chain.difficulty_iter().unwrap());
 	let mut b = core::core::Block::new(
-		&prev,
+		prev,
 		txs.into_iter().cloned().collect(),
 		next_header_info.clone().difficulty,
 		(reward.output, reward.kernel),
@@ -90,7 +149,11 @@ pub fn add_block_with_reward(chain: &Chain, txs: Vec<&Transaction>, reward: CbDa
 	.unwrap();
 	b.header.timestamp = prev.timestamp + Duration::seconds(60);
 	b.header.pow.secondary_scaling = next_header_info.secondary_scaling;
-	chain.set_txhashset_roots(&mut b).unwrap();
+	if prev.hash() == chain.head_header().unwrap().hash() {
+		chain.set_txhashset_roots(&mut b).unwrap();
+	} else {
+		chain.set_txhashset_roots_forked(&mut b, prev).unwrap();
+	}
 	pow::pow_size(
 		&mut b.header,
 		next_header_info.difficulty,
@@ -98,8 +161,35 @@ pub fn add_block_with_reward(chain: &Chain, txs: Vec<&Transaction>, reward: CbDa
 		global::min_edge_bits(),
 	)
 	.unwrap();
+	let header = b.header.clone();
 	chain.process_block(b, chain::Options::MINE).unwrap();
 	chain.validate(false).unwrap();
+	header
+}
+
+/// Builds a wallet's coinbase for a block on top of `prev` with the given
+/// transactions
+fn coinbase_for_wallet<C, K>(
+	prev: &BlockHeader,
+	txs: &[&Transaction],
+	wallet: Arc<Mutex<dyn WalletInst<C, K>>>,
+) -> Result<CbData, libwallet::Error>
+where
+	C: NodeClient,
+	K: keychain::Keychain,
+{
+	let fee_amt = txs.iter().map(|tx| tx.fee()).sum();
+	let block_fees = BlockFees {
+		fees: fee_amt,
+		key_id: None,
+		height: prev.height + 1,
+	};
+	// build coinbase (via api)
+	let mut w = wallet.lock();
+	w.open_with_credentials()?;
+	let res = foreign::build_coinbase(&mut *w, &block_fees, false)?;
+	w.close()?;
+	Ok(res)
 }
 
 /// adds a reward output to a wallet, includes that reward in a block, mines
@@ -114,26 +204,37 @@ where
 	C: NodeClient,
 	K: keychain::Keychain,
 {
-	// build block fees
 	let prev = chain.head_header().unwrap();
-	let fee_amt = txs.iter().map(|tx| tx.fee()).sum();
-	let block_fees = BlockFees {
-		fees: fee_amt,
-		key_id: None,
-		height: prev.height + 1,
-	};
-	// build coinbase (via api) and add block
-	let coinbase_tx = {
-		let mut w = wallet.lock();
-		w.open_with_credentials()?;
-		let res = foreign::build_coinbase(&mut *w, &block_fees, false)?;
-		w.close()?;
-		res
-	};
+	let coinbase_tx = coinbase_for_wallet(&prev, &txs, wallet)?;
 	add_block_with_reward(chain, txs, coinbase_tx.clone());
 	Ok(())
 }
 
+/// Mines a fork of `number` blocks rewarding a wallet, on top of the block at
+/// `fork_height` rather than the chain head, with the transactions given in
+/// its first block. Once the fork has more work than the chain it replaced,
+/// the blocks above `fork_height` are orphaned. Returns the last header.
+pub fn award_blocks_on_fork<C, K>(
+	chain: &Chain,
+	fork_height: u64,
+	txs: Vec<&Transaction>,
+	wallet: Arc<Mutex<dyn WalletInst<C, K>>>,
+	number: usize,
+) -> Result<BlockHeader, libwallet::Error>
+where
+	C: NodeClient,
+	K: keychain::Keychain,
+{
+	let mut prev = chain.get_header_by_height(fork_height).unwrap();
+	let mut txs = txs;
+	for _ in 0..number {
+		let coinbase_tx = coinbase_for_wallet(&prev, &txs, wallet.clone())?;
+		prev = add_block_with_reward_on(chain, &prev, txs, coinbase_tx);
+		txs = vec![];
+	}
+	Ok(prev)
+}
+
 /// Award a blocks to a wallet directly
 pub fn award_blocks_to_wallet<C, K>(
 	chain: &Chain,
@@ -160,6 +261,79 @@ pub fn create_wallet<C, K>(
 	n_client: C,
 	rec_phrase: Option<&str>,
 ) -> Arc<Mutex<dyn WalletInst<C, K>>>
+where
+	C: NodeClient + 'static,
+	K: keychain::Keychain + 'static,
+{
+	create_wallet_with_backend(dir, n_client, rec_phrase, test_backend())
+}
+
+/// dispatch a db wallet whose keychain combines the recovery phrase with a
+/// BIP39 passphrase
+pub fn create_wallet_with_mnemonic_passphrase<C, K>(
+	dir: &str,
+	n_client: C,
+	rec_phrase: Option<&str>,
+	mnemonic_passphrase: &str,
+) -> Arc<Mutex<dyn WalletInst<C, K>>>
+where
+	C: NodeClient + 'static,
+	K: keychain::Keychain + 'static,
+{
+	create_test_wallet(
+		test_wallet_config(dir, test_backend()),
+		n_client,
+		rec_phrase,
+		Some(mnemonic_passphrase),
+	)
+}
+
+/// dispatch a wallet using the given storage backend. In-memory wallets
+/// don't write anything under `dir`, including the seed file
+pub fn create_wallet_with_backend<C, K>(
+	dir: &str,
+	n_client: C,
+	rec_phrase: Option<&str>,
+	backend: WalletBackendType,
+) -> Arc<Mutex<dyn WalletInst<C, K>>>
+where
+	C: NodeClient + 'static,
+	K: keychain::Keychain + 'static,
+{
+	create_test_wallet(test_wallet_config(dir, backend), n_client, rec_phrase, None)
+}
+
+/// dispatch a wallet with the given configuration, as built by
+/// `test_wallet_config`
+pub fn create_wallet_with_config<C, K>(
+	wallet_config: WalletConfig,
+	n_client: C,
+	rec_phrase: Option<&str>,
+) -> Arc<Mutex<dyn WalletInst<C, K>>>
+where
+	C: NodeClient + 'static,
+	K: keychain::Keychain + 'static,
+{
+	create_test_wallet(wallet_config, n_client, rec_phrase, None)
+}
+
+/// Configuration of a test wallet in `dir` using the given storage backend
+pub fn test_wallet_config(dir: &str, backend: WalletBackendType) -> WalletConfig {
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = String::from(dir);
+	wallet_config.wallet_backend = Some(backend);
+	// keep seed encryption cheap, test wallets are opened often
+	wallet_config.seed_kdf_memory_kib = Some(64);
+	wallet_config.seed_kdf_iterations = Some(1);
+	wallet_config
+}
+
+fn create_test_wallet<C, K>(
+	wallet_config: WalletConfig,
+	n_client: C,
+	rec_phrase: Option<&str>,
+	mnemonic_passphrase: Option<&str>,
+) -> Arc<Mutex<dyn WalletInst<C, K>>>
 where
 	C: NodeClient + 'static,
 	K: keychain::Keychain + 'static,
@@ -168,10 +342,54 @@ where
 		Some(s) => Some(util::ZeroingString::from(s)),
 		None => None,
 	};
-	let mut wallet_config = WalletConfig::default();
-	wallet_config.data_file_dir = String::from(dir);
-	let _ = WalletSeed::init_file(&wallet_config, 32, z_string, "");
-	let mut wallet = LMDBBackend::new(wallet_config.clone(), "", n_client)
+	let z_passphrase = match mnemonic_passphrase {
+		Some(s) => Some(util::ZeroingString::from(s)),
+		None => None,
+	};
+	match wallet_config.wallet_backend() {
+		WalletBackendType::Lmdb => {
+			let _ = WalletSeed::init_file(&wallet_config, 32, z_string, z_passphrase, "");
+			let wallet = LMDBBackend::new(wallet_config.clone(), "", n_client);
+			open_test_wallet(wallet, &wallet_config)
+		}
+		WalletBackendType::Sqlite => {
+			let _ = WalletSeed::init_file(&wallet_config, 32, z_string, z_passphrase, "");
+			let wallet = SqliteBackend::new(wallet_config.clone(), "", n_client);
+			open_test_wallet(wallet, &wallet_config)
+		}
+		WalletBackendType::Memory => {
+			let seed = match z_string {
+				Some(s) => WalletSeed::from_mnemonic(&s).unwrap(),
+				None => WalletSeed::init_new(32),
+			}
+			.with_mnemonic_passphrase(z_passphrase);
+			let wallet = MemoryBackend::new(wallet_config.clone(), seed, n_client);
+			open_test_wallet(wallet, &wallet_config)
+		}
+	}
+}
+
+/// Backend used by test wallets, selected with the `WALLET_TEST_BACKEND`
+/// environment variable (`lmdb`, `sqlite` or `memory`) so the same suites
+/// can be run against each backend
+pub fn test_backend() -> WalletBackendType {
+	match env::var("WALLET_TEST_BACKEND") {
+		Ok(ref b) if b == "sqlite" => WalletBackendType::Sqlite,
+		Ok(ref b) if b == "memory" => WalletBackendType::Memory,
+		_ => WalletBackendType::Lmdb,
+	}
+}
+
+fn open_test_wallet<W, C, K>(
+	wallet: Result<W, libwallet::Error>,
+	wallet_config: &WalletConfig,
+) -> Arc<Mutex<dyn WalletInst<C, K>>>
+where
+	W: WalletInst<C, K>,
+	C: NodeClient + 'static,
+	K: keychain::Keychain + 'static,
+{
+	let mut wallet = wallet
 		.unwrap_or_else(|e| panic!("Error creating wallet: {:?} Config: {:?}", e, wallet_config));
 	wallet.open_with_credentials().unwrap_or_else(|e| {
 		panic!(
@@ -26,7 +26,7 @@ use crate::core::global::{set_mining_mode, ChainTypes};
 use crate::core::{pow, ser};
 use crate::keychain::Keychain;
 use crate::libwallet::api_impl::foreign;
-use crate::libwallet::{NodeClient, Slate, TxWrapper, WalletInst};
+use crate::libwallet::{BlockHeaderInfo, NodeClient, Slate, TxWrapper, WalletInst};
 use crate::util;
 use crate::util::secp::pedersen;
 use crate::util::secp::pedersen::Commitment;
@@ -148,6 +148,8 @@ where
 				"get_chain_height" => self.get_chain_height(m)?,
 				"get_outputs_from_node" => self.get_outputs_from_node(m)?,
 				"get_outputs_by_pmmr_index" => self.get_outputs_by_pmmr_index(m)?,
+				"height_range_to_pmmr_indices" => self.height_range_to_pmmr_indices(m)?,
+				"get_block_headers" => self.get_block_headers(m)?,
 				"send_tx_slate" => self.send_tx_slate(m)?,
 				"post_tx" => self.post_tx(m)?,
 				_ => panic!("Unknown Wallet Proxy Message"),
@@ -284,6 +286,47 @@ where
 			body: serde_json::to_string(&ol).unwrap(),
 		})
 	}
+
+	/// get pmmr index range of a block height range
+	fn height_range_to_pmmr_indices(
+		&mut self,
+		m: WalletProxyMessage,
+	) -> Result<WalletProxyMessage, libwallet::Error> {
+		let split = m.body.split(",").collect::<Vec<&str>>();
+		let start_height = split[0].parse::<u64>().unwrap();
+		let end_height = match split[1] {
+			"" => None,
+			h => Some(h.parse::<u64>().unwrap()),
+		};
+		let ol =
+			super::height_range_to_pmmr_indices_local(self.chain.clone(), start_height, end_height);
+		Ok(WalletProxyMessage {
+			sender_id: "node".to_owned(),
+			dest: m.sender_id,
+			method: m.method,
+			body: serde_json::to_string(&ol).unwrap(),
+		})
+	}
+
+	/// get headers of blocks by height
+	fn get_block_headers(
+		&mut self,
+		m: WalletProxyMessage,
+	) -> Result<WalletProxyMessage, libwallet::Error> {
+		let heights = m
+			.body
+			.split(",")
+			.filter(|h| !h.is_empty())
+			.map(|h| h.parse::<u64>().unwrap())
+			.collect();
+		let headers = super::get_block_headers_local(self.chain.clone(), heights);
+		Ok(WalletProxyMessage {
+			sender_id: "node".to_owned(),
+			dest: m.sender_id,
+			method: m.method,
+			body: serde_json::to_string(&headers).unwrap(),
+		})
+	}
 }
 
 #[derive(Clone)]
@@ -402,6 +445,9 @@ impl NodeClient for LocalWalletClient {
 	}
 	fn set_node_url(&mut self, _node_url: &str) {}
 	fn set_node_api_secret(&mut self, _node_api_secret: Option<String>) {}
+	fn socks_proxy(&self) -> Option<String> {
+		None
+	}
 	/// Posts a transaction to a grin node
 	/// In this case it will create a new block with award rewarded to
 	fn post_tx(&self, tx: &TxWrapper, _fluff: bool) -> Result<(), libwallet::Error> {
@@ -531,4 +577,64 @@ impl NodeClient for LocalWalletClient {
 		}
 		Ok((o.highest_index, o.last_retrieved_index, api_outputs))
 	}
+
+	fn height_range_to_pmmr_indices(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<(u64, u64), libwallet::Error> {
+		// start height, end height (empty for the chain tip)
+		let end_str = match end_height {
+			Some(h) => format!("{}", h),
+			None => "".to_owned(),
+		};
+		let query_str = format!("{},{}", start_height, end_str);
+		let m = WalletProxyMessage {
+			sender_id: self.id.clone(),
+			dest: self.node_url().to_owned(),
+			method: "height_range_to_pmmr_indices".to_owned(),
+			body: query_str,
+		};
+		{
+			let p = self.proxy_tx.lock();
+			p.send(m).context(libwallet::ErrorKind::ClientCallback(
+				"Get PMMR indices of height range send".to_owned(),
+			))?;
+		}
+
+		let r = self.rx.lock();
+		let m = r.recv().unwrap();
+		let o: api::OutputListing = serde_json::from_str(&m.body).unwrap();
+		Ok((o.last_retrieved_index, o.highest_index))
+	}
+
+	fn get_block_headers(
+		&self,
+		heights: Vec<u64>,
+	) -> Result<Vec<BlockHeaderInfo>, libwallet::Error> {
+		let query_str = heights
+			.iter()
+			.map(|h| format!("{}", h))
+			.collect::<Vec<String>>()
+			.join(",");
+		let m = WalletProxyMessage {
+			sender_id: self.id.clone(),
+			dest: self.node_url().to_owned(),
+			method: "get_block_headers".to_owned(),
+			body: query_str,
+		};
+		{
+			let p = self.proxy_tx.lock();
+			p.send(m).context(libwallet::ErrorKind::ClientCallback(
+				"Get block headers send".to_owned(),
+			))?;
+		}
+
+		let r = self.rx.lock();
+		let m = r.recv().unwrap();
+		let headers: Vec<BlockHeaderInfo> = serde_json::from_str(&m.body).context(
+			libwallet::ErrorKind::ClientCallback("Error parsing block headers".to_owned()),
+		)?;
+		Ok(headers)
+	}
 }
@@ -0,0 +1,470 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet database encryption tests
+extern crate grin_wallet_config as config;
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_libwallet as libwallet;
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_store as store;
+use grin_wallet_util::grin_util as util;
+
+use self::core::core::Transaction;
+use self::core::ser;
+use self::keychain::{ExtKeychain, Keychain};
+use config::WalletConfig;
+use impls::test_framework::LocalWalletClient;
+use impls::{LMDBBackend, SqliteBackend, WalletSeed};
+use libwallet::{AcctPathMapping, OutputData, OutputStatus, TxLogEntry, TxLogEntryType};
+use libwallet::{WalletBackend, WalletOutputBatch};
+use rusqlite::{params, Connection, NO_PARAMS};
+use std::fs;
+use std::sync::mpsc::channel;
+
+/// Raw stored value, written the way the wallet types write themselves
+struct RawValue(Vec<u8>);
+
+impl ser::Writeable for RawValue {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.0)
+	}
+}
+
+impl ser::Readable for RawValue {
+	fn read(reader: &mut dyn ser::Reader) -> Result<RawValue, ser::Error> {
+		Ok(RawValue(reader.read_bytes_len_prefix()?))
+	}
+}
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn test_output() -> OutputData {
+	OutputData {
+		root_key_id: ExtKeychain::derive_key_id(2, 0, 0, 0, 0),
+		key_id: ExtKeychain::derive_key_id(3, 0, 0, 0, 0),
+		n_child: 0,
+		commit: None,
+		mmr_index: None,
+		value: 1_000,
+		status: OutputStatus::Unspent,
+		height: 1,
+		lock_height: 0,
+		is_coinbase: false,
+		tx_log_entry: None,
+		block_hash: None,
+	}
+}
+
+fn open_wallet(wallet_config: &WalletConfig) -> LMDBBackend<LocalWalletClient, ExtKeychain> {
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+	let mut wallet = LMDBBackend::new(wallet_config.clone(), "", client).unwrap();
+	wallet.open_with_credentials().unwrap();
+	wallet
+}
+
+/// Every value in the store, as raw bytes
+fn raw_values(test_dir: &str) -> Vec<Vec<u8>> {
+	let db_path = format!("{}/db", test_dir);
+	let store = store::Store::new(&db_path, None, Some("db"), None).unwrap();
+	let mut res = vec![];
+	for prefix in ['o', 'a'].iter() {
+		for (_, v) in store.iter::<RawValue>(&[*prefix as u8]).unwrap() {
+			res.push(v.0);
+		}
+	}
+	res
+}
+
+fn open_sqlite_wallet(
+	wallet_config: &WalletConfig,
+) -> SqliteBackend<LocalWalletClient, ExtKeychain> {
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+	let mut wallet = SqliteBackend::new(wallet_config.clone(), "", client).unwrap();
+	wallet.open_with_credentials().unwrap();
+	wallet
+}
+
+/// Every output and account value and key in the SQLite database, as raw
+/// bytes
+fn raw_sqlite_values(test_dir: &str) -> Vec<Vec<u8>> {
+	let conn = Connection::open(format!("{}/db/wallet.sqlite", test_dir)).unwrap();
+	let mut res = vec![];
+	for sql in [
+		"SELECT data FROM outputs",
+		"SELECT data FROM account_paths",
+		"SELECT label FROM account_paths",
+	]
+	.iter()
+	{
+		let mut stmt = conn.prepare(sql).unwrap();
+		let rows = stmt
+			.query_map(NO_PARAMS, |row| row.get::<_, Vec<u8>>(0))
+			.unwrap();
+		for r in rows {
+			res.push(r.unwrap());
+		}
+	}
+	res
+}
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+	haystack
+		.windows(needle.len())
+		.any(|w| w == needle.as_bytes())
+}
+
+/// Write a database and stored tx the way wallets did before encryption
+fn write_plaintext_wallet(test_dir: &str, out: &OutputData) {
+	let db_path = format!("{}/db", test_dir);
+	fs::create_dir_all(&db_path).unwrap();
+	let store = store::Store::new(&db_path, None, Some("db"), None).unwrap();
+	let batch = store.batch().unwrap();
+	let key = store::to_key('o' as u8, &mut out.key_id.to_bytes().to_vec());
+	batch.put_ser(&key, out).unwrap();
+	let acct = AcctPathMapping {
+		label: "savings".to_owned(),
+		path: ExtKeychain::derive_key_id(2, 1, 0, 0, 0),
+	};
+	let key = store::to_key('a' as u8, &mut acct.label.as_bytes().to_vec());
+	batch.put_ser(&key, &acct).unwrap();
+	batch
+		.put_ser(
+			&store::to_key('v' as u8, &mut "schema".as_bytes().to_vec()),
+			&1u32,
+		)
+		.unwrap();
+	batch.commit().unwrap();
+
+	let tx_dir = format!("{}/saved_txs", test_dir);
+	fs::create_dir_all(&tx_dir).unwrap();
+	let tx_hex = util::to_hex(ser::ser_vec(&Transaction::empty()).unwrap());
+	fs::write(format!("{}/legacy.grintx", tx_dir), tx_hex).unwrap();
+}
+
+/// Write a SQLite database the way wallets did before encryption, along
+/// with the same stored tx as `write_plaintext_wallet`
+fn write_plaintext_sqlite_wallet(test_dir: &str, out: &OutputData) {
+	let db_path = format!("{}/db", test_dir);
+	fs::create_dir_all(&db_path).unwrap();
+	let conn = Connection::open(format!("{}/wallet.sqlite", db_path)).unwrap();
+	conn.execute_batch(
+		"CREATE TABLE outputs (
+			key_id BLOB NOT NULL,
+			mmr_index INTEGER NOT NULL,
+			data TEXT NOT NULL,
+			PRIMARY KEY (key_id, mmr_index)
+		);
+		CREATE TABLE deriv_indices (
+			parent_key_id BLOB PRIMARY KEY NOT NULL,
+			child_index INTEGER NOT NULL
+		);
+		CREATE TABLE account_paths (
+			label TEXT PRIMARY KEY NOT NULL,
+			data TEXT NOT NULL
+		);",
+	)
+	.unwrap();
+	conn.execute(
+		"INSERT INTO outputs (key_id, mmr_index, data) VALUES (?1, -1, ?2)",
+		params![
+			out.key_id.to_bytes().to_vec(),
+			serde_json::to_string(out).unwrap()
+		],
+	)
+	.unwrap();
+	conn.execute(
+		"INSERT INTO deriv_indices (parent_key_id, child_index) VALUES (?1, 3)",
+		params![ExtKeychain::derive_key_id(2, 0, 0, 0, 0)
+			.to_bytes()
+			.to_vec()],
+	)
+	.unwrap();
+	let acct = AcctPathMapping {
+		label: "savings".to_owned(),
+		path: ExtKeychain::derive_key_id(2, 1, 0, 0, 0),
+	};
+	conn.execute(
+		"INSERT INTO account_paths (label, data) VALUES (?1, ?2)",
+		params![acct.label, serde_json::to_string(&acct).unwrap()],
+	)
+	.unwrap();
+
+	let tx_dir = format!("{}/saved_txs", test_dir);
+	fs::create_dir_all(&tx_dir).unwrap();
+	let tx_hex = util::to_hex(ser::ser_vec(&Transaction::empty()).unwrap());
+	fs::write(format!("{}/legacy.grintx", tx_dir), tx_hex).unwrap();
+}
+
+#[test]
+fn lmdb_values_encrypted() {
+	let test_dir = "test_output/lmdb_values_encrypted";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	WalletSeed::init_file(&wallet_config, 32, None, None, "").unwrap();
+	let out = test_output();
+
+	{
+		let mut wallet = open_wallet(&wallet_config);
+		let parent = wallet.parent_key_id();
+		let mut entry = TxLogEntry::new(parent.clone(), TxLogEntryType::TxSent, 0);
+		entry.stored_tx = Some("stored.grintx".to_owned());
+		{
+			let mut batch = wallet.batch().unwrap();
+			batch.save(out.clone()).unwrap();
+			batch.save_tx_log_entry(entry.clone(), &parent).unwrap();
+			batch.commit().unwrap();
+		}
+		wallet.store_tx("stored", &Transaction::empty()).unwrap();
+
+		assert_eq!(
+			wallet.iter().unwrap().collect::<Vec<_>>(),
+			vec![out.clone()]
+		);
+		assert_eq!(
+			wallet.get_stored_tx(&entry).unwrap(),
+			Some(Transaction::empty())
+		);
+		assert!(wallet
+			.get_acct_path("default".to_owned())
+			.unwrap()
+			.is_some());
+	}
+
+	// neither field names nor account labels are visible on disk
+	let values = raw_values(test_dir);
+	assert!(values.len() >= 2);
+	for v in values {
+		assert!(!contains(&v, "key_id"));
+		assert!(!contains(&v, "default"));
+	}
+	let tx_file = fs::read_to_string(format!("{}/saved_txs/stored.grintx", test_dir)).unwrap();
+	assert!(tx_file.starts_with("ENC1:"));
+
+	clean_output_dir(test_dir);
+}
+
+#[test]
+fn lmdb_plaintext_wallet_converted() {
+	let test_dir = "test_output/lmdb_plaintext_wallet_converted";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	WalletSeed::init_file(&wallet_config, 32, None, None, "").unwrap();
+	let out = test_output();
+	write_plaintext_wallet(test_dir, &out);
+
+	// converted on open, and everything is still readable
+	for _ in 0..2 {
+		let mut wallet = open_wallet(&wallet_config);
+		assert_eq!(
+			wallet.iter().unwrap().collect::<Vec<_>>(),
+			vec![out.clone()]
+		);
+		wallet.set_parent_key_id_by_name("savings").unwrap();
+		assert_eq!(
+			wallet.parent_key_id(),
+			ExtKeychain::derive_key_id(2, 1, 0, 0, 0)
+		);
+		let mut entry = TxLogEntry::new(wallet.parent_key_id(), TxLogEntryType::TxSent, 0);
+		entry.stored_tx = Some("legacy.grintx".to_owned());
+		assert_eq!(
+			wallet.get_stored_tx(&entry).unwrap(),
+			Some(Transaction::empty())
+		);
+	}
+
+	for v in raw_values(test_dir) {
+		assert!(!contains(&v, "key_id"));
+		assert!(!contains(&v, "savings"));
+	}
+	let tx_file = fs::read_to_string(format!("{}/saved_txs/legacy.grintx", test_dir)).unwrap();
+	assert!(tx_file.starts_with("ENC1:"));
+
+	clean_output_dir(test_dir);
+}
+
+#[test]
+fn lmdb_locked_store_unreadable() {
+	let test_dir = "test_output/lmdb_locked_store_unreadable";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	WalletSeed::init_file(&wallet_config, 32, None, None, "").unwrap();
+	{
+		let mut wallet = open_wallet(&wallet_config);
+		let mut batch = wallet.batch().unwrap();
+		batch.save(test_output()).unwrap();
+		batch.commit().unwrap();
+	}
+
+	// opened with the wrong password, the store stays locked, and reading
+	// it fails rather than finding an empty wallet
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+	let mut wallet: LMDBBackend<LocalWalletClient, ExtKeychain> =
+		LMDBBackend::new(wallet_config.clone(), "wrong", client).unwrap();
+	assert!(wallet.iter().is_err());
+	assert!(wallet.tx_log_iter().is_err());
+	assert!(wallet.acct_path_iter().is_err());
+	assert!(wallet.open_with_credentials().is_err());
+
+	clean_output_dir(test_dir);
+}
+
+#[test]
+fn sqlite_values_encrypted() {
+	let test_dir = "test_output/sqlite_values_encrypted";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	WalletSeed::init_file(&wallet_config, 32, None, None, "").unwrap();
+	let out = test_output();
+
+	{
+		let mut wallet = open_sqlite_wallet(&wallet_config);
+		let parent = wallet.parent_key_id();
+		let mut entry = TxLogEntry::new(parent.clone(), TxLogEntryType::TxSent, 0);
+		entry.stored_tx = Some("stored.grintx".to_owned());
+		{
+			let mut batch = wallet.batch().unwrap();
+			batch.save(out.clone()).unwrap();
+			batch.save_tx_log_entry(entry.clone(), &parent).unwrap();
+			batch.commit().unwrap();
+		}
+		wallet.store_tx("stored", &Transaction::empty()).unwrap();
+
+		assert_eq!(
+			wallet.iter().unwrap().collect::<Vec<_>>(),
+			vec![out.clone()]
+		);
+		assert_eq!(
+			wallet.get_stored_tx(&entry).unwrap(),
+			Some(Transaction::empty())
+		);
+		assert!(wallet
+			.get_acct_path("default".to_owned())
+			.unwrap()
+			.is_some());
+	}
+
+	// neither field names nor account labels are visible on disk
+	let values = raw_sqlite_values(test_dir);
+	assert!(values.len() >= 3);
+	for v in values {
+		assert!(!contains(&v, "key_id"));
+		assert!(!contains(&v, "default"));
+	}
+	let tx_file = fs::read_to_string(format!("{}/saved_txs/stored.grintx", test_dir)).unwrap();
+	assert!(tx_file.starts_with("ENC1:"));
+
+	clean_output_dir(test_dir);
+}
+
+#[test]
+fn sqlite_plaintext_wallet_converted() {
+	let test_dir = "test_output/sqlite_plaintext_wallet_converted";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	WalletSeed::init_file(&wallet_config, 32, None, None, "").unwrap();
+	let out = test_output();
+	write_plaintext_sqlite_wallet(test_dir, &out);
+
+	// converted on open, and everything is still readable
+	for _ in 0..2 {
+		let mut wallet = open_sqlite_wallet(&wallet_config);
+		assert_eq!(
+			wallet.iter().unwrap().collect::<Vec<_>>(),
+			vec![out.clone()]
+		);
+		assert_eq!(wallet.current_child_index().unwrap(), 3);
+		wallet.set_parent_key_id_by_name("savings").unwrap();
+		assert_eq!(
+			wallet.parent_key_id(),
+			ExtKeychain::derive_key_id(2, 1, 0, 0, 0)
+		);
+		let mut entry = TxLogEntry::new(wallet.parent_key_id(), TxLogEntryType::TxSent, 0);
+		entry.stored_tx = Some("legacy.grintx".to_owned());
+		assert_eq!(
+			wallet.get_stored_tx(&entry).unwrap(),
+			Some(Transaction::empty())
+		);
+	}
+
+	for v in raw_sqlite_values(test_dir) {
+		assert!(!contains(&v, "key_id"));
+		assert!(!contains(&v, "savings"));
+	}
+	let tx_file = fs::read_to_string(format!("{}/saved_txs/legacy.grintx", test_dir)).unwrap();
+	assert!(tx_file.starts_with("ENC1:"));
+
+	// once converted, a plaintext stored tx is refused
+	let tx_hex = util::to_hex(ser::ser_vec(&Transaction::empty()).unwrap());
+	fs::write(format!("{}/saved_txs/planted.grintx", test_dir), tx_hex).unwrap();
+	let wallet = open_sqlite_wallet(&wallet_config);
+	let mut entry = TxLogEntry::new(
+		ExtKeychain::derive_key_id(2, 0, 0, 0, 0),
+		TxLogEntryType::TxSent,
+		0,
+	);
+	entry.stored_tx = Some("planted.grintx".to_owned());
+	assert!(wallet.get_stored_tx(&entry).is_err());
+
+	clean_output_dir(test_dir);
+}
+
+#[test]
+fn seed_file_decrypted_once() {
+	let test_dir = "test_output/seed_file_decrypted_once";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	WalletSeed::init_file(&wallet_config, 32, None, None, "").unwrap();
+	let seed_path = format!("{}/wallet.seed", test_dir);
+	let seed_file = fs::read(&seed_path).unwrap();
+
+	// once the backend holds the keychain, opening the wallet again doesn't
+	// go back to the seed file
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+	let mut wallet: LMDBBackend<LocalWalletClient, ExtKeychain> =
+		LMDBBackend::new(wallet_config.clone(), "", client).unwrap();
+	fs::remove_file(&seed_path).unwrap();
+	for _ in 0..2 {
+		wallet.open_with_credentials().unwrap();
+		assert_eq!(wallet.iter().unwrap().count(), 0);
+		wallet.close().unwrap();
+	}
+	drop(wallet);
+
+	fs::write(&seed_path, &seed_file).unwrap();
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+	let mut wallet: SqliteBackend<LocalWalletClient, ExtKeychain> =
+		SqliteBackend::new(wallet_config.clone(), "", client).unwrap();
+	fs::remove_file(&seed_path).unwrap();
+	for _ in 0..2 {
+		wallet.open_with_credentials().unwrap();
+		assert_eq!(wallet.iter().unwrap().count(), 0);
+		wallet.close().unwrap();
+	}
+
+	clean_output_dir(test_dir);
+}
@@ -12,15 +12,36 @@
 // limitations under the License.
 
 //! Wallet seed encryption tests
+extern crate grin_wallet_config as config;
 extern crate grin_wallet_impls as impls;
 
-use impls::{EncryptedWalletSeed, WalletSeed};
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::keychain::{ExtKeychain, Keychain};
+use config::WalletConfig;
+use impls::{EncryptedWalletSeed, SeedKdf, WalletSeed, SEED_FILE, SEED_FILE_VERSION};
+use std::fs;
+use util::ZeroingString;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn test_kdf() -> SeedKdf {
+	SeedKdf::Argon2id {
+		memory_kib: 64,
+		iterations: 1,
+		parallelism: 1,
+	}
+}
 
 #[test]
 fn wallet_seed_encrypt() {
 	let password = "passwoid";
 	let wallet_seed = WalletSeed::init_new(32);
-	let mut enc_wallet_seed = EncryptedWalletSeed::from_seed(&wallet_seed, password).unwrap();
+	let mut enc_wallet_seed =
+		EncryptedWalletSeed::from_seed(&wallet_seed, password, test_kdf()).unwrap();
 	println!("EWS: {:?}", enc_wallet_seed);
 	let decrypted_wallet_seed = enc_wallet_seed.decrypt(password).unwrap();
 	assert_eq!(wallet_seed, decrypted_wallet_seed);
@@ -34,3 +55,197 @@ fn wallet_seed_encrypt() {
 	let decrypted_wallet_seed = enc_wallet_seed.decrypt(password);
 	assert!(decrypted_wallet_seed.is_err());
 }
+
+#[test]
+fn wallet_seed_decrypt_legacy() {
+	let password = "passwoid";
+	let wallet_seed = WalletSeed::init_new(32);
+	let enc_wallet_seed =
+		EncryptedWalletSeed::from_seed(&wallet_seed, password, SeedKdf::legacy()).unwrap();
+
+	// files written before the KDF was recorded have no version or kdf
+	let mut json = serde_json::to_value(&enc_wallet_seed).unwrap();
+	json.as_object_mut().unwrap().remove("version");
+	json.as_object_mut().unwrap().remove("kdf");
+	let legacy: EncryptedWalletSeed = serde_json::from_value(json).unwrap();
+	assert_eq!(legacy.version, 1);
+	assert_eq!(legacy.kdf, SeedKdf::legacy());
+	assert_eq!(legacy.decrypt(password).unwrap(), wallet_seed);
+	assert!(legacy.decrypt("").is_err());
+}
+
+#[test]
+fn wallet_seed_upgrade_file() {
+	let test_dir = "test_output/wallet_seed_upgrade_file";
+	clean_output_dir(test_dir);
+	fs::create_dir_all(test_dir).unwrap();
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	wallet_config.seed_kdf_memory_kib = Some(64);
+	wallet_config.seed_kdf_iterations = Some(1);
+
+	let password = "passwoid";
+	let wallet_seed = WalletSeed::init_new(32);
+	let seed_file = format!("{}/{}", test_dir, SEED_FILE);
+	let enc_wallet_seed =
+		EncryptedWalletSeed::from_seed(&wallet_seed, password, SeedKdf::legacy()).unwrap();
+	let mut json = serde_json::to_value(&enc_wallet_seed).unwrap();
+	json.as_object_mut().unwrap().remove("version");
+	json.as_object_mut().unwrap().remove("kdf");
+	let legacy_json = serde_json::to_string_pretty(&json).unwrap();
+	fs::write(&seed_file, &legacy_json).unwrap();
+
+	// wrong password leaves the file alone
+	assert!(WalletSeed::upgrade_file(&wallet_config, "").is_err());
+	assert_eq!(fs::read_to_string(&seed_file).unwrap(), legacy_json);
+
+	assert!(WalletSeed::upgrade_file(&wallet_config, password).unwrap());
+	let upgraded: EncryptedWalletSeed =
+		serde_json::from_str(&fs::read_to_string(&seed_file).unwrap()).unwrap();
+	assert_eq!(upgraded.version, SEED_FILE_VERSION);
+	assert_eq!(upgraded.kdf, test_kdf());
+	assert_eq!(
+		WalletSeed::from_file(&wallet_config, password).unwrap(),
+		wallet_seed
+	);
+
+	// the previous file is kept, and a second upgrade does nothing
+	let backup = format!("{}.bak", seed_file);
+	assert_eq!(fs::read_to_string(&backup).unwrap(), legacy_json);
+	assert!(!WalletSeed::upgrade_file(&wallet_config, password).unwrap());
+
+	clean_output_dir(test_dir);
+}
+
+#[test]
+fn wallet_seed_change_password() {
+	let test_dir = "test_output/wallet_seed_change_password";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	wallet_config.seed_kdf_memory_kib = Some(64);
+	wallet_config.seed_kdf_iterations = Some(1);
+
+	let wallet_seed =
+		WalletSeed::init_file_with_birthday(&wallet_config, 32, None, None, "old", Some(100))
+			.unwrap();
+	let seed_file = format!("{}/{}", test_dir, SEED_FILE);
+	let old_json = fs::read_to_string(&seed_file).unwrap();
+
+	// wrong current password leaves the file alone
+	assert!(WalletSeed::change_password(&wallet_config, "wrong", "new").is_err());
+	assert_eq!(fs::read_to_string(&seed_file).unwrap(), old_json);
+
+	WalletSeed::change_password(&wallet_config, "old", "new").unwrap();
+	assert!(WalletSeed::from_file(&wallet_config, "old").is_err());
+	assert_eq!(
+		WalletSeed::from_file(&wallet_config, "new").unwrap(),
+		wallet_seed
+	);
+	assert_eq!(
+		WalletSeed::file_birthday_height(&wallet_config).unwrap(),
+		Some(100)
+	);
+
+	// previous file kept as a backup, no temporary file left behind
+	let backup = format!("{}.bak", seed_file);
+	assert_eq!(fs::read_to_string(&backup).unwrap(), old_json);
+	assert!(!fs::metadata(format!("{}.tmp", seed_file)).is_ok());
+
+	clean_output_dir(test_dir);
+}
+
+#[test]
+fn wallet_seed_mnemonic_passphrase() {
+	let test_dir = "test_output/wallet_seed_mnemonic_passphrase";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	wallet_config.seed_kdf_memory_kib = Some(64);
+	wallet_config.seed_kdf_iterations = Some(1);
+
+	let passphrase = Some(ZeroingString::from("correct horse"));
+	let wallet_seed =
+		WalletSeed::init_file(&wallet_config, 32, None, passphrase.clone(), "").unwrap();
+	assert!(wallet_seed.has_mnemonic_passphrase());
+
+	// the passphrase is stored with the seed
+	let read_seed = WalletSeed::from_file(&wallet_config, "").unwrap();
+	assert_eq!(read_seed, wallet_seed);
+	let k1: ExtKeychain = read_seed.derive_keychain(false).unwrap();
+
+	// the same words without the passphrase, or with another, are another wallet
+	let words = wallet_seed.to_mnemonic().unwrap();
+	let plain = WalletSeed::from_mnemonic(&words).unwrap();
+	let other = WalletSeed::from_mnemonic(&words)
+		.unwrap()
+		.with_mnemonic_passphrase(Some(ZeroingString::from("battery staple")));
+	let k2: ExtKeychain = plain.derive_keychain(false).unwrap();
+	let k3: ExtKeychain = other.derive_keychain(false).unwrap();
+	let id = ExtKeychain::root_key_id();
+	let key = k1.derive_key(0, &id).unwrap();
+	assert_ne!(key, k2.derive_key(0, &id).unwrap());
+	assert_ne!(key, k3.derive_key(0, &id).unwrap());
+	let same: ExtKeychain = WalletSeed::from_mnemonic(&words)
+		.unwrap()
+		.with_mnemonic_passphrase(passphrase)
+		.derive_keychain(false)
+		.unwrap();
+	assert_eq!(key, same.derive_key(0, &id).unwrap());
+
+	// an empty passphrase is the same as none
+	let empty = WalletSeed::from_mnemonic(&words)
+		.unwrap()
+		.with_mnemonic_passphrase(Some(ZeroingString::from("")));
+	assert_eq!(empty, plain);
+
+	clean_output_dir(test_dir);
+}
+
+#[test]
+fn wallet_seed_verify_phrase() {
+	let test_dir = "test_output/wallet_seed_verify_phrase";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	wallet_config.seed_kdf_memory_kib = Some(64);
+	wallet_config.seed_kdf_iterations = Some(1);
+
+	let wallet_seed = WalletSeed::init_file(&wallet_config, 16, None, None, "").unwrap();
+	assert_eq!(WalletSeed::file_word_count(&wallet_config).unwrap(), 12);
+	let phrase = wallet_seed.to_mnemonic().unwrap();
+	let words: Vec<&str> = phrase.split_whitespace().collect();
+
+	assert!(wallet_seed.verify_phrase(&phrase));
+	assert!(!wallet_seed.verify_phrase(&words[..11].join(" ")));
+	assert!(!wallet_seed.verify_phrase("not a recovery phrase"));
+	let other = WalletSeed::init_new(16).to_mnemonic().unwrap();
+	assert!(!wallet_seed.verify_phrase(&other));
+
+	let positions = WalletSeed::random_word_positions(words.len(), 4);
+	assert_eq!(positions.len(), 4);
+	assert!(positions.windows(2).all(|p| p[0] < p[1]));
+	let mut entered: Vec<(usize, ZeroingString)> = positions
+		.iter()
+		.map(|p| (*p, ZeroingString::from(words[*p])))
+		.collect();
+	assert!(wallet_seed.verify_words(&entered).unwrap());
+
+	// one wrong word, or one that isn't in the word list, is a mismatch
+	let wrong = if words[positions[2]] == "zoo" {
+		"abandon"
+	} else {
+		"zoo"
+	};
+	entered[2].1 = ZeroingString::from(wrong);
+	assert!(!wallet_seed.verify_words(&entered).unwrap());
+	entered[2].1 = ZeroingString::from("notaword");
+	assert!(!wallet_seed.verify_words(&entered).unwrap());
+
+	assert!(wallet_seed
+		.verify_words(&[(12, ZeroingString::from(words[0]))])
+		.is_err());
+	assert!(wallet_seed.verify_words(&[]).is_err());
+
+	clean_output_dir(test_dir);
+}
@@ -0,0 +1,80 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory wallet backend tests
+extern crate grin_wallet_config as config;
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_libwallet as libwallet;
+use grin_wallet_util::grin_keychain as keychain;
+
+use self::keychain::{ExtKeychain, Keychain};
+use config::WalletConfig;
+use impls::test_framework::LocalWalletClient;
+use impls::{MemoryBackend, WalletSeed};
+use libwallet::{OutputData, OutputStatus, WalletBackend, WalletOutputBatch};
+use std::sync::mpsc::channel;
+
+fn output(n_child: u32) -> OutputData {
+	OutputData {
+		root_key_id: ExtKeychain::derive_key_id(2, 0, 0, 0, 0),
+		key_id: ExtKeychain::derive_key_id(3, 0, 0, n_child, 0),
+		n_child,
+		commit: None,
+		mmr_index: None,
+		value: 1_000,
+		status: OutputStatus::Unspent,
+		height: 1,
+		lock_height: 0,
+		is_coinbase: false,
+		tx_log_entry: None,
+		block_hash: None,
+	}
+}
+
+#[test]
+fn memory_backend_batches() {
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+	let mut wallet: MemoryBackend<LocalWalletClient, ExtKeychain> =
+		MemoryBackend::new(WalletConfig::default(), WalletSeed::init_new(32), client).unwrap();
+	wallet.open_with_credentials().unwrap();
+	assert_eq!(wallet.acct_path_iter().unwrap().count(), 1);
+
+	// changes are only visible outside the batch once committed
+	{
+		let mut batch = wallet.batch().unwrap();
+		batch.save(output(0)).unwrap();
+		assert_eq!(batch.iter().unwrap().count(), 1);
+		assert_eq!(batch.next_tx_log_id(&ExtKeychain::root_key_id()).unwrap(), 0);
+		batch.commit().unwrap();
+		// a committed batch can't be used again
+		assert!(batch.commit().is_err());
+		assert!(batch.save(output(1)).is_err());
+	}
+	assert_eq!(wallet.iter().unwrap().count(), 1);
+
+	// dropping a batch discards everything done in it
+	{
+		let mut batch = wallet.batch().unwrap();
+		batch.save(output(1)).unwrap();
+		batch.delete(&output(0).key_id, &None).unwrap();
+		assert_eq!(batch.next_tx_log_id(&ExtKeychain::root_key_id()).unwrap(), 1);
+	}
+	assert_eq!(wallet.iter().unwrap().count(), 1);
+	assert!(wallet.get(&output(0).key_id, &None).is_ok());
+	{
+		let mut batch = wallet.batch().unwrap();
+		assert_eq!(batch.next_tx_log_id(&ExtKeychain::root_key_id()).unwrap(), 1);
+	}
+}
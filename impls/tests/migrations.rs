@@ -0,0 +1,112 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet database schema migration tests
+extern crate grin_wallet_config as config;
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_libwallet as libwallet;
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_store as store;
+
+use self::core::ser;
+use self::keychain::{ExtKeychain, Keychain};
+use config::WalletConfig;
+use impls::test_framework::LocalWalletClient;
+use impls::{migrate_wallet_db, LMDBBackend, WalletSeed, CURRENT_SCHEMA_VERSION};
+use libwallet::{OutputData, OutputStatus, WalletBackend};
+use std::fs;
+use std::sync::mpsc::channel;
+
+/// Raw JSON value, written the same way the wallet types write themselves
+struct RawJson(Vec<u8>);
+
+impl ser::Writeable for RawJson {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.0)
+	}
+}
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+/// Write an output the way wallets did before `mmr_index` was added
+fn write_v0_output(test_dir: &str) -> OutputData {
+	let out = OutputData {
+		root_key_id: ExtKeychain::derive_key_id(2, 0, 0, 0, 0),
+		key_id: ExtKeychain::derive_key_id(3, 0, 0, 0, 0),
+		n_child: 0,
+		commit: None,
+		mmr_index: None,
+		value: 1_000,
+		status: OutputStatus::Unspent,
+		height: 1,
+		lock_height: 0,
+		is_coinbase: false,
+		tx_log_entry: None,
+		block_hash: None,
+	};
+	let mut json = serde_json::to_value(&out).unwrap();
+	json.as_object_mut().unwrap().remove("mmr_index");
+
+	let db_path = format!("{}/db", test_dir);
+	fs::create_dir_all(&db_path).unwrap();
+	let store = store::Store::new(&db_path, None, Some("db"), None).unwrap();
+	let batch = store.batch().unwrap();
+	let key = store::to_key('o' as u8, &mut out.key_id.to_bytes().to_vec());
+	batch
+		.put_ser(&key, &RawJson(serde_json::to_vec(&json).unwrap()))
+		.unwrap();
+	batch.commit().unwrap();
+	out
+}
+
+#[test]
+fn lmdb_schema_migration() {
+	let test_dir = "test_output/lmdb_schema_migration";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	WalletSeed::init_file(&wallet_config, 32, None, None, "").unwrap();
+	let out = write_v0_output(test_dir);
+
+	// dry run reports the change without making it
+	for _ in 0..2 {
+		let report = migrate_wallet_db(&wallet_config, "", true).unwrap();
+		assert_eq!(report.from_version, 0);
+		assert_eq!(report.to_version, CURRENT_SCHEMA_VERSION);
+		assert!(report.dry_run);
+		assert_eq!(report.steps.len(), 1);
+		assert_eq!(report.steps[0].changed.outputs, 1);
+		assert_eq!(report.steps[0].changed.tx_log_entries, 0);
+	}
+
+	// opening the wallet migrates, after which the old output is readable
+	{
+		let (tx, _rx) = channel();
+		let client = LocalWalletClient::new("wallet1", tx);
+		let mut wallet: LMDBBackend<LocalWalletClient, ExtKeychain> =
+			LMDBBackend::new(wallet_config.clone(), "", client).unwrap();
+		wallet.open_with_credentials().unwrap();
+		let outputs: Vec<_> = wallet.iter().unwrap().collect();
+		assert_eq!(outputs, vec![out]);
+	}
+
+	let report = migrate_wallet_db(&wallet_config, "", true).unwrap();
+	assert!(report.is_current());
+	assert_eq!(report.from_version, CURRENT_SCHEMA_VERSION);
+
+	clean_output_dir(test_dir);
+}
@@ -0,0 +1,335 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Failover and quorum tests for the multi-node client
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_libwallet as libwallet;
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_util as util;
+
+use self::core::core::hash::Hash;
+use self::libwallet::{BlockHeaderInfo, BlockIdentifier, NodeClient, TxWrapper};
+use self::util::secp::pedersen;
+use self::util::Mutex;
+use chrono::Utc;
+use impls::MultiNodeClient;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+type OutputMap = HashMap<pedersen::Commitment, (String, u64, u64)>;
+
+/// How a stand-in node answers
+#[derive(Clone)]
+enum Behaviour {
+	Answer,
+	Fail,
+	Hang,
+}
+
+/// Local stand-in for a node, answering from what it's given
+#[derive(Clone)]
+struct StandInNode {
+	url: String,
+	height: u64,
+	outputs: OutputMap,
+	/// Blocks up to its height, on the fork of the given number
+	fork: u8,
+	behaviour: Arc<Mutex<Behaviour>>,
+	posted: Arc<Mutex<usize>>,
+}
+
+impl StandInNode {
+	fn new(url: &str, height: u64, outputs: OutputMap) -> StandInNode {
+		StandInNode {
+			url: url.to_owned(),
+			height,
+			outputs,
+			fork: 0,
+			behaviour: Arc::new(Mutex::new(Behaviour::Answer)),
+			posted: Arc::new(Mutex::new(0)),
+		}
+	}
+
+	fn on_fork(mut self, fork: u8) -> StandInNode {
+		self.fork = fork;
+		self
+	}
+
+	fn set_behaviour(&self, b: Behaviour) {
+		*self.behaviour.lock() = b;
+	}
+
+	fn answer<T>(&self, res: T) -> Result<T, libwallet::Error> {
+		let behaviour = self.behaviour.lock().clone();
+		match behaviour {
+			Behaviour::Answer => Ok(res),
+			Behaviour::Fail => {
+				let report = format!("{} is down", self.url);
+				Err(libwallet::ErrorKind::ClientCallback(report))?
+			}
+			Behaviour::Hang => {
+				thread::sleep(Duration::from_secs(2));
+				Ok(res)
+			}
+		}
+	}
+}
+
+impl NodeClient for StandInNode {
+	fn node_url(&self) -> &str {
+		&self.url
+	}
+	fn set_node_url(&mut self, node_url: &str) {
+		self.url = node_url.to_owned();
+	}
+	fn node_api_secret(&self) -> Option<String> {
+		None
+	}
+	fn set_node_api_secret(&mut self, _node_api_secret: Option<String>) {}
+
+	fn socks_proxy(&self) -> Option<String> {
+		None
+	}
+
+	fn post_tx(&self, _tx: &TxWrapper, _fluff: bool) -> Result<(), libwallet::Error> {
+		self.answer(())?;
+		*self.posted.lock() += 1;
+		Ok(())
+	}
+
+	fn get_chain_height(&self) -> Result<u64, libwallet::Error> {
+		self.answer(self.height)
+	}
+
+	fn get_outputs_from_node(
+		&self,
+		wallet_outputs: Vec<pedersen::Commitment>,
+	) -> Result<OutputMap, libwallet::Error> {
+		let found = wallet_outputs
+			.iter()
+			.filter_map(|c| self.outputs.get(c).map(|o| (c.clone(), o.clone())))
+			.collect();
+		self.answer(found)
+	}
+
+	fn get_outputs_by_pmmr_index(
+		&self,
+		_start_height: u64,
+		_max_outputs: u64,
+	) -> Result<
+		(
+			u64,
+			u64,
+			Vec<(pedersen::Commitment, pedersen::RangeProof, bool, u64, u64)>,
+		),
+		libwallet::Error,
+	> {
+		self.answer((0, 0, vec![]))
+	}
+
+	fn height_range_to_pmmr_indices(
+		&self,
+		_start_height: u64,
+		_end_height: Option<u64>,
+	) -> Result<(u64, u64), libwallet::Error> {
+		self.answer((0, 0))
+	}
+
+	fn get_block_headers(
+		&self,
+		heights: Vec<u64>,
+	) -> Result<Vec<BlockHeaderInfo>, libwallet::Error> {
+		let headers = heights
+			.into_iter()
+			.filter(|h| *h <= self.height)
+			.map(|h| header(h, self.fork))
+			.collect();
+		self.answer(headers)
+	}
+}
+
+fn commit(i: u8) -> pedersen::Commitment {
+	pedersen::Commitment::from_vec(vec![i; 33])
+}
+
+fn header(height: u64, fork: u8) -> BlockHeaderInfo {
+	let mut hash = vec![fork; 32];
+	hash[..8].copy_from_slice(&height.to_be_bytes());
+	BlockHeaderInfo {
+		height,
+		hash: BlockIdentifier(Hash::from_vec(&hash)),
+		timestamp: Utc::now(),
+	}
+}
+
+fn outputs(entries: &[(u8, u64)]) -> OutputMap {
+	entries
+		.iter()
+		.map(|(i, height)| {
+			let c = commit(*i);
+			let hex = util::to_hex(c.as_ref().to_vec());
+			(c, (hex, *height, *height * 2))
+		})
+		.collect()
+}
+
+#[test]
+fn fails_over_on_errors_and_timeouts() {
+	let nodes = vec![
+		StandInNode::new("http://node1", 100, outputs(&[(1, 10)])),
+		StandInNode::new("http://node2", 101, outputs(&[(1, 10)])),
+		StandInNode::new("http://node3", 102, outputs(&[(1, 10)])),
+	];
+	let client = MultiNodeClient::new(nodes.clone(), 1, Duration::from_millis(500)).unwrap();
+	assert_eq!(client.node_url(), "http://node1");
+	assert_eq!(client.get_chain_height().unwrap(), 100);
+
+	// the primary goes down, so the next node answers
+	nodes[0].set_behaviour(Behaviour::Fail);
+	assert_eq!(client.get_chain_height().unwrap(), 101);
+
+	// and the failing node is tried last from then on, while the next one
+	// hangs
+	nodes[1].set_behaviour(Behaviour::Hang);
+	assert_eq!(client.get_chain_height().unwrap(), 102);
+	let found = client.get_outputs_from_node(vec![commit(1)]).unwrap();
+	assert_eq!(found, outputs(&[(1, 10)]));
+	let tx = TxWrapper {
+		tx_hex: "00".to_owned(),
+	};
+	client.post_tx(&tx, false).unwrap();
+	assert_eq!(*nodes[2].posted.lock(), 1);
+
+	let health = client.node_health();
+	assert_eq!(health[0].url, "http://node1");
+	assert_eq!(health[0].successes, 1);
+	assert_eq!(health[0].failures, 1);
+	assert!(health[0].last_error.clone().unwrap().contains("down"));
+	assert_eq!(health[1].failures, 1);
+	assert!(health[1].last_error.clone().unwrap().contains("Timed out"));
+	assert_eq!(health[2].successes, 3);
+	assert_eq!(health[2].consecutive_failures, 0);
+
+	// with every node down, the request fails
+	nodes[1].set_behaviour(Behaviour::Fail);
+	nodes[2].set_behaviour(Behaviour::Fail);
+	assert!(client.get_chain_height().is_err());
+
+	// and recovers when any comes back
+	nodes[0].set_behaviour(Behaviour::Answer);
+	assert_eq!(client.get_chain_height().unwrap(), 100);
+}
+
+#[test]
+fn hanging_node_is_not_flooded() {
+	let nodes = vec![StandInNode::new("http://node1", 100, HashMap::new())];
+	let client = MultiNodeClient::new(nodes.clone(), 1, Duration::from_millis(50)).unwrap();
+
+	// requests that time out are left running, until too many are and the
+	// node isn't asked any more
+	nodes[0].set_behaviour(Behaviour::Hang);
+	for _ in 0..10 {
+		assert!(client.get_chain_height().is_err());
+	}
+	let health = client.node_health();
+	assert_eq!(health[0].failures, 10);
+	assert!(health[0].last_error.clone().unwrap().contains("unanswered"));
+
+	// once they finish, it's asked again
+	nodes[0].set_behaviour(Behaviour::Answer);
+	thread::sleep(Duration::from_millis(2500));
+	assert_eq!(client.get_chain_height().unwrap(), 100);
+}
+
+#[test]
+fn quorum_outvotes_a_lying_node() {
+	// the third node claims a far higher tip, an output the others don't
+	// know of, and a different height for one they do
+	let nodes = vec![
+		StandInNode::new("http://node1", 100, outputs(&[(1, 10), (2, 20)])),
+		StandInNode::new("http://node2", 99, outputs(&[(1, 10), (2, 20)])),
+		StandInNode::new("http://node3", 5000, outputs(&[(1, 10), (2, 21), (3, 30)])),
+	];
+	let client = MultiNodeClient::new(nodes.clone(), 2, Duration::from_millis(500)).unwrap();
+
+	// the highest height two nodes have reached
+	assert_eq!(client.get_chain_height().unwrap(), 100);
+
+	let found = client
+		.get_outputs_from_node(vec![commit(1), commit(2), commit(3)])
+		.unwrap();
+	assert_eq!(found, outputs(&[(1, 10), (2, 20)]));
+	let health = client.node_health();
+	assert_eq!(health[0].disagreements, 0);
+	assert_eq!(health[1].disagreements, 0);
+	assert_eq!(health[2].disagreements, 2);
+
+	// without a quorum of answers the request fails
+	nodes[0].set_behaviour(Behaviour::Fail);
+	nodes[1].set_behaviour(Behaviour::Hang);
+	assert!(client.get_chain_height().is_err());
+
+	// and without a quorum agreeing, only the outputs they agree on can be
+	// looked up
+	nodes[0].set_behaviour(Behaviour::Answer);
+	assert!(client.get_outputs_from_node(vec![commit(2)]).is_err());
+	assert_eq!(
+		client.get_outputs_from_node(vec![commit(1)]).unwrap(),
+		outputs(&[(1, 10)])
+	);
+}
+
+#[test]
+fn quorum_agrees_on_block_hashes() {
+	// the third node is on a fork of its own, and the second is behind
+	let nodes = vec![
+		StandInNode::new("http://node1", 100, HashMap::new()),
+		StandInNode::new("http://node2", 99, HashMap::new()),
+		StandInNode::new("http://node3", 100, HashMap::new()).on_fork(1),
+	];
+	let client = MultiNodeClient::new(nodes.clone(), 2, Duration::from_millis(500)).unwrap();
+
+	let hashes = |headers: Vec<BlockHeaderInfo>| -> Vec<(u64, BlockIdentifier)> {
+		headers.into_iter().map(|h| (h.height, h.hash)).collect()
+	};
+	let found = client.get_block_headers(vec![98, 99, 101]).unwrap();
+	assert_eq!(hashes(found), hashes(vec![header(98, 0), header(99, 0)]));
+	let health = client.node_health();
+	assert_eq!(health[0].disagreements, 0);
+	assert_eq!(health[1].disagreements, 0);
+	assert_eq!(health[2].disagreements, 2);
+
+	// the second node hasn't reached 100, and the third has another block
+	// there, so no two nodes agree on it
+	assert!(client.get_block_headers(vec![100]).is_err());
+
+	// and without a quorum of answers the request fails
+	nodes[0].set_behaviour(Behaviour::Fail);
+	nodes[1].set_behaviour(Behaviour::Hang);
+	assert!(client.get_block_headers(vec![98]).is_err());
+}
+
+#[test]
+fn quorum_must_be_reachable() {
+	let nodes = vec![
+		StandInNode::new("http://node1", 100, HashMap::new()),
+		StandInNode::new("http://node2", 100, HashMap::new()),
+	];
+	assert!(MultiNodeClient::new(nodes.clone(), 0, Duration::from_secs(1)).is_err());
+	assert!(MultiNodeClient::new(nodes.clone(), 3, Duration::from_secs(1)).is_err());
+	assert!(MultiNodeClient::<StandInNode>::new(vec![], 1, Duration::from_secs(1)).is_err());
+	assert!(MultiNodeClient::new(nodes, 2, Duration::from_secs(1)).is_ok());
+}
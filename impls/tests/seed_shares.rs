@@ -0,0 +1,91 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Seed share backup tests
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_util::grin_util as util;
+
+use impls::{SeedShare, WalletSeed};
+use util::ZeroingString;
+
+fn to_z(phrases: &[&String]) -> Vec<ZeroingString> {
+	phrases
+		.iter()
+		.map(|p| ZeroingString::from(p.as_str()))
+		.collect()
+}
+
+#[test]
+fn seed_shares_combine() {
+	for seed_length in vec![16, 32] {
+		let seed = WalletSeed::init_new(seed_length);
+		let shares = seed.to_shares(3, 5).unwrap();
+		assert_eq!(shares.len(), 5);
+
+		// any 3 of the 5, in any order, recover the seed
+		for a in 0..5 {
+			for b in (a + 1)..5 {
+				for c in (b + 1)..5 {
+					let subset = to_z(&[&shares[c], &shares[a], &shares[b]]);
+					assert_eq!(WalletSeed::from_shares(&subset).unwrap(), seed);
+				}
+			}
+		}
+
+		// extra shares are fine, too few aren't
+		let all: Vec<&String> = shares.iter().collect();
+		assert_eq!(WalletSeed::from_shares(&to_z(&all)).unwrap(), seed);
+		assert!(WalletSeed::from_shares(&to_z(&all[..2])).is_err());
+	}
+
+	// 1-of-1 is just the seed
+	let seed = WalletSeed::init_new(32);
+	let shares = seed.to_shares(1, 1).unwrap();
+	assert_eq!(WalletSeed::from_shares(&to_z(&[&shares[0]])).unwrap(), seed);
+
+	assert!(seed.to_shares(4, 3).is_err());
+	assert!(seed.to_shares(0, 3).is_err());
+	assert!(seed.to_shares(2, 17).is_err());
+}
+
+#[test]
+fn seed_shares_rejected() {
+	let seed = WalletSeed::init_new(32);
+	let shares = seed.to_shares(2, 3).unwrap();
+	let share = SeedShare::from_phrase(&shares[1]).unwrap();
+	assert_eq!(share.threshold, 2);
+	assert_eq!(share.index, 2);
+	assert_eq!(share.data.len(), 32);
+
+	// a changed word fails the checksum
+	let mut words: Vec<&str> = shares[0].split_whitespace().collect();
+	words[3] = if words[3] == "abandon" {
+		"ability"
+	} else {
+		"abandon"
+	};
+	assert!(SeedShare::from_phrase(&words.join(" ")).is_err());
+
+	// as do missing words, and words that aren't in the list
+	let words: Vec<&str> = shares[0].split_whitespace().collect();
+	assert!(SeedShare::from_phrase(&words[1..].join(" ")).is_err());
+	assert!(SeedShare::from_phrase(&format!("{} notaword", shares[0])).is_err());
+
+	// shares from another split of the same seed can't be mixed in
+	let other = seed.to_shares(2, 3).unwrap();
+	assert!(WalletSeed::from_shares(&to_z(&[&shares[0], &other[1]])).is_err());
+
+	// nor can the same share be used twice
+	assert!(WalletSeed::from_shares(&to_z(&[&shares[0], &shares[0]])).is_err());
+}
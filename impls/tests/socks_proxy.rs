@@ -0,0 +1,222 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests sending the wallet's outbound HTTP through a SOCKS5 proxy
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_libwallet as libwallet;
+use grin_wallet_util::grin_util as util;
+
+use self::libwallet::{NodeClient, Slate, TxWrapper};
+use self::util::{to_base64, Mutex};
+use impls::{HTTPNodeClient, HTTPWalletCommAdapter};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+/// Read an HTTP request, returning its head and body
+fn read_request(stream: &mut TcpStream) -> io::Result<(String, String)> {
+	let mut buf = vec![];
+	let mut byte = [0u8; 1];
+	while !buf.ends_with(b"\r\n\r\n") {
+		stream.read_exact(&mut byte)?;
+		buf.push(byte[0]);
+	}
+	let head = String::from_utf8_lossy(&buf).to_string();
+	let len = head
+		.lines()
+		.find(|l| l.to_lowercase().starts_with("content-length:"))
+		.and_then(|l| l[15..].trim().parse().ok())
+		.unwrap_or(0);
+	let mut body = vec![0u8; len];
+	stream.read_exact(&mut body)?;
+	Ok((head, String::from_utf8_lossy(&body).to_string()))
+}
+
+/// Local stand-in for a node and a peer wallet, recording the head of each
+/// request it's sent. The chain tip is sent chunked, and slates are echoed
+/// back.
+fn http_stand_in(requests: Arc<Mutex<Vec<String>>>) -> SocketAddr {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let mut stream = stream.unwrap();
+			let (head, body) = read_request(&mut stream).unwrap();
+			let request_line = head.lines().next().unwrap().to_owned();
+			requests.lock().push(head);
+			let res = if request_line.starts_with("GET /v1/chain ") {
+				let tip = r#"{"height":42,"last_block_pushed":"00","prev_block_to_last":"00","total_difficulty":1}"#;
+				let (first, second) = tip.split_at(10);
+				format!(
+					"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+					first.len(),
+					first,
+					second.len(),
+					second
+				)
+			} else if request_line.starts_with("POST /v1/wallet/foreign/receive_tx ") {
+				format!(
+					"HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+					body.len(),
+					body
+				)
+			} else if request_line.starts_with("POST /v1/pool/push") {
+				"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_owned()
+			} else {
+				"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_owned()
+			};
+			stream.write_all(res.as_bytes()).unwrap();
+		}
+	});
+	addr
+}
+
+/// Local server that hangs up on every connection as soon as it's made
+fn hang_up_stand_in() -> SocketAddr {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			drop(stream);
+		}
+	});
+	addr
+}
+
+/// Local stand-in for a SOCKS5 proxy, recording the destination of each
+/// connection it's asked for and connecting them all to `target`
+fn socks_stand_in(target: SocketAddr, destinations: Arc<Mutex<Vec<String>>>) -> SocketAddr {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let mut client = stream.unwrap();
+			let mut greeting = [0u8; 2];
+			client.read_exact(&mut greeting).unwrap();
+			let mut methods = vec![0u8; greeting[1] as usize];
+			client.read_exact(&mut methods).unwrap();
+			assert_eq!(greeting[0], 5);
+			assert!(methods.contains(&0));
+			client.write_all(&[5, 0]).unwrap();
+
+			let mut req = [0u8; 4];
+			client.read_exact(&mut req).unwrap();
+			assert_eq!(&req[..3], &[5, 1, 0]);
+			let host = match req[3] {
+				3 => {
+					let mut len = [0u8; 1];
+					client.read_exact(&mut len).unwrap();
+					let mut name = vec![0u8; len[0] as usize];
+					client.read_exact(&mut name).unwrap();
+					String::from_utf8(name).unwrap()
+				}
+				1 => {
+					let mut ip = [0u8; 4];
+					client.read_exact(&mut ip).unwrap();
+					format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3])
+				}
+				_ => panic!("unexpected address type"),
+			};
+			let mut port = [0u8; 2];
+			client.read_exact(&mut port).unwrap();
+			let port = (port[0] as u16) << 8 | port[1] as u16;
+			destinations.lock().push(format!("{}:{}", host, port));
+
+			let mut server = TcpStream::connect(target).unwrap();
+			client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).unwrap();
+			let (mut client_r, mut server_w) =
+				(client.try_clone().unwrap(), server.try_clone().unwrap());
+			thread::spawn(move || {
+				let _ = io::copy(&mut client_r, &mut server_w);
+			});
+			let _ = io::copy(&mut server, &mut client);
+			let _ = client.shutdown(Shutdown::Both);
+		}
+	});
+	addr
+}
+
+#[test]
+fn outbound_http_through_socks_proxy() {
+	let requests = Arc::new(Mutex::new(vec![]));
+	let destinations = Arc::new(Mutex::new(vec![]));
+	let http_addr = http_stand_in(requests.clone());
+	let proxy_addr = socks_stand_in(http_addr, destinations.clone());
+	let proxy = Some(proxy_addr.to_string());
+
+	// node queries, with host names left for the proxy to resolve
+	let client = HTTPNodeClient::new(
+		"http://node.example:3413",
+		Some("secret".to_owned()),
+		proxy.clone(),
+	);
+	assert_eq!(client.socks_proxy(), proxy);
+	assert_eq!(client.get_chain_height().unwrap(), 42);
+	let tx = TxWrapper {
+		tx_hex: "00".to_owned(),
+	};
+	client.post_tx(&tx, true).unwrap();
+	{
+		let requests = requests.lock();
+		assert_eq!(requests.len(), 2);
+		assert!(requests[0].starts_with("GET /v1/chain HTTP/1.1\r\n"));
+		assert!(requests[0].to_lowercase().contains(
+			&format!("authorization: Basic {}", to_base64("grin:secret")).to_lowercase()
+		));
+		assert!(requests[1].starts_with("POST /v1/pool/push?fluff HTTP/1.1\r\n"));
+	}
+
+	// slates posted to other wallets
+	let slate = Slate::blank(2);
+	let adapter = HTTPWalletCommAdapter::new(proxy.clone());
+	let res = adapter
+		.send_tx_sync("http://10.0.0.7:3415", &slate)
+		.unwrap();
+	assert_eq!(res.id, slate.id);
+	assert_eq!(
+		*destinations.lock(),
+		vec![
+			"node.example:3413".to_owned(),
+			"node.example:3413".to_owned(),
+			"10.0.0.7:3415".to_owned(),
+		]
+	);
+
+	// https goes through the proxy too, to the default port if none is
+	// given, with TLS on top. This destination hangs up, so the handshake
+	// fails.
+	let tls_destinations = Arc::new(Mutex::new(vec![]));
+	let tls_proxy = socks_stand_in(hang_up_stand_in(), tls_destinations.clone());
+	let client = HTTPNodeClient::new("https://node.example", None, Some(tls_proxy.to_string()));
+	assert!(client.get_chain_height().is_err());
+	assert_eq!(
+		*tls_destinations.lock(),
+		vec!["node.example:443".to_owned()]
+	);
+
+	// nothing is sent once the proxy is gone
+	let gone = TcpListener::bind("127.0.0.1:0")
+		.unwrap()
+		.local_addr()
+		.unwrap();
+	let client = HTTPNodeClient::new(
+		&format!("http://{}", http_addr),
+		None,
+		Some(gone.to_string()),
+	);
+	assert!(client.get_chain_height().is_err());
+	assert_eq!(requests.lock().len(), 3);
+	assert_eq!(destinations.lock().len(), 3);
+}
@@ -0,0 +1,118 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SQLite wallet backend tests
+extern crate grin_wallet_config as config;
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_libwallet as libwallet;
+use grin_wallet_util::grin_keychain as keychain;
+
+use self::keychain::{ExtKeychain, Keychain};
+use config::{WalletBackendType, WalletConfig};
+use impls::test_framework::LocalWalletClient;
+use impls::{SqliteBackend, WalletSeed};
+use libwallet::{OutputData, OutputStatus, WalletBackend, WalletOutputBatch};
+use std::fs;
+use std::sync::mpsc::channel;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn wallet_parent() -> keychain::Identifier {
+	ExtKeychain::derive_key_id(2, 0, 0, 0, 0)
+}
+
+fn output(n_child: u32, mmr_index: Option<u64>) -> OutputData {
+	OutputData {
+		root_key_id: wallet_parent(),
+		key_id: ExtKeychain::derive_key_id(3, 0, 0, n_child, 0),
+		n_child,
+		commit: None,
+		mmr_index,
+		value: 1_000,
+		status: OutputStatus::Unspent,
+		height: 1,
+		lock_height: 0,
+		is_coinbase: false,
+		tx_log_entry: None,
+		block_hash: None,
+	}
+}
+
+#[test]
+fn sqlite_backend_batches() {
+	let test_dir = "test_output/sqlite_backend_batches";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	wallet_config.wallet_backend = Some(WalletBackendType::Sqlite);
+	WalletSeed::init_file(&wallet_config, 32, None, None, "").unwrap();
+
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+	let mut wallet: SqliteBackend<LocalWalletClient, ExtKeychain> =
+		SqliteBackend::new(wallet_config.clone(), "", client).unwrap();
+	wallet.open_with_credentials().unwrap();
+	assert!(SqliteBackend::<LocalWalletClient, ExtKeychain>::exists(
+		wallet_config.clone()
+	));
+
+	// default account is created on open
+	assert_eq!(wallet.acct_path_iter().unwrap().count(), 1);
+	wallet.set_parent_key_id_by_name("default").unwrap();
+	assert!(wallet.set_parent_key_id_by_name("missing").is_err());
+
+	// committed batch is visible, outputs keyed by mmr index as well
+	{
+		let mut batch = wallet.batch().unwrap();
+		batch.save(output(0, None)).unwrap();
+		batch.save(output(1, Some(5))).unwrap();
+		assert_eq!(batch.next_tx_log_id(&ExtKeychain::root_key_id()).unwrap(), 0);
+		assert_eq!(batch.next_tx_log_id(&ExtKeychain::root_key_id()).unwrap(), 1);
+		batch.commit().unwrap();
+	}
+	assert_eq!(wallet.iter().unwrap().count(), 2);
+	let key_id = ExtKeychain::derive_key_id(3, 0, 0, 1, 0);
+	assert!(wallet.get(&key_id, &None).is_err());
+	assert_eq!(wallet.get(&key_id, &Some(5)).unwrap().mmr_index, Some(5));
+
+	// dropped batch is rolled back
+	{
+		let mut batch = wallet.batch().unwrap();
+		batch.save(output(2, None)).unwrap();
+		batch.delete(&key_id, &Some(5)).unwrap();
+		batch.save_last_confirmed_height(&wallet_parent(), 12).unwrap();
+	}
+	assert_eq!(wallet.iter().unwrap().count(), 2);
+	assert_eq!(wallet.last_confirmed_height().unwrap(), 0);
+
+	// derivation indices persist across reopening the database
+	assert_eq!(
+		wallet.next_child().unwrap(),
+		ExtKeychain::derive_key_id(3, 0, 0, 0, 0)
+	);
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+	let mut wallet: SqliteBackend<LocalWalletClient, ExtKeychain> =
+		SqliteBackend::new(wallet_config.clone(), "", client).unwrap();
+	wallet.open_with_credentials().unwrap();
+	assert_eq!(
+		wallet.next_child().unwrap(),
+		ExtKeychain::derive_key_id(3, 0, 0, 1, 0)
+	);
+	assert_eq!(wallet.iter().unwrap().count(), 2);
+
+	clean_output_dir(test_dir);
+}
@@ -0,0 +1,193 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet export and import tests
+extern crate grin_wallet_config as config;
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_libwallet as libwallet;
+use grin_wallet_util::grin_core as core;
+use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_util as util;
+
+use self::core::core::Transaction;
+use self::keychain::{ExtKeychain, Keychain};
+use config::WalletConfig;
+use impls::test_framework::LocalWalletClient;
+use impls::{LMDBBackend, WalletSeed};
+use libwallet::{AcctPathMapping, ErrorKind, OutputData, OutputStatus};
+use libwallet::{TxLogEntry, TxLogEntryType, WalletBackend};
+use std::fs;
+use std::sync::mpsc::channel;
+use uuid::Uuid;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn wallet_config(test_dir: &str) -> WalletConfig {
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	wallet_config
+}
+
+fn open_wallet(wallet_config: &WalletConfig) -> LMDBBackend<LocalWalletClient, ExtKeychain> {
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+	let mut wallet = LMDBBackend::new(wallet_config.clone(), "", client).unwrap();
+	wallet.open_with_credentials().unwrap();
+	wallet
+}
+
+fn test_output(key_id: keychain::Identifier, value: u64) -> OutputData {
+	OutputData {
+		root_key_id: key_id.parent_path(),
+		key_id,
+		n_child: 0,
+		commit: None,
+		mmr_index: None,
+		value,
+		status: OutputStatus::Unspent,
+		height: 1,
+		lock_height: 0,
+		is_coinbase: false,
+		tx_log_entry: Some(0),
+		block_hash: None,
+	}
+}
+
+/// Give the wallet a second account with outputs, and a sent transaction
+/// with a stored tx in the default account
+fn populate_wallet(wallet: &mut LMDBBackend<LocalWalletClient, ExtKeychain>) {
+	let default_acct = wallet.parent_key_id();
+	let savings = AcctPathMapping {
+		label: "savings".to_owned(),
+		path: ExtKeychain::derive_key_id(2, 1, 0, 0, 0),
+	};
+	{
+		let mut batch = wallet.batch().unwrap();
+		batch.save_acct_path(savings.clone()).unwrap();
+		batch.commit().unwrap();
+	}
+
+	wallet.set_parent_key_id(savings.path.clone());
+	for value in vec![1_000, 2_000] {
+		let key_id = wallet.next_child().unwrap();
+		let mut batch = wallet.batch().unwrap();
+		batch.save(test_output(key_id, value)).unwrap();
+		batch.commit().unwrap();
+	}
+	{
+		let mut batch = wallet.batch().unwrap();
+		batch
+			.save_last_confirmed_height(&savings.path, 100)
+			.unwrap();
+		batch.commit().unwrap();
+	}
+
+	wallet.set_parent_key_id(default_acct.clone());
+	let slate_id = Uuid::new_v4();
+	wallet
+		.store_tx(&slate_id.to_string(), &Transaction::empty())
+		.unwrap();
+	let mut batch = wallet.batch().unwrap();
+	let log_id = batch.next_tx_log_id(&default_acct).unwrap();
+	let mut entry = TxLogEntry::new(default_acct.clone(), TxLogEntryType::TxSent, log_id);
+	entry.tx_slate_id = Some(slate_id);
+	entry.stored_tx = Some(format!("{}.grintx", slate_id));
+	batch.save_tx_log_entry(entry, &default_acct).unwrap();
+	batch.commit().unwrap();
+}
+
+fn import_err_kind(
+	wallet: &mut LMDBBackend<LocalWalletClient, ExtKeychain>,
+	path: &str,
+) -> ErrorKind {
+	match wallet.import_archive(path) {
+		Err(e) => e.kind(),
+		Ok(_) => panic!("archive imported when it shouldn't be"),
+	}
+}
+
+#[test]
+fn wallet_export_import() {
+	let test_dir = "test_output/wallet_export_import";
+	clean_output_dir(test_dir);
+	let archive_path = format!("{}/wallet_archive.json", test_dir);
+	let src_config = wallet_config(&format!("{}/src", test_dir));
+	let dest_config = wallet_config(&format!("{}/dest", test_dir));
+	let other_config = wallet_config(&format!("{}/other", test_dir));
+
+	let seed = WalletSeed::init_file(&src_config, 32, None, None, "").unwrap();
+	let phrase = util::ZeroingString::from(seed.to_mnemonic().unwrap());
+	WalletSeed::init_file(&dest_config, 32, Some(phrase), None, "").unwrap();
+	WalletSeed::init_file(&other_config, 32, None, None, "").unwrap();
+
+	let mut src = open_wallet(&src_config);
+	populate_wallet(&mut src);
+	let exported = src.export_archive(&archive_path).unwrap();
+	assert_eq!(exported.accounts, 2);
+	assert_eq!(exported.outputs, 2);
+	assert_eq!(exported.tx_log_entries, 1);
+	assert_eq!(exported.stored_txs, 1);
+
+	// nothing in the archive is readable without the seed
+	let archive = fs::read_to_string(&archive_path).unwrap();
+	assert!(!archive.contains("savings"));
+
+	// a wallet with another seed can't import it
+	let mut other = open_wallet(&other_config);
+	match import_err_kind(&mut other, &archive_path) {
+		ErrorKind::Archive(msg) => assert!(msg.contains("different seed")),
+		k => panic!("unexpected error {:?}", k),
+	}
+	assert_eq!(other.iter().unwrap().count(), 0);
+
+	let mut dest = open_wallet(&dest_config);
+	let imported = dest.import_archive(&archive_path).unwrap();
+	assert_eq!(imported, exported);
+
+	let mut src_outputs: Vec<OutputData> = src.iter().unwrap().collect();
+	let mut dest_outputs: Vec<OutputData> = dest.iter().unwrap().collect();
+	src_outputs.sort_by_key(|o| o.value);
+	dest_outputs.sort_by_key(|o| o.value);
+	assert_eq!(src_outputs, dest_outputs);
+	assert!(dest.get_acct_path("savings".to_owned()).unwrap().is_some());
+
+	// the tx log and its stored transaction came across
+	let entries: Vec<TxLogEntry> = dest.tx_log_iter().unwrap().collect();
+	assert_eq!(entries.len(), 1);
+	assert!(dest.get_stored_tx(&entries[0]).unwrap().is_some());
+
+	// derivation carries on where the source wallet left off
+	let savings = ExtKeychain::derive_key_id(2, 1, 0, 0, 0);
+	src.set_parent_key_id(savings.clone());
+	dest.set_parent_key_id(savings.clone());
+	assert_eq!(dest.current_child_index().unwrap(), 2);
+	assert_eq!(dest.last_confirmed_height().unwrap(), 100);
+	assert_eq!(dest.next_child().unwrap(), src.next_child().unwrap());
+
+	// new transactions don't reuse imported tx log ids
+	let default_acct = ExtKeychain::derive_key_id(2, 0, 0, 0, 0);
+	let mut batch = dest.batch().unwrap();
+	assert_eq!(batch.next_tx_log_id(&default_acct).unwrap(), entries[0].id + 1);
+	drop(batch);
+
+	// and a wallet with contents of its own is left alone
+	match import_err_kind(&mut dest, &archive_path) {
+		ErrorKind::Archive(msg) => assert!(msg.contains("already has outputs")),
+		k => panic!("unexpected error {:?}", k),
+	}
+
+	clean_output_dir(test_dir);
+}
@@ -0,0 +1,147 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet data directory lock tests
+extern crate grin_wallet_config as config;
+extern crate grin_wallet_impls as impls;
+
+use grin_wallet_libwallet as libwallet;
+use grin_wallet_util::grin_keychain as keychain;
+
+use self::keychain::ExtKeychain;
+use config::WalletConfig;
+use fs2::FileExt;
+use impls::test_framework::LocalWalletClient;
+use impls::{LMDBBackend, LockMode, WalletDirLock, WalletSeed, LOCK_FILE};
+use libwallet::{ErrorKind, WalletBackend};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::process;
+use std::sync::mpsc::channel;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+/// Open the lock file separately, standing in for another process
+fn other_process_lock(test_dir: &str, pid: u32) -> File {
+	fs::create_dir_all(test_dir).unwrap();
+	let mut file = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.create(true)
+		.open(format!("{}/{}", test_dir, LOCK_FILE))
+		.unwrap();
+	file.set_len(0).unwrap();
+	write!(file, "{}\n", pid).unwrap();
+	file
+}
+
+#[test]
+fn wallet_dir_lock() {
+	let test_dir = "test_output/wallet_dir_lock";
+	clean_output_dir(test_dir);
+
+	// another writer blocks everyone, and is named in the error
+	let other = other_process_lock(test_dir, 4242);
+	other.lock_exclusive().unwrap();
+	for mode in vec![LockMode::Exclusive, LockMode::Shared] {
+		match WalletDirLock::acquire(test_dir, mode) {
+			Err(e) => match e.kind() {
+				ErrorKind::WalletLocked(msg) => assert!(msg.contains("pid 4242")),
+				k => panic!("unexpected error {:?}", k),
+			},
+			Ok(_) => panic!("acquired a lock held by another process"),
+		}
+	}
+	other.unlock().unwrap();
+
+	// other readers only block writers
+	other.lock_shared().unwrap();
+	let lock = WalletDirLock::acquire(test_dir, LockMode::Shared).unwrap();
+	assert_eq!(lock.mode(), LockMode::Shared);
+	drop(lock);
+	assert!(WalletDirLock::acquire(test_dir, LockMode::Exclusive).is_err());
+	other.unlock().unwrap();
+
+	// within a process the lock is shared, and our PID is recorded
+	let lock = WalletDirLock::acquire(test_dir, LockMode::Exclusive).unwrap();
+	let lock2 = WalletDirLock::acquire(test_dir, LockMode::Exclusive).unwrap();
+	let read_lock = WalletDirLock::acquire(test_dir, LockMode::Shared).unwrap();
+	assert_eq!(read_lock.mode(), LockMode::Exclusive);
+	let lock_file = format!("{}/{}", test_dir, LOCK_FILE);
+	let contents = fs::read_to_string(&lock_file).unwrap();
+	assert_eq!(contents.trim(), format!("{}", process::id()));
+	assert!(other.try_lock_shared().is_err());
+
+	// released once all are dropped, clearing the PID
+	drop(lock);
+	drop(lock2);
+	assert!(other.try_lock_shared().is_err());
+	drop(read_lock);
+	other.try_lock_exclusive().unwrap();
+	other.unlock().unwrap();
+	assert_eq!(fs::read_to_string(&lock_file).unwrap(), "");
+
+	clean_output_dir(test_dir);
+}
+
+#[test]
+fn wallet_read_only_backend() {
+	let test_dir = "test_output/wallet_read_only_backend";
+	clean_output_dir(test_dir);
+	let mut wallet_config = WalletConfig::default();
+	wallet_config.data_file_dir = test_dir.to_owned();
+	wallet_config.seed_kdf_memory_kib = Some(64);
+	wallet_config.seed_kdf_iterations = Some(1);
+	let (tx, _rx) = channel();
+	let client = LocalWalletClient::new("wallet1", tx);
+
+	// nothing to read yet
+	assert!(LMDBBackend::<_, ExtKeychain>::new_read_only(
+		wallet_config.clone(),
+		"",
+		client.clone()
+	)
+	.is_err());
+
+	WalletSeed::init_file(&wallet_config, 32, None, None, "").unwrap();
+	{
+		let mut wallet: LMDBBackend<_, ExtKeychain> =
+			LMDBBackend::new(wallet_config.clone(), "", client.clone()).unwrap();
+		wallet.open_with_credentials().unwrap();
+		wallet.next_child().unwrap();
+	}
+
+	let mut wallet: LMDBBackend<_, ExtKeychain> =
+		LMDBBackend::new_read_only(wallet_config.clone(), "", client.clone()).unwrap();
+	wallet.open_with_credentials().unwrap();
+	assert_eq!(wallet.acct_path_iter().unwrap().count(), 1);
+	match wallet.batch() {
+		Err(e) => assert_eq!(e.kind(), ErrorKind::ReadOnly),
+		Ok(_) => panic!("write batch from a read-only wallet"),
+	}
+	assert!(wallet.next_child().is_err());
+
+	// the read-only wallet only holds a shared lock
+	let other = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.open(format!("{}/{}", test_dir, LOCK_FILE))
+		.unwrap();
+	assert!(other.try_lock_exclusive().is_err());
+	other.try_lock_shared().unwrap();
+	other.unlock().unwrap();
+
+	clean_output_dir(test_dir);
+}
@@ -279,9 +279,10 @@ impl LocalServerContainer {
 		self.wallet_config.owner_api_include_foreign = Some(self.config.owner_api_include_foreign);
 
 		let _ = fs::create_dir_all(self.wallet_config.clone().data_file_dir);
-		let r = wallet::WalletSeed::init_file(&self.wallet_config, 32, None, "");
+		let r = wallet::WalletSeed::init_file(&self.wallet_config, 32, None, None, "");
 
-		let client_n = HTTPNodeClient::new(&self.wallet_config.check_node_api_http_addr, None);
+		let client_n =
+			HTTPNodeClient::new(&self.wallet_config.check_node_api_http_addr, None, None);
 
 		if let Err(_e) = r {
 			//panic!("Error initializing wallet seed: {}", e);
@@ -328,6 +329,7 @@ impl LocalServerContainer {
 			None,
 			None,
 			self.wallet_config.owner_api_include_foreign.clone(),
+			None,
 		)
 		.unwrap_or_else(|e| {
 			panic!(
@@ -340,7 +342,7 @@ impl LocalServerContainer {
 	#[allow(dead_code)]
 	pub fn get_wallet_seed(config: &WalletConfig) -> wallet::WalletSeed {
 		let _ = fs::create_dir_all(config.clone().data_file_dir);
-		wallet::WalletSeed::init_file(config, 32, None, "").unwrap();
+		wallet::WalletSeed::init_file(config, 32, None, None, "").unwrap();
 		let wallet_seed =
 			wallet::WalletSeed::from_file(config, "").expect("Failed to read wallet seed file.");
 		wallet_seed
@@ -354,7 +356,7 @@ impl LocalServerContainer {
 		let keychain: keychain::ExtKeychain = wallet_seed
 			.derive_keychain(false)
 			.expect("Failed to derive keychain from seed file and passphrase.");
-		let client_n = HTTPNodeClient::new(&config.check_node_api_http_addr, None);
+		let client_n = HTTPNodeClient::new(&config.check_node_api_http_addr, None, None);
 		let mut wallet = LMDBBackend::new(config.clone(), "", client_n)
 			.unwrap_or_else(|e| panic!("Error creating wallet: {:?} Config: {:?}", e, config));
 		wallet.keychain = Some(keychain);
@@ -382,8 +384,8 @@ impl LocalServerContainer {
 			.derive_keychain(false)
 			.expect("Failed to derive keychain from seed file and passphrase.");
 
-		let client_n = HTTPNodeClient::new(&config.check_node_api_http_addr, None);
-		let client_w = HTTPWalletCommAdapter::new();
+		let client_n = HTTPNodeClient::new(&config.check_node_api_http_addr, None, None);
+		let client_w = HTTPWalletCommAdapter::new(None);
 
 		let max_outputs = 500;
 		let change_outputs = 1;
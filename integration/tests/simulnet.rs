@@ -884,7 +884,7 @@ pub fn create_wallet(
 ) -> Arc<Mutex<dyn WalletInst<HTTPNodeClient, keychain::ExtKeychain>>> {
 	let mut wallet_config = WalletConfig::default();
 	wallet_config.data_file_dir = String::from(dir);
-	let _ = wallet::WalletSeed::init_file(&wallet_config, 32, None, "");
+	let _ = wallet::WalletSeed::init_file(&wallet_config, 32, None, None, "");
 	let mut wallet: LMDBBackend<HTTPNodeClient, keychain::ExtKeychain> =
 		LMDBBackend::new(wallet_config.clone(), "", client_n).unwrap_or_else(|e| {
 			panic!("Error creating wallet: {:?} Config: {:?}", e, wallet_config)
@@ -908,8 +908,8 @@ fn replicate_tx_fluff_failure() {
 
 	// Create Wallet 1 (Mining Input) and start it listening
 	// Wallet 1 post to another node, just for fun
-	let client1 = HTTPNodeClient::new("http://127.0.0.1:23003", None);
-	let client1_w = HTTPWalletCommAdapter::new();
+	let client1 = HTTPNodeClient::new("http://127.0.0.1:23003", None, None);
+	let client1_w = HTTPWalletCommAdapter::new(None);
 	let wallet1 = create_wallet("target/tmp/tx_fluff/wallet1", client1.clone());
 	let _wallet1_handle = thread::spawn(move || {
 		controller::foreign_listener(wallet1, "127.0.0.1:33000", None)
@@ -917,7 +917,7 @@ fn replicate_tx_fluff_failure() {
 	});
 
 	// Create Wallet 2 (Recipient) and launch
-	let client2 = HTTPNodeClient::new("http://127.0.0.1:23001", None);
+	let client2 = HTTPNodeClient::new("http://127.0.0.1:23001", None, None);
 	let wallet2 = create_wallet("target/tmp/tx_fluff/wallet2", client2.clone());
 	let _wallet2_handle = thread::spawn(move || {
 		controller::foreign_listener(wallet2, "127.0.0.1:33001", None)
@@ -23,4 +23,5 @@
 
 pub mod foreign;
 pub mod owner;
+pub mod owner_updater;
 pub mod types;
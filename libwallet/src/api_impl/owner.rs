@@ -14,6 +14,7 @@
 
 //! Generic implementation of owner API functions
 
+use std::sync::mpsc::Sender;
 use uuid::Uuid;
 
 use crate::grin_core::core::hash::Hashed;
@@ -22,9 +23,12 @@ use crate::grin_core::ser;
 use crate::grin_util;
 
 use crate::grin_keychain::{Identifier, Keychain};
-use crate::internal::{keys, selection, tx, updater};
+use crate::internal::{gc, integrity, keys, selection, tx, updater};
 use crate::slate::Slate;
-use crate::types::{AcctPathMapping, NodeClient, TxLogEntry, TxWrapper, WalletBackend, WalletInfo};
+use crate::types::{
+	AcctPathMapping, ArchiveSummary, CheckDiscrepancy, GcReport, IntegrityReport, NodeClient,
+	ScanStatus, TxLogEntry, TxWrapper, WalletBackend, WalletInfo,
+};
 use crate::{Error, ErrorKind};
 use crate::{
 	InitTxArgs, IssueInvoiceTxArgs, NodeHeightResult, OutputCommitMapping, TxLogEntryType,
@@ -439,24 +443,100 @@ pub fn verify_slate_messages(slate: &Slate) -> Result<(), Error> {
 }
 
 /// Attempt to restore contents of wallet
-pub fn restore<T: ?Sized, C, K>(w: &mut T) -> Result<(), Error>
+pub fn restore<T: ?Sized, C, K>(
+	w: &mut T,
+	start_height: Option<u64>,
+	account_labels: Option<Vec<AcctPathMapping>>,
+	status_send: &Option<Sender<ScanStatus>>,
+) -> Result<(), Error>
 where
 	T: WalletBackend<C, K>,
 	C: NodeClient,
 	K: Keychain,
 {
-	w.restore()
+	w.restore(
+		start_height,
+		&account_labels.unwrap_or_default(),
+		status_send,
+	)
 }
 
 /// check repair
-pub fn check_repair<T: ?Sized, C, K>(w: &mut T, delete_unconfirmed: bool) -> Result<(), Error>
+pub fn check_repair<T: ?Sized, C, K>(
+	w: &mut T,
+	delete_unconfirmed: bool,
+	start_height: Option<u64>,
+	dry_run: bool,
+	status_send: &Option<Sender<ScanStatus>>,
+) -> Result<Vec<CheckDiscrepancy>, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	// A refresh writes output states, and can collect garbage and roll back
+	// confirmations, so a dry run goes without one
+	if !dry_run {
+		update_outputs(w, true);
+	}
+	w.check_repair(delete_unconfirmed, start_height, dry_run, status_send)
+}
+
+/// check the consistency of the wallet's stored data
+pub fn check_integrity<T: ?Sized, C, K>(w: &mut T, fix: bool) -> Result<IntegrityReport, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	integrity::check_integrity(w, fix)
+}
+
+/// delete the private contexts and stored transactions the wallet no longer
+/// needs, according to its retention policy
+pub fn collect_garbage<T: ?Sized, C, K>(w: &mut T) -> Result<GcReport, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let height = w.w2n_client().get_chain_height()?;
+	let policy = w.retention_policy();
+	gc::collect_garbage(w, &policy, height)
+}
+
+/// change the wallet password
+pub fn change_password<T: ?Sized, C, K>(
+	w: &mut T,
+	old_password: &str,
+	new_password: &str,
+) -> Result<(), Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	w.change_password(old_password, new_password)
+}
+
+/// export the wallet's contents to an encrypted archive file
+pub fn export_wallet<T: ?Sized, C, K>(w: &mut T, path: &str) -> Result<ArchiveSummary, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	w.export_archive(path)
+}
+
+/// import the contents of an archive written by `export_wallet`
+pub fn import_wallet<T: ?Sized, C, K>(w: &mut T, path: &str) -> Result<ArchiveSummary, Error>
 where
 	T: WalletBackend<C, K>,
 	C: NodeClient,
 	K: Keychain,
 {
-	update_outputs(w, true);
-	w.check_repair(delete_unconfirmed)
+	w.import_archive(path)
 }
 
 /// node height
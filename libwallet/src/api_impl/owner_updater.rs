@@ -0,0 +1,164 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Background updater, refreshing the wallet's outputs from the node on a
+//! schedule and whenever the chain height changes, so reads can be served
+//! from what it last found instead of each going to the node
+
+use std::cmp;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+
+use crate::api_impl::types::{RefreshSchedule, RefreshStatus};
+use crate::grin_keychain::Keychain;
+use crate::grin_util::{Mutex, RwLock};
+use crate::internal::updater;
+use crate::types::{NodeClient, WalletBackend};
+use crate::Error;
+
+/// Longest the updater sleeps at a time, so it stops soon after being asked
+const SLEEP_STEP: Duration = Duration::from_millis(100);
+
+/// Refreshes a wallet's outputs in the background, publishing what it finds
+/// to a shared status
+pub struct Updater<T: ?Sized, C, K>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	wallet: Arc<Mutex<T>>,
+	status: Arc<RwLock<Option<RefreshStatus>>>,
+	phantom_c: PhantomData<C>,
+	phantom_k: PhantomData<K>,
+}
+
+impl<T: ?Sized, C, K> Updater<T, C, K>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	/// Create an updater for the given wallet, publishing to `status`
+	pub fn new(wallet: Arc<Mutex<T>>, status: Arc<RwLock<Option<RefreshStatus>>>) -> Self {
+		Updater {
+			wallet,
+			status,
+			phantom_c: PhantomData,
+			phantom_k: PhantomData,
+		}
+	}
+
+	/// Check the chain height and refresh as scheduled until `keep_running`
+	/// is cleared
+	pub fn run(&self, schedule: RefreshSchedule, keep_running: Arc<AtomicBool>) {
+		*self.status.write() = Some(RefreshStatus {
+			chain_height: None,
+			refreshed_height: None,
+			last_refresh: None,
+			last_checked: None,
+			last_error: None,
+			stale: true,
+		});
+		let mut last_refresh: Option<Instant> = None;
+		while keep_running.load(Ordering::Relaxed) {
+			self.check(&schedule, &mut last_refresh);
+			let wake = Instant::now() + schedule.height_poll;
+			loop {
+				let now = Instant::now();
+				if now >= wake || !keep_running.load(Ordering::Relaxed) {
+					break;
+				}
+				thread::sleep(cmp::min(wake - now, SLEEP_STEP));
+			}
+		}
+	}
+
+	fn update_status<F>(&self, f: F)
+	where
+		F: FnOnce(&mut RefreshStatus),
+	{
+		if let Some(ref mut status) = *self.status.write() {
+			f(status);
+		}
+	}
+
+	/// Get the chain height without holding the wallet lock, and refresh if
+	/// it's changed since the last refresh, the last attempt failed, or the
+	/// interval is up
+	fn check(&self, schedule: &RefreshSchedule, last_refresh: &mut Option<Instant>) {
+		let client = self.wallet.lock().w2n_client().clone();
+		let height = match client.get_chain_height() {
+			Ok(h) => h,
+			Err(e) => {
+				warn!("Background refresh can't get the chain height: {}", e);
+				self.update_status(|s| {
+					s.last_checked = Some(Utc::now());
+					s.last_error = Some(format!("{}", e));
+					s.stale = true;
+				});
+				return;
+			}
+		};
+
+		let mut due = match last_refresh {
+			Some(t) => t.elapsed() >= schedule.interval,
+			None => true,
+		};
+		self.update_status(|s| {
+			s.chain_height = Some(height);
+			s.last_checked = Some(Utc::now());
+			if s.refreshed_height != Some(height) || s.last_error.is_some() {
+				s.stale = true;
+				due = true;
+			}
+		});
+		if !due {
+			return;
+		}
+
+		*last_refresh = Some(Instant::now());
+		match self.refresh(height) {
+			Ok(()) => {
+				debug!("Background refresh done at height {}", height);
+				self.update_status(|s| {
+					s.refreshed_height = Some(height);
+					s.last_refresh = Some(Utc::now());
+					s.last_error = None;
+					s.stale = s.chain_height != Some(height);
+				});
+			}
+			Err(e) => {
+				warn!("Background refresh failed: {}", e);
+				self.update_status(|s| {
+					s.last_error = Some(format!("{}", e));
+					s.stale = true;
+				});
+			}
+		}
+	}
+
+	fn refresh(&self, height: u64) -> Result<(), Error> {
+		let mut w = self.wallet.lock();
+		w.open_with_credentials()?;
+		let res = updater::refresh_all_accounts(&mut *w, height);
+		w.close()?;
+		res
+	}
+}
@@ -20,6 +20,8 @@ use crate::grin_keychain::Identifier;
 use crate::grin_util::secp::pedersen;
 use crate::slate_versions::SlateVersion;
 use crate::types::OutputData;
+use chrono::prelude::*;
+use std::time::Duration;
 
 /// Send TX API Args
 // TODO: This is here to ensure the legacy V1 API remains intact
@@ -213,6 +215,38 @@ pub struct NodeHeightResult {
 	pub updated_from_node: bool,
 }
 
+/// When a background updater refreshes the wallet's outputs from the node
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RefreshSchedule {
+	/// Longest time between refreshes
+	pub interval: Duration,
+	/// How often the node's chain height is checked, the outputs being
+	/// refreshed as soon as it changes
+	pub height_poll: Duration,
+}
+
+/// What a background updater last found, which reads are served from
+/// while it runs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RefreshStatus {
+	/// Chain height last reported by the node
+	#[serde(with = "secp_ser::opt_string_or_u64")]
+	pub chain_height: Option<u64>,
+	/// Chain height the outputs were last refreshed at
+	#[serde(with = "secp_ser::opt_string_or_u64")]
+	pub refreshed_height: Option<u64>,
+	/// Time of the last successful refresh
+	pub last_refresh: Option<DateTime<Utc>>,
+	/// Time the node was last asked for its chain height
+	pub last_checked: Option<DateTime<Utc>>,
+	/// Why the last check or refresh failed, if it did
+	pub last_error: Option<String>,
+	/// Whether what's served may be out of date: no refresh has succeeded
+	/// yet, the last check or refresh failed, or the chain has moved on
+	/// since the last refresh
+	pub stale: bool,
+}
+
 /// Version request result
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VersionInfo {
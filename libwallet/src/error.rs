@@ -193,6 +193,18 @@ pub enum ErrorKind {
 	#[fail(display = "Unknown Slate Version: {}", _0)]
 	SlateVersion(u16),
 
+	/// Wallet export archive error
+	#[fail(display = "Wallet archive error: {}", _0)]
+	Archive(String),
+
+	/// Wallet data directory is locked by another process
+	#[fail(display = "Wallet is in use: {}", _0)]
+	WalletLocked(String),
+
+	/// Attempt to write to a wallet opened read-only
+	#[fail(display = "Wallet was opened read-only")]
+	ReadOnly,
+
 	/// Other
 	#[fail(display = "Generic error: {}", _0)]
 	GenericError(String),
@@ -21,7 +21,10 @@
 #![deny(unused_mut)]
 #![warn(missing_docs)]
 
+pub mod gc;
+pub mod integrity;
 pub mod keys;
+pub mod reorg;
 pub mod restore;
 pub mod selection;
 pub mod tx;
@@ -0,0 +1,188 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Garbage collection of the private contexts and stored transactions the
+//! wallet no longer needs
+
+use crate::grin_core::consensus;
+use crate::grin_keychain::Keychain;
+use crate::types::*;
+use crate::Error;
+use chrono::prelude::*;
+use uuid::Uuid;
+
+fn is_cancelled(t: &TxLogEntry) -> bool {
+	t.tx_type == TxLogEntryType::TxSentCancelled || t.tx_type == TxLogEntryType::TxReceivedCancelled
+}
+
+/// Whether a transaction was confirmed at least the policy's minimum
+/// confirmation depth ago, so a reorg isn't expected to undo it.
+/// Transactions confirmed by older wallets don't record the height they
+/// were confirmed at, so it's estimated from their confirmation time.
+pub fn is_settled(t: &TxLogEntry, policy: &RetentionPolicy, height: u64) -> bool {
+	if !t.confirmed {
+		return false;
+	}
+	let confirmation_height = match (t.confirmation_height, t.confirmation_ts) {
+		(Some(h), _) => h,
+		(None, Some(ts)) => {
+			let age = Utc::now().signed_duration_since(ts).num_seconds();
+			height.saturating_sub((age.max(0) as u64) / consensus::BLOCK_TIME_SEC)
+		}
+		(None, None) => return policy.min_confirmation_depth == 0,
+	};
+	height.saturating_sub(confirmation_height) >= policy.min_confirmation_depth
+}
+
+/// Chain height a context was created at. Contexts saved by older wallets
+/// don't record it, so it's estimated from the creation time of their
+/// transaction, if they have one.
+fn context_height(ctx: Option<Context>, txs: &[&TxLogEntry], height: u64) -> Option<u64> {
+	if let Some(h) = ctx.and_then(|c| c.created_height) {
+		return Some(h);
+	}
+	let now = Utc::now();
+	txs.iter()
+		.map(|t| {
+			let age = now.signed_duration_since(t.creation_ts).num_seconds();
+			let age_blocks = (age.max(0) as u64) / consensus::BLOCK_TIME_SEC;
+			height.saturating_sub(age_blocks)
+		})
+		.min()
+}
+
+/// Why a context should be deleted, if it should
+fn context_removal_reason<T: ?Sized, C, K>(
+	wallet: &mut T,
+	slate_id: &Uuid,
+	participant_id: usize,
+	tx_entries: &[TxLogEntry],
+	policy: &RetentionPolicy,
+	height: u64,
+) -> Option<ContextRemovalReason>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let txs: Vec<&TxLogEntry> = tx_entries
+		.iter()
+		.filter(|t| t.tx_slate_id == Some(*slate_id))
+		.collect();
+	if !txs.is_empty() {
+		if txs.iter().all(|t| is_cancelled(t)) {
+			return Some(ContextRemovalReason::Cancelled);
+		}
+		if txs
+			.iter()
+			.all(|t| is_settled(t, policy, height) || is_cancelled(t))
+		{
+			return Some(ContextRemovalReason::Confirmed);
+		}
+	}
+	if policy.context_max_age == 0 {
+		return None;
+	}
+	let ctx = wallet
+		.get_private_context(slate_id.as_bytes(), participant_id)
+		.ok();
+	match context_height(ctx, &txs, height) {
+		Some(h) if height.saturating_sub(h) > policy.context_max_age => {
+			Some(ContextRemovalReason::Expired)
+		}
+		_ => None,
+	}
+}
+
+/// Delete the private contexts of cancelled and settled transactions, and
+/// of unfinished ones kept longer than the policy allows, then archive or
+/// prune the stored transactions of settled transactions as the policy
+/// says. `height` is the current chain height.
+pub fn collect_garbage<T: ?Sized, C, K>(
+	wallet: &mut T,
+	policy: &RetentionPolicy,
+	height: u64,
+) -> Result<GcReport, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let tx_entries: Vec<TxLogEntry> = wallet.tx_log_iter()?.collect();
+	let contexts: Vec<(Vec<u8>, usize)> = wallet.private_context_iter()?.collect();
+	let mut report = GcReport::default();
+
+	for (slate_id, participant_id) in contexts.into_iter() {
+		let slate_id = match Uuid::from_slice(&slate_id) {
+			Ok(u) => u,
+			Err(_) => continue,
+		};
+		let reason = context_removal_reason(
+			wallet,
+			&slate_id,
+			participant_id,
+			&tx_entries,
+			policy,
+			height,
+		);
+		if let Some(reason) = reason {
+			report.contexts.push(RemovedContext {
+				slate_id,
+				participant_id,
+				reason,
+			});
+		}
+	}
+
+	let stored: Vec<&TxLogEntry> = match policy.stored_txs {
+		StoredTxRetention::Keep => vec![],
+		_ => tx_entries
+			.iter()
+			.filter(|t| t.stored_tx.is_some() && is_settled(t, policy, height))
+			.collect(),
+	};
+	if report.contexts.is_empty() && stored.is_empty() {
+		return Ok(report);
+	}
+
+	// entries are unlinked before their files are moved, so an interrupted
+	// collection leaves a stray file rather than a broken link
+	{
+		let mut batch = wallet.batch()?;
+		for c in report.contexts.iter() {
+			debug!(
+				"Deleting private context for slate {} ({:?})",
+				c.slate_id, c.reason
+			);
+			batch.delete_private_context(c.slate_id.as_bytes(), c.participant_id)?;
+		}
+		for t in stored.iter() {
+			let mut entry = (*t).clone();
+			entry.stored_tx = None;
+			batch.save_tx_log_entry(entry, &t.parent_key_id)?;
+		}
+		batch.commit()?;
+	}
+
+	let archive = policy.stored_txs == StoredTxRetention::Archive;
+	for t in stored.into_iter() {
+		wallet.remove_stored_tx(t, archive)?;
+		let filename = t.stored_tx.clone().unwrap_or_default();
+		match archive {
+			true => report.archived_txs.push(filename),
+			false => report.pruned_txs.push(filename),
+		}
+	}
+	Ok(report)
+}
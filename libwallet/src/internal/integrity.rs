@@ -0,0 +1,306 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Offline integrity check of the wallet's stored data, cross-checking the
+//! references between outputs, tx log entries, private contexts, stored
+//! transactions and account mappings
+
+use crate::grin_keychain::{Identifier, Keychain};
+use crate::grin_util;
+use crate::internal::{gc, keys};
+use crate::types::*;
+use crate::Error;
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use uuid::Uuid;
+
+/// Whether a transaction is finished with, so has no more use for the
+/// private context it was built with. Confirmed transactions are only
+/// finished with once buried as deep as the retention policy asks.
+fn is_finished(t: &TxLogEntry, policy: &RetentionPolicy, height: u64) -> bool {
+	gc::is_settled(t, policy, height)
+		|| t.tx_type == TxLogEntryType::TxSentCancelled
+		|| t.tx_type == TxLogEntryType::TxReceivedCancelled
+}
+
+/// Private contexts left for finished transactions, or with no tx log entry
+/// at all. `height` is the last chain height the wallet was refreshed at.
+fn context_issues(
+	contexts: &[(Vec<u8>, usize)],
+	tx_entries: &[TxLogEntry],
+	policy: &RetentionPolicy,
+	height: u64,
+) -> Vec<IntegrityIssue> {
+	let mut issues = vec![];
+	for (slate_id, participant_id) in contexts.iter() {
+		let slate_id = match Uuid::from_slice(slate_id) {
+			Ok(u) => u,
+			Err(_) => {
+				warn!(
+					"Skipping private context with invalid slate id {}",
+					grin_util::to_hex(slate_id.clone())
+				);
+				continue;
+			}
+		};
+		let txs: Vec<&TxLogEntry> = tx_entries
+			.iter()
+			.filter(|t| t.tx_slate_id == Some(slate_id))
+			.collect();
+		if txs.is_empty() {
+			issues.push(IntegrityIssue::OrphanedContext {
+				slate_id,
+				participant_id: *participant_id,
+			});
+		} else if txs.iter().all(|t| is_finished(t, policy, height)) {
+			issues.push(IntegrityIssue::StaleContext {
+				slate_id,
+				participant_id: *participant_id,
+				tx: txs[0].clone(),
+			});
+		}
+	}
+	issues
+}
+
+/// Accounts whose next child index has already been used by one of their
+/// outputs
+fn child_index_issues<T: ?Sized, C, K>(
+	wallet: &mut T,
+	outputs: &[OutputData],
+) -> Result<Vec<IntegrityIssue>, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let mut highest_used: BTreeMap<Identifier, u32> = BTreeMap::new();
+	for o in outputs.iter() {
+		let highest = highest_used.entry(o.root_key_id.clone()).or_insert(0);
+		*highest = cmp::max(*highest, o.n_child);
+	}
+
+	// the child index is only readable for the active account, so each is
+	// made active in turn
+	let active = wallet.parent_key_id();
+	let mut next_indices = vec![];
+	for parent_key_id in highest_used.keys() {
+		wallet.set_parent_key_id(parent_key_id.clone());
+		next_indices.push(wallet.current_child_index());
+	}
+	wallet.set_parent_key_id(active);
+
+	let mut issues = vec![];
+	for ((parent_key_id, highest_used), next_child_index) in
+		highest_used.into_iter().zip(next_indices.into_iter())
+	{
+		let next_child_index = next_child_index?;
+		if next_child_index <= highest_used {
+			issues.push(IntegrityIssue::ChildIndexBehind {
+				parent_key_id,
+				next_child_index,
+				highest_used,
+			});
+		}
+	}
+	Ok(issues)
+}
+
+/// Accounts in use without a label, and those with more than one
+fn account_issues(
+	accounts: &[AcctPathMapping],
+	outputs: &[OutputData],
+	tx_entries: &[TxLogEntry],
+) -> Vec<IntegrityIssue> {
+	let mut issues = vec![];
+	let mut labels: BTreeMap<Identifier, Vec<String>> = BTreeMap::new();
+	for a in accounts.iter() {
+		labels
+			.entry(a.path.clone())
+			.or_insert(vec![])
+			.push(a.label.clone());
+	}
+	for (parent_key_id, l) in labels.iter().filter(|(_, l)| l.len() > 1) {
+		issues.push(IntegrityIssue::DuplicateAccountPath {
+			parent_key_id: parent_key_id.clone(),
+			labels: l.clone(),
+		});
+	}
+
+	let used: BTreeSet<Identifier> = outputs
+		.iter()
+		.map(|o| o.root_key_id.clone())
+		.chain(tx_entries.iter().map(|t| t.parent_key_id.clone()))
+		.collect();
+	for parent_key_id in used.into_iter() {
+		if !labels.contains_key(&parent_key_id) {
+			issues.push(IntegrityIssue::UnlabelledAccount(parent_key_id));
+		}
+	}
+	issues
+}
+
+/// Fix a single issue
+fn fix_issue<T: ?Sized, C, K>(wallet: &mut T, issue: &IntegrityIssue) -> Result<(), Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	match issue {
+		IntegrityIssue::MissingTxLogEntry(output) => {
+			warn!(
+				"Output with ID {} is linked to missing tx log entry {:?}. Unlinking.",
+				output.key_id, output.tx_log_entry,
+			);
+			let mut o = output.clone();
+			o.tx_log_entry = None;
+			let mut batch = wallet.batch()?;
+			batch.save(o)?;
+			batch.commit()?;
+		}
+		IntegrityIssue::MissingStoredTx(t) => {
+			warn!(
+				"Stored transaction {:?} of tx log entry {} is missing. Unlinking.",
+				t.stored_tx, t.id,
+			);
+			let mut entry = t.clone();
+			entry.stored_tx = None;
+			let mut batch = wallet.batch()?;
+			batch.save_tx_log_entry(entry, &t.parent_key_id)?;
+			batch.commit()?;
+		}
+		IntegrityIssue::StaleContext {
+			slate_id,
+			participant_id,
+			tx,
+		} => {
+			warn!(
+				"Private context for slate {} is kept for finished transaction {}. Deleting.",
+				slate_id, tx.id,
+			);
+			let mut batch = wallet.batch()?;
+			batch.delete_private_context(slate_id.as_bytes(), *participant_id)?;
+			batch.commit()?;
+		}
+		IntegrityIssue::ChildIndexBehind {
+			parent_key_id,
+			highest_used,
+			..
+		} => {
+			warn!(
+				"Next child index of account {} has already been used. Moving it to {}.",
+				parent_key_id,
+				highest_used + 1,
+			);
+			let mut batch = wallet.batch()?;
+			batch.save_child_index(parent_key_id, highest_used + 1)?;
+			batch.commit()?;
+		}
+		IntegrityIssue::UnlabelledAccount(parent_key_id) => {
+			let label = keys::unused_acct_label(wallet)?;
+			warn!(
+				"Account {} has no label. Labelling it {}.",
+				parent_key_id, label,
+			);
+			keys::set_acct_path(wallet, &label, parent_key_id)?;
+		}
+		IntegrityIssue::StaleScannedOutputs(count) => {
+			warn!(
+				"{} scanned outputs are kept without a scan checkpoint. Deleting.",
+				count,
+			);
+			let mut batch = wallet.batch()?;
+			batch.clear_scan_checkpoint()?;
+			batch.commit()?;
+		}
+		IntegrityIssue::OrphanedContext { .. }
+		| IntegrityIssue::DuplicateAccountPath { .. }
+		| IntegrityIssue::UnreadableStoredTx { .. } => {}
+	}
+	Ok(())
+}
+
+/// Check the references between everything the wallet stores, without
+/// contacting a node. Issues that can be fixed without losing anything are
+/// fixed if `fix` is set.
+pub fn check_integrity<T: ?Sized, C, K>(wallet: &mut T, fix: bool) -> Result<IntegrityReport, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let outputs: Vec<OutputData> = wallet.iter()?.collect();
+	let tx_entries: Vec<TxLogEntry> = wallet.tx_log_iter()?.collect();
+	let contexts: Vec<(Vec<u8>, usize)> = wallet.private_context_iter()?.collect();
+	let accounts: Vec<AcctPathMapping> = wallet.acct_path_iter()?.collect();
+
+	let mut issues = vec![];
+
+	// outputs linked to tx log entries that don't exist
+	let tx_ids: HashSet<(Identifier, u32)> = tx_entries
+		.iter()
+		.map(|t| (t.parent_key_id.clone(), t.id))
+		.collect();
+	for o in outputs.iter() {
+		if let Some(id) = o.tx_log_entry {
+			if !tx_ids.contains(&(o.root_key_id.clone(), id)) {
+				issues.push(IntegrityIssue::MissingTxLogEntry(o.clone()));
+			}
+		}
+	}
+
+	// tx log entries linked to stored transactions that are missing or
+	// can't be read
+	for t in tx_entries.iter().filter(|t| t.stored_tx.is_some()) {
+		match wallet.get_stored_tx(t) {
+			Ok(Some(_)) => {}
+			Ok(None) => issues.push(IntegrityIssue::MissingStoredTx(t.clone())),
+			Err(e) => issues.push(IntegrityIssue::UnreadableStoredTx {
+				tx: t.clone(),
+				error: format!("{}", e),
+			}),
+		}
+	}
+
+	let policy = wallet.retention_policy();
+	let height = wallet.last_confirmed_height()?;
+	issues.append(&mut context_issues(&contexts, &tx_entries, &policy, height));
+	issues.append(&mut child_index_issues(wallet, &outputs)?);
+	issues.append(&mut account_issues(&accounts, &outputs, &tx_entries));
+
+	if wallet.scan_checkpoint()?.is_none() {
+		let count = wallet.scanned_output_iter()?.count();
+		if count > 0 {
+			issues.push(IntegrityIssue::StaleScannedOutputs(count));
+		}
+	}
+
+	let mut fixed = 0;
+	if fix {
+		for issue in issues.iter().filter(|i| i.is_fixable()) {
+			fix_issue(wallet, issue)?;
+			fixed += 1;
+		}
+	}
+
+	Ok(IntegrityReport {
+		outputs: outputs.len(),
+		tx_log_entries: tx_entries.len(),
+		private_contexts: contexts.len(),
+		accounts: accounts.len(),
+		issues,
+		fixed,
+	})
+}
@@ -52,7 +52,7 @@ where
 	C: NodeClient,
 	K: Keychain,
 {
-	Ok(wallet.acct_path_iter().collect())
+	Ok(wallet.acct_path_iter()?.collect())
 }
 
 /// Adds an new parent account path with a given label
@@ -63,7 +63,7 @@ where
 	K: Keychain,
 {
 	let label = label.to_owned();
-	if let Some(_) = wallet.acct_path_iter().find(|l| l.label == label) {
+	if let Some(_) = wallet.acct_path_iter()?.find(|l| l.label == label) {
 		return Err(ErrorKind::AccountLabelAlreadyExists(label.clone()).into());
 	}
 
@@ -71,7 +71,7 @@ where
 	// so find the highest of those, then increment (to conform with external/internal
 	// derivation chains in BIP32 spec)
 
-	let highest_entry = wallet.acct_path_iter().max_by(|a, b| {
+	let highest_entry = wallet.acct_path_iter()?.max_by(|a, b| {
 		<u32>::from(a.path.to_path().path[0]).cmp(&<u32>::from(b.path.to_path().path[0]))
 	});
 
@@ -96,6 +96,24 @@ where
 	Ok(return_id)
 }
 
+/// The first label of the form `account_N` not already taken, counting up
+/// from the number of accounts
+pub fn unused_acct_label<T: ?Sized, C, K>(wallet: &mut T) -> Result<String, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let label_base = "account";
+	let mut acct_index = wallet.acct_path_iter()?.count();
+	let mut label = format!("{}_{}", label_base, acct_index);
+	while wallet.acct_path_iter()?.any(|a| a.label == label) {
+		acct_index += 1;
+		label = format!("{}_{}", label_base, acct_index);
+	}
+	Ok(label)
+}
+
 /// Adds/sets a particular account path with a given label
 pub fn set_acct_path<T: ?Sized, C, K>(
 	wallet: &mut T,
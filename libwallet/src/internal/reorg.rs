@@ -0,0 +1,182 @@
+// Copyright 2019 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of chain reorganizations that orphaned blocks the wallet
+//! recorded confirmations in, and rollback of those confirmations
+
+use std::collections::HashMap;
+
+use crate::error::{Error, ErrorKind};
+use crate::grin_keychain::{Identifier, Keychain};
+use crate::grin_util as util;
+use crate::grin_util::secp::pedersen;
+use crate::types::{
+	BlockHeaderInfo, NodeClient, OutputData, OutputStatus, TxLogEntry, WalletBackend,
+};
+use failure::ResultExt;
+
+/// The node's blocks at the given heights, keyed by height. Heights past the
+/// node's chain head are left out.
+pub fn node_blocks<T: ?Sized, C, K>(
+	wallet: &mut T,
+	mut heights: Vec<u64>,
+) -> Result<HashMap<u64, BlockHeaderInfo>, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	heights.sort();
+	heights.dedup();
+	if heights.is_empty() {
+		return Ok(HashMap::new());
+	}
+	let headers = wallet.w2n_client().get_block_headers(heights)?;
+	Ok(headers.into_iter().map(|h| (h.height, h)).collect())
+}
+
+/// Checks the block at an account's last confirmed height is still on the
+/// node's chain. If it isn't, every confirmation from the lowest orphaned
+/// block up is undone: unspent outputs confirmed in those blocks go back to
+/// unconfirmed along with the transactions they confirmed, and spent outputs
+/// the node has back in its UTXO set are unspent again. Returns whether the
+/// chain had diverged.
+pub fn rollback_orphaned<T: ?Sized, C, K>(
+	wallet: &mut T,
+	height: u64,
+	parent_key_id: &Identifier,
+) -> Result<bool, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let last_block = match wallet.last_confirmed_block(parent_key_id)? {
+		Some(b) => b,
+		None => return Ok(false),
+	};
+	// A node behind the wallet is syncing or yet to settle on a fork, and
+	// can't say anything about the block until it catches up
+	if last_block.height > height {
+		return Ok(false);
+	}
+	let blocks = node_blocks(wallet, vec![last_block.height])?;
+	if blocks.get(&last_block.height).map(|b| &b.hash) == Some(&last_block.hash) {
+		return Ok(false);
+	}
+	warn!(
+		"Block {} at height {} is no longer on the node's chain, rolling back \
+		 confirmations from orphaned blocks",
+		last_block.hash.hash().to_hex(),
+		last_block.height
+	);
+
+	let outputs: Vec<OutputData> = wallet
+		.iter()?
+		.filter(|o| o.root_key_id == *parent_key_id && o.status != OutputStatus::Unconfirmed)
+		.collect();
+	let checked: Vec<u64> = outputs
+		.iter()
+		.filter(|o| o.block_hash.is_some())
+		.map(|o| o.height)
+		.collect();
+	let blocks = node_blocks(wallet, checked)?;
+	let is_orphaned = |o: &OutputData| match o.block_hash {
+		Some(ref hash) => blocks.get(&o.height).map(|b| &b.hash) != Some(hash),
+		None => false,
+	};
+
+	// Blocks are orphaned from the fork point up, so everything confirmed
+	// from the lowest orphaned block is undone, hash recorded or not
+	let fork_height = outputs
+		.iter()
+		.filter(|o| is_orphaned(o))
+		.map(|o| o.height)
+		.fold(last_block.height, |min, h| min.min(h));
+	let orphaned: Vec<OutputData> = outputs
+		.into_iter()
+		.filter(|o| is_orphaned(o) || (o.block_hash.is_none() && o.height >= fork_height))
+		.collect();
+	let orphaned_txs: Vec<u32> = orphaned.iter().filter_map(|o| o.tx_log_entry).collect();
+	let is_rolled_back = |t: &TxLogEntry| {
+		t.parent_key_id == *parent_key_id
+			&& t.confirmed
+			&& (orphaned_txs.contains(&t.id)
+				|| t.confirmation_height.map(|h| h >= fork_height) == Some(true))
+	};
+
+	{
+		let mut batch = wallet.batch()?;
+		for mut output in orphaned.into_iter() {
+			if output.status == OutputStatus::Unspent {
+				output.status = OutputStatus::Unconfirmed;
+			}
+			output.block_hash = None;
+			batch.save(output)?;
+		}
+		let txs: Vec<_> = batch.tx_log_iter()?.filter(|t| is_rolled_back(t)).collect();
+		for mut t in txs.into_iter() {
+			t.confirmed = false;
+			t.confirmation_ts = None;
+			t.confirmation_height = None;
+			batch.save_tx_log_entry(t, parent_key_id)?;
+		}
+		batch.commit()?;
+	}
+
+	revive_unspent(wallet, parent_key_id)?;
+	Ok(true)
+}
+
+/// Marks spent outputs unspent again if the node has them in its UTXO set,
+/// as when the block spending them was orphaned
+fn revive_unspent<T: ?Sized, C, K>(wallet: &mut T, parent_key_id: &Identifier) -> Result<(), Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let keychain = wallet.keychain().clone();
+	let spent_outputs: Vec<OutputData> = wallet
+		.iter()?
+		.filter(|o| o.root_key_id == *parent_key_id && o.status == OutputStatus::Spent)
+		.collect();
+	let mut spent: HashMap<pedersen::Commitment, OutputData> = HashMap::new();
+	for o in spent_outputs.into_iter() {
+		let commit = match o.commit.clone() {
+			Some(c) => pedersen::Commitment::from_vec(
+				util::from_hex(c.clone())
+					.context(ErrorKind::GenericError(format!("Invalid commitment {}", c)))?,
+			),
+			None => keychain.commit(o.value, &o.key_id)?,
+		};
+		spent.insert(commit, o);
+	}
+	if spent.is_empty() {
+		return Ok(());
+	}
+	let api_outputs = wallet
+		.w2n_client()
+		.get_outputs_from_node(spent.keys().cloned().collect())?;
+
+	let mut batch = wallet.batch()?;
+	for (commit, mut output) in spent.into_iter() {
+		if api_outputs.contains_key(&commit) {
+			output.status = OutputStatus::Unspent;
+			batch.save(output)?;
+		}
+	}
+	batch.commit()?;
+	Ok(())
+}
@@ -15,39 +15,23 @@
 
 use crate::grin_core::global;
 use crate::grin_core::libtx::proof;
-use crate::grin_keychain::{ExtKeychain, Identifier, Keychain};
-use crate::grin_util::secp::{key::SecretKey, pedersen};
+use crate::grin_keychain::{Identifier, Keychain};
+use crate::grin_util;
+use crate::grin_util::secp::pedersen;
 use crate::internal::{keys, updater};
 use crate::types::*;
-use crate::{Error, OutputCommitMapping};
-use std::collections::HashMap;
+use crate::{Error, ErrorKind};
+use crossbeam_utils::thread;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Sender;
 
-/// Utility struct for return values from below
-#[derive(Clone)]
-struct OutputResult {
-	///
-	pub commit: pedersen::Commitment,
-	///
-	pub key_id: Identifier,
-	///
-	pub n_child: u32,
-	///
-	pub mmr_index: u64,
-	///
-	pub value: u64,
-	///
-	pub height: u64,
-	///
-	pub lock_height: u64,
-	///
-	pub is_coinbase: bool,
-	///
-	pub blinding: SecretKey,
-}
+/// Number of outputs requested from the node at a time while scanning
+const SCAN_BATCH_SIZE: u64 = 1000;
 
 #[derive(Debug, Clone)]
 /// Collect stats in case we want to just output a single tx log entry
-/// for restored non-coinbase outputs
+/// for the non-coinbase outputs restored from a block
 struct RestoredTxStats {
 	///
 	pub log_id: u32,
@@ -57,27 +41,61 @@ struct RestoredTxStats {
 	pub num_outputs: usize,
 }
 
-fn identify_utxo_outputs<T, C, K>(
-	wallet: &mut T,
-	outputs: Vec<(pedersen::Commitment, pedersen::RangeProof, bool, u64, u64)>,
-) -> Result<Vec<OutputResult>, Error>
+/// Derivation path depths tried when recovering an output's key id from its
+/// rewind message, most common first
+const PATH_DEPTHS: [u8; 4] = [3, 4, 2, 1];
+
+/// Outputs identified as belonging to the wallet, and those whose
+/// derivation path couldn't be recovered
+type IdentifiedOutputs = (Vec<ScannedOutput>, Vec<UnknownPathOutput>);
+
+/// Recover the key id of an output from its rewind message. The message
+/// holds the path but not its depth, so each depth is tried in turn, and
+/// taken if the key id at that depth commits to the output's value with the
+/// output's commitment.
+fn key_id_from_message<K>(
+	keychain: &K,
+	message: &[u8],
+	value: u64,
+	commit: &pedersen::Commitment,
+) -> Result<Option<Identifier>, Error>
 where
-	T: WalletBackend<C, K>,
-	C: NodeClient,
 	K: Keychain,
 {
-	let mut wallet_outputs: Vec<OutputResult> = Vec::new();
+	for depth in PATH_DEPTHS.iter() {
+		let key_id = Identifier::from_serialized_path(*depth, message);
+		// path components past the depth are never set
+		let path = key_id.to_path();
+		if path.path[*depth as usize..]
+			.iter()
+			.any(|c| <u32>::from(*c) != 0)
+		{
+			continue;
+		}
+		if keychain.commit(value, &key_id)? == *commit {
+			return Ok(Some(key_id));
+		}
+	}
+	Ok(None)
+}
 
-	println!(
-		"Scanning {} outputs in the current Forest utxo set",
-		outputs.len(),
-	);
+/// Rewind the range proofs of a run of outputs, keeping those that belong
+/// to the keychain
+fn rewind_outputs<K>(
+	keychain: &K,
+	outputs: &[(pedersen::Commitment, pedersen::RangeProof, bool, u64, u64)],
+) -> Result<IdentifiedOutputs, Error>
+where
+	K: Keychain,
+{
+	let mut wallet_outputs: Vec<ScannedOutput> = Vec::new();
+	let mut unknown_path_outputs: Vec<UnknownPathOutput> = Vec::new();
 
 	for output in outputs.iter() {
 		let (commit, proof, is_coinbase, height, mmr_index) = output;
 		// attempt to unwind message from the RP and get a value
 		// will fail if it's not ours
-		let info = proof::rewind(wallet.keychain(), *commit, None, *proof)?;
+		let info = proof::rewind(keychain, *commit, None, *proof)?;
 
 		if !info.success {
 			continue;
@@ -89,16 +107,32 @@ where
 			*height
 		};
 
-		// TODO: Output paths are always going to be length 3 for now, but easy enough to grind
-		// through to find the right path if required later
-		let key_id = Identifier::from_serialized_path(3u8, &info.message.as_bytes());
+		let message = info.message.as_bytes();
+		let key_id = match key_id_from_message(keychain, message, info.value, commit)? {
+			Some(k) => k,
+			None => {
+				warn!(
+					"Output found: {:?}, amount: {:?}, mmr_index: {}, but its derivation path \
+					 could not be recovered",
+					commit, info.value, mmr_index,
+				);
+				unknown_path_outputs.push(UnknownPathOutput {
+					commit: *commit,
+					message: grin_util::to_hex(message.to_vec()),
+					mmr_index: *mmr_index,
+					value: info.value,
+					height: *height,
+				});
+				continue;
+			}
+		};
 
 		info!(
 			"Output found: {:?}, amount: {:?}, key_id: {:?}, mmr_index: {},",
 			commit, info.value, key_id, mmr_index,
 		);
 
-		wallet_outputs.push(OutputResult {
+		wallet_outputs.push(ScannedOutput {
 			commit: *commit,
 			key_id: key_id.clone(),
 			n_child: key_id.to_path().last_path_index(),
@@ -106,49 +140,382 @@ where
 			height: *height,
 			lock_height: lock_height,
 			is_coinbase: *is_coinbase,
-			blinding: info.blinding,
 			mmr_index: *mmr_index,
 		});
 	}
-	Ok(wallet_outputs)
+	Ok((wallet_outputs, unknown_path_outputs))
+}
+
+/// Identify the outputs belonging to the keychain by rewinding their range
+/// proofs on up to `threads` threads, each with its own clone of the
+/// keychain. The outputs are split into contiguous runs, one per thread, and
+/// the results joined back in order, so they're the same as those of a
+/// single threaded scan. Outputs that rewind but whose derivation path
+/// can't be recovered are returned separately.
+pub fn identify_outputs<K>(
+	keychain: &K,
+	outputs: &[(pedersen::Commitment, pedersen::RangeProof, bool, u64, u64)],
+	threads: usize,
+) -> Result<IdentifiedOutputs, Error>
+where
+	K: Keychain,
+{
+	if threads <= 1 || outputs.len() <= 1 {
+		return rewind_outputs(keychain, outputs);
+	}
+	let chunk_size = (outputs.len() + threads - 1) / threads;
+	let thread_err = || -> Error {
+		ErrorKind::GenericError("Output scanning thread panicked".to_owned()).into()
+	};
+	let results = thread::scope(|s| {
+		let handles: Vec<_> = outputs
+			.chunks(chunk_size)
+			.map(|chunk| {
+				let keychain = keychain.clone();
+				s.spawn(move |_| rewind_outputs(&keychain, chunk))
+			})
+			.collect();
+		handles.into_iter().map(|h| h.join()).collect::<Vec<_>>()
+	})
+	.map_err(|_| thread_err())?;
+
+	let mut wallet_outputs: Vec<ScannedOutput> = Vec::new();
+	let mut unknown_path_outputs: Vec<UnknownPathOutput> = Vec::new();
+	for res in results {
+		let (found, unknown) = res.map_err(|_| thread_err())??;
+		wallet_outputs.extend(found);
+		unknown_path_outputs.extend(unknown);
+	}
+	Ok((wallet_outputs, unknown_path_outputs))
+}
+
+fn identify_utxo_outputs<T, C, K>(
+	wallet: &mut T,
+	outputs: Vec<(pedersen::Commitment, pedersen::RangeProof, bool, u64, u64)>,
+	threads: usize,
+) -> Result<IdentifiedOutputs, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	debug!(
+		"Scanning {} outputs in the current Forest utxo set",
+		outputs.len(),
+	);
+
+	identify_outputs(wallet.keychain(), &outputs, threads)
+}
+
+/// First output PMMR index to scan from the given height, or the start of
+/// the PMMR if none. Unless given, the height is the wallet's birthday.
+fn scan_start_index<T, C, K>(wallet: &mut T, start_height: Option<u64>) -> Result<u64, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let start_height = match start_height {
+		Some(h) => Some(h),
+		None => wallet.birthday_height()?,
+	};
+	match start_height {
+		Some(h) if h > 0 => {
+			let (start_index, _) = wallet.w2n_client().height_range_to_pmmr_indices(h, None)?;
+			warn!(
+				"Scanning from block height {} (output index {})",
+				h, start_index
+			);
+			Ok(cmp::max(start_index, 1))
+		}
+		_ => Ok(1),
+	}
+}
+
+/// Publishes the status of a restore or check as it progresses. One
+/// dropped before the scan has ended, as on an error, publishes that the
+/// scan failed, so listeners always hear how it ended.
+struct StatusReporter<'a> {
+	status_send: &'a Option<Sender<ScanStatus>>,
+	status: ScanStatus,
+}
+
+impl<'a> StatusReporter<'a> {
+	fn new(kind: ScanKind, status_send: &'a Option<Sender<ScanStatus>>) -> StatusReporter<'a> {
+		StatusReporter {
+			status_send,
+			status: ScanStatus::new(kind),
+		}
+	}
+
+	fn publish(&self) {
+		if let Some(ref s) = self.status_send {
+			// the scan carries on if nobody is listening any more
+			let _ = s.send(self.status.clone());
+		}
+	}
+
+	/// Progress through the PMMR, from `first_index` up to `next_index`
+	fn scanned(
+		&mut self,
+		first_index: u64,
+		next_index: u64,
+		highest_index: u64,
+		outputs_found: usize,
+	) {
+		let percent = if highest_index < first_index {
+			100
+		} else {
+			(next_index - first_index) * 100 / (highest_index + 1 - first_index)
+		};
+		self.status.percent_complete = cmp::min(percent, 100) as u8;
+		self.status.last_pmmr_index = next_index - 1;
+		self.status.highest_pmmr_index = highest_index;
+		self.status.outputs_found = outputs_found;
+		self.publish();
+	}
+
+	fn phase(&mut self, phase: ScanPhase) {
+		self.status.phase = phase;
+		if phase == ScanPhase::Updating || phase == ScanPhase::Complete {
+			self.status.percent_complete = 100;
+		}
+		self.publish();
+	}
 }
 
-fn collect_chain_outputs<T, C, K>(wallet: &mut T) -> Result<Vec<OutputResult>, Error>
+impl<'a> Drop for StatusReporter<'a> {
+	fn drop(&mut self) {
+		if self.status.phase != ScanPhase::Complete && self.status.phase != ScanPhase::Skipped {
+			self.phase(ScanPhase::Failed);
+		}
+	}
+}
+
+/// Scan the output PMMR for outputs belonging to the wallet, from the given
+/// start height or from where an interrupted scan left off. The outputs found in each
+/// batch are saved along with the scan checkpoint, so an interrupted scan
+/// loses at most one batch of work. Returns every output found, including
+/// those found before the scan was resumed. Unless `save_progress` is set,
+/// nothing is saved, and the scan can't be resumed.
+fn collect_chain_outputs<T, C, K>(
+	wallet: &mut T,
+	status: &mut StatusReporter,
+	checkpoint: Option<ScanCheckpoint>,
+	start_height: Option<u64>,
+	save_progress: bool,
+	threads: usize,
+) -> Result<Vec<ScannedOutput>, Error>
 where
 	T: WalletBackend<C, K>,
 	C: NodeClient,
 	K: Keychain,
 {
-	let batch_size = 1000;
-	let mut start_index = 1;
-	let mut result_vec: Vec<OutputResult> = vec![];
+	let kind = status.status.kind;
+	// progress is reported through the whole range being scanned, from
+	// where the scan first started
+	let (first_index, mut start_index) = match checkpoint {
+		Some(c) => {
+			warn!(
+				"Resuming interrupted scan from output index {} (Highest index: {})",
+				c.next_pmmr_index, c.highest_pmmr_index,
+			);
+			(cmp::max(c.start_pmmr_index, 1), c.next_pmmr_index)
+		}
+		None => {
+			let start_index = scan_start_index(wallet, start_height)?;
+			(start_index, start_index)
+		}
+	};
+	let mut unsaved_outputs = vec![];
+	let mut outputs_found = match save_progress {
+		true => wallet.scanned_output_iter()?.count(),
+		false => 0,
+	};
+	status.publish();
 	loop {
 		let (highest_index, last_retrieved_index, outputs) = wallet
 			.w2n_client()
-			.get_outputs_by_pmmr_index(start_index, batch_size)?;
-		println!(
+			.get_outputs_by_pmmr_index(start_index, SCAN_BATCH_SIZE)?;
+		info!(
 			"Checking {} outputs, up to index {}. (Highest index: {})",
 			outputs.len(),
-			highest_index,
 			last_retrieved_index,
+			highest_index,
 		);
 
-		result_vec.append(&mut identify_utxo_outputs(wallet, outputs.clone())?);
+		let (found, unknown) = identify_utxo_outputs(wallet, outputs, threads)?;
+		// nothing retrieved when resuming a scan that had already finished
+		let next_index = cmp::max(last_retrieved_index + 1, start_index);
+		outputs_found += found.len();
+		if save_progress {
+			let mut batch = wallet.batch()?;
+			for o in found.iter() {
+				batch.save_scanned_output(o)?;
+			}
+			batch.save_scan_checkpoint(&ScanCheckpoint {
+				kind,
+				start_pmmr_index: first_index,
+				next_pmmr_index: next_index,
+				highest_pmmr_index: highest_index,
+			})?;
+			batch.commit()?;
+		} else {
+			unsaved_outputs.extend(found);
+		}
+		status.status.unknown_path_outputs.extend(unknown);
+		status.scanned(first_index, next_index, highest_index, outputs_found);
 
-		if highest_index == last_retrieved_index {
+		if highest_index <= last_retrieved_index || next_index == start_index {
 			break;
 		}
-		start_index = last_retrieved_index + 1;
+		start_index = next_index;
+	}
+	match save_progress {
+		true => Ok(wallet.scanned_output_iter()?.collect()),
+		false => Ok(unsaved_outputs),
+	}
+}
+
+/// Checkpoint of an interrupted scan of the given kind, to resume from. A
+/// checkpoint left by the other kind of scan is discarded, so the scan
+/// starts over.
+fn resume_checkpoint<T, C, K>(
+	wallet: &mut T,
+	kind: ScanKind,
+) -> Result<Option<ScanCheckpoint>, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	match wallet.scan_checkpoint()? {
+		Some(c) => {
+			if c.kind == kind {
+				return Ok(Some(c));
+			}
+			clear_scan_checkpoint(wallet)?;
+			Ok(None)
+		}
+		None => Ok(None),
+	}
+}
+
+fn clear_scan_checkpoint<T, C, K>(wallet: &mut T) -> Result<(), Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let mut batch = wallet.batch()?;
+	batch.clear_scan_checkpoint()?;
+	batch.commit()?;
+	Ok(())
+}
+
+/// Drop outputs that have been spent since an interrupted scan found them
+fn unspent_scanned_outputs<T, C, K>(
+	wallet: &mut T,
+	outputs: Vec<ScannedOutput>,
+) -> Result<Vec<ScannedOutput>, Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	if outputs.is_empty() {
+		return Ok(outputs);
+	}
+	let commits: Vec<pedersen::Commitment> = outputs.iter().map(|o| o.commit).collect();
+	let unspent = wallet.w2n_client().get_outputs_from_node(commits)?;
+	Ok(outputs
+		.into_iter()
+		.filter(|o| unspent.contains_key(&o.commit))
+		.collect())
+}
+
+/// Headers of the blocks at the heights outputs were confirmed at. Entries
+/// restored from blocks the node can't give a header for are stamped with
+/// the current time instead.
+fn output_block_headers<T, C, K>(
+	wallet: &mut T,
+	mut heights: Vec<u64>,
+) -> HashMap<u64, BlockHeaderInfo>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	heights.sort();
+	heights.dedup();
+	if heights.is_empty() {
+		return HashMap::new();
+	}
+	match wallet.w2n_client().get_block_headers(heights) {
+		Ok(headers) => headers.into_iter().map(|h| (h.height, h)).collect(),
+		Err(e) => {
+			warn!(
+				"Block times of restored outputs could not be retrieved, \
+				 using the current time: {}",
+				e
+			);
+			HashMap::new()
+		}
+	}
+}
+
+/// A confirmed tx log entry for outputs restored from the block at `height`
+fn restored_tx_log_entry(
+	parent_key_id: &Identifier,
+	entry_type: TxLogEntryType,
+	log_id: u32,
+	height: u64,
+	headers: &HashMap<u64, BlockHeaderInfo>,
+) -> TxLogEntry {
+	let mut t = TxLogEntry::new(parent_key_id.clone(), entry_type, log_id);
+	t.confirmed = true;
+	t.confirmation_height = Some(height);
+	match headers.get(&height) {
+		Some(h) => {
+			t.creation_ts = h.timestamp;
+			t.confirmation_ts = Some(h.timestamp);
+		}
+		None => t.update_confirmation_ts(),
 	}
-	Ok(result_vec)
+	t
 }
 
-///
+/// The entry for all of an account's non-coinbase outputs restored from the
+/// block at `height`
+fn restored_block_tx_log_entry(
+	parent_key_id: &Identifier,
+	height: u64,
+	stats: &RestoredTxStats,
+	headers: &HashMap<u64, BlockHeaderInfo>,
+) -> TxLogEntry {
+	let mut t = restored_tx_log_entry(
+		parent_key_id,
+		TxLogEntryType::TxReceived,
+		stats.log_id,
+		height,
+		headers,
+	);
+	t.amount_credited = stats.amount_credited;
+	t.num_outputs = stats.num_outputs;
+	t
+}
+
+/// Save a restored output, with a tx log entry of its own, or if `tx_stats`
+/// is given, counted towards an entry for all of the account's
+/// non-coinbase outputs from the same block. Either way the entry is saved
+/// along with the output, so an interrupted restore leaves no output
+/// pointing at an entry that was never written.
 fn restore_missing_output<T, C, K>(
 	wallet: &mut T,
-	output: OutputResult,
+	output: ScannedOutput,
 	found_parents: &mut HashMap<Identifier, u32>,
-	tx_stats: &mut Option<&mut HashMap<Identifier, RestoredTxStats>>,
+	tx_stats: &mut Option<&mut HashMap<(Identifier, u64), RestoredTxStats>>,
+	headers: &HashMap<u64, BlockHeaderInfo>,
 ) -> Result<(), Error>
 where
 	T: WalletBackend<C, K>,
@@ -161,49 +528,45 @@ where
 	let parent_key_id = output.key_id.parent_path();
 	if !found_parents.contains_key(&parent_key_id) {
 		found_parents.insert(parent_key_id.clone(), 0);
-		if let Some(ref mut s) = tx_stats {
-			s.insert(
-				parent_key_id.clone(),
-				RestoredTxStats {
-					log_id: batch.next_tx_log_id(&parent_key_id)?,
-					amount_credited: 0,
-					num_outputs: 0,
-				},
-			);
-		}
 	}
 
-	let log_id = if tx_stats.is_none() || output.is_coinbase {
-		let log_id = batch.next_tx_log_id(&parent_key_id)?;
-		let entry_type = match output.is_coinbase {
-			true => TxLogEntryType::ConfirmedCoinbase,
-			false => TxLogEntryType::TxReceived,
-		};
-		let mut t = TxLogEntry::new(parent_key_id.clone(), entry_type, log_id);
-		t.confirmed = true;
-		t.amount_credited = output.value;
-		t.num_outputs = 1;
-		t.update_confirmation_ts();
-		batch.save_tx_log_entry(t, &parent_key_id)?;
-		log_id
-	} else {
-		if let Some(ref mut s) = tx_stats {
-			let ts = s.get(&parent_key_id).unwrap().clone();
-			s.insert(
-				parent_key_id.clone(),
-				RestoredTxStats {
-					log_id: ts.log_id,
-					amount_credited: ts.amount_credited + output.value,
-					num_outputs: ts.num_outputs + 1,
-				},
-			);
+	let log_id = match tx_stats {
+		Some(ref mut s) if !output.is_coinbase => {
+			let block_key = (parent_key_id.clone(), output.height);
+			if !s.contains_key(&block_key) {
+				let log_id = batch.next_tx_log_id(&parent_key_id)?;
+				s.insert(
+					block_key.clone(),
+					RestoredTxStats {
+						log_id,
+						amount_credited: 0,
+						num_outputs: 0,
+					},
+				);
+			}
+			let ts = s.get_mut(&block_key).unwrap();
+			ts.amount_credited += output.value;
+			ts.num_outputs += 1;
+			let t = restored_block_tx_log_entry(&parent_key_id, output.height, ts, headers);
+			batch.save_tx_log_entry(t, &parent_key_id)?;
 			ts.log_id
-		} else {
-			0
+		}
+		_ => {
+			let log_id = batch.next_tx_log_id(&parent_key_id)?;
+			let entry_type = match output.is_coinbase {
+				true => TxLogEntryType::ConfirmedCoinbase,
+				false => TxLogEntryType::TxReceived,
+			};
+			let mut t =
+				restored_tx_log_entry(&parent_key_id, entry_type, log_id, output.height, headers);
+			t.amount_credited = output.value;
+			t.num_outputs = 1;
+			batch.save_tx_log_entry(t, &parent_key_id)?;
+			log_id
 		}
 	};
 
-	let _ = batch.save(OutputData {
+	batch.save(OutputData {
 		root_key_id: parent_key_id.clone(),
 		key_id: output.key_id,
 		n_child: output.n_child,
@@ -215,7 +578,8 @@ where
 		lock_height: output.lock_height,
 		is_coinbase: output.is_coinbase,
 		tx_log_entry: Some(log_id),
-	});
+		block_hash: None,
+	})?;
 
 	let max_child_index = found_parents.get(&parent_key_id).unwrap().clone();
 	if output.n_child >= max_child_index {
@@ -226,230 +590,421 @@ where
 	Ok(())
 }
 
-///
-fn cancel_tx_log_entry<T, C, K>(wallet: &mut T, output: &OutputData) -> Result<(), Error>
+/// The tx log entry of an output, if it's one a repair of the output would
+/// cancel
+fn cancellable_tx_log_entry<T, C, K>(
+	wallet: &mut T,
+	output: &OutputData,
+) -> Result<Option<TxLogEntry>, Error>
 where
 	T: WalletBackend<C, K>,
 	C: NodeClient,
 	K: Keychain,
 {
-	let parent_key_id = output.key_id.parent_path();
-	let updated_tx_entry = if output.tx_log_entry.is_some() {
-		let entries = updater::retrieve_txs(
-			wallet,
-			output.tx_log_entry.clone(),
-			None,
-			Some(&parent_key_id),
-			false,
-		)?;
-		if entries.len() > 0 {
-			let mut entry = entries[0].clone();
-			match entry.tx_type {
-				TxLogEntryType::TxSent => entry.tx_type = TxLogEntryType::TxSentCancelled,
-				TxLogEntryType::TxReceived => entry.tx_type = TxLogEntryType::TxReceivedCancelled,
-				_ => {}
-			}
-			Some(entry)
-		} else {
-			None
-		}
-	} else {
-		None
-	};
-	let mut batch = wallet.batch()?;
-	if let Some(t) = updated_tx_entry {
-		batch.save_tx_log_entry(t, &parent_key_id)?;
+	if output.tx_log_entry.is_none() {
+		return Ok(None);
 	}
-	batch.commit()?;
-	Ok(())
+	let parent_key_id = output.key_id.parent_path();
+	let entries = updater::retrieve_txs(
+		wallet,
+		output.tx_log_entry.clone(),
+		None,
+		Some(&parent_key_id),
+		false,
+	)?;
+	Ok(entries
+		.into_iter()
+		.next()
+		.filter(|t| t.tx_type.cancelled().is_some()))
 }
 
-/// Check / repair wallet contents
-/// assume wallet contents have been freshly updated with contents
-/// of latest block
-pub fn check_repair<T, C, K>(wallet: &mut T, delete_unconfirmed: bool) -> Result<(), Error>
+/// Work out what a check needs to repair, from the outputs of the wallet
+/// found on chain. Reads the wallet, but doesn't change it.
+fn find_discrepancies<T, C, K>(
+	wallet: &mut T,
+	chain_outs: Vec<ScannedOutput>,
+	delete_unconfirmed: bool,
+) -> Result<Vec<CheckDiscrepancy>, Error>
 where
 	T: WalletBackend<C, K>,
 	C: NodeClient,
 	K: Keychain,
 {
-	// First, get a definitive list of outputs we own from the chain
-	//warn!("Starting wallet check.");
-	println!("Starting wallet check.");
-	let chain_outs = collect_chain_outputs(wallet)?;
-	warn!(
-		"Identified {} wallet_outputs as belonging to this wallet",
-		chain_outs.len(),
-	);
-
 	// Now, get all outputs owned by this wallet (regardless of account)
-	let wallet_outputs = {
-		let res = updater::retrieve_outputs(&mut *wallet, true, None, None)?;
-		res
-	};
+	let wallet_outputs = updater::retrieve_outputs(&mut *wallet, true, None, None)?;
 
-	let mut missing_outs = vec![];
-	let mut accidental_spend_outs = vec![];
-	let mut locked_outs = vec![];
+	let mut discrepancies = vec![];
+	// outputs repaired to a new status, any transactions associated with
+	// them are cancelled
+	let mut repaired_outs = vec![];
 
 	// check all definitive outputs exist in the wallet outputs
 	for deffo in chain_outs.into_iter() {
 		let matched_out = wallet_outputs.iter().find(|wo| wo.commit == deffo.commit);
 		match matched_out {
 			Some(s) => {
-				if s.output.status == OutputStatus::Spent {
-					accidental_spend_outs.push((s.output.clone(), deffo.clone()));
+				// marked spent but still on chain (confirmed against a short-lived
+				// fork, for example), or locked, so unlocked if unconfirmed
+				// transactions are being cleared
+				if s.output.status == OutputStatus::Spent
+					|| (delete_unconfirmed && s.output.status == OutputStatus::Locked)
+				{
+					repaired_outs.push((s.output.clone(), Some(OutputStatus::Unspent)));
 				}
-				if s.output.status == OutputStatus::Locked {
-					locked_outs.push((s.output.clone(), deffo.clone()));
+			}
+			None => {
+				// a wallet output at the same key id and position, but with
+				// another value, is replaced
+				let same_key = wallet_outputs.iter().find(|wo| {
+					wo.output.key_id == deffo.key_id && wo.output.mmr_index == Some(deffo.mmr_index)
+				});
+				match same_key {
+					Some(s) => discrepancies.push(CheckDiscrepancy::WrongValue {
+						output: s.output.clone(),
+						chain_output: deffo,
+					}),
+					None => discrepancies.push(CheckDiscrepancy::MissingOutput(deffo)),
 				}
 			}
-			None => missing_outs.push(deffo),
 		}
 	}
 
-	// mark problem spent outputs as unspent (confirmed against a short-lived fork, for example)
-	for m in accidental_spend_outs.into_iter() {
-		let mut o = m.0;
-		warn!(
-			"Output for {} with ID {} ({:?}) marked as spent but exists in UTXO set. \
-			 Marking unspent and cancelling any associated transaction log entries.",
-			o.value, o.key_id, m.1.commit,
-		);
-		o.status = OutputStatus::Unspent;
-		// any transactions associated with this should be cancelled
-		cancel_tx_log_entry(wallet, &o)?;
-		let mut batch = wallet.batch()?;
-		batch.save(o)?;
-		batch.commit()?;
+	// unconfirmed outputs aren't in the UTXO set, and are deleted
+	if delete_unconfirmed {
+		for m in wallet_outputs
+			.iter()
+			.filter(|o| o.output.status == OutputStatus::Unconfirmed)
+		{
+			repaired_outs.push((m.output.clone(), None));
+		}
 	}
 
-	let mut found_parents: HashMap<Identifier, u32> = HashMap::new();
-
-	// Restore missing outputs, adding transaction for it back to the log
-	for m in missing_outs.into_iter() {
-		warn!(
-			"Confirmed output for {} with ID {} ({:?}) exists in UTXO set but not in wallet. \
-			 Restoring.",
-			m.value, m.key_id, m.commit,
-		);
-		restore_missing_output(wallet, m, &mut found_parents, &mut None)?;
+	let mut cancelled: HashSet<(Identifier, u32)> = HashSet::new();
+	for (output, repaired_status) in repaired_outs.into_iter() {
+		let entry = cancellable_tx_log_entry(wallet, &output)?;
+		discrepancies.push(CheckDiscrepancy::WrongStatus {
+			output,
+			repaired_status,
+		});
+		if let Some(t) = entry {
+			if cancelled.insert((t.parent_key_id.clone(), t.id)) {
+				discrepancies.push(CheckDiscrepancy::CancelledTx(t));
+			}
+		}
 	}
+	Ok(discrepancies)
+}
 
-	if delete_unconfirmed {
-		// Unlock locked outputs
-		for m in locked_outs.into_iter() {
-			let mut o = m.0;
-			warn!(
-				"Confirmed output for {} with ID {} ({:?}) exists in UTXO set and is locked. \
-				 Unlocking and cancelling associated transaction log entries.",
-				o.value, o.key_id, m.1.commit,
-			);
-			o.status = OutputStatus::Unspent;
-			cancel_tx_log_entry(wallet, &o)?;
-			let mut batch = wallet.batch()?;
-			batch.save(o)?;
-			batch.commit()?;
-		}
+/// Repair the discrepancies found by a check
+fn repair_discrepancies<T, C, K>(
+	wallet: &mut T,
+	discrepancies: &[CheckDiscrepancy],
+) -> Result<(), Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let restored_outs: Vec<ScannedOutput> = discrepancies
+		.iter()
+		.filter_map(|d| match d {
+			CheckDiscrepancy::MissingOutput(o) => Some(o.clone()),
+			CheckDiscrepancy::WrongValue { chain_output, .. } => Some(chain_output.clone()),
+			_ => None,
+		})
+		.collect();
+	let heights = restored_outs.iter().map(|o| o.height).collect();
+	let headers = output_block_headers(wallet, heights);
+	let mut found_parents: HashMap<Identifier, u32> = HashMap::new();
 
-		let unconfirmed_outs: Vec<&OutputCommitMapping> = wallet_outputs
-			.iter()
-			.filter(|o| o.output.status == OutputStatus::Unconfirmed)
-			.collect();
-		// Delete unconfirmed outputs
-		for m in unconfirmed_outs.into_iter() {
-			let o = m.output.clone();
-			warn!(
-				"Unconfirmed output for {} with ID {} ({:?}) not in UTXO set. \
-				 Deleting and cancelling associated transaction log entries.",
-				o.value, o.key_id, m.commit,
-			);
-			cancel_tx_log_entry(wallet, &o)?;
-			let mut batch = wallet.batch()?;
-			batch.delete(&o.key_id, &o.mmr_index)?;
-			batch.commit()?;
+	for d in discrepancies.iter() {
+		match d {
+			CheckDiscrepancy::MissingOutput(m) => {
+				warn!(
+					"Confirmed output for {} with ID {} ({:?}) exists in UTXO set but not in \
+					 wallet. Restoring.",
+					m.value, m.key_id, m.commit,
+				);
+				restore_missing_output(wallet, m.clone(), &mut found_parents, &mut None, &headers)?;
+			}
+			CheckDiscrepancy::WrongValue {
+				output,
+				chain_output,
+			} => {
+				warn!(
+					"Output with ID {} has value {} in wallet, but {} in UTXO set ({:?}). \
+					 Replacing.",
+					output.key_id, output.value, chain_output.value, chain_output.commit,
+				);
+				restore_missing_output(
+					wallet,
+					chain_output.clone(),
+					&mut found_parents,
+					&mut None,
+					&headers,
+				)?;
+			}
+			CheckDiscrepancy::WrongStatus {
+				output,
+				repaired_status,
+			} => {
+				let mut batch = wallet.batch()?;
+				match repaired_status {
+					Some(status) => {
+						warn!(
+							"Output for {} with ID {} is {} in wallet, but {} in UTXO set. \
+							 Marking {}.",
+							output.value, output.key_id, output.status, status, status,
+						);
+						let mut o = output.clone();
+						o.status = status.clone();
+						batch.save(o)?;
+					}
+					None => {
+						warn!(
+							"Unconfirmed output for {} with ID {} not in UTXO set. Deleting.",
+							output.value, output.key_id,
+						);
+						batch.delete(&output.key_id, &output.mmr_index)?;
+					}
+				}
+				batch.commit()?;
+			}
+			CheckDiscrepancy::CancelledTx(t) => {
+				warn!(
+					"Cancelling transaction log entry {} of account {}",
+					t.id, t.parent_key_id,
+				);
+				let mut entry = t.clone();
+				if let Some(tx_type) = entry.tx_type.cancelled() {
+					entry.tx_type = tx_type;
+				}
+				let mut batch = wallet.batch()?;
+				batch.save_tx_log_entry(entry, &t.parent_key_id)?;
+				batch.commit()?;
+			}
 		}
 	}
 
-	// restore labels, account paths and child derivation indices
-	let label_base = "account";
-	let mut acct_index = 1;
+	restore_acct_paths(wallet, &found_parents)
+}
+
+/// Label the accounts outputs were found in that don't have a label yet,
+/// and move each account's next child index past the highest found
+fn restore_acct_paths<T, C, K>(
+	wallet: &mut T,
+	found_parents: &HashMap<Identifier, u32>,
+) -> Result<(), Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
 	for (path, max_child_index) in found_parents.iter() {
-		// default path already exists
-		if *path != ExtKeychain::derive_key_id(2, 0, 0, 0, 0) {
-			let label = format!("{}_{}", label_base, acct_index);
+		// default path already exists, as do known accounts and any labelled
+		// before an interruption
+		if !wallet.acct_path_iter()?.any(|a| a.path == *path) {
+			let label = keys::unused_acct_label(wallet)?;
 			keys::set_acct_path(wallet, &label, path)?;
-			acct_index += 1;
 		}
 		let mut batch = wallet.batch()?;
-		debug!("Next child for account {} is {}", path, max_child_index + 1);
 		batch.save_child_index(path, max_child_index + 1)?;
+		debug!("Next child for account {} is {}", path, max_child_index + 1);
 		batch.commit()?;
 	}
 	Ok(())
 }
 
-/// Restore a wallet
-pub fn restore<T, C, K>(wallet: &mut T) -> Result<(), Error>
+/// Check / repair wallet contents
+/// assume wallet contents have been freshly updated with contents
+/// of latest block. Range proofs are rewound on up to `threads` threads.
+/// Returns the discrepancies found. With `dry_run`, they're left as they
+/// are, and nothing is written to the wallet, scan progress included.
+pub fn check_repair<T, C, K>(
+	wallet: &mut T,
+	delete_unconfirmed: bool,
+	start_height: Option<u64>,
+	dry_run: bool,
+	threads: usize,
+	status_send: &Option<Sender<ScanStatus>>,
+) -> Result<Vec<CheckDiscrepancy>, Error>
 where
 	T: WalletBackend<C, K>,
 	C: NodeClient,
 	K: Keychain,
 {
-	// Don't proceed if wallet_data has anything in it
-	let is_empty = wallet.iter().next().is_none();
-	if !is_empty {
+	// First, get a definitive list of outputs we own from the chain
+	warn!("Starting wallet check.");
+	let mut status = StatusReporter::new(ScanKind::CheckRepair, status_send);
+	let checkpoint = match dry_run {
+		true => None,
+		false => resume_checkpoint(wallet, ScanKind::CheckRepair)?,
+	};
+	let resumed = checkpoint.is_some();
+	let mut chain_outs = collect_chain_outputs(
+		wallet,
+		&mut status,
+		checkpoint,
+		start_height,
+		!dry_run,
+		threads,
+	)?;
+	if resumed {
+		chain_outs = unspent_scanned_outputs(wallet, chain_outs)?;
+	}
+	warn!(
+		"Identified {} wallet_outputs as belonging to this wallet",
+		chain_outs.len(),
+	);
+	status.phase(ScanPhase::Updating);
+
+	let discrepancies = find_discrepancies(wallet, chain_outs, delete_unconfirmed)?;
+	if dry_run {
+		warn!(
+			"Wallet check found {} discrepancies, none repaired",
+			discrepancies.len()
+		);
+	} else {
+		repair_discrepancies(wallet, &discrepancies)?;
+		clear_scan_checkpoint(wallet)?;
+	}
+	status.phase(ScanPhase::Complete);
+	Ok(discrepancies)
+}
+
+/// Restore a wallet, rewinding range proofs on up to `threads` threads
+pub fn restore<T, C, K>(
+	wallet: &mut T,
+	start_height: Option<u64>,
+	account_labels: &[AcctPathMapping],
+	threads: usize,
+	status_send: &Option<Sender<ScanStatus>>,
+) -> Result<(), Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let mut status = StatusReporter::new(ScanKind::Restore, status_send);
+
+	// Don't proceed if wallet_data has anything in it, unless it was put
+	// there by an interrupted restore
+	let checkpoint = resume_checkpoint(wallet, ScanKind::Restore)?;
+	let resumed = checkpoint.is_some();
+	let is_empty = wallet.iter()?.next().is_none();
+	if !is_empty && !resumed {
 		error!("Not restoring. Please back up and remove existing db directory first.");
+		status.phase(ScanPhase::Skipped);
 		return Ok(());
 	}
 
 	warn!("Starting restore.");
 
-	let result_vec = collect_chain_outputs(wallet)?;
+	let mut result_vec =
+		collect_chain_outputs(wallet, &mut status, checkpoint, start_height, true, threads)?;
+	let restored_outputs: Vec<OutputData> = wallet.iter()?.collect();
+	if resumed {
+		// skip anything already restored before the interruption
+		let restored: HashSet<(Identifier, Option<u64>)> = restored_outputs
+			.iter()
+			.map(|o| (o.key_id.clone(), o.mmr_index))
+			.collect();
+		result_vec = unspent_scanned_outputs(wallet, result_vec)?
+			.into_iter()
+			.filter(|o| !restored.contains(&(o.key_id.clone(), Some(o.mmr_index))))
+			.collect();
+	}
 
 	warn!(
 		"Identified {} wallet_outputs as belonging to this wallet",
 		result_vec.len(),
 	);
+	status.phase(ScanPhase::Updating);
 
 	let mut found_parents: HashMap<Identifier, u32> = HashMap::new();
 	let mut restore_stats = HashMap::new();
+	let heights = result_vec
+		.iter()
+		.map(|o| o.height)
+		.chain(restored_outputs.iter().map(|o| o.height))
+		.collect();
+	let headers = output_block_headers(wallet, heights);
+
+	// the entries of blocks partly restored before an interruption carry on
+	// counting their outputs, and are written if the interruption left them
+	// unwritten
+	for o in restored_outputs.iter().filter(|o| !o.is_coinbase) {
+		if let Some(log_id) = o.tx_log_entry {
+			let s = restore_stats
+				.entry((o.root_key_id.clone(), o.height))
+				.or_insert(RestoredTxStats {
+					log_id,
+					amount_credited: 0,
+					num_outputs: 0,
+				});
+			s.amount_credited += o.value;
+			s.num_outputs += 1;
+		}
+	}
+	if resumed {
+		let logged: HashSet<(Identifier, u32)> = wallet
+			.tx_log_iter()?
+			.map(|t| (t.parent_key_id, t.id))
+			.collect();
+		let mut batch = wallet.batch()?;
+		for ((path, height), s) in restore_stats.iter() {
+			if logged.contains(&(path.clone(), s.log_id)) {
+				continue;
+			}
+			let t = restored_block_tx_log_entry(path, *height, s, &headers);
+			batch.save_tx_log_entry(t, path)?;
+		}
+		batch.commit()?;
+	}
 
-	// Now save what we have
+	// Now save what we have, oldest first so the tx log reads in chain order.
+	// The node can't say which of a block's outputs came from which of its
+	// transactions, so each account gets one entry per block.
+	result_vec.sort_by_key(|o| (o.height, o.mmr_index));
 	for output in result_vec {
 		restore_missing_output(
 			wallet,
 			output,
 			&mut found_parents,
 			&mut Some(&mut restore_stats),
+			&headers,
 		)?;
 	}
 
-	// restore labels, account paths and child derivation indices
-	let label_base = "account";
-	let mut acct_index = 1;
-	for (path, max_child_index) in found_parents.iter() {
-		// default path already exists
-		if *path != ExtKeychain::derive_key_id(2, 0, 0, 0, 0) {
-			let label = format!("{}_{}", label_base, acct_index);
-			keys::set_acct_path(wallet, &label, path)?;
-			acct_index += 1;
-		}
-		// restore tx log entry for non-coinbase outputs
-		if let Some(s) = restore_stats.get(path) {
-			let mut batch = wallet.batch()?;
-			let mut t = TxLogEntry::new(path.clone(), TxLogEntryType::TxReceived, s.log_id);
-			t.confirmed = true;
-			t.amount_credited = s.amount_credited;
-			t.num_outputs = s.num_outputs;
-			t.update_confirmation_ts();
-			batch.save_tx_log_entry(t, &path)?;
-			batch.commit()?;
+	// outputs restored before an interruption count towards the next child
+	// index of their account too
+	for o in restored_outputs.iter() {
+		let max_child_index = found_parents.entry(o.root_key_id.clone()).or_insert(0);
+		*max_child_index = cmp::max(*max_child_index, o.n_child);
+	}
+
+	// restore the labels of known accounts, whether or not anything was found
+	// in them
+	for m in account_labels.iter() {
+		let existing = wallet
+			.acct_path_iter()?
+			.find(|a| a.path == m.path || a.label == m.label);
+		match existing {
+			None => keys::set_acct_path(wallet, &m.label, &m.path)?,
+			Some(ref a) if a.path == m.path && a.label == m.label => {}
+			Some(a) => warn!(
+				"Account {} ({}) not restored, as account {} ({}) already exists",
+				m.label,
+				m.path.to_bip_32_string(),
+				a.label,
+				a.path.to_bip_32_string(),
+			),
 		}
-		let mut batch = wallet.batch()?;
-		batch.save_child_index(path, max_child_index + 1)?;
-		debug!("Next child for account {} is {}", path, max_child_index + 1);
-		batch.commit()?;
 	}
+
+	// restore labels, account paths and child derivation indices
+	restore_acct_paths(wallet, &found_parents)?;
+	clear_scan_checkpoint(wallet)?;
+	status.phase(ScanPhase::Complete);
 	Ok(())
 }
@@ -156,6 +156,7 @@ where
 				lock_height: 0,
 				is_coinbase: false,
 				tx_log_entry: Some(log_id),
+				block_hash: None,
 			})?;
 		}
 		batch.save_tx_log_entry(t.clone(), &parent_key_id)?;
@@ -225,6 +226,7 @@ where
 		lock_height: 0,
 		is_coinbase: false,
 		tx_log_entry: Some(log_id),
+		block_hash: None,
 	})?;
 	batch.save_tx_log_entry(t, &parent_key_id)?;
 	batch.commit()?;
@@ -314,7 +316,7 @@ where
 		max_outputs,
 		selection_strategy_is_use_all,
 		parent_key_id,
-	);
+	)?;
 
 	// sender is responsible for setting the fee on the partial tx
 	// recipient should double check the fee calculation and not blindly trust the
@@ -376,7 +378,7 @@ where
 				max_outputs,
 				selection_strategy_is_use_all,
 				parent_key_id,
-			)
+			)?
 			.1;
 			fee = tx_fee(coins.len(), num_outputs, 1, None);
 			total = coins.iter().map(|c| c.value).sum();
@@ -472,7 +474,7 @@ pub fn select_coins<T: ?Sized, C, K>(
 	max_outputs: usize,
 	select_all: bool,
 	parent_key_id: &Identifier,
-) -> (usize, Vec<OutputData>)
+) -> Result<(usize, Vec<OutputData>), Error>
 //    max_outputs_available, Outputs
 where
 	T: WalletBackend<C, K>,
@@ -481,7 +483,7 @@ where
 {
 	// first find all eligible outputs based on number of confirmations
 	let mut eligible = wallet
-		.iter()
+		.iter()?
 		.filter(|out| {
 			out.root_key_id == *parent_key_id
 				&& out.eligible_to_spend(current_height, minimum_confirmations)
@@ -505,7 +507,7 @@ where
 		for window in eligible.windows(max_outputs) {
 			let windowed_eligibles = window.iter().cloned().collect::<Vec<_>>();
 			if let Some(outputs) = select_from(amount, select_all, windowed_eligibles) {
-				return (max_available, outputs);
+				return Ok((max_available, outputs));
 			}
 		}
 		// Not exist in any window of which total amount >= amount.
@@ -516,11 +518,11 @@ where
 				"Extending maximum number of outputs. {} outputs selected.",
 				outputs.len()
 			);
-			return (max_available, outputs);
+			return Ok((max_available, outputs));
 		}
 	} else {
 		if let Some(outputs) = select_from(amount, select_all, eligible.clone()) {
-			return (max_available, outputs);
+			return Ok((max_available, outputs));
 		}
 	}
 
@@ -528,10 +530,10 @@ where
 	// so return the largest amount we can so we can provide guidance on what is
 	// possible
 	eligible.reverse();
-	(
+	Ok((
 		max_available,
 		eligible.iter().take(max_outputs).cloned().collect(),
-	)
+	))
 }
 
 fn select_from(amount: u64, select_all: bool, outputs: Vec<OutputData>) -> Option<Vec<OutputData>> {
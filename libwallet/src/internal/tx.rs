@@ -169,6 +169,8 @@ where
 		)?;
 	}
 
+	// so garbage collection can tell how long it's been kept
+	context.created_height = Some(slate.height);
 	Ok(context)
 }
 
@@ -211,6 +213,7 @@ where
 		)?;
 	}
 
+	context.created_height = Some(slate.height);
 	Ok(context)
 }
 
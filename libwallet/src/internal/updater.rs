@@ -26,9 +26,10 @@ use crate::grin_core::libtx::reward;
 use crate::grin_keychain::{Identifier, Keychain};
 use crate::grin_util as util;
 use crate::grin_util::secp::pedersen;
-use crate::internal::keys;
+use crate::internal::{gc, keys, reorg};
 use crate::types::{
-	NodeClient, OutputData, OutputStatus, TxLogEntry, TxLogEntryType, WalletBackend, WalletInfo,
+	BlockHeaderInfo, NodeClient, OutputData, OutputStatus, TxLogEntry, TxLogEntryType,
+	WalletBackend, WalletInfo,
 };
 use crate::{BlockFees, CbData, OutputCommitMapping};
 
@@ -46,7 +47,7 @@ where
 {
 	// just read the wallet here, no need for a write lock
 	let mut outputs = wallet
-		.iter()
+		.iter()?
 		.filter(|out| show_spent || out.status != OutputStatus::Spent)
 		.collect::<Vec<_>>();
 
@@ -97,7 +98,7 @@ where
 	K: Keychain,
 {
 	let mut txs: Vec<TxLogEntry> = wallet
-		.tx_log_iter()
+		.tx_log_iter()?
 		.filter(|tx_entry| {
 			let f_pk = match parent_key_id {
 				Some(k) => tx_entry.parent_key_id == *k,
@@ -127,7 +128,8 @@ where
 }
 
 /// Refreshes the outputs in a wallet with the latest information
-/// from a node
+/// from a node, then collects garbage if the wallet's retention policy
+/// asks for it
 pub fn refresh_outputs<T: ?Sized, C, K>(
 	wallet: &mut T,
 	parent_key_id: &Identifier,
@@ -140,9 +142,41 @@ where
 {
 	let height = wallet.w2n_client().get_chain_height()?;
 	refresh_output_state(wallet, height, parent_key_id, update_all)?;
+	gc_after_refresh(wallet, height);
 	Ok(())
 }
 
+/// Refreshes the outputs of every account in a wallet as of the given chain
+/// height, then collects garbage if the wallet's retention policy asks for
+/// it
+pub fn refresh_all_accounts<T: ?Sized, C, K>(wallet: &mut T, height: u64) -> Result<(), Error>
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let parent_key_ids: Vec<Identifier> = wallet.acct_path_iter()?.map(|a| a.path).collect();
+	for parent_key_id in parent_key_ids.iter() {
+		refresh_output_state(wallet, height, parent_key_id, false)?;
+	}
+	gc_after_refresh(wallet, height);
+	Ok(())
+}
+
+fn gc_after_refresh<T: ?Sized, C, K>(wallet: &mut T, height: u64)
+where
+	T: WalletBackend<C, K>,
+	C: NodeClient,
+	K: Keychain,
+{
+	let policy = wallet.retention_policy();
+	if policy.on_refresh {
+		if let Err(e) = gc::collect_garbage(wallet, &policy, height) {
+			warn!("Garbage collection after refresh failed: {}", e);
+		}
+	}
+}
+
 /// build a local map of wallet outputs keyed by commit
 /// and a list of outputs we want to query the node for
 pub fn map_wallet_outputs<T: ?Sized, C, K>(
@@ -159,7 +193,7 @@ where
 		HashMap::new();
 	let keychain = wallet.keychain().clone();
 	let unspents: Vec<OutputData> = wallet
-		.iter()
+		.iter()?
 		.filter(|x| x.root_key_id == *parent_key_id && x.status != OutputStatus::Spent)
 		.collect();
 
@@ -229,11 +263,13 @@ where
 	Ok(())
 }
 
-/// Apply refreshed API output data to the wallet
+/// Apply refreshed API output data to the wallet, recording the blocks
+/// outputs were confirmed in and the block at `height` from `blocks`
 pub fn apply_api_outputs<T: ?Sized, C, K>(
 	wallet: &mut T,
 	wallet_outputs: &HashMap<pedersen::Commitment, (Identifier, Option<u64>)>,
 	api_outputs: &HashMap<pedersen::Commitment, (String, u64, u64)>,
+	blocks: &HashMap<u64, BlockHeaderInfo>,
 	height: u64,
 	parent_key_id: &Identifier,
 ) -> Result<(), Error>
@@ -262,8 +298,23 @@ where
 			if let Ok(mut output) = batch.get(id, mmr_index) {
 				match api_outputs.get(&commit) {
 					Some(o) => {
-						// if this is a coinbase tx being confirmed, it's recordable in tx log
-						if output.is_coinbase && output.status == OutputStatus::Unconfirmed {
+						// mark the transaction in which this output is involved as confirmed
+						// note that one involved input/output confirmation SHOULD be enough
+						// to reliably confirm the tx
+						let tx = match output.status {
+							OutputStatus::Unconfirmed => batch.tx_log_iter()?.find(|t| {
+								Some(t.id) == output.tx_log_entry
+									&& t.parent_key_id == *parent_key_id
+							}),
+							_ => None,
+						};
+						if let Some(mut t) = tx {
+							t.update_confirmation_ts();
+							t.confirmed = true;
+							t.confirmation_height = Some(o.1);
+							batch.save_tx_log_entry(t, &parent_key_id)?;
+						} else if output.is_coinbase && output.status == OutputStatus::Unconfirmed {
+							// if this is a coinbase tx being confirmed, it's recordable in tx log
 							let log_id = batch.next_tx_log_id(parent_key_id)?;
 							let mut t = TxLogEntry::new(
 								parent_key_id.clone(),
@@ -275,22 +326,12 @@ where
 							t.amount_debited = 0;
 							t.num_outputs = 1;
 							t.update_confirmation_ts();
+							t.confirmation_height = Some(o.1);
 							output.tx_log_entry = Some(log_id);
 							batch.save_tx_log_entry(t, &parent_key_id)?;
 						}
-						// also mark the transaction in which this output is involved as confirmed
-						// note that one involved input/output confirmation SHOULD be enough
-						// to reliably confirm the tx
-						if !output.is_coinbase && output.status == OutputStatus::Unconfirmed {
-							let tx = batch.tx_log_iter().find(|t| {
-								Some(t.id) == output.tx_log_entry
-									&& t.parent_key_id == *parent_key_id
-							});
-							if let Some(mut t) = tx {
-								t.update_confirmation_ts();
-								t.confirmed = true;
-								batch.save_tx_log_entry(t, &parent_key_id)?;
-							}
+						if output.height != o.1 || output.block_hash.is_none() {
+							output.block_hash = blocks.get(&o.1).map(|b| b.hash.clone());
 						}
 						output.height = o.1;
 						output.mark_unspent();
@@ -302,6 +343,9 @@ where
 		}
 		{
 			batch.save_last_confirmed_height(parent_key_id, height)?;
+			if let Some(block) = blocks.get(&height) {
+				batch.save_last_confirmed_block(parent_key_id, block)?;
+			}
 		}
 		batch.commit()?;
 	}
@@ -323,16 +367,39 @@ where
 {
 	debug!("Refreshing wallet outputs");
 
+	// undo confirmations from blocks a reorg orphaned, then check all
+	// outputs, as those confirmed in them may be found elsewhere
+	let reorged = reorg::rollback_orphaned(wallet, height, parent_key_id)?;
+
 	// build a local map of wallet outputs keyed by commit
 	// and a list of outputs we want to query the node for
-	let wallet_outputs = map_wallet_outputs(wallet, parent_key_id, update_all)?;
+	let wallet_outputs = map_wallet_outputs(wallet, parent_key_id, update_all || reorged)?;
 
 	let wallet_output_keys = wallet_outputs.keys().map(|commit| commit.clone()).collect();
 
 	let api_outputs = wallet
 		.w2n_client()
 		.get_outputs_from_node(wallet_output_keys)?;
-	apply_api_outputs(wallet, &wallet_outputs, &api_outputs, height, parent_key_id)?;
+
+	// blocks of outputs confirmed or moved since they were last seen, and
+	// the block at the height we're refreshing to
+	let mut heights = vec![height];
+	for (commit, (id, mmr_index)) in wallet_outputs.iter() {
+		if let (Some(o), Ok(output)) = (api_outputs.get(commit), wallet.get(id, mmr_index)) {
+			if output.height != o.1 || output.block_hash.is_none() {
+				heights.push(o.1);
+			}
+		}
+	}
+	let blocks = reorg::node_blocks(wallet, heights)?;
+	apply_api_outputs(
+		wallet,
+		&wallet_outputs,
+		&api_outputs,
+		&blocks,
+		height,
+		parent_key_id,
+	)?;
 	clean_old_unconfirmed(wallet, height)?;
 	Ok(())
 }
@@ -347,7 +414,7 @@ where
 		return Ok(());
 	}
 	let mut ids_to_del = vec![];
-	for out in wallet.iter() {
+	for out in wallet.iter()? {
 		if out.status == OutputStatus::Unconfirmed
 			&& out.height > 0
 			&& out.height < height - 50
@@ -378,7 +445,7 @@ where
 {
 	let current_height = wallet.last_confirmed_height()?;
 	let outputs = wallet
-		.iter()
+		.iter()?
 		.filter(|out| out.root_key_id == *parent_key_id);
 
 	let mut unspent_total = 0;
@@ -490,6 +557,7 @@ where
 			lock_height: lock_height,
 			is_coinbase: true,
 			tx_log_entry: None,
+			block_hash: None,
 		})?;
 		batch.commit()?;
 	}
@@ -55,10 +55,13 @@ pub use crate::slate::{ParticipantData, ParticipantMessageData, Slate};
 pub use crate::slate_versions::{SlateVersion, VersionedSlate};
 pub use api_impl::types::{
 	BlockFees, CbData, InitTxArgs, InitTxSendArgs, IssueInvoiceTxArgs, NodeHeightResult,
-	OutputCommitMapping, SendTXArgs, VersionInfo,
+	OutputCommitMapping, RefreshSchedule, RefreshStatus, SendTXArgs, VersionInfo,
 };
-pub use internal::restore::{check_repair, restore};
+pub use internal::restore::{check_repair, identify_outputs, restore};
 pub use types::{
-	AcctPathMapping, BlockIdentifier, Context, NodeClient, OutputData, OutputStatus, TxLogEntry,
-	TxLogEntryType, TxWrapper, WalletBackend, WalletInfo, WalletInst, WalletOutputBatch,
+	AcctPathMapping, ArchiveSummary, BlockHeaderInfo, BlockIdentifier, CheckDiscrepancy, Context,
+	ContextRemovalReason, GcReport, IntegrityIssue, IntegrityReport, NodeClient, OutputData,
+	OutputStatus, RemovedContext, RetentionPolicy, ScanCheckpoint, ScanKind, ScanPhase, ScanStatus,
+	ScannedOutput, StoredTxRetention, TxLogEntry, TxLogEntryType, TxWrapper, UnknownPathOutput,
+	WalletBackend, WalletInfo, WalletInst, WalletOutputBatch,
 };
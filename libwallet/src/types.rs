@@ -30,8 +30,11 @@ use serde;
 use serde_json;
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::mpsc::Sender;
 use uuid::Uuid;
 
+pub use grin_wallet_config::StoredTxRetention;
+
 /// Combined trait to allow dynamic wallet dispatch
 pub trait WalletInst<C, K>: WalletBackend<C, K> + Send + Sync + 'static
 where
@@ -85,8 +88,9 @@ where
 	/// return the parent path
 	fn parent_key_id(&mut self) -> Identifier;
 
-	/// Iterate over all output data stored by the backend
-	fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = OutputData> + 'a>;
+	/// Iterate over all output data stored by the backend. Fails if any of
+	/// it can't be read.
+	fn iter<'a>(&'a self) -> Result<Box<dyn Iterator<Item = OutputData> + 'a>, Error>;
 
 	/// Get output data by id
 	fn get(&self, id: &Identifier, mmr_index: &Option<u64>) -> Result<OutputData, Error>;
@@ -101,11 +105,21 @@ where
 		participant_id: usize,
 	) -> Result<Context, Error>;
 
-	/// Iterate over all output data stored by the backend
-	fn tx_log_iter<'a>(&'a self) -> Box<dyn Iterator<Item = TxLogEntry> + 'a>;
+	/// Iterate over the slate and participant ids of all stored private
+	/// contexts. Fails if the context store can't be read.
+	fn private_context_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = (Vec<u8>, usize)> + 'a>, Error>;
+
+	/// Iterate over all tx log entries stored by the backend. Fails if any of
+	/// them can't be read.
+	fn tx_log_iter<'a>(&'a self) -> Result<Box<dyn Iterator<Item = TxLogEntry> + 'a>, Error>;
 
-	/// Iterate over all stored account paths
-	fn acct_path_iter<'a>(&'a self) -> Box<dyn Iterator<Item = AcctPathMapping> + 'a>;
+	/// Iterate over all stored account paths. Fails if any of them can't be
+	/// read.
+	fn acct_path_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = AcctPathMapping> + 'a>, Error>;
 
 	/// Gets an account path for a given label
 	fn get_acct_path(&self, label: String) -> Result<Option<AcctPathMapping>, Error>;
@@ -113,9 +127,18 @@ where
 	/// Stores a transaction
 	fn store_tx(&self, uuid: &str, tx: &Transaction) -> Result<(), Error>;
 
-	/// Retrieves a stored transaction from a TxLogEntry
+	/// Retrieves a stored transaction from a TxLogEntry, or None if the
+	/// entry has none or its file is missing
 	fn get_stored_tx(&self, entry: &TxLogEntry) -> Result<Option<Transaction>, Error>;
 
+	/// Archives or deletes the stored transaction of a TxLogEntry. The entry
+	/// itself isn't changed.
+	fn remove_stored_tx(&self, entry: &TxLogEntry, archive: bool) -> Result<(), Error>;
+
+	/// Which stale data garbage collection removes, as configured for this
+	/// wallet
+	fn retention_policy(&self) -> RetentionPolicy;
+
 	/// Create a new write batch to update or remove output data
 	fn batch<'a>(&'a mut self) -> Result<Box<dyn WalletOutputBatch<K> + 'a>, Error>;
 
@@ -125,11 +148,62 @@ where
 	/// last verified height of outputs directly descending from the given parent key
 	fn last_confirmed_height<'a>(&mut self) -> Result<u64, Error>;
 
-	/// Attempt to restore the contents of a wallet from seed
-	fn restore(&mut self) -> Result<(), Error>;
+	/// Block at the last confirmed height of outputs descending from the given
+	/// parent key, if it was recorded
+	fn last_confirmed_block(
+		&self,
+		parent_key_id: &Identifier,
+	) -> Result<Option<BlockHeaderInfo>, Error>;
+
+	/// Child index of the current parent key that `next_child` will use next,
+	/// without allocating it
+	fn current_child_index<'a>(&mut self) -> Result<u32, Error>;
+
+	/// Progress of an interrupted restore or check, if any
+	fn scan_checkpoint(&self) -> Result<Option<ScanCheckpoint>, Error>;
+
+	/// Iterate over the outputs found so far by an interrupted restore or
+	/// check, in PMMR index order. Fails if any of them can't be read.
+	fn scanned_output_iter<'a>(
+		&'a self,
+	) -> Result<Box<dyn Iterator<Item = ScannedOutput> + 'a>, Error>;
+
+	/// Chain height the wallet was created at, if known. Nothing before it
+	/// needs scanning.
+	fn birthday_height(&self) -> Result<Option<u64>, Error>;
+
+	/// Attempt to restore the contents of a wallet from seed, scanning the
+	/// chain from the given height, or from the wallet's birthday if none.
+	/// Accounts found at the paths in `account_labels` are given those
+	/// labels. Progress is published to `status_send`, if given.
+	fn restore(
+		&mut self,
+		start_height: Option<u64>,
+		account_labels: &[AcctPathMapping],
+		status_send: &Option<Sender<ScanStatus>>,
+	) -> Result<(), Error>;
 
-	/// Attempt to check and fix wallet state
-	fn check_repair(&mut self, delete_unconfirmed: bool) -> Result<(), Error>;
+	/// Attempt to check and fix wallet state, scanning the chain from the
+	/// given height, or from the wallet's birthday if none. Returns the
+	/// discrepancies found, which are only repaired if `dry_run` isn't set.
+	/// Progress is published to `status_send`, if given.
+	fn check_repair(
+		&mut self,
+		delete_unconfirmed: bool,
+		start_height: Option<u64>,
+		dry_run: bool,
+		status_send: &Option<Sender<ScanStatus>>,
+	) -> Result<Vec<CheckDiscrepancy>, Error>;
+
+	/// Re-encrypt the wallet seed under a new password
+	fn change_password(&mut self, old_password: &str, new_password: &str) -> Result<(), Error>;
+
+	/// Write everything stored by the wallet to an encrypted archive file
+	fn export_archive(&mut self, path: &str) -> Result<ArchiveSummary, Error>;
+
+	/// Import an archive written by `export_archive`, from a wallet with the
+	/// same seed, into this (empty) wallet
+	fn import_archive(&mut self, path: &str) -> Result<ArchiveSummary, Error>;
 }
 
 /// Batch trait to update the output data backend atomically. Trying to use a
@@ -150,8 +224,9 @@ where
 	/// Gets output data by id
 	fn get(&self, id: &Identifier, mmr_index: &Option<u64>) -> Result<OutputData, Error>;
 
-	/// Iterate over all output data stored by the backend
-	fn iter(&self) -> Box<dyn Iterator<Item = OutputData>>;
+	/// Iterate over all output data stored by the backend. Fails if any of
+	/// it can't be read.
+	fn iter(&self) -> Result<Box<dyn Iterator<Item = OutputData>>, Error>;
 
 	/// Delete data about an output from the backend
 	fn delete(&mut self, id: &Identifier, mmr_index: &Option<u64>) -> Result<(), Error>;
@@ -166,11 +241,22 @@ where
 		height: u64,
 	) -> Result<(), Error>;
 
+	/// Save the block at the last confirmed height for a given parent
+	fn save_last_confirmed_block(
+		&mut self,
+		parent_key_id: &Identifier,
+		block: &BlockHeaderInfo,
+	) -> Result<(), Error>;
+
 	/// get next tx log entry for the parent
 	fn next_tx_log_id(&mut self, parent_key_id: &Identifier) -> Result<u32, Error>;
 
-	/// Iterate over tx log data stored by the backend
-	fn tx_log_iter(&self) -> Box<dyn Iterator<Item = TxLogEntry>>;
+	/// Set the id the next tx log entry of the parent is given
+	fn save_next_tx_log_id(&mut self, parent_key_id: &Identifier, id: u32) -> Result<(), Error>;
+
+	/// Iterate over tx log data stored by the backend. Fails if any of it
+	/// can't be read.
+	fn tx_log_iter(&self) -> Result<Box<dyn Iterator<Item = TxLogEntry>>, Error>;
 
 	/// save a tx log entry
 	fn save_tx_log_entry(&mut self, t: TxLogEntry, parent_id: &Identifier) -> Result<(), Error>;
@@ -178,8 +264,9 @@ where
 	/// save an account label -> path mapping
 	fn save_acct_path(&mut self, mapping: AcctPathMapping) -> Result<(), Error>;
 
-	/// Iterate over account names stored in backend
-	fn acct_path_iter(&self) -> Box<dyn Iterator<Item = AcctPathMapping>>;
+	/// Iterate over account names stored in backend. Fails if any of them
+	/// can't be read.
+	fn acct_path_iter(&self) -> Result<Box<dyn Iterator<Item = AcctPathMapping>>, Error>;
 
 	/// Save an output as locked in the backend
 	fn lock_output(&mut self, out: &mut OutputData) -> Result<(), Error>;
@@ -199,6 +286,15 @@ where
 		participant_id: usize,
 	) -> Result<(), Error>;
 
+	/// Save the progress of a restore or check
+	fn save_scan_checkpoint(&mut self, checkpoint: &ScanCheckpoint) -> Result<(), Error>;
+
+	/// Save an output found by a restore or check
+	fn save_scanned_output(&mut self, output: &ScannedOutput) -> Result<(), Error>;
+
+	/// Delete the scan checkpoint and all outputs found so far
+	fn clear_scan_checkpoint(&mut self) -> Result<(), Error>;
+
 	/// Write the wallet data to backend file
 	fn commit(&self) -> Result<(), Error>;
 }
@@ -218,6 +314,10 @@ pub trait NodeClient: Sync + Send + Clone {
 	/// Change the API secret
 	fn set_node_api_secret(&mut self, node_api_secret: Option<String>);
 
+	/// Return the address of the SOCKS5 proxy the wallet's outbound HTTP
+	/// goes through, if any, so slates sent to other wallets go the same way
+	fn socks_proxy(&self) -> Option<String>;
+
 	/// Posts a transaction to a grin node
 	fn post_tx(&self, tx: &TxWrapper, fluff: bool) -> Result<(), Error>;
 
@@ -248,6 +348,21 @@ pub trait NodeClient: Sync + Send + Clone {
 		),
 		Error,
 	>;
+
+	/// Get the range of output PMMR indices of the outputs created in the
+	/// blocks from `start_height` to `end_height` (the chain tip if none).
+	/// Returns
+	/// (first output index at or after start height, last output index at or
+	/// before end height)
+	fn height_range_to_pmmr_indices(
+		&self,
+		start_height: u64,
+		end_height: Option<u64>,
+	) -> Result<(u64, u64), Error>;
+
+	/// Get the hash and timestamp of the blocks at the given heights, for
+	/// those of the heights the node's chain has reached
+	fn get_block_headers(&self, heights: Vec<u64>) -> Result<Vec<BlockHeaderInfo>, Error>;
 }
 
 /// Information about an output that's being tracked by the wallet. Must be
@@ -283,6 +398,10 @@ pub struct OutputData {
 	pub is_coinbase: bool,
 	/// Optional corresponding internal entry in tx entry log
 	pub tx_log_entry: Option<u32>,
+	/// Block the output was confirmed in, if known. Checked against the
+	/// node's chain to find confirmations undone by a reorg.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub block_hash: Option<BlockIdentifier>,
 }
 
 impl ser::Writeable for OutputData {
@@ -406,6 +525,10 @@ pub struct Context {
 	pub fee: u64,
 	/// keep track of the participant id
 	pub participant_id: usize,
+	/// Chain height when the context was created, if known. Contexts
+	/// saved by older wallets don't have it.
+	#[serde(default)]
+	pub created_height: Option<u64>,
 }
 
 impl Context {
@@ -429,6 +552,7 @@ impl Context {
 			output_ids: vec![],
 			fee: 0,
 			participant_id: participant_id,
+			created_height: None,
 		}
 	}
 }
@@ -539,6 +663,31 @@ impl<'de> serde::de::Visitor<'de> for BlockIdentifierVisitor {
 	}
 }
 
+/// A block on the node's chain, as much of its header as the wallet needs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockHeaderInfo {
+	/// Height of the block
+	#[serde(with = "secp_ser::string_or_u64")]
+	pub height: u64,
+	/// Hash of the block
+	pub hash: BlockIdentifier,
+	/// Time the block was mined
+	pub timestamp: DateTime<Utc>,
+}
+
+impl ser::Writeable for BlockHeaderInfo {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&serde_json::to_vec(self).map_err(|_| ser::Error::CorruptedData)?)
+	}
+}
+
+impl ser::Readable for BlockHeaderInfo {
+	fn read(reader: &mut dyn ser::Reader) -> Result<BlockHeaderInfo, ser::Error> {
+		let data = reader.read_bytes_len_prefix()?;
+		serde_json::from_slice(&data[..]).map_err(|_| ser::Error::CorruptedData)
+	}
+}
+
 /// a contained wallet info struct, so automated tests can parse wallet info
 /// can add more fields here over time as needed
 #[derive(Serialize, Eq, PartialEq, Deserialize, Debug, Clone)]
@@ -569,6 +718,371 @@ pub struct WalletInfo {
 	pub amount_locked: u64,
 }
 
+/// Which operation a chain scan was started by
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanKind {
+	/// Restore of an empty wallet from seed
+	Restore,
+	/// Check and repair of an existing wallet
+	CheckRepair,
+}
+
+/// Stage a running restore or check has reached
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanPhase {
+	/// Rewinding the range proofs of chain outputs to find the wallet's
+	Scanning,
+	/// Bringing the wallet's outputs and transactions in line with those
+	/// found on chain
+	Updating,
+	/// Finished successfully
+	Complete,
+	/// Stopped by an error
+	Failed,
+	/// Not run, as a restore into a wallet that already holds outputs
+	Skipped,
+}
+
+impl fmt::Display for ScanPhase {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self {
+			ScanPhase::Scanning => write!(f, "Scanning"),
+			ScanPhase::Updating => write!(f, "Updating"),
+			ScanPhase::Complete => write!(f, "Complete"),
+			ScanPhase::Failed => write!(f, "Failed"),
+			ScanPhase::Skipped => write!(f, "Skipped"),
+		}
+	}
+}
+
+/// Status of a restore or check, published as it progresses
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScanStatus {
+	/// The operation running the scan
+	pub kind: ScanKind,
+	/// Stage the scan has reached
+	pub phase: ScanPhase,
+	/// Percentage of the output PMMR range being scanned that has been
+	/// scanned so far
+	pub percent_complete: u8,
+	/// Last PMMR index scanned
+	#[serde(with = "secp_ser::string_or_u64")]
+	pub last_pmmr_index: u64,
+	/// Highest PMMR index reported by the node
+	#[serde(with = "secp_ser::string_or_u64")]
+	pub highest_pmmr_index: u64,
+	/// Number of outputs found belonging to the wallet so far
+	pub outputs_found: usize,
+	/// Outputs belonging to the wallet that couldn't be restored, as their
+	/// derivation path couldn't be recovered
+	#[serde(default)]
+	pub unknown_path_outputs: Vec<UnknownPathOutput>,
+}
+
+impl ScanStatus {
+	/// Status of a scan of the given kind that's only just started
+	pub fn new(kind: ScanKind) -> ScanStatus {
+		ScanStatus {
+			kind,
+			phase: ScanPhase::Scanning,
+			percent_complete: 0,
+			last_pmmr_index: 0,
+			highest_pmmr_index: 0,
+			outputs_found: 0,
+			unknown_path_outputs: vec![],
+		}
+	}
+}
+
+impl fmt::Display for ScanStatus {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{}: {}% of outputs scanned, up to index {} of {}, {} found",
+			self.phase,
+			self.percent_complete,
+			self.last_pmmr_index,
+			self.highest_pmmr_index,
+			self.outputs_found,
+		)
+	}
+}
+
+/// An output that rewound with the wallet's keychain, but whose rewind
+/// message didn't give its derivation path at any of the path depths tried
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UnknownPathOutput {
+	/// The output commitment
+	#[serde(
+		serialize_with = "secp_ser::as_hex",
+		deserialize_with = "secp_ser::commitment_from_hex"
+	)]
+	pub commit: pedersen::Commitment,
+	/// Hex of the rewind message the path should have been recovered from
+	pub message: String,
+	/// Position of the output in the output PMMR
+	#[serde(with = "secp_ser::string_or_u64")]
+	pub mmr_index: u64,
+	/// Value of the output
+	#[serde(with = "secp_ser::string_or_u64")]
+	pub value: u64,
+	/// Height of the block the output was confirmed in
+	#[serde(with = "secp_ser::string_or_u64")]
+	pub height: u64,
+}
+
+/// A difference between the wallet's contents and the chain found by a
+/// check, and what repairing it does
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum CheckDiscrepancy {
+	/// An output on chain the wallet doesn't have, which is restored
+	MissingOutput(ScannedOutput),
+	/// A wallet output whose status doesn't match the chain, which is given
+	/// the repaired status, or deleted if none
+	WrongStatus {
+		/// The output as the wallet has it
+		output: OutputData,
+		/// Status the output is repaired to
+		repaired_status: Option<OutputStatus>,
+	},
+	/// A wallet output with a different value from the output on chain with
+	/// the same key id and position, which is replaced by the output on chain
+	WrongValue {
+		/// The output as the wallet has it
+		output: OutputData,
+		/// The output as found on chain
+		chain_output: ScannedOutput,
+	},
+	/// A tx log entry involving a repaired output, which is cancelled
+	CancelledTx(TxLogEntry),
+}
+
+/// An inconsistency in the wallet's stored data found by an integrity
+/// check. Only those that can be fixed without losing anything are fixed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum IntegrityIssue {
+	/// An output linked to a tx log entry that doesn't exist, which is
+	/// unlinked
+	MissingTxLogEntry(OutputData),
+	/// A tx log entry whose stored transaction is missing, which is unlinked
+	/// from it
+	MissingStoredTx(TxLogEntry),
+	/// A tx log entry whose stored transaction can't be read. The file may
+	/// only be unreadable for now, so it isn't fixed
+	UnreadableStoredTx {
+		/// The tx log entry
+		tx: TxLogEntry,
+		/// Why the stored transaction couldn't be read
+		error: String,
+	},
+	/// A private context kept for a transaction that has since been
+	/// cancelled, or confirmed at least the retention policy's minimum
+	/// confirmation depth ago, which is deleted
+	StaleContext {
+		/// Slate the context belongs to
+		slate_id: Uuid,
+		/// Participant the context belongs to
+		participant_id: usize,
+		/// The transaction's tx log entry
+		tx: TxLogEntry,
+	},
+	/// A private context for a slate with no tx log entry. It may belong to
+	/// a transaction that's still being built, so isn't fixed
+	OrphanedContext {
+		/// Slate the context belongs to
+		slate_id: Uuid,
+		/// Participant the context belongs to
+		participant_id: usize,
+	},
+	/// An account whose next child index has already been used by one of
+	/// its outputs, which is moved past the highest one used
+	ChildIndexBehind {
+		/// The account's parent key
+		parent_key_id: Identifier,
+		/// Child index the account will derive its next key at
+		next_child_index: u32,
+		/// Highest child index used by the account's outputs
+		highest_used: u32,
+	},
+	/// An account holding outputs or transactions without a label, which is
+	/// given one
+	UnlabelledAccount(Identifier),
+	/// An account with more than one label. Either may be in use, so it
+	/// isn't fixed
+	DuplicateAccountPath {
+		/// The account's parent key
+		parent_key_id: Identifier,
+		/// All labels mapped to it
+		labels: Vec<String>,
+	},
+	/// Outputs found by a restore or check, kept without a checkpoint to
+	/// resume it from, which are deleted
+	StaleScannedOutputs(usize),
+}
+
+impl IntegrityIssue {
+	/// Whether the issue is fixed when fixing is asked for
+	pub fn is_fixable(&self) -> bool {
+		match self {
+			IntegrityIssue::OrphanedContext { .. } => false,
+			IntegrityIssue::DuplicateAccountPath { .. } => false,
+			IntegrityIssue::UnreadableStoredTx { .. } => false,
+			_ => true,
+		}
+	}
+}
+
+/// Outcome of an integrity check of the wallet's stored data
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IntegrityReport {
+	/// Number of outputs checked
+	pub outputs: usize,
+	/// Number of tx log entries checked
+	pub tx_log_entries: usize,
+	/// Number of private contexts checked
+	pub private_contexts: usize,
+	/// Number of account mappings checked
+	pub accounts: usize,
+	/// Inconsistencies found
+	pub issues: Vec<IntegrityIssue>,
+	/// Number of the issues that were fixed
+	pub fixed: usize,
+}
+
+/// Which stale data garbage collection removes, and when
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+	/// Age in blocks after which the private context of an unfinished
+	/// transaction is deleted, or 0 to keep them. Contexts of cancelled
+	/// transactions, and of those confirmed at least
+	/// `min_confirmation_depth` blocks ago, are always deleted.
+	pub context_max_age: u64,
+	/// What happens to the stored transactions of confirmed transactions
+	pub stored_txs: StoredTxRetention,
+	/// Number of blocks a transaction must have been confirmed for before
+	/// its context and stored transaction are removed, so that neither is
+	/// lost to a reorg that undoes it
+	pub min_confirmation_depth: u64,
+	/// Whether garbage is collected after each refresh from the node
+	pub on_refresh: bool,
+}
+
+/// Why garbage collection deleted a private context
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ContextRemovalReason {
+	/// The transaction was cancelled
+	Cancelled,
+	/// The transaction was confirmed
+	Confirmed,
+	/// The transaction was never finished, and the context outlived the
+	/// retention period
+	Expired,
+}
+
+/// A private context deleted by garbage collection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RemovedContext {
+	/// Slate the context belonged to
+	pub slate_id: Uuid,
+	/// Participant the context belonged to
+	pub participant_id: usize,
+	/// Why it was deleted
+	pub reason: ContextRemovalReason,
+}
+
+/// What a garbage collection removed
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GcReport {
+	/// Private contexts deleted
+	pub contexts: Vec<RemovedContext>,
+	/// Stored transactions archived, by file name
+	pub archived_txs: Vec<String>,
+	/// Stored transactions deleted, by file name
+	pub pruned_txs: Vec<String>,
+}
+
+/// Progress of a restore or check through the output PMMR, saved after each
+/// batch of outputs is scanned so that an interrupted scan can be resumed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScanCheckpoint {
+	/// The operation that started the scan
+	pub kind: ScanKind,
+	/// PMMR index the scan started from, before any interruption
+	#[serde(default)]
+	pub start_pmmr_index: u64,
+	/// PMMR index the scan resumes from
+	pub next_pmmr_index: u64,
+	/// Highest PMMR index reported by the node as of the last batch
+	pub highest_pmmr_index: u64,
+}
+
+impl ser::Writeable for ScanCheckpoint {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&serde_json::to_vec(self).map_err(|_| ser::Error::CorruptedData)?)
+	}
+}
+
+impl ser::Readable for ScanCheckpoint {
+	fn read(reader: &mut dyn ser::Reader) -> Result<ScanCheckpoint, ser::Error> {
+		let data = reader.read_bytes_len_prefix()?;
+		serde_json::from_slice(&data[..]).map_err(|_| ser::Error::CorruptedData)
+	}
+}
+
+/// An unspent output on chain identified as belonging to the wallet by a
+/// restore or check
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScannedOutput {
+	/// The output commitment
+	#[serde(
+		serialize_with = "secp_ser::as_hex",
+		deserialize_with = "secp_ser::commitment_from_hex"
+	)]
+	pub commit: pedersen::Commitment,
+	/// Key id the output was built from, recovered from its range proof
+	pub key_id: Identifier,
+	/// Child index of the key id
+	pub n_child: u32,
+	/// Position of the output in the output PMMR
+	pub mmr_index: u64,
+	/// Value of the output
+	pub value: u64,
+	/// Height of the block the output was confirmed in
+	pub height: u64,
+	/// Height from which the output can be spent
+	pub lock_height: u64,
+	/// Whether the output is a coinbase output
+	pub is_coinbase: bool,
+}
+
+impl ser::Writeable for ScannedOutput {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&serde_json::to_vec(self).map_err(|_| ser::Error::CorruptedData)?)
+	}
+}
+
+impl ser::Readable for ScannedOutput {
+	fn read(reader: &mut dyn ser::Reader) -> Result<ScannedOutput, ser::Error> {
+		let data = reader.read_bytes_len_prefix()?;
+		serde_json::from_slice(&data[..]).map_err(|_| ser::Error::CorruptedData)
+	}
+}
+
+/// Number of records of each kind written to or read from a wallet archive
+#[derive(Serialize, Eq, PartialEq, Deserialize, Debug, Clone, Default)]
+pub struct ArchiveSummary {
+	/// Account label to path mappings
+	pub accounts: usize,
+	/// Outputs
+	pub outputs: usize,
+	/// Transaction log entries
+	pub tx_log_entries: usize,
+	/// Private contexts of transactions in progress
+	pub private_contexts: usize,
+	/// Stored transactions
+	pub stored_txs: usize,
+}
+
 /// Types of transactions that can be contained within a TXLog entry
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub enum TxLogEntryType {
@@ -584,6 +1098,18 @@ pub enum TxLogEntryType {
 	TxSentCancelled,
 }
 
+impl TxLogEntryType {
+	/// The type of a cancelled entry of this type, if entries of this type
+	/// can be cancelled
+	pub fn cancelled(&self) -> Option<TxLogEntryType> {
+		match *self {
+			TxLogEntryType::TxSent => Some(TxLogEntryType::TxSentCancelled),
+			TxLogEntryType::TxReceived => Some(TxLogEntryType::TxReceivedCancelled),
+			_ => None,
+		}
+	}
+}
+
 impl fmt::Display for TxLogEntryType {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		match *self {
@@ -599,7 +1125,7 @@ impl fmt::Display for TxLogEntryType {
 /// Optional transaction information, recorded when an event happens
 /// to add or remove funds from a wallet. One Transaction log entry
 /// maps to one or many outputs
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TxLogEntry {
 	/// BIP32 account path used for creating this tx
 	pub parent_key_id: Identifier,
@@ -636,6 +1162,13 @@ pub struct TxLogEntry {
 	pub messages: Option<ParticipantMessages>,
 	/// Location of the store transaction, (reference or resending)
 	pub stored_tx: Option<String>,
+	/// Height of the block the transaction was confirmed in, if known
+	#[serde(
+		default,
+		skip_serializing_if = "Option::is_none",
+		with = "secp_ser::opt_string_or_u64"
+	)]
+	pub confirmation_height: Option<u64>,
 }
 
 impl ser::Writeable for TxLogEntry {
@@ -669,6 +1202,7 @@ impl TxLogEntry {
 			fee: None,
 			messages: None,
 			stored_tx: None,
+			confirmation_height: None,
 		}
 	}
 
@@ -709,7 +1243,7 @@ impl ser::Readable for AcctPathMapping {
 }
 
 /// Dummy wrapper for the hex-encoded serialized transaction.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TxWrapper {
 	/// hex representation of transaction
 	pub tx_hex: String,
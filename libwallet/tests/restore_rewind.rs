@@ -0,0 +1,109 @@
+// Copyright 2019 The Grin Developers
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Range proof rewinding during restore, single and multi threaded, and
+//! recovery of derivation paths of any depth
+use grin_wallet_libwallet::identify_outputs;
+use grin_wallet_util::grin_core::libtx::proof;
+use grin_wallet_util::grin_keychain::{ExtKeychain, Keychain};
+use grin_wallet_util::grin_util::secp::pedersen;
+use std::time::Instant;
+
+/// Number of outputs in the synthetic batch
+const BATCH_SIZE: u64 = 400;
+
+/// A batch of outputs as returned by the node, every other one belonging to
+/// `ours`, the rest to `theirs`
+fn synthetic_outputs(
+	ours: &ExtKeychain,
+	theirs: &ExtKeychain,
+) -> Vec<(pedersen::Commitment, pedersen::RangeProof, bool, u64, u64)> {
+	let mut outputs = vec![];
+	for i in 0..BATCH_SIZE {
+		let keychain = match i % 2 {
+			0 => ours,
+			_ => theirs,
+		};
+		let key_id = ExtKeychain::derive_key_id(3, 0, 0, i as u32, 0);
+		let value = 1_000 + i;
+		let commit = keychain.commit(value, &key_id).unwrap();
+		let proof = proof::create(keychain, value, &key_id, commit, None).unwrap();
+		outputs.push((commit, proof, false, i + 1, i + 1));
+	}
+	outputs
+}
+
+#[test]
+fn parallel_rewind_matches_serial() {
+	let ours = ExtKeychain::from_random_seed(true).unwrap();
+	let theirs = ExtKeychain::from_random_seed(true).unwrap();
+	let outputs = synthetic_outputs(&ours, &theirs);
+
+	let start = Instant::now();
+	let (serial, unknown) = identify_outputs(&ours, &outputs, 1).unwrap();
+	let serial_time = start.elapsed();
+	assert_eq!(serial.len() as u64, BATCH_SIZE / 2);
+	assert!(unknown.is_empty());
+	assert!(serial.iter().all(|o| o.mmr_index % 2 == 1));
+	assert!(serial.iter().all(|o| o.value == 1_000 + o.mmr_index - 1));
+
+	for threads in vec![2, 3, 4, 8] {
+		let start = Instant::now();
+		let (parallel, _) = identify_outputs(&ours, &outputs, threads).unwrap();
+		let parallel_time = start.elapsed();
+		assert_eq!(parallel, serial);
+		println!(
+			"Rewound {} outputs: 1 thread {:?}, {} threads {:?}",
+			BATCH_SIZE, serial_time, threads, parallel_time
+		);
+	}
+
+	// more threads than outputs, and nothing to scan
+	assert_eq!(
+		identify_outputs(&ours, &outputs[..3], 8).unwrap().0,
+		serial[..2].to_vec()
+	);
+	assert!(identify_outputs(&ours, &[], 4).unwrap().0.is_empty());
+}
+
+#[test]
+fn rewind_recovers_path_depths() {
+	let keychain = ExtKeychain::from_random_seed(true).unwrap();
+	let key_ids = vec![
+		ExtKeychain::derive_key_id(1, 7, 0, 0, 0),
+		ExtKeychain::derive_key_id(2, 1, 5, 0, 0),
+		ExtKeychain::derive_key_id(3, 0, 0, 0, 0),
+		ExtKeychain::derive_key_id(4, 1, 2, 3, 0),
+		ExtKeychain::derive_key_id(4, 2, 0, 0, 9),
+		// the root key's path is empty, so it can't be recovered
+		ExtKeychain::derive_key_id(0, 0, 0, 0, 0),
+	];
+	let mut outputs = vec![];
+	for (i, key_id) in key_ids.iter().enumerate() {
+		let value = 1_000 + i as u64;
+		let commit = keychain.commit(value, key_id).unwrap();
+		let proof = proof::create(&keychain, value, key_id, commit, None).unwrap();
+		outputs.push((commit, proof, false, 1, i as u64 + 1));
+	}
+
+	let (found, unknown) = identify_outputs(&keychain, &outputs, 1).unwrap();
+	let found_ids: Vec<_> = found.iter().map(|o| o.key_id.clone()).collect();
+	assert_eq!(found_ids, key_ids[..5].to_vec());
+	assert_eq!(found[3].n_child, 0);
+	assert_eq!(found[4].n_child, 9);
+
+	assert_eq!(unknown.len(), 1);
+	assert_eq!(unknown[0].commit, outputs[5].0);
+	assert_eq!(unknown[0].value, 1_005);
+	assert_eq!(unknown[0].mmr_index, 6);
+}
@@ -15,8 +15,11 @@
 use crate::cmd::wallet_args;
 use crate::config::GlobalWalletConfig;
 use clap::ArgMatches;
-use grin_wallet_config::WalletConfig;
-use grin_wallet_impls::{HTTPNodeClient, WalletSeed, SEED_FILE};
+use forest_wallet_controller::Error;
+use grin_wallet_config::{WalletBackendType, WalletConfig};
+use grin_wallet_impls::{
+	HTTPNodeClient, MultiNodeClient, RecordingNodeClient, WalletSeed, SEED_FILE,
+};
 use grin_wallet_libwallet::NodeClient;
 use std::path::PathBuf;
 use std::thread;
@@ -24,7 +27,7 @@ use std::time::Duration;
 
 pub fn _init_wallet_seed(wallet_config: WalletConfig, password: &str) {
 	if let Err(_) = WalletSeed::from_file(&wallet_config, password) {
-		WalletSeed::init_file(&wallet_config, 32, None, password)
+		WalletSeed::init_file(&wallet_config, 32, None, None, password)
 			.expect("Failed to create wallet seed file.");
 	};
 }
@@ -40,14 +43,45 @@ pub fn seed_exists(wallet_config: WalletConfig) -> bool {
 	}
 }
 
+/// Run the command, recording what's asked of the node and how it answers
+/// to a fixture file if asked to, for replaying in tests
+fn run_wallet_command<C>(
+	wallet_args: &ArgMatches<'_>,
+	wallet_config: WalletConfig,
+	node_client: C,
+) -> Result<String, Error>
+where
+	C: NodeClient + 'static,
+{
+	match wallet_args.value_of("record_node") {
+		Some(path) => {
+			let node_client = RecordingNodeClient::new(node_client, path);
+			wallet_args::wallet_command(wallet_args, wallet_config, node_client)
+		}
+		None => wallet_args::wallet_command(wallet_args, wallet_config, node_client),
+	}
+}
+
 pub fn wallet_command(wallet_args: &ArgMatches<'_>, config: GlobalWalletConfig) -> i32 {
 	// just get defaults from the global config
 	let wallet_config = config.members.unwrap().wallet;
 
+	// nothing would be kept once the command exits
+	if wallet_config.wallet_backend() == WalletBackendType::Memory {
+		let err_str = "The in-memory wallet backend is only for tests, set wallet_backend to \"lmdb\" or \"sqlite\"";
+		error!("{}", err_str);
+		println!("{}", err_str);
+		return 1;
+	}
+
 	// TODO: Very temporary code to obsolete grin wallet for the first hard fork
 	// All tx operations call get_chain_height as a first order of business,
 	// so this is the most non-intrusive place to put this
-	let mut node_client = HTTPNodeClient::new(&wallet_config.check_node_api_http_addr, None);
+	let mut node_client = HTTPNodeClient::new(
+		&wallet_config.check_node_api_http_addr,
+		None,
+		wallet_config.socks_proxy_addr.clone(),
+	);
 	let global_wallet_args = wallet_args::parse_global_args(&wallet_config, &wallet_args)
 		.expect("Can't read configuration file");
 	node_client.set_node_api_secret(global_wallet_args.node_api_secret.clone());
@@ -71,7 +105,21 @@ pub fn wallet_command(wallet_args: &ArgMatches<'_>, config: GlobalWalletConfig)
 		Err(_) => {}
 	}
 
-	let res = wallet_args::wallet_command(wallet_args, wallet_config, node_client);
+	// spread requests over the additional nodes too, if there are any. A
+	// quorum other than 1 needs as many nodes, so is checked against them
+	// even if there's only the one
+	let single_node =
+		wallet_config.additional_nodes().is_empty() && wallet_config.node_quorum() == 1;
+	let res = match single_node {
+		true => run_wallet_command(wallet_args, wallet_config, node_client),
+		false => match MultiNodeClient::from_config(
+			&wallet_config,
+			global_wallet_args.node_api_secret.clone(),
+		) {
+			Ok(c) => run_wallet_command(wallet_args, wallet_config, c),
+			Err(e) => Err(e.into()),
+		},
+	};
 
 	// we need to give log output a chance to catch up before exiting
 	thread::sleep(Duration::from_millis(100));
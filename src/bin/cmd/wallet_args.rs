@@ -21,10 +21,13 @@ use failure::Fail;
 use grin_wallet_config::WalletConfig;
 use forest_wallet_controller::command;
 use forest_wallet_controller::{Error, ErrorKind};
-use grin_wallet_impls::{instantiate_wallet, WalletSeed};
-use grin_wallet_libwallet::{IssueInvoiceTxArgs, NodeClient, WalletInst};
+use grin_wallet_impls::{
+	instantiate_wallet_with_lock, LockMode, SeedShare, WalletSeed, MAX_SHARES,
+};
+use grin_wallet_libwallet::{AcctPathMapping, IssueInvoiceTxArgs, NodeClient, WalletInst};
 use grin_wallet_util::grin_core as core;
 use grin_wallet_util::grin_keychain as keychain;
+use grin_wallet_util::grin_keychain::Keychain;
 use linefeed::terminal::Signal;
 use linefeed::{Interface, ReadResult};
 use rpassword;
@@ -89,6 +92,110 @@ fn prompt_password_confirm() -> ZeroingString {
 	first
 }
 
+/// Prompt for share phrases until enough have been entered to recover the
+/// seed, rejecting any that fail their checksum or belong to another backup,
+/// and return the recovered seed's recovery phrase
+fn prompt_recovery_shares() -> Result<ZeroingString, ParseError> {
+	let interface = Arc::new(Interface::new("recover")?);
+	let mut shares: Vec<SeedShare> = vec![];
+	let mut phrases: Vec<ZeroingString> = vec![];
+	interface.set_report_signal(Signal::Interrupt, true);
+	interface.set_prompt("share> ")?;
+	loop {
+		match shares.first() {
+			Some(s) => println!(
+				"Please enter another share ({} of {} entered):",
+				shares.len(),
+				s.threshold
+			),
+			None => println!("Please enter a recovery share:"),
+		}
+		let res = interface.read_line()?;
+		match res {
+			ReadResult::Eof => return Err(ParseError::CancelledError),
+			ReadResult::Signal(sig) => {
+				if sig == Signal::Interrupt {
+					interface.cancel_read_line()?;
+					return Err(ParseError::CancelledError);
+				}
+			}
+			ReadResult::Input(line) => {
+				let share = match SeedShare::from_phrase(&line) {
+					Ok(s) => s,
+					Err(e) => {
+						println!();
+						println!("{}", e);
+						println!();
+						interface.set_buffer(&line)?;
+						continue;
+					}
+				};
+				if let Some(first) = shares.first() {
+					if share.set_id != first.set_id || share.threshold != first.threshold {
+						println!();
+						println!("Share is from a different backup.");
+						println!();
+						continue;
+					}
+				}
+				if shares.iter().any(|s| s.index == share.index) {
+					println!();
+					println!("Share {} was already entered.", share.index);
+					println!();
+					continue;
+				}
+				let threshold = share.threshold as usize;
+				shares.push(share);
+				phrases.push(ZeroingString::from(line));
+				if shares.len() >= threshold {
+					break;
+				}
+			}
+		}
+	}
+	let seed = match WalletSeed::from_shares(&phrases) {
+		Ok(s) => s,
+		Err(e) => return Err(ParseError::ArgumentError(format!("{}", e))),
+	};
+	match seed.to_mnemonic() {
+		Ok(p) => Ok(ZeroingString::from(p)),
+		Err(e) => Err(ParseError::ArgumentError(format!("{}", e))),
+	}
+}
+
+/// Parse a share scheme of the form "M-of-N"
+fn parse_shares(arg: &str) -> Result<(u8, u8), ParseError> {
+	let msg = format!(
+		"Could not parse shares '{}', expected M-of-N with 1 <= M <= N <= {}",
+		arg, MAX_SHARES
+	);
+	let parts: Vec<&str> = arg.split("-of-").collect();
+	if parts.len() != 2 {
+		return Err(ParseError::ArgumentError(msg));
+	}
+	let threshold = parts[0].trim().parse::<u8>();
+	let shares = parts[1].trim().parse::<u8>();
+	match (threshold, shares) {
+		(Ok(m), Ok(n)) if m >= 1 && m <= n && n <= MAX_SHARES => Ok((m, n)),
+		_ => Err(ParseError::ArgumentError(msg)),
+	}
+}
+
+fn prompt_mnemonic_passphrase() -> ZeroingString {
+	println!();
+	println!("Please enter the BIP39 passphrase to combine with the recovery phrase.");
+	println!("A different passphrase gives a different wallet, so if it's mistyped here");
+	println!("or when recovering, the wallet will open without error but will be empty.");
+	println!();
+	let mut first = ZeroingString::from("first");
+	let mut second = ZeroingString::from("second");
+	while first != second {
+		first = prompt_password_stdout("BIP39 Passphrase: ");
+		second = prompt_password_stdout("Confirm BIP39 Passphrase: ");
+	}
+	first
+}
+
 fn prompt_replace_seed() -> Result<bool, ParseError> {
 	let interface = Arc::new(Interface::new("replace_seed")?);
 	interface.set_report_signal(Signal::Interrupt, true);
@@ -148,6 +255,56 @@ fn prompt_recovery_phrase() -> Result<ZeroingString, ParseError> {
 	Ok(phrase)
 }
 
+/// Prompt for a recovery phrase to check, without validating it first
+fn prompt_verify_phrase() -> Result<ZeroingString, ParseError> {
+	let interface = Arc::new(Interface::new("verify")?);
+	interface.set_report_signal(Signal::Interrupt, true);
+	interface.set_prompt("phrase> ")?;
+	println!("Please enter your recovery phrase:");
+	loop {
+		let res = interface.read_line()?;
+		match res {
+			ReadResult::Eof => return Err(ParseError::CancelledError),
+			ReadResult::Signal(sig) => {
+				if sig == Signal::Interrupt {
+					interface.cancel_read_line()?;
+					return Err(ParseError::CancelledError);
+				}
+			}
+			ReadResult::Input(line) => return Ok(ZeroingString::from(line)),
+		}
+	}
+}
+
+/// Prompt for the recovery phrase words at each of the (zero based)
+/// `positions`
+fn prompt_verify_words(positions: &[usize]) -> Result<Vec<(usize, ZeroingString)>, ParseError> {
+	let interface = Arc::new(Interface::new("verify")?);
+	let mut words = vec![];
+	interface.set_report_signal(Signal::Interrupt, true);
+	println!("Please enter the words of your recovery phrase at the following positions:");
+	for pos in positions {
+		interface.set_prompt(&format!("word #{}> ", pos + 1))?;
+		loop {
+			let res = interface.read_line()?;
+			match res {
+				ReadResult::Eof => return Err(ParseError::CancelledError),
+				ReadResult::Signal(sig) => {
+					if sig == Signal::Interrupt {
+						interface.cancel_read_line()?;
+						return Err(ParseError::CancelledError);
+					}
+				}
+				ReadResult::Input(line) => {
+					words.push((*pos, ZeroingString::from(line.trim())));
+					break;
+				}
+			}
+		}
+	}
+	Ok(words)
+}
+
 #[cfg(not(test))]
 fn prompt_pay_invoice(slate: &Slate, method: &str, dest: &str) -> Result<bool, ParseError> {
 	let interface = Arc::new(Interface::new("pay")?);
@@ -211,9 +368,16 @@ pub fn inst_wallet(
 	config: WalletConfig,
 	g_args: &command::GlobalArgs,
 	node_client: impl NodeClient + 'static,
+	lock_mode: LockMode,
 ) -> Result<Arc<Mutex<WalletInst<impl NodeClient + 'static, keychain::ExtKeychain>>>, ParseError> {
 	let passphrase = prompt_password(&g_args.password);
-	let res = instantiate_wallet(config.clone(), node_client, &passphrase, &g_args.account);
+	let res = instantiate_wallet_with_lock(
+		config.clone(),
+		node_client,
+		&passphrase,
+		&g_args.account,
+		lock_mode,
+	);
 	match res {
 		Ok(p) => Ok(p),
 		Err(e) => {
@@ -305,10 +469,18 @@ pub fn parse_init_args(
 		false => 32,
 		true => 16,
 	};
+	let shares = match args.value_of("shares") {
+		Some(s) => Some(parse_shares(s)?),
+		None => None,
+	};
 	let recovery_phrase = match args.is_present("recover") {
 		true => Some(prompt_recovery_phrase()?),
 		false => None,
 	};
+	let mnemonic_passphrase = match args.is_present("mnemonic_passphrase") {
+		true => Some(prompt_mnemonic_passphrase()),
+		false => None,
+	};
 
 	if recovery_phrase.is_some() {
 		println!("Please provide a new password for the recovered wallet");
@@ -326,18 +498,31 @@ pub fn parse_init_args(
 		password: password,
 		config: config.clone(),
 		recovery_phrase: recovery_phrase,
+		mnemonic_passphrase: mnemonic_passphrase,
+		shares: shares,
 		restore: false,
 	})
 }
 
+pub fn parse_passwd_args(g_args: &command::GlobalArgs) -> Result<command::PasswdArgs, ParseError> {
+	println!("Please enter your current password");
+	let old_password = prompt_password(&g_args.password);
+	println!("Please enter a new password for your wallet");
+	let new_password = prompt_password_confirm();
+	Ok(command::PasswdArgs {
+		old_password,
+		new_password,
+	})
+}
+
 pub fn parse_recover_args(
 	config: &WalletConfig,
 	g_args: &command::GlobalArgs,
 	args: &ArgMatches,
 ) -> Result<command::RecoverArgs, ParseError> {
-	let (passphrase, recovery_phrase) = {
+	let (passphrase, recovery_phrase, mnemonic_passphrase) = {
 		match args.is_present("display") {
-			true => (prompt_password(&g_args.password), None),
+			true => (prompt_password(&g_args.password), None, None),
 			false => {
 				let cont = {
 					if command::wallet_seed_exists(config).is_err() {
@@ -349,15 +534,58 @@ pub fn parse_recover_args(
 				if !cont {
 					return Err(ParseError::CancelledError);
 				}
-				let phrase = prompt_recovery_phrase()?;
+				let phrase = match args.is_present("from_shares") {
+					true => prompt_recovery_shares()?,
+					false => prompt_recovery_phrase()?,
+				};
+				let mnemonic_passphrase = match args.is_present("mnemonic_passphrase") {
+					true => Some(prompt_mnemonic_passphrase()),
+					false => None,
+				};
 				println!("Please provide a new password for the recovered wallet");
-				(prompt_password_confirm(), Some(phrase.to_owned()))
+				(
+					prompt_password_confirm(),
+					Some(phrase.to_owned()),
+					mnemonic_passphrase,
+				)
 			}
 		}
 	};
 	Ok(command::RecoverArgs {
 		passphrase: passphrase,
 		recovery_phrase: recovery_phrase,
+		mnemonic_passphrase: mnemonic_passphrase,
+	})
+}
+
+pub fn parse_verify_phrase_args(
+	config: &WalletConfig,
+	g_args: &command::GlobalArgs,
+	args: &ArgMatches,
+) -> Result<command::VerifyPhraseArgs, ParseError> {
+	let (recovery_phrase, words) = match args.value_of("words") {
+		Some(w) => {
+			let count = parse_u64(w, "words")? as usize;
+			let word_count = match WalletSeed::file_word_count(config) {
+				Ok(c) => c,
+				Err(e) => return Err(ParseError::ArgumentError(format!("{}", e))),
+			};
+			if count == 0 || count > word_count {
+				let msg = format!(
+					"Number of words to check must be between 1 and {}",
+					word_count
+				);
+				return Err(ParseError::ArgumentError(msg));
+			}
+			let positions = WalletSeed::random_word_positions(word_count, count);
+			(None, prompt_verify_words(&positions)?)
+		}
+		None => (Some(prompt_verify_phrase()?), vec![]),
+	};
+	Ok(command::VerifyPhraseArgs {
+		password: prompt_password(&g_args.password),
+		recovery_phrase,
+		words,
 	})
 }
 
@@ -655,10 +883,103 @@ pub fn parse_info_args(args: &ArgMatches) -> Result<command::InfoArgs, ParseErro
 	})
 }
 
+fn parse_start_height(args: &ArgMatches) -> Result<Option<u64>, ParseError> {
+	match args.value_of("start_height") {
+		None => Ok(None),
+		Some(h) => Ok(Some(parse_u64(h, "start_height")?)),
+	}
+}
+
+/// Parse a BIP32 path such as m/1/0 into a key id
+fn parse_bip32_path(arg: &str) -> Result<keychain::Identifier, ParseError> {
+	let err = || ParseError::ArgumentError(format!("{} is not a derivation path like m/1/0", arg));
+	let mut parts = arg.split('/');
+	if parts.next() != Some("m") {
+		return Err(err());
+	}
+	let mut path = [0u32; 4];
+	let mut depth = 0;
+	for p in parts {
+		if depth == path.len() {
+			return Err(err());
+		}
+		path[depth] = p.parse::<u32>().map_err(|_| err())?;
+		depth += 1;
+	}
+	Ok(keychain::ExtKeychain::derive_key_id(
+		depth as u8,
+		path[0],
+		path[1],
+		path[2],
+		path[3],
+	))
+}
+
+fn parse_account_labels(args: &ArgMatches) -> Result<Option<Vec<AcctPathMapping>>, ParseError> {
+	let arg = match args.value_of("accounts") {
+		None => return Ok(None),
+		Some(a) => a,
+	};
+	let mut res = vec![];
+	for a in arg.split(',') {
+		let mut parts = a.trim().splitn(2, '=');
+		match (parts.next(), parts.next()) {
+			(Some(label), Some(path)) if !label.is_empty() => res.push(AcctPathMapping {
+				label: label.to_owned(),
+				path: parse_bip32_path(path)?,
+			}),
+			_ => {
+				let msg = format!("Could not parse account {}, expected label=path", a);
+				return Err(ParseError::ArgumentError(msg));
+			}
+		}
+	}
+	Ok(Some(res))
+}
+
+pub fn parse_restore_args(args: &ArgMatches) -> Result<command::RestoreArgs, ParseError> {
+	Ok(command::RestoreArgs {
+		start_height: parse_start_height(args)?,
+		account_labels: parse_account_labels(args)?,
+	})
+}
+
 pub fn parse_check_args(args: &ArgMatches) -> Result<command::CheckArgs, ParseError> {
 	let delete_unconfirmed = args.is_present("delete_unconfirmed");
 	Ok(command::CheckArgs {
 		delete_unconfirmed: delete_unconfirmed,
+		start_height: parse_start_height(args)?,
+		dry_run: args.is_present("dry_run"),
+	})
+}
+
+pub fn parse_export_args(args: &ArgMatches) -> Result<command::ExportArgs, ParseError> {
+	let dest = parse_required(args, "dest")?;
+	Ok(command::ExportArgs {
+		dest: dest.to_owned(),
+	})
+}
+
+pub fn parse_import_args(args: &ArgMatches) -> Result<command::ImportArgs, ParseError> {
+	let input = parse_required(args, "input")?;
+	Ok(command::ImportArgs {
+		input: input.to_owned(),
+	})
+}
+
+pub fn parse_db_migrate_args(
+	g_args: &command::GlobalArgs,
+	args: &ArgMatches,
+) -> Result<command::DbMigrateArgs, ParseError> {
+	Ok(command::DbMigrateArgs {
+		password: prompt_password(&g_args.password),
+		dry_run: args.is_present("dry_run"),
+	})
+}
+
+pub fn parse_db_fsck_args(args: &ArgMatches) -> Result<command::DbFsckArgs, ParseError> {
+	Ok(command::DbFsckArgs {
+		fix: args.is_present("fix"),
 	})
 }
 
@@ -746,8 +1067,29 @@ pub fn wallet_command(
 	node_client.set_node_api_secret(global_wallet_args.node_api_secret.clone());
 
 	// closure to instantiate wallet as needed by each subcommand
+	let read_only_client = node_client.clone();
 	let inst_wallet = || {
-		let res = inst_wallet(wallet_config.clone(), &global_wallet_args, node_client);
+		let res = inst_wallet(
+			wallet_config.clone(),
+			&global_wallet_args,
+			node_client,
+			LockMode::Exclusive,
+		);
+		res.unwrap_or_else(|e| {
+			println!("{}", e);
+			std::process::exit(1);
+		})
+	};
+
+	// as above, for subcommands that only read the wallet, so can run
+	// alongside other readers
+	let inst_wallet_read_only = || {
+		let res = self::inst_wallet(
+			wallet_config.clone(),
+			&global_wallet_args,
+			read_only_client,
+			LockMode::Shared,
+		);
 		res.unwrap_or_else(|e| {
 			println!("{}", e);
 			std::process::exit(1);
@@ -767,6 +1109,24 @@ pub fn wallet_command(
 			));
 			command::recover(&wallet_config, a)
 		}
+		("passwd", Some(_)) => {
+			let a = arg_parse!(parse_passwd_args(&global_wallet_args));
+			command::passwd(&wallet_config, &global_wallet_args, a)
+		}
+		("upgrade-seed", Some(_)) => {
+			let a = command::UpgradeSeedArgs {
+				password: prompt_password(&global_wallet_args.password),
+			};
+			command::upgrade_seed(&wallet_config, a)
+		}
+		("verify-phrase", Some(args)) => {
+			let a = arg_parse!(parse_verify_phrase_args(
+				&wallet_config,
+				&global_wallet_args,
+				&args
+			));
+			command::verify_phrase(&wallet_config, a)
+		}
 		("listen", Some(args)) => {
 			let mut c = wallet_config.clone();
 			let mut g = global_wallet_args.clone();
@@ -781,7 +1141,10 @@ pub fn wallet_command(
 		("web", Some(_)) => command::owner_api(inst_wallet(), &wallet_config, &global_wallet_args),
 		("account", Some(args)) => {
 			let a = arg_parse!(parse_account_args(&args));
-			command::account(inst_wallet(), a)
+			match a.create.is_some() {
+				true => command::account(inst_wallet(), a),
+				false => command::account(inst_wallet_read_only(), a),
+			}
 		}
 		("send", Some(args)) => {
 			let a = arg_parse!(parse_send_args(&args));
@@ -842,11 +1205,40 @@ pub fn wallet_command(
 			let a = arg_parse!(parse_cancel_args(&args));
 			command::cancel(inst_wallet(), a)
 		}
-		("restore", Some(_)) => command::restore(inst_wallet()),
+		("restore", Some(args)) => {
+			let a = arg_parse!(parse_restore_args(&args));
+			command::restore(inst_wallet(), a)
+		}
 		("check", Some(args)) => {
 			let a = arg_parse!(parse_check_args(&args));
 			command::check_repair(inst_wallet(), a)
 		}
+		("export", Some(args)) => {
+			let a = arg_parse!(parse_export_args(&args));
+			command::export(inst_wallet_read_only(), a)
+		}
+		("import", Some(args)) => {
+			let a = arg_parse!(parse_import_args(&args));
+			command::import(inst_wallet(), a)
+		}
+		("db", Some(args)) => match args.subcommand() {
+			("migrate", Some(args)) => {
+				let a = arg_parse!(parse_db_migrate_args(&global_wallet_args, &args));
+				command::db_migrate(&wallet_config, a)
+			}
+			("fsck", Some(args)) => {
+				let a = arg_parse!(parse_db_fsck_args(&args));
+				match a.fix {
+					true => command::db_fsck(inst_wallet(), a),
+					false => command::db_fsck(inst_wallet_read_only(), a),
+				}
+			}
+			("gc", Some(_)) => command::db_gc(inst_wallet()),
+			_ => {
+				let msg = format!("Unknown db command, use 'grin help db' for details");
+				return Err(ErrorKind::ArgumentError(msg).into());
+			}
+		},
 		_ => {
 			let msg = format!("Unknown wallet command, use 'grin help wallet' for details");
 			return Err(ErrorKind::ArgumentError(msg).into());